@@ -0,0 +1,32 @@
+//! Timelock and Lightning analysis of a transaction in one call.
+
+use schemars::JsonSchema;
+use serde::Serialize;
+
+use crate::api::types::ApiTransaction;
+use crate::lightning::detector::classify_lightning;
+use crate::lightning::types::LightningClassification;
+use crate::timelock::extractor::analyze_transaction;
+use crate::timelock::types::{RiskReport, TransactionAnalysis};
+
+/// Everything the timelock extractor and the Lightning detector found in a transaction.
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct CombinedAnalysis {
+    pub timelock: TransactionAnalysis,
+    pub lightning: LightningClassification,
+}
+
+impl CombinedAnalysis {
+    /// The timelock risk findings, also under `timelock.risk`.
+    pub fn risk(&self) -> &RiskReport {
+        &self.timelock.risk
+    }
+}
+
+/// Run both [`analyze_transaction`] and [`classify_lightning`] on `tx`.
+pub fn analyze(tx: &ApiTransaction) -> CombinedAnalysis {
+    CombinedAnalysis {
+        timelock: analyze_transaction(tx),
+        lightning: classify_lightning(tx),
+    }
+}
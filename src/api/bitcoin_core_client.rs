@@ -0,0 +1,566 @@
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use anyhow::Context;
+use bitcoin::script::Instruction;
+use bitcoin::{Script, ScriptBuf};
+use reqwest::StatusCode;
+use serde::Deserialize;
+use serde::de::DeserializeOwned;
+use serde_json::json;
+use tokio::sync::OnceCell;
+
+use super::block_index::BlockIndex;
+use super::client::DEFAULT_TIMEOUT;
+use super::source::{BLOCK_TXS_PAGE_SIZE, DataSource, SyncStatus};
+use super::types::{ApiPrevout, ApiStatus, ApiTransaction, ApiVin, ApiVout};
+use crate::error::{Error, Result};
+
+/// A block's hash and mapped transactions.
+type FetchedBlock = (String, Arc<Vec<ApiTransaction>>);
+
+/// Number of mempool entries returned by get_mempool_recent_txids (matches mempool.space).
+const RECENT_MEMPOOL_COUNT: usize = 10;
+/// `RPC_INVALID_ADDRESS_OR_KEY`: unknown transaction or block.
+const RPC_INVALID_ADDRESS_OR_KEY: i64 = -5;
+/// `RPC_INVALID_PARAMETER`, e.g. a block height past the tip.
+const RPC_INVALID_PARAMETER: i64 = -8;
+/// How to authenticate against bitcoind's RPC interface.
+#[derive(Debug, Clone)]
+pub enum CoreAuth {
+    /// No credentials (e.g. behind an authenticating proxy).
+    None,
+    /// `.cookie` file written by bitcoind on startup.
+    CookieFile(PathBuf),
+    /// `rpcuser` / `rpcpassword` (or `rpcauth`) credentials.
+    UserPass { user: String, pass: String },
+}
+
+// ─── RPC response types ─────────────────────────────────────────────────────
+
+#[derive(Deserialize)]
+struct RpcResponse<T> {
+    result: Option<T>,
+    error: Option<RpcError>,
+}
+
+#[derive(Deserialize)]
+struct RpcError {
+    code: i64,
+    message: String,
+}
+
+#[derive(Deserialize)]
+struct CoreTx {
+    txid: String,
+    version: i32,
+    size: u64,
+    weight: u64,
+    locktime: u32,
+    vin: Vec<CoreVin>,
+    vout: Vec<CoreVout>,
+    /// Present with getrawtransaction verbosity 2 and getblock verbosity 2/3 (BTC).
+    fee: Option<f64>,
+    blockhash: Option<String>,
+    confirmations: Option<u64>,
+    blocktime: Option<u64>,
+}
+
+#[derive(Deserialize)]
+struct CoreVin {
+    txid: Option<String>,
+    vout: Option<u32>,
+    coinbase: Option<String>,
+    #[serde(rename = "scriptSig")]
+    script_sig: Option<CoreScriptSig>,
+    txinwitness: Option<Vec<String>>,
+    /// Present with getrawtransaction verbosity 2 and getblock verbosity 3.
+    prevout: Option<CorePrevout>,
+    sequence: u32,
+}
+
+#[derive(Deserialize)]
+struct CoreScriptSig {
+    asm: String,
+    hex: String,
+}
+
+#[derive(Deserialize)]
+struct CoreVout {
+    value: f64,
+    n: u32,
+    #[serde(rename = "scriptPubKey")]
+    script_pub_key: CoreScriptPubKey,
+}
+
+#[derive(Deserialize)]
+struct CorePrevout {
+    value: f64,
+    #[serde(rename = "scriptPubKey")]
+    script_pub_key: CoreScriptPubKey,
+}
+
+#[derive(Deserialize)]
+struct CoreScriptPubKey {
+    asm: String,
+    hex: String,
+    address: Option<String>,
+    #[serde(rename = "type")]
+    type_: String,
+}
+
+#[derive(Deserialize)]
+struct CoreBlock {
+    height: u64,
+    tx: Vec<CoreTx>,
+}
+
+#[derive(Deserialize)]
+struct CoreBlockHeader {
+    height: u64,
+}
+
+#[derive(Deserialize)]
+struct CoreMempoolEntry {
+    time: u64,
+}
+
+#[derive(Deserialize)]
+struct CoreBlockchainInfo {
+    initialblockdownload: bool,
+    verificationprogress: f64,
+}
+
+// ─── Client ─────────────────────────────────────────────────────────────────
+
+/// DataSource backed by a local bitcoind JSON-RPC endpoint.
+pub struct BitcoinCoreClient {
+    client: reqwest::Client,
+    rpc_url: String,
+    credentials: Option<(String, String)>,
+    txindex: OnceCell<bool>,
+    block_index: BlockIndex,
+    /// The block `get_block_txs` last paged through, so a walk fetches it once.
+    last_block: Mutex<Option<FetchedBlock>>,
+    timeout: Duration,
+}
+
+impl BitcoinCoreClient {
+    pub fn new(rpc_url: &str, auth: CoreAuth) -> anyhow::Result<Self> {
+        let credentials = match auth {
+            CoreAuth::None => None,
+            CoreAuth::UserPass { user, pass } => Some((user, pass)),
+            CoreAuth::CookieFile(path) => {
+                let cookie = fs::read_to_string(&path)
+                    .with_context(|| format!("reading RPC cookie file {}", path.display()))?;
+                let (user, pass) = cookie
+                    .trim()
+                    .split_once(':')
+                    .with_context(|| format!("malformed RPC cookie file {}", path.display()))?;
+                Some((user.to_string(), pass.to_string()))
+            }
+        };
+
+        Ok(Self {
+            client: reqwest::Client::new(),
+            rpc_url: rpc_url.trim_end_matches('/').to_string(),
+            credentials,
+            txindex: OnceCell::new(),
+            block_index: BlockIndex::new(),
+            last_block: Mutex::new(None),
+            timeout: DEFAULT_TIMEOUT,
+        })
+    }
+
+    /// Per-call timeout (default 30s).
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Unknown transactions, blocks and heights are `NotFound`; other RPC errors,
+    /// including warmup and failed authentication, are `RpcUnavailable`.
+    async fn call<T: DeserializeOwned>(&self, method: &str, params: serde_json::Value) -> Result<T> {
+        let body = json!({
+            "jsonrpc": "1.0",
+            "id": "cltv-scan",
+            "method": method,
+            "params": params,
+        });
+
+        let mut req = self.client.post(&self.rpc_url).json(&body).timeout(self.timeout);
+        if let Some((ref user, ref pass)) = self.credentials {
+            req = req.basic_auth(user, Some(pass));
+        }
+
+        let resp = match req.send().await {
+            Ok(resp) => resp,
+            Err(e) if e.is_timeout() => {
+                return Err(Error::Network(format!(
+                    "RPC {method} {params} to {} timed out after {}s",
+                    self.rpc_url,
+                    self.timeout.as_secs_f64()
+                )));
+            }
+            Err(e) => {
+                return Err(Error::Network(format!(
+                    "RPC {method} to {}: {:#}",
+                    self.rpc_url,
+                    anyhow::Error::new(e)
+                )));
+            }
+        };
+
+        if resp.status() == StatusCode::UNAUTHORIZED {
+            return Err(Error::RpcUnavailable(format!(
+                "RPC authentication failed for {}",
+                self.rpc_url
+            )));
+        }
+
+        // bitcoind reports RPC errors with a non-2xx status but a JSON body
+        let status = resp.status();
+        let reply = resp
+            .json::<RpcResponse<T>>()
+            .await
+            .map_err(|e| {
+                Error::Decode(format!(
+                    "deserializing RPC {method} response (HTTP {status}): {:#}",
+                    anyhow::Error::new(e)
+                ))
+            })?;
+
+        if let Some(err) = reply.error {
+            let msg = format!("RPC {method} failed: {} (code {})", err.message, err.code);
+            return Err(match err.code {
+                RPC_INVALID_ADDRESS_OR_KEY | RPC_INVALID_PARAMETER => Error::NotFound(msg),
+                _ => Error::RpcUnavailable(msg),
+            });
+        }
+        reply
+            .result
+            .ok_or_else(|| Error::Decode(format!("RPC {method} returned no result")))
+    }
+
+    /// Whether bitcoind was started with `-txindex`, checked once per client.
+    async fn has_txindex(&self) -> bool {
+        *self
+            .txindex
+            .get_or_init(|| async {
+                self.call::<HashMap<String, serde_json::Value>>("getindexinfo", json!([]))
+                    .await
+                    .map(|indexes| indexes.contains_key("txindex"))
+                    .unwrap_or(false)
+            })
+            .await
+    }
+
+    /// Fill in previous outputs that bitcoind didn't include inline by looking up the
+    /// funding transactions. Only attempted when `-txindex` is available.
+    async fn chase_prevouts(&self, tx: &mut CoreTx) {
+        if tx.vin.iter().all(|v| v.prevout.is_some() || v.coinbase.is_some()) {
+            return;
+        }
+        if !self.has_txindex().await {
+            return;
+        }
+
+        for vin in tx.vin.iter_mut().filter(|v| v.prevout.is_none()) {
+            let (Some(prev_txid), Some(prev_vout)) = (vin.txid.as_deref(), vin.vout) else {
+                continue;
+            };
+            let Ok(prev) = self
+                .call::<CoreTx>("getrawtransaction", json!([prev_txid, 1]))
+                .await
+            else {
+                continue;
+            };
+            vin.prevout = prev
+                .vout
+                .into_iter()
+                .find(|o| o.n == prev_vout)
+                .map(|o| CorePrevout {
+                    value: o.value,
+                    script_pub_key: o.script_pub_key,
+                });
+        }
+    }
+
+    /// Height of block `hash`, from the block index or `getblockheader`. `None` if the
+    /// node doesn't know the block.
+    async fn block_height(&self, hash: &str) -> Option<u64> {
+        if let Some(height) = self.block_index.height(hash) {
+            return Some(height);
+        }
+        let header: CoreBlockHeader =
+            self.call("getblockheader", json!([hash, true])).await.ok()?;
+        self.block_index.insert(header.height, hash);
+        Some(header.height)
+    }
+
+    /// Fetch block `hash` in one `getblock` call and hand `f` each transaction, mapped
+    /// in block order.
+    async fn walk_block<E: From<Error>>(
+        &self,
+        hash: &str,
+        mut f: impl FnMut(ApiTransaction) -> std::result::Result<(), E>,
+    ) -> std::result::Result<(), E> {
+        // Verbosity 3 includes prevouts (Core 23+); older nodes fall back to chasing
+        let block: CoreBlock = self.call("getblock", json!([hash, 3])).await?;
+        let height = block.height;
+        self.block_index.insert(height, hash);
+
+        for mut tx in block.tx {
+            self.chase_prevouts(&mut tx).await;
+            f(map_core_tx_to_api(tx, Some(hash), Some(height)))?;
+        }
+        Ok(())
+    }
+
+    async fn get_block_by_hash(&self, hash: &str) -> Result<Vec<ApiTransaction>> {
+        let mut txs = Vec::new();
+        self.walk_block(hash, |tx| {
+            txs.push(tx);
+            Ok::<_, Error>(())
+        })
+        .await?;
+        Ok(txs)
+    }
+
+    /// Block `hash`, reusing the last one fetched when it's the same block.
+    async fn paged_block(&self, hash: &str) -> Result<Arc<Vec<ApiTransaction>>> {
+        if let Ok(last) = self.last_block.lock()
+            && let Some((last_hash, txs)) = last.as_ref()
+            && last_hash == hash
+        {
+            return Ok(txs.clone());
+        }
+        let txs = Arc::new(self.get_block_by_hash(hash).await?);
+        if let Ok(mut last) = self.last_block.lock() {
+            *last = Some((hash.to_string(), txs.clone()));
+        }
+        Ok(txs)
+    }
+}
+
+impl DataSource for BitcoinCoreClient {
+    async fn get_transaction(&self, txid: &str) -> Result<ApiTransaction> {
+        let mut tx: CoreTx = self
+            .call("getrawtransaction", json!([txid, 2]))
+            .await
+            .map_err(|e| {
+                e.context("fetching transaction (confirmed txs outside the wallet need -txindex)")
+            })?;
+        self.chase_prevouts(&mut tx).await;
+
+        let block_height = match tx.blockhash {
+            Some(ref hash) => self.block_height(hash).await,
+            None => None,
+        };
+
+        Ok(map_core_tx_to_api(tx, None, block_height))
+    }
+
+    async fn get_transaction_hex(&self, txid: &str) -> Result<String> {
+        self.call("getrawtransaction", json!([txid, 0])).await
+    }
+
+    async fn get_block_txs(&self, hash: &str, start_index: u32) -> Result<Vec<ApiTransaction>> {
+        let txs = self.paged_block(hash).await?;
+        let start = (start_index as usize).min(txs.len());
+        let end = (start + BLOCK_TXS_PAGE_SIZE).min(txs.len());
+        Ok(txs[start..end].to_vec())
+    }
+
+    async fn get_block_tip_height(&self) -> Result<u64> {
+        self.call("getblockcount", json!([])).await
+    }
+
+    async fn get_block_hash(&self, height: u64) -> Result<String> {
+        if let Some(hash) = self.block_index.hash(height) {
+            return Ok(hash);
+        }
+        let hash: String = self.call("getblockhash", json!([height])).await?;
+        self.block_index.insert(height, &hash);
+        Ok(hash)
+    }
+
+    async fn get_all_block_txs(&self, height: u64) -> Result<Vec<ApiTransaction>> {
+        let hash = self.get_block_hash(height).await?;
+        self.get_block_by_hash(&hash).await
+    }
+
+    async fn for_each_block_tx<E, F>(&self, height: u64, f: F) -> std::result::Result<(), E>
+    where
+        E: From<Error> + Send,
+        F: FnMut(ApiTransaction) -> std::result::Result<(), E> + Send,
+    {
+        let hash = self.get_block_hash(height).await?;
+        self.walk_block(&hash, f).await
+    }
+
+    async fn get_mempool_recent_txids(&self) -> Result<Vec<String>> {
+        let entries: HashMap<String, CoreMempoolEntry> =
+            self.call("getrawmempool", json!([true])).await?;
+
+        let mut entries: Vec<_> = entries.into_iter().collect();
+        entries.sort_by_key(|(_, entry)| Reverse(entry.time));
+        Ok(entries
+            .into_iter()
+            .take(RECENT_MEMPOOL_COUNT)
+            .map(|(txid, _)| txid)
+            .collect())
+    }
+
+    async fn get_mempool_txids(&self) -> Result<Vec<String>> {
+        self.call("getrawmempool", json!([false])).await
+    }
+
+    async fn sync_status(&self) -> Result<Option<SyncStatus>> {
+        let info: CoreBlockchainInfo = self.call("getblockchaininfo", json!([])).await?;
+        Ok(Some(SyncStatus {
+            synced: !info.initialblockdownload,
+            progress: Some(info.verificationprogress),
+        }))
+    }
+}
+
+// ─── Mapping ────────────────────────────────────────────────────────────────
+
+fn map_core_tx_to_api(
+    tx: CoreTx,
+    block_hash: Option<&str>,
+    block_height: Option<u64>,
+) -> ApiTransaction {
+    let vin = tx.vin.into_iter().map(map_core_vin).collect();
+
+    let vout = tx
+        .vout
+        .into_iter()
+        .map(|output| ApiVout {
+            scriptpubkey: output.script_pub_key.hex,
+            scriptpubkey_asm: output.script_pub_key.asm,
+            scriptpubkey_type: map_script_type(&output.script_pub_key.type_),
+            scriptpubkey_address: output.script_pub_key.address,
+            value: btc_to_sats(output.value),
+        })
+        .collect();
+
+    let block_hash = tx.blockhash.or(block_hash.map(str::to_string));
+    let confirmed = tx.confirmations.unwrap_or(0) > 0 || block_height.is_some();
+
+    ApiTransaction {
+        txid: tx.txid,
+        version: tx.version,
+        locktime: tx.locktime,
+        vin,
+        vout,
+        size: tx.size,
+        weight: tx.weight,
+        fee: tx.fee.map(btc_to_sats),
+        status: ApiStatus {
+            confirmed,
+            block_height: if confirmed { block_height } else { None },
+            block_hash: if confirmed { block_hash } else { None },
+            block_time: tx.blocktime,
+        },
+    }
+}
+
+fn map_core_vin(input: CoreVin) -> ApiVin {
+    let is_coinbase = input.coinbase.is_some();
+    let prevout = input.prevout.map(|p| ApiPrevout {
+        scriptpubkey: p.script_pub_key.hex,
+        scriptpubkey_asm: p.script_pub_key.asm,
+        scriptpubkey_type: map_script_type(&p.script_pub_key.type_),
+        scriptpubkey_address: p.script_pub_key.address,
+        value: btc_to_sats(p.value),
+    });
+    let prevout_type = prevout.as_ref().map(|p| p.scriptpubkey_type.as_str());
+
+    let (scriptsig, scriptsig_asm) = match input.script_sig {
+        Some(s) => (Some(s.hex), Some(s.asm)),
+        None => (None, None),
+    };
+
+    let redeem_script = if prevout_type == Some("p2sh") {
+        scriptsig.as_deref().and_then(last_push)
+    } else {
+        None
+    };
+
+    let witness = input.txinwitness;
+    let witness_script = witness
+        .as_ref()
+        .and_then(|w| witness_script_hex(w, prevout_type, redeem_script.as_ref()))
+        .and_then(|hex| ScriptBuf::from_hex(hex).ok());
+
+    ApiVin {
+        txid: if is_coinbase { None } else { input.txid },
+        vout: if is_coinbase { None } else { input.vout },
+        prevout,
+        scriptsig,
+        scriptsig_asm,
+        inner_redeemscript_asm: redeem_script.map(|s| s.to_asm_string()),
+        inner_witnessscript_asm: witness_script.map(|s| s.to_asm_string()),
+        witness,
+        is_coinbase,
+        sequence: input.sequence,
+    }
+}
+
+/// The witness script of a P2WSH (or P2SH-wrapped P2WSH) spend is the last witness item.
+/// Without prevout data, any witness with more than two items is assumed to be P2WSH.
+fn witness_script_hex<'a>(
+    witness: &'a [String],
+    prevout_type: Option<&str>,
+    redeem_script: Option<&ScriptBuf>,
+) -> Option<&'a str> {
+    let is_p2wsh = match prevout_type {
+        Some("v0_p2wsh") => true,
+        Some("p2sh") => redeem_script.is_some_and(|s| s.is_p2wsh()),
+        Some(_) => false,
+        None => witness.len() > 2,
+    };
+
+    if is_p2wsh {
+        witness.last().map(String::as_str)
+    } else {
+        None
+    }
+}
+
+/// The redeem script of a P2SH spend is the final push of the scriptSig.
+fn last_push(scriptsig_hex: &str) -> Option<ScriptBuf> {
+    let script = ScriptBuf::from_hex(scriptsig_hex).ok()?;
+    let last = script.instructions().filter_map(Result::ok).last()?;
+    match last {
+        Instruction::PushBytes(bytes) => Some(Script::from_bytes(bytes.as_bytes()).to_owned()),
+        Instruction::Op(_) => None,
+    }
+}
+
+/// Translate bitcoind script type names to the mempool.space names the detectors expect.
+fn map_script_type(core_type: &str) -> String {
+    match core_type {
+        "pubkey" => "p2pk",
+        "pubkeyhash" => "p2pkh",
+        "scripthash" => "p2sh",
+        "witness_v0_keyhash" => "v0_p2wpkh",
+        "witness_v0_scripthash" => "v0_p2wsh",
+        "witness_v1_taproot" => "v1_p2tr",
+        "nulldata" => "op_return",
+        "multisig" => "multisig",
+        "anchor" => "anchor",
+        _ => "unknown",
+    }
+    .to_string()
+}
+
+fn btc_to_sats(btc: f64) -> u64 {
+    (btc * 100_000_000.0).round() as u64
+}
@@ -0,0 +1,142 @@
+use std::path::Path;
+
+use anyhow::{Context, bail};
+use bitcoin::consensus::encode::{deserialize_partial, serialize_hex};
+use bitcoin::hex::FromHex;
+use bitcoin::{Block, Network};
+
+use super::raw::api_transaction_from_bitcoin;
+use super::source::{BLOCK_TXS_PAGE_SIZE, DataSource};
+use super::types::{ApiStatus, ApiTransaction};
+use crate::error::{Error, Result};
+
+/// A single block read from a local file, served as a data source with no network
+/// access. The file holds either the hex of `bitcoin-cli getblock <hash> 0` or the raw
+/// bytes of a block, optionally still framed by the network magic and length of a
+/// `blocks/blk*.dat` record (only the first block is read).
+pub struct BlockFileSource {
+    block: Block,
+    hash: String,
+    height: u64,
+    txs: Vec<ApiTransaction>,
+}
+
+impl BlockFileSource {
+    /// Read the block at `path`. Its height comes from the coinbase (BIP 34) unless
+    /// `height` is given, which blocks before BIP 34 need.
+    pub fn open(path: &Path, height: Option<u64>, network: Network) -> anyhow::Result<Self> {
+        let data =
+            std::fs::read(path).with_context(|| format!("reading block file {}", path.display()))?;
+        Self::from_bytes(&data, height, network)
+            .with_context(|| format!("decoding block file {}", path.display()))
+    }
+
+    /// Decode a block from the contents of a block file, see [`BlockFileSource`].
+    pub fn from_bytes(data: &[u8], height: Option<u64>, network: Network) -> anyhow::Result<Self> {
+        let bytes = match std::str::from_utf8(data) {
+            Ok(text) => Vec::<u8>::from_hex(text.trim()).context("invalid block hex")?,
+            Err(_) => data.to_vec(),
+        };
+        // blk*.dat records start with the network magic and the block's length
+        let bytes = match bytes.strip_prefix(&network.magic().to_bytes()) {
+            Some(record) if record.len() >= 4 => &record[4..],
+            _ => &bytes[..],
+        };
+        let (block, _) = deserialize_partial::<Block>(bytes).context("invalid block")?;
+        if !block.check_merkle_root() {
+            bail!("merkle root doesn't match the block's transactions");
+        }
+
+        let height = match height {
+            Some(height) => height,
+            None => block
+                .bip34_block_height()
+                .context("the coinbase has no BIP 34 height; pass the block height")?,
+        };
+        let hash = block.block_hash().to_string();
+        let status = ApiStatus {
+            confirmed: true,
+            block_height: Some(height),
+            block_hash: Some(hash.clone()),
+            block_time: Some(block.header.time as u64),
+        };
+        let txs = block
+            .txdata
+            .iter()
+            .map(|tx| ApiTransaction {
+                status: status.clone(),
+                ..api_transaction_from_bitcoin(tx, network)
+            })
+            .collect();
+
+        Ok(Self { block, hash, height, txs })
+    }
+
+    /// Height of the block, from the coinbase or as given to [`BlockFileSource::open`].
+    pub fn height(&self) -> u64 {
+        self.height
+    }
+
+    fn position(&self, txid: &str) -> Result<usize> {
+        self.txs
+            .iter()
+            .position(|tx| tx.txid == txid)
+            .ok_or_else(|| Error::NotFound(format!("transaction {txid} is not in the block file")))
+    }
+
+    fn check_height(&self, height: u64) -> Result<()> {
+        if height == self.height {
+            Ok(())
+        } else {
+            Err(Error::NotFound(format!(
+                "the block file holds block {}, not {height}",
+                self.height
+            )))
+        }
+    }
+}
+
+impl DataSource for BlockFileSource {
+    async fn get_transaction(&self, txid: &str) -> Result<ApiTransaction> {
+        Ok(self.txs[self.position(txid)?].clone())
+    }
+
+    async fn get_transaction_hex(&self, txid: &str) -> Result<String> {
+        Ok(serialize_hex(&self.block.txdata[self.position(txid)?]))
+    }
+
+    async fn get_block_txs(&self, hash: &str, start_index: u32) -> Result<Vec<ApiTransaction>> {
+        if hash != self.hash {
+            return Err(Error::NotFound(format!("block {hash} is not the block file's")));
+        }
+        Ok(self
+            .txs
+            .iter()
+            .skip(start_index as usize)
+            .take(BLOCK_TXS_PAGE_SIZE)
+            .cloned()
+            .collect())
+    }
+
+    async fn get_block_tip_height(&self) -> Result<u64> {
+        Ok(self.height)
+    }
+
+    async fn get_block_hash(&self, height: u64) -> Result<String> {
+        self.check_height(height)?;
+        Ok(self.hash.clone())
+    }
+
+    async fn get_all_block_txs(&self, height: u64) -> Result<Vec<ApiTransaction>> {
+        self.check_height(height)?;
+        Ok(self.txs.clone())
+    }
+
+    async fn get_mempool_recent_txids(&self) -> Result<Vec<String>> {
+        Err(Error::RpcUnavailable("a block file has no mempool".to_string()))
+    }
+
+    async fn get_mempool_txids(&self) -> Result<Vec<String>> {
+        Err(Error::RpcUnavailable("a block file has no mempool".to_string()))
+    }
+}
@@ -0,0 +1,40 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+/// Block hash ↔ height translations learned from a node, shared by every clone so
+/// concurrent RPC tasks fill and read one cache. Like [`super::cache::CachedClient`],
+/// it treats the blocks it has seen as final and never invalidates an entry.
+#[derive(Debug, Clone, Default)]
+pub struct BlockIndex {
+    inner: Arc<Mutex<Entries>>,
+}
+
+#[derive(Debug, Default)]
+struct Entries {
+    heights: HashMap<String, u64>,
+    hashes: HashMap<u64, String>,
+}
+
+impl BlockIndex {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record that block `hash` is at `height`, for lookups in either direction.
+    pub fn insert(&self, height: u64, hash: &str) {
+        if let Ok(mut entries) = self.inner.lock() {
+            entries.heights.insert(hash.to_string(), height);
+            entries.hashes.insert(height, hash.to_string());
+        }
+    }
+
+    /// Height of block `hash`, if it has been seen.
+    pub fn height(&self, hash: &str) -> Option<u64> {
+        self.inner.lock().ok()?.heights.get(hash).copied()
+    }
+
+    /// Hash of the block at `height`, if it has been seen.
+    pub fn hash(&self, height: u64) -> Option<String> {
+        self.inner.lock().ok()?.hashes.get(&height).cloned()
+    }
+}
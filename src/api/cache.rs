@@ -1,11 +1,11 @@
 use std::sync::Arc;
 use std::time::Duration;
 
-use anyhow::Result;
 use moka::future::Cache;
 
-use super::source::DataSource;
+use super::source::{DataSource, SyncStatus};
 use super::types::ApiTransaction;
+use crate::error::{Error, Result};
 
 /// Caching wrapper around any DataSource. Confirmed transactions and blocks are
 /// cached indefinitely. Unconfirmed data uses a short TTL.
@@ -99,8 +99,25 @@ impl<S: DataSource + Send + Sync> DataSource for CachedClient<S> {
         self.inner.get_all_block_txs(height).await
     }
 
+    async fn for_each_block_tx<E, F>(&self, height: u64, f: F) -> std::result::Result<(), E>
+    where
+        E: From<Error> + Send,
+        F: FnMut(ApiTransaction) -> std::result::Result<(), E> + Send,
+    {
+        // Streamed transactions aren't kept, so there is nothing to cache
+        self.inner.for_each_block_tx(height, f).await
+    }
+
     async fn get_mempool_recent_txids(&self) -> Result<Vec<String>> {
         // No caching — always want fresh mempool data
         self.inner.get_mempool_recent_txids().await
     }
+
+    async fn get_mempool_txids(&self) -> Result<Vec<String>> {
+        self.inner.get_mempool_txids().await
+    }
+
+    async fn sync_status(&self) -> Result<Option<SyncStatus>> {
+        self.inner.sync_status().await
+    }
 }
@@ -1,13 +1,17 @@
 use std::time::Duration;
 
-use anyhow::{Context, Result, bail};
+use bitcoin::Network;
+use rand::Rng;
 use reqwest::StatusCode;
 use tokio::time::sleep;
 
 use serde::Deserialize;
+use serde::de::DeserializeOwned;
 
-use super::source::DataSource;
+use super::rate_limit::RateLimiter;
+use super::source::{BLOCK_TXS_PAGE_SIZE, DataSource};
 use super::types::ApiTransaction;
+use crate::error::{Error, Result};
 
 #[derive(Deserialize)]
 struct MempoolRecentEntry {
@@ -18,55 +22,158 @@ pub struct MempoolClient {
     client: reqwest::Client,
     base_url: String,
     request_delay: Duration,
-    max_retries: u32,
+    rate_limit: Option<RateLimiter>,
+    retry_attempts: u32,
+    retry_base_delay: Duration,
+    timeout: Duration,
+    quiet: bool,
 }
 
+/// Total attempts per GET, including the first.
+const DEFAULT_RETRY_ATTEMPTS: u32 = 4;
+const DEFAULT_RETRY_BASE_DELAY: Duration = Duration::from_millis(500);
+/// Per-request timeout, covering connect through reading the body.
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+/// Pause before each request, to stay under public API rate limits.
+pub const DEFAULT_REQUEST_DELAY: Duration = Duration::from_millis(250);
+/// Most requests per second, counting retries.
+pub const DEFAULT_RATE_LIMIT: u32 = 10;
+
+/// Public mempool.space instance; network API roots live under it.
+pub const MEMPOOL_SPACE_URL: &str = "https://mempool.space";
+
 impl MempoolClient {
+    /// `base_url` is the esplora API root, e.g. `https://mempool.space/api`. Proxies
+    /// set in `ALL_PROXY`, `HTTPS_PROXY` or `HTTP_PROXY` are used.
     pub fn new(base_url: &str, request_delay: Duration) -> Self {
         Self {
             client: reqwest::Client::new(),
             base_url: base_url.trim_end_matches('/').to_string(),
             request_delay,
-            max_retries: 5,
+            rate_limit: Some(RateLimiter::new(DEFAULT_RATE_LIMIT)),
+            retry_attempts: DEFAULT_RETRY_ATTEMPTS,
+            retry_base_delay: DEFAULT_RETRY_BASE_DELAY,
+            timeout: DEFAULT_TIMEOUT,
+            quiet: false,
         }
     }
 
+    /// Send every request through `proxy` instead of any proxy set in the environment:
+    /// `socks5h://127.0.0.1:9050` for Tor (the `h` resolves host names through the
+    /// proxy), or a `socks5://`, `http://` or `https://` URL.
+    pub fn with_proxy(base_url: &str, proxy: &str) -> Result<Self> {
+        let proxy = reqwest::Proxy::all(proxy)
+            .map_err(|e| Error::Decode(format!("invalid proxy URL {proxy}: {e}")))?;
+        let client = reqwest::Client::builder()
+            .proxy(proxy)
+            .build()
+            .map_err(|e| Error::RpcUnavailable(format!("HTTP client with proxy: {e}")))?;
+        Ok(Self {
+            client,
+            ..Self::new(base_url, DEFAULT_REQUEST_DELAY)
+        })
+    }
+
+    /// Pause before each request.
+    pub fn with_request_delay(mut self, delay: Duration) -> Self {
+        self.request_delay = delay;
+        self
+    }
+
+    /// Send at most `per_second` requests a second, retries included; 0 lifts the limit.
+    pub fn with_rate_limit(mut self, per_second: u32) -> Self {
+        self.rate_limit = (per_second > 0).then(|| RateLimiter::new(per_second));
+        self
+    }
+
+    /// Per-request timeout. Timed-out requests are retried like connection errors.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Total attempts per request (at least 1).
+    pub fn with_retry_attempts(mut self, attempts: u32) -> Self {
+        self.retry_attempts = attempts.max(1);
+        self
+    }
+
+    /// Delay before the first retry; doubles on each subsequent one.
+    pub fn with_retry_base_delay(mut self, delay: Duration) -> Self {
+        self.retry_base_delay = delay;
+        self
+    }
+
+    /// Don't announce retries on stderr. The error that ends them is still returned.
+    pub fn with_quiet(mut self, quiet: bool) -> Self {
+        self.quiet = quiet;
+        self
+    }
+
     pub fn default() -> Self {
-        Self::new("https://mempool.space", Duration::from_millis(250))
+        Self::new(&Self::api_url(MEMPOOL_SPACE_URL, Network::Bitcoin), DEFAULT_REQUEST_DELAY)
     }
 
+    /// API root for `network` on a mempool.space-style host (`/api`, `/testnet/api`, `/signet/api`).
+    pub fn api_url(host: &str, network: Network) -> String {
+        let path = match network {
+            Network::Testnet => "/testnet/api",
+            Network::Signet => "/signet/api",
+            _ => "/api",
+        };
+        format!("{}{path}", host.trim_end_matches('/'))
+    }
+
+    /// GET with retries on 429, 5xx and connection errors. Other failures are returned
+    /// immediately; after the last attempt the last error is returned. A 404 is
+    /// `NotFound`, a 429 `RateLimited`, and everything else `Network`.
     async fn get_with_retry(&self, url: &str) -> Result<reqwest::Response> {
-        let mut delay = self.request_delay;
+        let mut last_err = None;
 
-        for attempt in 0..=self.max_retries {
+        for attempt in 0..self.retry_attempts {
             if attempt > 0 {
-                sleep(delay).await;
-                delay *= 2; // exponential backoff
-            }
-
-            let resp = self
-                .client
-                .get(url)
-                .send()
-                .await
-                .with_context(|| format!("request to {url}"))?;
-
-            if resp.status() == StatusCode::TOO_MANY_REQUESTS {
-                if attempt == self.max_retries {
-                    bail!("rate limited after {} retries: {url}", self.max_retries);
+                let delay = backoff_delay(self.retry_base_delay, attempt, &mut rand::rng());
+                if !self.quiet {
+                    eprintln!("{}, retrying in {delay:?}...", last_err.as_ref().unwrap());
                 }
-                eprintln!("rate limited, backing off {delay:?}...");
-                continue;
+                sleep(delay).await;
             }
-
-            if !resp.status().is_success() {
-                bail!("HTTP {} for {url}", resp.status());
+            if let Some(limiter) = &self.rate_limit {
+                limiter.acquire().await;
             }
 
-            return Ok(resp);
+            let err = match self.client.get(url).timeout(self.timeout).send().await {
+                Ok(resp) if resp.status().is_success() => return Ok(resp),
+                Ok(resp) => {
+                    let status = resp.status();
+                    let msg = format!("HTTP {status} for {url}");
+                    let err = match status {
+                        StatusCode::NOT_FOUND => Error::NotFound(msg),
+                        StatusCode::TOO_MANY_REQUESTS => Error::RateLimited(msg),
+                        _ => Error::Network(msg),
+                    };
+                    if !is_retryable_status(status) {
+                        return Err(err);
+                    }
+                    err
+                }
+                Err(e) if e.is_timeout() => Error::Network(format!(
+                    "request to {url} timed out after {}s",
+                    self.timeout.as_secs_f64()
+                )),
+                Err(e) => {
+                    let retryable = e.is_connect();
+                    let err = Error::Network(format!("request to {url}: {}", error_chain(&e)));
+                    if !retryable {
+                        return Err(err);
+                    }
+                    err
+                }
+            };
+            last_err = Some(err);
         }
 
-        unreachable!()
+        Err(last_err.expect("at least one attempt"))
     }
 
     async fn throttle(&self) {
@@ -76,70 +183,82 @@ impl MempoolClient {
 
 impl DataSource for MempoolClient {
     async fn get_transaction(&self, txid: &str) -> Result<ApiTransaction> {
-        let url = format!("{}/api/tx/{txid}", self.base_url);
+        let url = format!("{}/tx/{txid}", self.base_url);
         let resp = self.get_with_retry(&url).await?;
-        let tx = resp
-            .json::<ApiTransaction>()
+        let tx = read_json::<ApiTransaction>(resp)
             .await
-            .context("deserializing transaction")?;
+            .map_err(|e| e.context("deserializing transaction"))?;
         Ok(tx)
     }
 
     async fn get_transaction_hex(&self, txid: &str) -> Result<String> {
-        let url = format!("{}/api/tx/{txid}/hex", self.base_url);
+        let url = format!("{}/tx/{txid}/hex", self.base_url);
         let resp = self.get_with_retry(&url).await?;
-        let hex = resp.text().await.context("reading transaction hex")?;
+        let hex = read_text(resp)
+            .await
+            .map_err(|e| e.context("reading transaction hex"))?;
         Ok(hex)
     }
 
     async fn get_block_txs(&self, hash: &str, start_index: u32) -> Result<Vec<ApiTransaction>> {
-        let url = format!("{}/api/block/{hash}/txs/{start_index}", self.base_url);
+        let url = format!("{}/block/{hash}/txs/{start_index}", self.base_url);
         let resp = self.get_with_retry(&url).await?;
-        let txs = resp
-            .json::<Vec<ApiTransaction>>()
+        let txs = read_json::<Vec<ApiTransaction>>(resp)
             .await
-            .context("deserializing block transactions")?;
+            .map_err(|e| e.context("deserializing block transactions"))?;
         Ok(txs)
     }
 
     async fn get_block_tip_height(&self) -> Result<u64> {
-        let url = format!("{}/api/blocks/tip/height", self.base_url);
+        let url = format!("{}/blocks/tip/height", self.base_url);
         let resp = self.get_with_retry(&url).await?;
-        let height = resp
-            .text()
+        let height = read_text(resp)
             .await
-            .context("reading tip height")?
+            .map_err(|e| e.context("reading tip height"))?
             .trim()
             .parse::<u64>()
-            .context("parsing tip height")?;
+            .map_err(|e| Error::Decode(format!("parsing tip height: {e}")))?;
         Ok(height)
     }
 
     async fn get_block_hash(&self, height: u64) -> Result<String> {
-        let url = format!("{}/api/block-height/{height}", self.base_url);
+        let url = format!("{}/block-height/{height}", self.base_url);
         let resp = self.get_with_retry(&url).await?;
-        let hash = resp
-            .text()
+        let hash = read_text(resp)
             .await
-            .context("reading block hash")?
+            .map_err(|e| e.context("reading block hash"))?
             .trim()
             .to_string();
         Ok(hash)
     }
 
     async fn get_all_block_txs(&self, height: u64) -> Result<Vec<ApiTransaction>> {
+        let mut all_txs = Vec::new();
+        self.for_each_block_tx(height, |tx| {
+            all_txs.push(tx);
+            Ok::<_, Error>(())
+        })
+        .await?;
+        Ok(all_txs)
+    }
+
+    async fn for_each_block_tx<E, F>(&self, height: u64, mut f: F) -> std::result::Result<(), E>
+    where
+        E: From<Error> + Send,
+        F: FnMut(ApiTransaction) -> std::result::Result<(), E> + Send,
+    {
         let hash = self.get_block_hash(height).await?;
         self.throttle().await;
 
-        let mut all_txs = Vec::new();
         let mut start_index: u32 = 0;
-
         loop {
             let page = self.get_block_txs(&hash, start_index).await?;
             let count = page.len() as u32;
-            all_txs.extend(page);
+            for tx in page {
+                f(tx)?;
+            }
 
-            if count < 25 {
+            if count < BLOCK_TXS_PAGE_SIZE as u32 {
                 break;
             }
 
@@ -147,16 +266,63 @@ impl DataSource for MempoolClient {
             self.throttle().await;
         }
 
-        Ok(all_txs)
+        Ok(())
     }
 
     async fn get_mempool_recent_txids(&self) -> Result<Vec<String>> {
-        let url = format!("{}/api/mempool/recent", self.base_url);
+        let url = format!("{}/mempool/recent", self.base_url);
         let resp = self.get_with_retry(&url).await?;
-        let entries = resp
-            .json::<Vec<MempoolRecentEntry>>()
+        let entries = read_json::<Vec<MempoolRecentEntry>>(resp)
             .await
-            .context("deserializing mempool recent transactions")?;
+            .map_err(|e| e.context("deserializing mempool recent transactions"))?;
         Ok(entries.into_iter().map(|e| e.txid).collect())
     }
+
+    async fn get_mempool_txids(&self) -> Result<Vec<String>> {
+        let url = format!("{}/mempool/txids", self.base_url);
+        let resp = self.get_with_retry(&url).await?;
+        read_json(resp)
+            .await
+            .map_err(|e| e.context("deserializing mempool txids"))
+    }
+}
+
+/// Read a response body. A failure mid-body is a `Network` error.
+async fn read_text(resp: reqwest::Response) -> Result<String> {
+    resp.text()
+        .await
+        .map_err(|e| Error::Network(error_chain(&e)))
+}
+
+/// Read and deserialize a JSON response body. A body that isn't the expected JSON is
+/// a `Decode` error.
+async fn read_json<T: DeserializeOwned>(resp: reqwest::Response) -> Result<T> {
+    let body = resp
+        .bytes()
+        .await
+        .map_err(|e| Error::Network(error_chain(&e)))?;
+    Ok(serde_json::from_slice(&body)?)
+}
+
+/// An error and its sources joined with `: `; reqwest's own message leaves out the
+/// underlying cause (connection refused, DNS failure, ...).
+fn error_chain(err: &dyn std::error::Error) -> String {
+    let mut msg = err.to_string();
+    let mut source = err.source();
+    while let Some(cause) = source {
+        msg.push_str(&format!(": {cause}"));
+        source = cause.source();
+    }
+    msg
+}
+
+fn is_retryable_status(status: StatusCode) -> bool {
+    status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error()
+}
+
+/// Exponential backoff with jitter: `base * 2^(retry-1)`, randomized into its upper half
+/// by `rng` so that clients failing together don't retry together.
+pub fn backoff_delay(base: Duration, retry: u32, rng: &mut impl Rng) -> Duration {
+    let ceiling = base.saturating_mul(1 << (retry - 1).min(16));
+    ceiling.mul_f64(rng.random_range(0.5..=1.0))
 }
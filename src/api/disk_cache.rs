@@ -0,0 +1,217 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+
+use super::source::{DataSource, SyncStatus};
+use super::types::ApiTransaction;
+use crate::error::{Error, Result};
+
+/// `$XDG_CACHE_HOME/cltv-scan`, falling back to `~/.cache/cltv-scan`.
+pub fn default_cache_dir() -> PathBuf {
+    match std::env::var_os("XDG_CACHE_HOME") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir).join("cltv-scan"),
+        _ => {
+            let home = std::env::var("HOME").unwrap_or_default();
+            PathBuf::from(home).join(".cache").join("cltv-scan")
+        }
+    }
+}
+
+/// On-disk cache wrapper around any DataSource. Confirmed transactions are immutable,
+/// so they are stored as JSON keyed by txid and never expire; unconfirmed ones are
+/// always fetched. A block's txid list is stored keyed by block hash, so a re-scanned
+/// block is served from disk after a single height → hash lookup. Block walks stream
+/// through the inner source, caching each transaction as it passes.
+pub struct CachedSource<S> {
+    inner: S,
+    dir: PathBuf,
+    quiet: bool,
+}
+
+impl<S> CachedSource<S> {
+    pub fn new(inner: S, dir: impl Into<PathBuf>) -> Self {
+        Self {
+            inner,
+            dir: dir.into(),
+            quiet: false,
+        }
+    }
+
+    /// Don't report failed cache writes on stderr.
+    pub fn with_quiet(mut self, quiet: bool) -> Self {
+        self.quiet = quiet;
+        self
+    }
+
+    fn entry_path(&self, kind: &str, hash: &str) -> Option<PathBuf> {
+        // Only 32-byte hex hashes become file names
+        let valid = hash.len() == 64 && hash.bytes().all(|b| b.is_ascii_hexdigit());
+        valid.then(|| self.dir.join(kind).join(format!("{}.json", hash.to_ascii_lowercase())))
+    }
+
+    async fn load_tx(&self, txid: &str) -> Option<ApiTransaction> {
+        read_json(&self.entry_path("tx", txid)?).await
+    }
+
+    async fn store_tx(&self, tx: &ApiTransaction) {
+        if !tx.status.confirmed {
+            return;
+        }
+        if let Some(path) = self.entry_path("tx", &tx.txid) {
+            self.write_json_logged(&path, tx).await;
+        }
+    }
+
+    /// A block's transactions, if its txid list and every transaction are on disk.
+    async fn load_block(&self, path: &Path) -> Option<Vec<ApiTransaction>> {
+        let txids = read_json::<Vec<String>>(path).await?;
+        let mut txs = Vec::with_capacity(txids.len());
+        for txid in &txids {
+            txs.push(self.load_tx(txid).await?);
+        }
+        Some(txs)
+    }
+
+    /// Cache writes never fail the request; problems are reported on stderr unless quiet.
+    async fn write_json_logged<T: Serialize + ?Sized>(&self, path: &Path, value: &T) {
+        if let Err(e) = write_json(path, value).await
+            && !self.quiet
+        {
+            eprintln!("cache write failed: {e:#}");
+        }
+    }
+}
+
+impl<S: DataSource + Send + Sync> DataSource for CachedSource<S> {
+    async fn get_transaction(&self, txid: &str) -> Result<ApiTransaction> {
+        if let Some(tx) = self.load_tx(txid).await {
+            return Ok(tx);
+        }
+        let tx = self.inner.get_transaction(txid).await?;
+        self.store_tx(&tx).await;
+        Ok(tx)
+    }
+
+    async fn get_transaction_hex(&self, txid: &str) -> Result<String> {
+        self.inner.get_transaction_hex(txid).await
+    }
+
+    async fn get_block_txs(&self, hash: &str, start_index: u32) -> Result<Vec<ApiTransaction>> {
+        self.inner.get_block_txs(hash, start_index).await
+    }
+
+    async fn get_block_tip_height(&self) -> Result<u64> {
+        self.inner.get_block_tip_height().await
+    }
+
+    async fn get_block_hash(&self, height: u64) -> Result<String> {
+        self.inner.get_block_hash(height).await
+    }
+
+    async fn get_all_block_txs(&self, height: u64) -> Result<Vec<ApiTransaction>> {
+        // Heights can be reorged; hashes can't, so the block entry is keyed by hash
+        let hash = self.inner.get_block_hash(height).await?;
+        let block_path = self.entry_path("block", &hash);
+
+        if let Some(ref path) = block_path
+            && let Some(txs) = self.load_block(path).await
+        {
+            return Ok(txs);
+        }
+
+        let txs = self.inner.get_all_block_txs(height).await?;
+        for tx in &txs {
+            self.store_tx(tx).await;
+        }
+        if let Some(ref path) = block_path {
+            let txids: Vec<&str> = txs.iter().map(|tx| tx.txid.as_str()).collect();
+            self.write_json_logged(path, &txids).await;
+        }
+        Ok(txs)
+    }
+
+    async fn for_each_block_tx<E, F>(&self, height: u64, mut f: F) -> std::result::Result<(), E>
+    where
+        E: From<Error> + Send,
+        F: FnMut(ApiTransaction) -> std::result::Result<(), E> + Send,
+    {
+        let hash = self.inner.get_block_hash(height).await?;
+        let block_path = self.entry_path("block", &hash);
+
+        if let Some(ref path) = block_path
+            && let Some(txs) = self.load_block(path).await
+        {
+            for tx in txs {
+                f(tx)?;
+            }
+            return Ok(());
+        }
+
+        // Let the inner source stream the block, writing each transaction to disk
+        // alongside the walk instead of holding the whole block for it
+        let (sender, mut receiver) = tokio::sync::mpsc::unbounded_channel();
+        let walk = async move {
+            let mut txids = Vec::new();
+            let result = self
+                .inner
+                .for_each_block_tx(height, |tx| {
+                    txids.push(tx.txid.clone());
+                    if tx.status.confirmed {
+                        let _ = sender.send(tx.clone());
+                    }
+                    f(tx)
+                })
+                .await;
+            result.map(|()| txids)
+        };
+        let store = async {
+            while let Some(tx) = receiver.recv().await {
+                self.store_tx(&tx).await;
+            }
+        };
+        let (txids, ()) = tokio::join!(walk, store);
+        let txids = txids?;
+
+        if let Some(ref path) = block_path {
+            self.write_json_logged(path, &txids).await;
+        }
+        Ok(())
+    }
+
+    async fn get_mempool_recent_txids(&self) -> Result<Vec<String>> {
+        self.inner.get_mempool_recent_txids().await
+    }
+
+    async fn get_mempool_txids(&self) -> Result<Vec<String>> {
+        self.inner.get_mempool_txids().await
+    }
+
+    async fn sync_status(&self) -> Result<Option<SyncStatus>> {
+        self.inner.sync_status().await
+    }
+}
+
+/// A missing or unreadable entry is just a cache miss.
+async fn read_json<T: DeserializeOwned>(path: &Path) -> Option<T> {
+    let bytes = tokio::fs::read(path).await.ok()?;
+    serde_json::from_slice(&bytes).ok()
+}
+
+async fn write_json<T: Serialize + ?Sized>(path: &Path, value: &T) -> anyhow::Result<()> {
+    let parent = path.parent().context("cache path has no parent")?;
+    tokio::fs::create_dir_all(parent)
+        .await
+        .with_context(|| format!("creating {}", parent.display()))?;
+
+    // Write then rename, so concurrent readers never see a partial entry
+    let tmp = path.with_extension(format!("tmp.{}", std::process::id()));
+    tokio::fs::write(&tmp, serde_json::to_vec(value)?)
+        .await
+        .with_context(|| format!("writing {}", tmp.display()))?;
+    tokio::fs::rename(&tmp, path)
+        .await
+        .with_context(|| format!("renaming {}", tmp.display()))?;
+    Ok(())
+}
@@ -0,0 +1,401 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use bitcoin::hex::DisplayHex;
+use bitcoin::block::Header;
+use bitcoin::{Network, Transaction, TxOut, Txid};
+use electrum_client::{Client, ElectrumApi, Param};
+use serde::Deserialize;
+use tokio::task::spawn_blocking;
+
+use super::block_index::BlockIndex;
+use super::client::DEFAULT_TIMEOUT;
+use super::raw::{api_transaction_from_bitcoin, api_transaction_with_prevouts};
+use super::source::{BLOCK_TXS_PAGE_SIZE, DataSource};
+use super::types::{ApiStatus, ApiTransaction};
+use crate::error::Error;
+
+/// Transactions requested per `blockchain.transaction.get` batch during block scans.
+const BATCH_SIZE: usize = 100;
+
+/// How servers word a missing transaction, block or block position. ElectrumX and
+/// Fulcrum pass on bitcoind's "No such mempool or blockchain transaction"; heights and
+/// positions past the end are "out of range", or "no tx at position" in a block.
+const NOT_FOUND_MESSAGES: &[&str] = &[
+    "no such mempool or blockchain transaction",
+    "not found",
+    "out of range",
+    "no tx at position",
+    "no transaction at position",
+    "invalid tx_pos",
+];
+
+/// How servers word a `blockchain.transaction.id_from_pos` position past the block's
+/// last transaction.
+const POSITION_OUT_OF_RANGE_MESSAGES: &[&str] =
+    &["no tx at position", "no transaction at position", "invalid tx_pos", "out of range"];
+
+/// Verbose `blockchain.transaction.get` response (bitcoind's `getrawtransaction`
+/// shape). Only the fields the raw hex doesn't carry are read.
+#[derive(Deserialize)]
+struct VerboseTx {
+    hex: String,
+    blockhash: Option<String>,
+    #[serde(default)]
+    confirmations: u64,
+    blocktime: Option<u64>,
+}
+
+/// Electrum protocol data source (ElectrumX, Fulcrum, electrs).
+///
+/// Transactions are decoded locally from their raw hex. Blocks are walked with
+/// `blockchain.transaction.id_from_pos`, which takes a height: Electrum can't look up
+/// a block by hash, so `get_block_txs` only serves blocks whose hash came from
+/// `get_block_hash`. The mempool can't be listed.
+pub struct ElectrumClient {
+    client: Arc<Client>,
+    network: Network,
+    block_index: BlockIndex,
+    fetch_prevouts: bool,
+    timeout: Duration,
+}
+
+impl ElectrumClient {
+    /// Connect to `url` (`host:port`, or with a `tcp://` / `ssl://` scheme).
+    pub fn new(url: &str, network: Network) -> Result<Self> {
+        let url = if url.contains("://") {
+            url.to_string()
+        } else {
+            format!("tcp://{url}")
+        };
+        let client =
+            Client::new(&url).with_context(|| format!("connecting to electrum server {url}"))?;
+        Ok(Self {
+            client: Arc::new(client),
+            network,
+            block_index: BlockIndex::new(),
+            fetch_prevouts: false,
+            timeout: DEFAULT_TIMEOUT,
+        })
+    }
+
+    /// Per-call timeout (default 30s). A timed-out call keeps its blocking thread until
+    /// the server answers, but the caller gets an error.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Fetch every input's previous transaction to fill in `prevout`, `fee`, and the
+    /// witness and redeem scripts. Off by default, since it adds a batch request per
+    /// transaction.
+    pub fn with_prevouts(mut self, enabled: bool) -> Self {
+        self.fetch_prevouts = enabled;
+        self
+    }
+
+    /// Run blocking Electrum calls off the async runtime, giving up after the timeout.
+    /// A server saying the transaction or block doesn't exist is `NotFound`; other
+    /// failures besides parse errors are `Network`: the server rejected the request or
+    /// the connection broke.
+    async fn blocking<T, F>(&self, operation: String, f: F) -> crate::error::Result<T>
+    where
+        F: FnOnce(&Client) -> Result<T> + Send + 'static,
+        T: Send + 'static,
+    {
+        let client = self.client.clone();
+        match tokio::time::timeout(self.timeout, spawn_blocking(move || f(&client))).await {
+            Ok(Ok(result)) => result.map_err(server_error),
+            Ok(Err(e)) => Err(Error::Network(format!("electrum {operation} failed: {e}"))),
+            Err(_) => Err(Error::Network(format!(
+                "electrum {operation} timed out after {}s",
+                self.timeout.as_secs_f64()
+            ))),
+        }
+    }
+
+    /// Header of the block at `height`, noting its hash for `get_block_txs`.
+    async fn block_header(&self, height: u64) -> crate::error::Result<Header> {
+        let header = self
+            .blocking(format!("blockchain.block.header {height}"), move |client| {
+                Ok(client.block_header(usize::try_from(height)?)?)
+            })
+            .await?;
+        self.block_index.insert(height, &header.block_hash().to_string());
+        Ok(header)
+    }
+
+    /// Confirmed status of the transactions in the block at `height`, from its header.
+    async fn block_status(&self, height: u64) -> crate::error::Result<ApiStatus> {
+        let header = self.block_header(height).await?;
+        Ok(ApiStatus {
+            confirmed: true,
+            block_height: Some(height),
+            block_hash: Some(header.block_hash().to_string()),
+            block_time: Some(u64::from(header.time)),
+        })
+    }
+
+    /// Up to `limit` txids of the block at `height` from position `start`, in order.
+    /// Electrum has no call for a block's transaction list, so positions are requested
+    /// one by one, each under its own timeout, until the server reports there are no
+    /// more. Any other failure ends the walk with an error.
+    async fn block_txids(
+        &self,
+        height: u64,
+        start: usize,
+        limit: usize,
+    ) -> crate::error::Result<Vec<Txid>> {
+        let height = usize::try_from(height)
+            .map_err(|_| Error::NotFound(format!("block height {height} is out of range")))?;
+        let mut txids = Vec::new();
+        for pos in start..start.saturating_add(limit) {
+            let operation = format!("blockchain.transaction.id_from_pos {height} {pos}");
+            let txid = self
+                .blocking(operation, move |client| {
+                    let params = [Param::Usize(height), Param::Usize(pos)];
+                    match client.raw_call("blockchain.transaction.id_from_pos", params) {
+                        Ok(value) => {
+                            let txid: String = serde_json::from_value(value)?;
+                            Ok(Some(txid.parse::<Txid>()?))
+                        }
+                        // Every block has a coinbase, so position 0 is never past the end
+                        Err(e) if pos > 0 && is_position_out_of_range(&e.to_string()) => Ok(None),
+                        Err(e) => Err(e.into()),
+                    }
+                })
+                .await?;
+            match txid {
+                Some(txid) => txids.push(txid),
+                None => break,
+            }
+        }
+        Ok(txids)
+    }
+
+    /// Fetch `txids`, a `blockchain.transaction.get` batch of up to `BATCH_SIZE` per
+    /// call, and give them the block's `status`. With prevouts on, each transaction's
+    /// previous transactions are one more call.
+    async fn block_transactions(
+        &self,
+        txids: &[Txid],
+        status: &ApiStatus,
+    ) -> crate::error::Result<Vec<ApiTransaction>> {
+        let network = self.network;
+        let fetch_prevouts = self.fetch_prevouts;
+
+        let mut txs = Vec::with_capacity(txids.len());
+        for chunk in txids.chunks(BATCH_SIZE) {
+            let chunk = chunk.to_vec();
+            let operation = format!("blockchain.transaction.get batch of {}", chunk.len());
+            let batch = self
+                .blocking(operation, move |client| Ok(client.batch_transaction_get(&chunk)?))
+                .await?;
+            for tx in batch {
+                let mut api_tx = if fetch_prevouts && !tx.is_coinbase() {
+                    let operation = format!("previous transactions of {}", tx.compute_txid());
+                    self.blocking(operation, move |client| to_api(client, &tx, network, true))
+                        .await?
+                } else {
+                    api_transaction_from_bitcoin(&tx, network)
+                };
+                api_tx.status = status.clone();
+                txs.push(api_tx);
+            }
+        }
+        Ok(txs)
+    }
+}
+
+/// Convert a decoded transaction, with prevouts when `fetch_prevouts` is set and every
+/// previous transaction could be fetched.
+fn to_api(
+    client: &Client,
+    tx: &Transaction,
+    network: Network,
+    fetch_prevouts: bool,
+) -> Result<ApiTransaction> {
+    if !fetch_prevouts || tx.is_coinbase() {
+        return Ok(api_transaction_from_bitcoin(tx, network));
+    }
+    let prevouts = prevouts(client, tx)?;
+    Ok(api_transaction_with_prevouts(tx, &prevouts, network))
+}
+
+/// The output spent by each input, from one batch of previous transactions.
+fn prevouts(client: &Client, tx: &Transaction) -> Result<Vec<TxOut>> {
+    let mut txids: Vec<Txid> = tx.input.iter().map(|i| i.previous_output.txid).collect();
+    txids.sort();
+    txids.dedup();
+
+    let prev_txs: HashMap<Txid, Transaction> = client
+        .batch_transaction_get(&txids)
+        .context("fetching previous transactions")?
+        .into_iter()
+        .map(|prev| (prev.compute_txid(), prev))
+        .collect();
+
+    tx.input
+        .iter()
+        .map(|input| {
+            let outpoint = input.previous_output;
+            prev_txs
+                .get(&outpoint.txid)
+                .and_then(|prev| prev.output.get(outpoint.vout as usize))
+                .cloned()
+                .with_context(|| format!("previous output {outpoint} not found"))
+        })
+        .collect()
+}
+
+/// A server's answer to a failed call: `NotFound` when it says the transaction or
+/// block doesn't exist, otherwise `Decode` or `Network`.
+fn server_error(err: anyhow::Error) -> Error {
+    let msg = format!("{err:#}");
+    if is_not_found(&msg) {
+        Error::NotFound(msg)
+    } else {
+        Error::classify(err, Error::Network)
+    }
+}
+
+fn is_not_found(msg: &str) -> bool {
+    let msg = msg.to_lowercase();
+    NOT_FOUND_MESSAGES.iter().any(|m| msg.contains(m))
+}
+
+fn is_position_out_of_range(msg: &str) -> bool {
+    let msg = msg.to_lowercase();
+    POSITION_OUT_OF_RANGE_MESSAGES.iter().any(|m| msg.contains(m))
+}
+
+impl DataSource for ElectrumClient {
+    async fn get_transaction(&self, txid: &str) -> crate::error::Result<ApiTransaction> {
+        let txid_str = txid.to_string();
+        let network = self.network;
+        let fetch_prevouts = self.fetch_prevouts;
+
+        self.blocking(format!("blockchain.transaction.get {txid}"), move |client| {
+            let params = [Param::String(txid_str.clone()), Param::Bool(true)];
+            let verbose = match client.raw_call("blockchain.transaction.get", params) {
+                Ok(value) => serde_json::from_value::<VerboseTx>(value)?,
+                // electrs doesn't support verbose responses; without them the
+                // confirmation status is unknown
+                Err(_) => {
+                    let txid: Txid = txid_str.parse()?;
+                    let raw = client
+                        .transaction_get_raw(&txid)
+                        .with_context(|| format!("fetching transaction {txid_str}"))?;
+                    VerboseTx {
+                        hex: raw.to_lower_hex_string(),
+                        blockhash: None,
+                        confirmations: 0,
+                        blocktime: None,
+                    }
+                }
+            };
+
+            let tx: Transaction = bitcoin::consensus::encode::deserialize_hex(&verbose.hex)?;
+            let mut api_tx = to_api(client, &tx, network, fetch_prevouts)?;
+
+            // A transaction with one confirmation is in the tip block
+            let block_height = if verbose.confirmations > 0 {
+                let tip = client.block_headers_subscribe()?.height as u64;
+                (tip + 1).checked_sub(verbose.confirmations)
+            } else {
+                None
+            };
+            api_tx.status = ApiStatus {
+                confirmed: verbose.confirmations > 0,
+                block_height,
+                block_hash: verbose.blockhash,
+                block_time: verbose.blocktime,
+            };
+            Ok(api_tx)
+        })
+        .await
+    }
+
+    async fn get_transaction_hex(&self, txid: &str) -> crate::error::Result<String> {
+        let txid: Txid = txid
+            .parse()
+            .map_err(|e| Error::Decode(format!("txid {txid}: {e}")))?;
+        self.blocking(format!("blockchain.transaction.get {txid}"), move |client| {
+            let raw = client.transaction_get_raw(&txid)?;
+            Ok(raw.to_lower_hex_string())
+        })
+        .await
+    }
+
+    async fn get_block_txs(
+        &self,
+        hash: &str,
+        start_index: u32,
+    ) -> crate::error::Result<Vec<ApiTransaction>> {
+        let Some(height) = self.block_index.height(hash) else {
+            return Err(Error::RpcUnavailable(format!(
+                "electrum servers can't look up block {hash} by hash; scan it by height instead"
+            )));
+        };
+        let status = self.block_status(height).await?;
+        let start = usize::try_from(start_index).unwrap_or(usize::MAX);
+        let txids = self.block_txids(height, start, BLOCK_TXS_PAGE_SIZE).await?;
+        self.block_transactions(&txids, &status).await
+    }
+
+    async fn get_block_tip_height(&self) -> crate::error::Result<u64> {
+        self.blocking("blockchain.headers.subscribe".to_string(), |client| {
+            Ok(client.block_headers_subscribe()?.height as u64)
+        })
+        .await
+    }
+
+    async fn get_block_hash(&self, height: u64) -> crate::error::Result<String> {
+        if let Some(hash) = self.block_index.hash(height) {
+            return Ok(hash);
+        }
+        let header = self.block_header(height).await?;
+        Ok(header.block_hash().to_string())
+    }
+
+    async fn get_all_block_txs(&self, height: u64) -> crate::error::Result<Vec<ApiTransaction>> {
+        let status = self.block_status(height).await?;
+        let txids = self.block_txids(height, 0, usize::MAX).await?;
+        self.block_transactions(&txids, &status).await
+    }
+
+    async fn get_mempool_recent_txids(&self) -> crate::error::Result<Vec<String>> {
+        Err(Error::RpcUnavailable(
+            "electrum servers don't list mempool transactions; use --source mempool or core"
+                .to_string(),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::server_error;
+    use crate::error::Error;
+
+    #[test]
+    fn missing_transaction_is_not_found() {
+        // ElectrumX relaying bitcoind's RPC error
+        let err = anyhow::anyhow!(
+            "Protocol(Object {{\"code\": Number(2), \"message\": String(\"daemon error: \
+             DaemonError({{'code': -5, 'message': 'No such mempool or blockchain transaction. \
+             Use gettransaction for wallet transactions.'}})\")}})"
+        );
+        assert!(matches!(server_error(err), Error::NotFound(_)));
+
+        let err = anyhow::anyhow!("Protocol(String(\"height 900000 out of range\"))");
+        assert!(matches!(server_error(err), Error::NotFound(_)));
+    }
+
+    #[test]
+    fn other_server_errors_are_network() {
+        let err = anyhow::anyhow!("IOError(Kind(ConnectionReset))");
+        assert!(matches!(server_error(err), Error::Network(_)));
+    }
+}
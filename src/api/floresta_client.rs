@@ -1,20 +1,29 @@
+use std::collections::HashMap;
 use std::fs;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
 
-use anyhow::{Context, Result};
-use bitcoin::{Network, Txid};
+use anyhow::Context;
+use bitcoin::{BlockHash, Network, Transaction, Txid};
 use floresta_node::{Config, Florestad};
 use floresta_rpc::jsonrpc_client::Client as FlorestaRpcClient;
 use floresta_rpc::rpc::{FlorestaRPC, JsonRPCClient};
 use floresta_rpc::rpc_types::{GetBlockRes, RawTx};
 use once_cell::sync::OnceCell;
-use tokio::task::spawn_blocking;
-use tokio::sync::OnceCell as AsyncOnceCell;
+use tokio::task::{JoinError, JoinSet, spawn_blocking};
+use tokio::sync::{OnceCell as AsyncOnceCell, Semaphore};
 
-use super::source::DataSource;
+use super::block_index::BlockIndex;
+use super::client::DEFAULT_TIMEOUT;
+use super::source::{BLOCK_TXS_PAGE_SIZE, DataSource, SyncStatus};
 use super::types::{ApiPrevout, ApiStatus, ApiTransaction, ApiVin, ApiVout};
+use crate::error::{Error, Result};
 
 const FLORESTA_RPC_URL: &str = "http://127.0.0.1:38332";
+/// How florestad words a missing transaction or block ("Transaction not found",
+/// "Block not found") and a height past its tip ("Invalid height").
+const NOT_FOUND_MESSAGES: &[&str] = &["not found", "invalid height"];
+const DEFAULT_CONCURRENCY: usize = 16;
 static EMBEDDED_FLORESTA: AsyncOnceCell<()> = AsyncOnceCell::const_new();
 static FLORESTA_CONFIG_INIT: OnceCell<Config> = OnceCell::new();
 
@@ -49,47 +58,214 @@ async fn ensure_embedded_floresta() -> Result<()> {
                 let _ = node.should_stop().await;
             });
 
-            Ok(())
+            Ok::<_, anyhow::Error>(())
         })
         .await
         .map(|_| ())
+        .map_err(|e| Error::RpcUnavailable(format!("{e:#}")))
 }
 
+/// Txid → outputs of previously fetched transactions, for prevout lookups.
+type PrevTxCache = Arc<Mutex<HashMap<String, Vec<ApiVout>>>>;
+
 pub struct FlorestaClient {
     client: Arc<FlorestaRpcClient>,
+    block_index: BlockIndex,
+    prev_txs: PrevTxCache,
+    concurrency: usize,
+    fetch_prevouts: bool,
+    timeout: Duration,
 }
 
 impl FlorestaClient {
     pub fn new(rpc_url: &str) -> Self {
         Self {
             client: Arc::new(FlorestaRpcClient::new(rpc_url.to_string())),
+            block_index: BlockIndex::new(),
+            prev_txs: Arc::new(Mutex::new(HashMap::new())),
+            concurrency: DEFAULT_CONCURRENCY,
+            fetch_prevouts: false,
+            timeout: DEFAULT_TIMEOUT,
         }
     }
 
+    /// Per-call timeout for blocking RPC work (default 30s). A timed-out call keeps
+    /// its blocking thread until the node answers, but the caller gets an error.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
     /// Defaults to a local florestad instance on the standard RPC port.
     pub fn default() -> Self {
         Self::new(FLORESTA_RPC_URL)
     }
 
-    fn map_raw_tx_to_api(tx: RawTx) -> ApiTransaction {
+    /// Maximum number of in-flight `getrawtransaction` calls during block scans.
+    pub fn with_concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = concurrency.max(1);
+        self
+    }
+
+    /// Fetch every input's previous transaction to fill in `prevout` and `fee`. Off by
+    /// default, since it adds a round-trip per distinct input txid.
+    pub fn with_prevouts(mut self, enabled: bool) -> Self {
+        self.fetch_prevouts = enabled;
+        self
+    }
+
+    /// Fetch transactions with at most `concurrency` blocking RPC calls in flight,
+    /// returning them in the same order as `txids`.
+    async fn fetch_transactions(
+        &self,
+        txids: Vec<String>,
+        block_height: Option<u64>,
+    ) -> Result<Vec<ApiTransaction>> {
+        let semaphore = Arc::new(Semaphore::new(self.concurrency));
+        let mut tasks = JoinSet::new();
+
+        for (index, txid_str) in txids.into_iter().enumerate() {
+            let permit = semaphore
+                .clone()
+                .acquire_owned()
+                .await
+                .expect("semaphore is never closed");
+            let client = self.client.clone();
+            let prev_txs = self.fetch_prevouts.then(|| self.prev_txs.clone());
+            let operation = format!("getrawtransaction {txid_str}");
+            let timeout = self.timeout;
+
+            // The permit is released on timeout even if the blocking call never returns
+            tasks.spawn(async move {
+                let _permit = permit;
+                let tx = blocking_with_timeout(timeout, operation, move || {
+                    let txid: Txid = txid_str.parse()?;
+                    let value: serde_json::Value = client.call(
+                        "getrawtransaction",
+                        &[
+                            serde_json::Value::String(txid.to_string()),
+                            serde_json::Value::Bool(true),
+                        ],
+                    )?;
+                    let raw: RawTx = serde_json::from_value(value)?;
+                    let mut tx = FlorestaClient::map_raw_tx_to_api(raw, block_height);
+                    if let Some(ref cache) = prev_txs {
+                        fill_prevouts(&client, cache, &mut tx);
+                    }
+                    Ok(tx)
+                })
+                .await?;
+                Ok::<_, Error>((index, tx))
+            });
+        }
+
+        let mut indexed = Vec::with_capacity(tasks.len());
+        while let Some(joined) = tasks.join_next().await {
+            indexed.push(joined.map_err(task_failed)??);
+        }
+        indexed.sort_by_key(|(index, _)| *index);
+
+        Ok(indexed.into_iter().map(|(_, tx)| tx).collect())
+    }
+
+    /// Resolve a block hash to its height, consulting the cache before asking the node.
+    /// Returns None for unconfirmed transactions (empty hash) or if the lookup fails.
+    fn resolve_block_height(
+        client: &FlorestaRpcClient,
+        block_index: &BlockIndex,
+        blockhash: &str,
+    ) -> Option<u64> {
+        if blockhash.is_empty() {
+            return None;
+        }
+        if let Some(height) = block_index.height(blockhash) {
+            return Some(height);
+        }
+
+        let hash = blockhash.parse::<BlockHash>().ok()?;
+        let height = match client.get_block(hash, Some(1)).ok()? {
+            GetBlockRes::One(b) => u64::from(b.height),
+            GetBlockRes::Zero(_) => return None,
+        };
+
+        block_index.insert(height, blockhash);
+        Some(height)
+    }
+
+    /// Txids of the block at `height`, in block order.
+    async fn block_txids(&self, height: u64) -> Result<Vec<String>> {
+        let hash = self.get_block_hash(height).await?;
+        let hash: BlockHash = hash
+            .parse()
+            .map_err(|e| Error::Decode(format!("block hash {hash}: {e}")))?;
+        let (_, txids) = self.block_by_hash(hash).await?;
+        Ok(txids)
+    }
+
+    /// Height and txids of the block `hash`, in block order, from one `getblock` call.
+    async fn block_by_hash(&self, hash: BlockHash) -> Result<(u64, Vec<String>)> {
+        ensure_embedded_floresta().await?;
+
+        let client = self.client.clone();
+        let block_index = self.block_index.clone();
+
+        let operation = format!("getblock {hash}");
+        blocking_with_timeout(self.timeout, operation, move || {
+            let verbose = match client.get_block(hash, Some(1))? {
+                GetBlockRes::One(b) => b,
+                GetBlockRes::Zero(_) => anyhow::bail!("unexpected non-verbose block response"),
+            };
+            let height = u64::from(verbose.height);
+            block_index.insert(height, &hash.to_string());
+            Ok((height, verbose.tx))
+        })
+        .await
+    }
+
+    /// Outputs of `txid`, from the cache or the node.
+    fn prev_tx_outputs(
+        client: &FlorestaRpcClient,
+        cache: &PrevTxCache,
+        txid: &str,
+    ) -> Option<Vec<ApiVout>> {
+        if let Some(outputs) = cache.lock().ok()?.get(txid) {
+            return Some(outputs.clone());
+        }
+
+        let value: serde_json::Value = client
+            .call(
+                "getrawtransaction",
+                &[
+                    serde_json::Value::String(txid.to_string()),
+                    serde_json::Value::Bool(true),
+                ],
+            )
+            .ok()?;
+        let raw: RawTx = serde_json::from_value(value).ok()?;
+        let outputs = Self::map_raw_tx_to_api(raw, None).vout;
+
+        cache.lock().ok()?.insert(txid.to_string(), outputs.clone());
+        Some(outputs)
+    }
+
+    fn map_raw_tx_to_api(tx: RawTx, block_height: Option<u64>) -> ApiTransaction {
+        let inputs: Vec<(&str, u32)> = tx.vin.iter().map(|i| (i.txid.as_str(), i.vout)).collect();
+        let is_coinbase = is_coinbase(&tx.hex, &inputs);
+
         let vin = tx
             .vin
             .into_iter()
-            .map(|input| {
-                let is_coinbase = input.txid.chars().all(|c| c == '0');
-
-                ApiVin {
-                    txid: Some(input.txid),
-                    vout: Some(input.vout),
-                    prevout: None::<ApiPrevout>,
-                    scriptsig: Some(input.script_sig.hex),
-                    scriptsig_asm: Some(input.script_sig.asm),
-                    inner_redeemscript_asm: None,
-                    inner_witnessscript_asm: None,
-                    witness: Some(input.witness),
-                    is_coinbase,
-                    sequence: input.sequence,
-                }
+            .map(|input| ApiVin {
+                txid: Some(input.txid),
+                vout: Some(input.vout),
+                prevout: None::<ApiPrevout>,
+                scriptsig: Some(input.script_sig.hex),
+                scriptsig_asm: Some(input.script_sig.asm),
+                inner_redeemscript_asm: None,
+                inner_witnessscript_asm: None,
+                witness: Some(input.witness),
+                is_coinbase,
+                sequence: input.sequence,
             })
             .collect();
 
@@ -113,9 +289,10 @@ impl FlorestaClient {
             })
             .collect();
 
+        let confirmed = tx.confirmations > 0;
         let status = ApiStatus {
-            confirmed: tx.confirmations > 0,
-            block_height: None,
+            confirmed,
+            block_height: if confirmed { block_height } else { None },
             block_hash: if tx.blockhash.is_empty() {
                 None
             } else {
@@ -138,14 +315,113 @@ impl FlorestaClient {
     }
 }
 
+/// Run blocking RPC work, giving up after `timeout`. `operation` names the call in the
+/// error so batch runs can report what timed out. The node saying the transaction or
+/// block doesn't exist is `NotFound`; other failures besides parse errors are
+/// `RpcUnavailable`, since the RPC client doesn't say why a call failed.
+async fn blocking_with_timeout<T, F>(timeout: Duration, operation: String, f: F) -> Result<T>
+where
+    F: FnOnce() -> anyhow::Result<T> + Send + 'static,
+    T: Send + 'static,
+{
+    match tokio::time::timeout(timeout, spawn_blocking(f)).await {
+        Ok(Ok(result)) => result.map_err(rpc_error),
+        Ok(Err(e)) => Err(task_failed(e)),
+        Err(_) => Err(Error::Network(format!(
+            "floresta {operation} timed out after {}s",
+            timeout.as_secs_f64()
+        ))),
+    }
+}
+
+fn rpc_error(err: anyhow::Error) -> Error {
+    let msg = format!("{err:#}");
+    let lower = msg.to_lowercase();
+    if NOT_FOUND_MESSAGES.iter().any(|m| lower.contains(m)) {
+        Error::NotFound(msg)
+    } else {
+        Error::classify(err, Error::RpcUnavailable)
+    }
+}
+
+fn task_failed(err: JoinError) -> Error {
+    Error::RpcUnavailable(format!("floresta RPC task failed: {err}"))
+}
+
+/// Heights are `u32` on the node's side; anything larger can't exist yet.
+fn height_u32(height: u64) -> Result<u32> {
+    u32::try_from(height)
+        .map_err(|_| Error::NotFound(format!("block height {height} is out of range")))
+}
+
+/// Fill each input's `prevout` from its previous transaction, then the fee.
+fn fill_prevouts(client: &FlorestaRpcClient, cache: &PrevTxCache, tx: &mut ApiTransaction) {
+    set_prevouts(tx, |txid, vout| {
+        let outputs = FlorestaClient::prev_tx_outputs(client, cache, txid)?;
+        outputs.get(usize::try_from(vout).ok()?).cloned()
+    });
+}
+
+/// Set `prevout` on every non-coinbase input `lookup` can resolve, and the fee when
+/// all of them resolved.
+fn set_prevouts(tx: &mut ApiTransaction, lookup: impl Fn(&str, u32) -> Option<ApiVout>) {
+    for vin in tx.vin.iter_mut().filter(|v| !v.is_coinbase) {
+        let (Some(txid), Some(vout)) = (vin.txid.as_deref(), vin.vout) else {
+            continue;
+        };
+        vin.prevout = lookup(txid, vout).map(|out| ApiPrevout {
+            scriptpubkey: out.scriptpubkey,
+            scriptpubkey_asm: out.scriptpubkey_asm,
+            scriptpubkey_type: out.scriptpubkey_type,
+            scriptpubkey_address: out.scriptpubkey_address,
+            value: out.value,
+        });
+    }
+    tx.fee = compute_fee(tx);
+}
+
+/// Sum of prevout values minus outputs. `None` for coinbases or when any prevout is
+/// missing.
+fn compute_fee(tx: &ApiTransaction) -> Option<u64> {
+    if tx.vin.iter().any(|v| v.is_coinbase) {
+        return None;
+    }
+    let mut spent = 0u64;
+    for vin in &tx.vin {
+        spent += vin.prevout.as_ref()?.value;
+    }
+    let created: u64 = tx.vout.iter().map(|o| o.value).sum();
+    spent.checked_sub(created)
+}
+
+/// Whether a transaction is a coinbase. Trusts the raw hex when it decodes, and
+/// otherwise looks for a single input spending the null outpoint, whose txid the
+/// verbose RPC may render as an empty string or as zeros.
+fn is_coinbase(hex: &str, inputs: &[(&str, u32)]) -> bool {
+    if let Ok(tx) = bitcoin::consensus::encode::deserialize_hex::<Transaction>(hex) {
+        return tx.is_coinbase();
+    }
+    matches!(inputs, [(txid, vout)] if is_null_outpoint(txid, *vout))
+}
+
+fn is_null_outpoint(txid: &str, vout: u32) -> bool {
+    vout == u32::MAX && txid.trim_start_matches("0x").chars().all(|c| c == '0')
+}
+
 impl DataSource for FlorestaClient {
     async fn get_transaction(&self, txid: &str) -> Result<ApiTransaction> {
         ensure_embedded_floresta().await?;
 
         let client = self.client.clone();
-        let txid = txid.parse::<Txid>()?;
+        let txid = txid
+            .parse::<Txid>()
+            .map_err(|e| Error::Decode(format!("txid {txid}: {e}")))?;
 
-        let raw = spawn_blocking(move || {
+        let block_index = self.block_index.clone();
+        let prev_txs = self.fetch_prevouts.then(|| self.prev_txs.clone());
+
+        let operation = format!("getrawtransaction {txid}");
+        let tx = blocking_with_timeout(self.timeout, operation, move || {
             let value: serde_json::Value = client.call(
                 "getrawtransaction",
                 &[
@@ -153,21 +429,29 @@ impl DataSource for FlorestaClient {
                     serde_json::Value::Bool(true),
                 ],
             )?;
-            let tx: RawTx = serde_json::from_value(value)?;
+            let raw: RawTx = serde_json::from_value(value)?;
+            let height = Self::resolve_block_height(&client, &block_index, &raw.blockhash);
+            let mut tx = Self::map_raw_tx_to_api(raw, height);
+            if let Some(ref cache) = prev_txs {
+                fill_prevouts(&client, cache, &mut tx);
+            }
             Ok::<_, anyhow::Error>(tx)
         })
-        .await??;
+        .await?;
 
-        Ok(Self::map_raw_tx_to_api(raw))
+        Ok(tx)
     }
 
     async fn get_transaction_hex(&self, txid: &str) -> Result<String> {
         ensure_embedded_floresta().await?;
 
         let client = self.client.clone();
-        let txid = txid.parse::<Txid>()?;
+        let txid = txid
+            .parse::<Txid>()
+            .map_err(|e| Error::Decode(format!("txid {txid}: {e}")))?;
 
-        let hex = spawn_blocking(move || {
+        let operation = format!("getrawtransaction {txid} (hex)");
+        let hex = blocking_with_timeout(self.timeout, operation, move || {
             let value: serde_json::Value = client.call(
                 "getrawtransaction",
                 &[
@@ -178,45 +462,21 @@ impl DataSource for FlorestaClient {
             let tx: RawTx = serde_json::from_value(value)?;
             Ok::<_, anyhow::Error>(tx.hex)
         })
-        .await??;
+        .await?;
 
         Ok(hex)
     }
 
     async fn get_block_txs(&self, hash: &str, start_index: u32) -> Result<Vec<ApiTransaction>> {
-        ensure_embedded_floresta().await?;
-
-        let client = self.client.clone();
-        let hash = hash.parse()?;
-
-        let txs = spawn_blocking(move || -> Result<Vec<ApiTransaction>> {
-            let block = client.get_block(hash, Some(1))?;
-            let verbose = match block {
-                GetBlockRes::One(b) => b,
-                GetBlockRes::Zero(_) => anyhow::bail!("unexpected non-verbose block response"),
-            };
-
-            let mut out = Vec::new();
-            for txid_str in verbose.tx {
-                let txid: Txid = txid_str.parse()?;
-                let value: serde_json::Value = client.call(
-                    "getrawtransaction",
-                    &[
-                        serde_json::Value::String(txid.to_string()),
-                        serde_json::Value::Bool(true),
-                    ],
-                )?;
-                let raw: RawTx = serde_json::from_value(value)?;
-                out.push(FlorestaClient::map_raw_tx_to_api(raw));
-            }
-
-            let start = usize::try_from(start_index).unwrap_or(0);
-            let end = (start + 25).min(out.len());
-            Ok(out.get(start..end).unwrap_or(&[]).to_vec())
-        })
-        .await??;
-
-        Ok(txs)
+        let hash: BlockHash = hash
+            .parse()
+            .map_err(|e| Error::Decode(format!("block hash {hash}: {e}")))?;
+        let (height, txids) = self.block_by_hash(hash).await?;
+
+        // Only the page's transactions are fetched, each call under its own timeout
+        let start = usize::try_from(start_index).unwrap_or(usize::MAX).min(txids.len());
+        let end = (start + BLOCK_TXS_PAGE_SIZE).min(txids.len());
+        self.fetch_transactions(txids[start..end].to_vec(), Some(height)).await
     }
 
     async fn get_block_tip_height(&self) -> Result<u64> {
@@ -224,11 +484,11 @@ impl DataSource for FlorestaClient {
 
         let client = self.client.clone();
 
-        let height = spawn_blocking(move || {
+        let height = blocking_with_timeout(self.timeout, "getblockcount".to_string(), move || {
             let h = client.get_block_count()?;
             Ok::<_, anyhow::Error>(u64::from(h))
         })
-        .await??;
+        .await?;
 
         Ok(height)
     }
@@ -236,64 +496,203 @@ impl DataSource for FlorestaClient {
     async fn get_block_hash(&self, height: u64) -> Result<String> {
         ensure_embedded_floresta().await?;
 
+        if let Some(hash) = self.block_index.hash(height) {
+            return Ok(hash);
+        }
         let client = self.client.clone();
-        let height_u32 = u32::try_from(height)?;
+        let height_u32 = height_u32(height)?;
 
-        let hash = spawn_blocking(move || {
+        let operation = format!("getblockhash {height}");
+        let hash = blocking_with_timeout(self.timeout, operation, move || {
             let h = client.get_block_hash(height_u32)?;
             Ok::<_, anyhow::Error>(h.to_string())
         })
-        .await??;
+        .await?;
 
+        self.block_index.insert(height, &hash);
         Ok(hash)
     }
 
     async fn get_all_block_txs(&self, height: u64) -> Result<Vec<ApiTransaction>> {
-        ensure_embedded_floresta().await?;
-
-        let client = self.client.clone();
-        let height_u32 = u32::try_from(height)?;
-
-        let txs = spawn_blocking(move || -> Result<Vec<ApiTransaction>> {
-            let hash = client.get_block_hash(height_u32)?;
-            let block = client.get_block(hash, Some(1))?;
-            let verbose = match block {
-                GetBlockRes::One(b) => b,
-                GetBlockRes::Zero(_) => anyhow::bail!("unexpected non-verbose block response"),
-            };
+        let txids = self.block_txids(height).await?;
+        self.fetch_transactions(txids, Some(height)).await
+    }
 
-            let mut out = Vec::new();
-            for txid_str in verbose.tx {
-                let txid: Txid = txid_str.parse()?;
-                let value: serde_json::Value = client.call(
-                    "getrawtransaction",
-                    &[
-                        serde_json::Value::String(txid.to_string()),
-                        serde_json::Value::Bool(true),
-                    ],
-                )?;
-                let raw: RawTx = serde_json::from_value(value)?;
-                out.push(FlorestaClient::map_raw_tx_to_api(raw));
+    async fn for_each_block_tx<E, F>(&self, height: u64, mut f: F) -> std::result::Result<(), E>
+    where
+        E: From<Error> + Send,
+        F: FnMut(ApiTransaction) -> std::result::Result<(), E> + Send,
+    {
+        let txids = self.block_txids(height).await?;
+        // One batch of concurrent fetches at a time, so only that batch is held
+        for batch in txids.chunks(self.concurrency) {
+            for tx in self.fetch_transactions(batch.to_vec(), Some(height)).await? {
+                f(tx)?;
             }
-
-            Ok(out)
-        })
-        .await??;
-        Ok(txs)
+        }
+        Ok(())
     }
 
     async fn get_mempool_recent_txids(&self) -> Result<Vec<String>> {
         // Floresta does not expose a mempool listing RPC yet.
         Ok(Vec::new())
     }
+
+    async fn sync_status(&self) -> Result<Option<SyncStatus>> {
+        ensure_embedded_floresta().await?;
+
+        let client = self.client.clone();
+
+        // Read as plain JSON: only `ibd` and `progress` are needed
+        let info = blocking_with_timeout(self.timeout, "getblockchaininfo".to_string(), move || {
+            let value: serde_json::Value = client.call("getblockchaininfo", &[])?;
+            Ok::<_, anyhow::Error>(value)
+        })
+        .await?;
+
+        let ibd = info["ibd"]
+            .as_bool()
+            .ok_or_else(|| Error::Decode("getblockchaininfo response has no ibd flag".into()))?;
+        Ok(Some(SyncStatus {
+            synced: !ibd,
+            progress: info["progress"].as_f64(),
+        }))
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::FlorestaClient;
     use super::DataSource;
+    use super::{is_coinbase, rpc_error, set_prevouts};
+    use crate::api::types::{ApiStatus, ApiTransaction, ApiVin, ApiVout};
+    use crate::error::Error;
     use floresta_rpc::rpc::FlorestaRPC;
 
+    /// Genesis block coinbase.
+    const COINBASE_HEX: &str = "01000000010000000000000000000000000000000000000000000000000000000000000000ffffffff4d04ffff001d0104455468652054696d65732030332f4a616e2f32303039204368616e63656c6c6f72206f6e206272696e6b206f66207365636f6e64206261696c6f757420666f722062616e6b73ffffffff0100f2052a01000000434104678afdb0fe5548271967f1a67130b7105cd6a828e03909a67962e0ea1f61deb649f6bc3f4cef38c4f35504e51ec112de5c384df7ba0b8d578a4c702b6bf11d5fac00000000";
+
+    #[test]
+    fn missing_transaction_or_block_is_not_found() {
+        for msg in ["Transaction not found", "Block not found", "Invalid height"] {
+            let err = anyhow::anyhow!("JsonRpcError {{ code: -1, message: \"{msg}\" }}");
+            assert!(matches!(rpc_error(err), Error::NotFound(_)), "{msg}");
+        }
+    }
+
+    #[test]
+    fn other_rpc_errors_are_unavailable() {
+        let err = anyhow::anyhow!("connection refused");
+        assert!(matches!(rpc_error(err), Error::RpcUnavailable(_)));
+    }
+
+    #[test]
+    fn coinbase_detected_from_raw_hex() {
+        // Even if the verbose fields are missing or garbled
+        assert!(is_coinbase(COINBASE_HEX, &[]));
+        assert!(is_coinbase(COINBASE_HEX, &[("", 0)]));
+    }
+
+    #[test]
+    fn coinbase_detected_from_null_outpoint() {
+        assert!(is_coinbase("", &[("", u32::MAX)]));
+        assert!(is_coinbase("", &[(&"0".repeat(64), u32::MAX)]));
+        assert!(is_coinbase("", &[("0x00", u32::MAX)]));
+    }
+
+    #[test]
+    fn zero_txid_alone_not_coinbase() {
+        // A null outpoint also needs vout 0xffffffff, and a coinbase has one input
+        assert!(!is_coinbase("", &[(&"0".repeat(64), 0)]));
+        assert!(!is_coinbase("", &[("", u32::MAX), (&"ab".repeat(32), 0)]));
+        assert!(!is_coinbase("", &[(&"ab".repeat(32), u32::MAX)]));
+    }
+
+    fn fee_test_tx(inputs: &[(&str, u32)], output_values: &[u64], is_coinbase: bool) -> ApiTransaction {
+        ApiTransaction {
+            txid: "bb".repeat(32),
+            version: 2,
+            locktime: 0,
+            vin: inputs
+                .iter()
+                .map(|(txid, vout)| ApiVin {
+                    txid: Some(txid.to_string()),
+                    vout: Some(*vout),
+                    prevout: None,
+                    scriptsig: None,
+                    scriptsig_asm: None,
+                    inner_redeemscript_asm: None,
+                    inner_witnessscript_asm: None,
+                    witness: None,
+                    is_coinbase,
+                    sequence: 0xFFFFFFFF,
+                })
+                .collect(),
+            vout: output_values
+                .iter()
+                .map(|value| ApiVout {
+                    scriptpubkey: "00".to_string(),
+                    scriptpubkey_asm: "OP_0".to_string(),
+                    scriptpubkey_type: "v0_p2wpkh".to_string(),
+                    scriptpubkey_address: None,
+                    value: *value,
+                })
+                .collect(),
+            size: 200,
+            weight: 800,
+            fee: None,
+            status: ApiStatus {
+                confirmed: false,
+                block_height: None,
+                block_hash: None,
+                block_time: None,
+            },
+        }
+    }
+
+    fn prev_output(txid: &str, vout: u32) -> Option<ApiVout> {
+        let value = match (txid, vout) {
+            ("aa", 0) => 60_000,
+            ("aa", 1) => 40_000,
+            ("cc", 0) => 10_000,
+            _ => return None,
+        };
+        Some(ApiVout {
+            scriptpubkey: "0020".to_string() + &"ab".repeat(32),
+            scriptpubkey_asm: format!("OP_0 OP_PUSHBYTES_32 {}", "ab".repeat(32)),
+            scriptpubkey_type: "v0_p2wsh".to_string(),
+            scriptpubkey_address: None,
+            value,
+        })
+    }
+
+    #[test]
+    fn prevouts_and_fee_filled() {
+        let mut tx = fee_test_tx(&[("aa", 0), ("aa", 1), ("cc", 0)], &[100_000, 9_000], false);
+        set_prevouts(&mut tx, prev_output);
+        assert_eq!(tx.fee, Some(1_000));
+        let prevout = tx.vin[1].prevout.as_ref().unwrap();
+        assert_eq!(prevout.value, 40_000);
+        assert_eq!(prevout.scriptpubkey_type, "v0_p2wsh");
+    }
+
+    #[test]
+    fn fee_unknown_when_a_prevout_is_missing() {
+        let mut tx = fee_test_tx(&[("aa", 0), ("dd", 0)], &[50_000], false);
+        set_prevouts(&mut tx, prev_output);
+        assert!(tx.vin[0].prevout.is_some());
+        assert!(tx.vin[1].prevout.is_none());
+        assert_eq!(tx.fee, None);
+    }
+
+    #[test]
+    fn coinbase_has_no_prevout_or_fee() {
+        let mut tx = fee_test_tx(&[("aa", 0)], &[50_000], true);
+        set_prevouts(&mut tx, prev_output);
+        assert!(tx.vin[0].prevout.is_none());
+        assert_eq!(tx.fee, None);
+    }
+
     #[tokio::test]
     async fn print_first_10_txs_from_tip_block() {
         let client = FlorestaClient::default();
@@ -323,7 +722,7 @@ mod tests {
         // Use the raw Floresta RPC client to fetch the last block and list its txids
         let client = super::FlorestaRpcClient::new(super::FLORESTA_RPC_URL.to_string());
 
-        let txids = super::spawn_blocking(move || -> super::Result<Vec<String>> {
+        let txids = super::spawn_blocking(move || -> anyhow::Result<Vec<String>> {
             let tip_height = client.get_block_count()?;
             let hash = client.get_block_hash(tip_height)?;
             let block = client.get_block(hash, Some(1))?;
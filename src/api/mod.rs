@@ -1,5 +1,12 @@
 pub mod client;
 pub mod floresta_client;
+pub mod raw;
+pub mod bitcoin_core_client;
+pub mod block_file;
+pub mod block_index;
 pub mod cache;
+pub mod disk_cache;
+pub mod electrum_client;
+pub mod rate_limit;
 pub mod source;
 pub mod types;
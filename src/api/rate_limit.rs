@@ -0,0 +1,73 @@
+//! Client-side pacing of requests to public APIs, which ban clients that send too many.
+
+use std::sync::{Mutex, PoisonError};
+use std::time::Duration;
+
+use tokio::sync::Semaphore;
+use tokio::time::{Instant, sleep};
+
+/// Token bucket allowing `per_second` requests a second, in bursts of up to a second's
+/// worth. The tokens are permits of one semaphore, so concurrent fetches through the
+/// same client share the budget. There is no refill task: whoever asks for a token
+/// first adds the ones that came due since. A caller that finds none sleeps until the
+/// next is due, plus some jitter so waiters don't all wake at once.
+#[derive(Debug)]
+pub struct RateLimiter {
+    tokens: Semaphore,
+    capacity: usize,
+    interval: Duration,
+    /// When the last token added came due.
+    refilled_at: Mutex<Instant>,
+}
+
+impl RateLimiter {
+    /// Starts with a full bucket. `per_second` is at least 1; past a billion, tokens
+    /// still come due no faster than one a nanosecond.
+    pub fn new(per_second: u32) -> Self {
+        let per_second = per_second.max(1);
+        Self {
+            tokens: Semaphore::new(per_second as usize),
+            capacity: per_second as usize,
+            interval: (Duration::from_secs(1) / per_second).max(Duration::from_nanos(1)),
+            refilled_at: Mutex::new(Instant::now()),
+        }
+    }
+
+    /// Wait for a token and use it up.
+    pub async fn acquire(&self) {
+        loop {
+            let next_due = self.refill();
+            if let Ok(permit) = self.tokens.try_acquire() {
+                permit.forget();
+                return;
+            }
+            sleep(next_due + jitter(self.interval)).await;
+        }
+    }
+
+    /// Add the tokens that came due, up to the capacity, and return how long until
+    /// the next one does.
+    fn refill(&self) -> Duration {
+        // An Instant is never left half updated, so a poisoned lock is still usable
+        let mut refilled_at = self.refilled_at.lock().unwrap_or_else(PoisonError::into_inner);
+        let now = Instant::now();
+        let elapsed = now - *refilled_at;
+        let room = self.capacity - self.tokens.available_permits();
+        let due = elapsed.as_nanos() / self.interval.as_nanos();
+        if due >= self.capacity as u128 {
+            // Idle long enough to fill the bucket
+            self.tokens.add_permits(room);
+            *refilled_at = now;
+            return self.interval;
+        }
+        let due = due as u32;
+        self.tokens.add_permits(room.min(due as usize));
+        *refilled_at += self.interval * due;
+        self.interval - (elapsed - self.interval * due)
+    }
+}
+
+/// Up to a tenth of `interval`.
+fn jitter(interval: Duration) -> Duration {
+    interval.mul_f64(rand::random_range(0.0..0.1))
+}
@@ -0,0 +1,157 @@
+use anyhow::{Context, Result};
+use bitcoin::hex::DisplayHex;
+use bitcoin::script::Instruction;
+use bitcoin::{Address, Network, Script, ScriptBuf, Transaction, TxIn, TxOut};
+
+use super::types::{ApiPrevout, ApiStatus, ApiTransaction, ApiVin, ApiVout};
+
+/// Decode a raw transaction hex string into the API shape, without any data source.
+/// `network` only affects how output addresses are encoded.
+pub fn decode_raw_transaction(hex: &str, network: Network) -> Result<ApiTransaction> {
+    let tx: Transaction = bitcoin::consensus::encode::deserialize_hex(hex.trim())
+        .context("invalid raw transaction hex")?;
+    Ok(api_transaction_from_bitcoin(&tx, network))
+}
+
+/// Convert a decoded `bitcoin::Transaction` into our `ApiTransaction`.
+///
+/// Only what the transaction itself carries is filled in: there are no prevouts, no
+/// fee, no confirmation status, and no `inner_witnessscript_asm` (the spent output's
+/// type is unknown, so the last witness element can't safely be read as a script).
+pub fn api_transaction_from_bitcoin(tx: &Transaction, network: Network) -> ApiTransaction {
+    let is_coinbase = tx.is_coinbase();
+
+    let vin = tx
+        .input
+        .iter()
+        .map(|input| ApiVin {
+            txid: Some(input.previous_output.txid.to_string()),
+            vout: Some(input.previous_output.vout),
+            prevout: None,
+            scriptsig: Some(input.script_sig.to_hex_string()),
+            scriptsig_asm: Some(input.script_sig.to_asm_string()),
+            inner_redeemscript_asm: None,
+            inner_witnessscript_asm: None,
+            witness: Some(
+                input
+                    .witness
+                    .iter()
+                    .map(|elem| elem.to_lower_hex_string())
+                    .collect(),
+            ),
+            is_coinbase,
+            sequence: input.sequence.0,
+        })
+        .collect();
+
+    let vout = tx
+        .output
+        .iter()
+        .map(|output| ApiVout {
+            scriptpubkey: output.script_pubkey.to_hex_string(),
+            scriptpubkey_asm: output.script_pubkey.to_asm_string(),
+            scriptpubkey_type: script_type(&output.script_pubkey).to_string(),
+            scriptpubkey_address: Address::from_script(&output.script_pubkey, network)
+                .ok()
+                .map(|a| a.to_string()),
+            value: output.value.to_sat(),
+        })
+        .collect();
+
+    ApiTransaction {
+        txid: tx.compute_txid().to_string(),
+        version: tx.version.0,
+        locktime: tx.lock_time.to_consensus_u32(),
+        vin,
+        vout,
+        size: tx.total_size() as u64,
+        weight: tx.weight().to_wu(),
+        fee: None,
+        status: ApiStatus {
+            confirmed: false,
+            block_height: None,
+            block_hash: None,
+            block_time: None,
+        },
+    }
+}
+
+/// Like [`api_transaction_from_bitcoin`], but with the outputs spent by each input
+/// (`prevouts[i]` for input `i`). Fills in prevouts and the fee, and recovers the
+/// redeem and witness scripts of P2SH and P2WSH spends the way esplora does.
+pub fn api_transaction_with_prevouts(
+    tx: &Transaction,
+    prevouts: &[TxOut],
+    network: Network,
+) -> ApiTransaction {
+    let mut api_tx = api_transaction_from_bitcoin(tx, network);
+
+    for ((vin, input), prevout) in api_tx.vin.iter_mut().zip(&tx.input).zip(prevouts) {
+        let spk = &prevout.script_pubkey;
+        vin.prevout = Some(ApiPrevout {
+            scriptpubkey: spk.to_hex_string(),
+            scriptpubkey_asm: spk.to_asm_string(),
+            scriptpubkey_type: script_type(spk).to_string(),
+            scriptpubkey_address: Address::from_script(spk, network).ok().map(|a| a.to_string()),
+            value: prevout.value.to_sat(),
+        });
+
+        let redeem_script = if spk.is_p2sh() { last_push(&input.script_sig) } else { None };
+        if let Some(ref redeem) = redeem_script {
+            vin.inner_redeemscript_asm = Some(redeem.to_asm_string());
+        }
+
+        // Native P2WSH, or P2WSH nested in P2SH
+        let spends_p2wsh = spk.is_p2wsh() || redeem_script.as_ref().is_some_and(|r| r.is_p2wsh());
+        if spends_p2wsh {
+            vin.inner_witnessscript_asm = witness_script(input).map(|s| s.to_asm_string());
+        }
+    }
+
+    if prevouts.len() == tx.input.len() {
+        let spent: u64 = prevouts.iter().map(|p| p.value.to_sat()).sum();
+        let created: u64 = tx.output.iter().map(|o| o.value.to_sat()).sum();
+        api_tx.fee = spent.checked_sub(created);
+    }
+
+    api_tx
+}
+
+/// The last data push of a scriptSig: the redeem script of a P2SH spend.
+fn last_push(script_sig: &Script) -> Option<ScriptBuf> {
+    script_sig
+        .instructions()
+        .filter_map(|ins| match ins {
+            Ok(Instruction::PushBytes(bytes)) => Some(ScriptBuf::from(bytes.as_bytes().to_vec())),
+            _ => None,
+        })
+        .last()
+}
+
+fn witness_script(input: &TxIn) -> Option<ScriptBuf> {
+    input.witness.last().map(|elem| ScriptBuf::from(elem.to_vec()))
+}
+
+/// mempool.space-style script type name.
+fn script_type(script: &Script) -> &'static str {
+    if script.is_p2pk() {
+        "p2pk"
+    } else if script.is_p2pkh() {
+        "p2pkh"
+    } else if script.is_p2sh() {
+        "p2sh"
+    } else if script.is_p2wpkh() {
+        "v0_p2wpkh"
+    } else if script.is_p2wsh() {
+        "v0_p2wsh"
+    } else if script.is_p2tr() {
+        "v1_p2tr"
+    } else if script.is_op_return() {
+        "op_return"
+    } else if script.as_bytes() == [0x51, 0x02, 0x4e, 0x73] {
+        // Pay-to-anchor: OP_1 <0x4e73>
+        "anchor"
+    } else {
+        "unknown"
+    }
+}
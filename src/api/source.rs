@@ -1,7 +1,29 @@
-use anyhow::Result;
+use async_stream::try_stream;
+use futures_core::Stream;
+use serde::Serialize;
 
+use super::bitcoin_core_client::BitcoinCoreClient;
+use super::client::MempoolClient;
+use super::electrum_client::ElectrumClient;
+use super::floresta_client::FlorestaClient;
 use super::types::ApiTransaction;
+use crate::error::{Error, Result};
 
+/// Transactions per `get_block_txs` page, mempool.space's page size, which every
+/// source follows.
+pub const BLOCK_TXS_PAGE_SIZE: usize = 25;
+
+/// How far a node backend is through initial block download.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct SyncStatus {
+    /// Initial block download is over, so the tip is current.
+    pub synced: bool,
+    /// Fraction of the chain verified, 0.0 to 1.0, when the node reports it.
+    pub progress: Option<f64>,
+}
+
+/// Where transactions and blocks come from. Failures are reported as a
+/// [`crate::Error`], whose variant says what kind of failure it was.
 pub trait DataSource {
     fn get_transaction(
         &self,
@@ -32,8 +54,226 @@ pub trait DataSource {
         height: u64,
     ) -> impl std::future::Future<Output = Result<Vec<ApiTransaction>>> + Send;
 
+    /// Call `f` with each transaction of the block at `height`, in block order, so it
+    /// can be analyzed and dropped before the next one arrives. The first error, from
+    /// the source or from `f`, ends the walk. By default the whole block is fetched with
+    /// `get_all_block_txs` first; sources that page through blocks hand over each page
+    /// as it arrives.
+    fn for_each_block_tx<E, F>(
+        &self,
+        height: u64,
+        mut f: F,
+    ) -> impl std::future::Future<Output = std::result::Result<(), E>> + Send
+    where
+        Self: Sync,
+        E: From<Error> + Send,
+        F: FnMut(ApiTransaction) -> std::result::Result<(), E> + Send,
+    {
+        async move {
+            for tx in self.get_all_block_txs(height).await? {
+                f(tx)?;
+            }
+            Ok(())
+        }
+    }
+
     /// Fetch txids of recent unconfirmed transactions from the mempool.
     fn get_mempool_recent_txids(
         &self,
     ) -> impl std::future::Future<Output = Result<Vec<String>>> + Send;
+
+    /// Fetch the txids of every unconfirmed transaction in the mempool. Sources that
+    /// can't list the whole mempool cheaply fail with [`Error::RpcUnavailable`].
+    fn get_mempool_txids(
+        &self,
+    ) -> impl std::future::Future<Output = Result<Vec<String>>> + Send {
+        async {
+            Err(Error::RpcUnavailable(
+                "this data source can't list the whole mempool; use --source mempool or core"
+                    .to_string(),
+            ))
+        }
+    }
+
+    /// Initial sync state of a node backend. `None` for sources that don't report one,
+    /// such as public APIs.
+    fn sync_status(
+        &self,
+    ) -> impl std::future::Future<Output = Result<Option<SyncStatus>>> + Send {
+        async { Ok(None) }
+    }
+}
+
+/// Page through block `hash` with `get_block_txs`, yielding each page as it arrives,
+/// so a block can be processed without holding all of its transactions. The stream
+/// ends after the first short page, or with the error of a failed page.
+pub fn block_tx_pages<'a, S: DataSource + Sync>(
+    source: &'a S,
+    hash: &'a str,
+) -> impl Stream<Item = Result<Vec<ApiTransaction>>> + Send + 'a {
+    try_stream! {
+        let mut start_index = 0;
+        loop {
+            let page = source.get_block_txs(hash, start_index).await?;
+            let count = page.len();
+            if count > 0 {
+                yield page;
+            }
+            if count < BLOCK_TXS_PAGE_SIZE {
+                break;
+            }
+            start_index += count as u32;
+        }
+    }
+}
+
+/// The first block whose header time is at or after `timestamp` (Unix seconds), found
+/// by binary search over heights. `None` when even the tip is older, i.e. `timestamp`
+/// is in the future. Header times only roughly increase with height, so around
+/// `timestamp` the result can be off by a block or two.
+pub async fn find_height_at_or_after<S: DataSource + Sync>(
+    source: &S,
+    timestamp: u64,
+) -> Result<Option<u64>> {
+    let tip = source.get_block_tip_height().await?;
+    if block_time(source, tip).await? < timestamp {
+        return Ok(None);
+    }
+
+    let (mut lo, mut hi) = (0, tip);
+    while lo < hi {
+        let mid = lo + (hi - lo) / 2;
+        if block_time(source, mid).await? >= timestamp {
+            hi = mid;
+        } else {
+            lo = mid + 1;
+        }
+    }
+    Ok(Some(lo))
+}
+
+/// Header time of the block at `height`, read from the first page of its transactions.
+async fn block_time<S: DataSource + Sync>(source: &S, height: u64) -> Result<u64> {
+    let hash = source.get_block_hash(height).await?;
+    let page = source.get_block_txs(&hash, 0).await?;
+    page.first()
+        .and_then(|tx| tx.status.block_time)
+        .ok_or_else(|| Error::NotFound(format!("block {height} has no timestamp")))
+}
+
+/// The transaction at position `index` (0 is the coinbase) of the block at `height`,
+/// as block explorers number them. Only the page holding it is fetched.
+pub async fn transaction_at<S: DataSource + Sync>(
+    source: &S,
+    height: u64,
+    index: u32,
+) -> Result<ApiTransaction> {
+    let hash = source.get_block_hash(height).await?;
+    let page_size = BLOCK_TXS_PAGE_SIZE as u32;
+    let page_start = index - index % page_size;
+    let page = source.get_block_txs(&hash, page_start).await?;
+    let position = (index - page_start) as usize;
+    let len = page.len();
+    page.into_iter().nth(position).ok_or_else(|| {
+        // A short page ends the block, so its length gives the count
+        let msg = if len > 0 {
+            let count = page_start as usize + len;
+            format!("block {height} has {count} transactions, no index {index}")
+        } else {
+            format!("block {height} has no transaction at index {index}")
+        };
+        Error::NotFound(msg)
+    })
+}
+
+/// A data source picked at runtime, e.g. from a `--source` flag. The async trait
+/// methods make `DataSource` unusable as `dyn DataSource`, so this dispatches to the
+/// concrete clients instead; each stays usable on its own.
+pub enum AnySource {
+    Mempool(MempoolClient),
+    Core(BitcoinCoreClient),
+    Electrum(ElectrumClient),
+    Floresta(FlorestaClient),
+}
+
+/// Call the same method on whichever client is inside.
+macro_rules! dispatch {
+    ($source:expr, $client:ident => $call:expr) => {
+        match $source {
+            AnySource::Mempool($client) => $call,
+            AnySource::Core($client) => $call,
+            AnySource::Electrum($client) => $call,
+            AnySource::Floresta($client) => $call,
+        }
+    };
+}
+
+impl DataSource for AnySource {
+    async fn get_transaction(&self, txid: &str) -> Result<ApiTransaction> {
+        dispatch!(self, c => c.get_transaction(txid).await)
+    }
+
+    async fn get_transaction_hex(&self, txid: &str) -> Result<String> {
+        dispatch!(self, c => c.get_transaction_hex(txid).await)
+    }
+
+    async fn get_block_txs(&self, hash: &str, start_index: u32) -> Result<Vec<ApiTransaction>> {
+        dispatch!(self, c => c.get_block_txs(hash, start_index).await)
+    }
+
+    async fn get_block_tip_height(&self) -> Result<u64> {
+        dispatch!(self, c => c.get_block_tip_height().await)
+    }
+
+    async fn get_block_hash(&self, height: u64) -> Result<String> {
+        dispatch!(self, c => c.get_block_hash(height).await)
+    }
+
+    async fn get_all_block_txs(&self, height: u64) -> Result<Vec<ApiTransaction>> {
+        dispatch!(self, c => c.get_all_block_txs(height).await)
+    }
+
+    async fn for_each_block_tx<E, F>(&self, height: u64, f: F) -> std::result::Result<(), E>
+    where
+        E: From<Error> + Send,
+        F: FnMut(ApiTransaction) -> std::result::Result<(), E> + Send,
+    {
+        dispatch!(self, c => c.for_each_block_tx(height, f).await)
+    }
+
+    async fn get_mempool_recent_txids(&self) -> Result<Vec<String>> {
+        dispatch!(self, c => c.get_mempool_recent_txids().await)
+    }
+
+    async fn get_mempool_txids(&self) -> Result<Vec<String>> {
+        dispatch!(self, c => c.get_mempool_txids().await)
+    }
+
+    async fn sync_status(&self) -> Result<Option<SyncStatus>> {
+        dispatch!(self, c => c.sync_status().await)
+    }
+}
+
+impl From<MempoolClient> for AnySource {
+    fn from(client: MempoolClient) -> Self {
+        Self::Mempool(client)
+    }
+}
+
+impl From<BitcoinCoreClient> for AnySource {
+    fn from(client: BitcoinCoreClient) -> Self {
+        Self::Core(client)
+    }
+}
+
+impl From<ElectrumClient> for AnySource {
+    fn from(client: ElectrumClient) -> Self {
+        Self::Electrum(client)
+    }
+}
+
+impl From<FlorestaClient> for AnySource {
+    fn from(client: FlorestaClient) -> Self {
+        Self::Floresta(client)
+    }
 }
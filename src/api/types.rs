@@ -1,6 +1,6 @@
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ApiTransaction {
     pub txid: String,
     pub version: i32,
@@ -13,7 +13,7 @@ pub struct ApiTransaction {
     pub status: ApiStatus,
 }
 
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ApiVin {
     pub txid: Option<String>,
     pub vout: Option<u32>,
@@ -27,7 +27,7 @@ pub struct ApiVin {
     pub sequence: u32,
 }
 
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ApiPrevout {
     pub scriptpubkey: String,
     pub scriptpubkey_asm: String,
@@ -36,7 +36,7 @@ pub struct ApiPrevout {
     pub value: u64,
 }
 
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ApiVout {
     pub scriptpubkey: String,
     pub scriptpubkey_asm: String,
@@ -45,7 +45,7 @@ pub struct ApiVout {
     pub value: u64,
 }
 
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ApiStatus {
     pub confirmed: bool,
     pub block_height: Option<u64>,
@@ -0,0 +1,300 @@
+//! What the `scan`, `stats`, `batch`, `mempool`, `lightning block`, `monitor` and
+//! `watch` commands fetch and compute, apart from how their results are printed, so
+//! they can be driven by any [`DataSource`].
+
+use rayon::prelude::*;
+
+use super::progress::{ScanProgress, Verbosity};
+use crate::api::source::DataSource;
+use crate::api::types::ApiTransaction;
+use crate::error::{Error, Result};
+use crate::lightning::channel;
+use crate::lightning::detector::{add_tip_reasons, classify_lightning_with_config};
+use crate::lightning::types::{Confidence, DetectorConfig, LightningClassification, LightningTxType};
+use crate::lightning::verify::verify_commitment;
+use crate::security::analyzer;
+use crate::security::types::{Alert, SecurityConfig, Severity};
+use crate::stats::RangeStats;
+use crate::timelock::extractor::{analyze_transaction, analyze_transaction_with_tip};
+use crate::timelock::types::{ChainTip, TransactionAnalysis};
+
+/// Classify `tx` with `detector`, judging HTLC-timeout expiries against `tip` when it
+/// is known: one far past it isn't a realistic expiry, and one still ahead of it can't
+/// have confirmed.
+pub fn classify_at(
+    tx: &ApiTransaction,
+    tip: Option<&ChainTip>,
+    detector: &DetectorConfig,
+) -> LightningClassification {
+    let Some(tip) = tip else {
+        return classify_lightning_with_config(tx, detector);
+    };
+    let detector = detector.clone().with_tip(tip.height);
+    let mut classification = classify_lightning_with_config(tx, &detector);
+    add_tip_reasons(&mut classification, tip);
+    classification
+}
+
+/// Security alerts for blocks `start..=end`, judged against the current tip, plus the
+/// HTLC-timeout clustering alerts across the whole range; critical alerts first.
+/// `inspect` sees each transaction's Lightning classification.
+pub async fn security_scan<S: DataSource + Sync>(
+    client: &S,
+    start: u64,
+    end: u64,
+    config: &SecurityConfig,
+    detector: &DetectorConfig,
+    verbosity: Verbosity,
+    inspect: impl Fn(&LightningClassification) + Sync,
+) -> Result<Vec<Alert>> {
+    let current_height = client.get_block_tip_height().await?;
+    verbosity.status(format!("Current tip: block {current_height}"));
+    let detector = &detector.clone().with_tip(current_height);
+
+    let mut all_alerts = Vec::new();
+    let mut htlc_expiries = Vec::new();
+
+    for height in start..=end {
+        verbosity.status(format!("Scanning block {height}..."));
+        let fetch = client.get_all_block_txs(height);
+        let txs = verbosity.timed(format!("block {height}"), fetch).await?;
+        verbosity.status(format!("  {} transactions", txs.len()));
+
+        let per_tx: Vec<_> = txs
+            .par_iter()
+            .map(|tx| {
+                let timelock = analyze_transaction(tx);
+                let lightning = classify_lightning_with_config(tx, detector);
+                let alerts =
+                    analyzer::analyze_transaction(&timelock, &lightning, current_height, config);
+                (lightning, alerts)
+            })
+            .collect();
+
+        for (lightning, mut alerts) in per_tx {
+            inspect(&lightning);
+            // Collect HTLC expiries for clustering analysis
+            if lightning.tx_type == Some(LightningTxType::HtlcTimeout)
+                && let Some(expiry) = lightning.params.cltv_expiry
+            {
+                htlc_expiries.push(expiry);
+            }
+
+            all_alerts.append(&mut alerts);
+        }
+    }
+
+    // Cross-transaction clustering analysis
+    let mut cluster_alerts = analyzer::detect_htlc_clustering(&htlc_expiries, config);
+    all_alerts.append(&mut cluster_alerts);
+
+    // Sort by severity (critical first)
+    all_alerts.sort_by_key(|a| std::cmp::Reverse(a.severity));
+    Ok(all_alerts)
+}
+
+/// Timelock and Lightning counts over blocks `start..=end`. Per-transaction results
+/// are folded into the counters and dropped right away; `inspect` sees each of them.
+pub async fn range_stats<S: DataSource + Sync>(
+    client: &S,
+    start: u64,
+    end: u64,
+    detector: &DetectorConfig,
+    progress: &ScanProgress,
+    inspect: impl Fn(&TransactionAnalysis, &LightningClassification) + Sync,
+) -> Result<RangeStats> {
+    let mut stats = RangeStats::default();
+    for h in start..=end {
+        progress.status(&format!("Fetching block {h}..."));
+        let txs = progress.timed(format!("block {h}"), client.get_all_block_txs(h)).await?;
+        let bar = progress.transactions(txs.len());
+        let block_stats = txs
+            .par_iter()
+            .fold(RangeStats::default, |mut acc, tx| {
+                let analysis = analyze_transaction(tx);
+                let lightning = classify_lightning_with_config(tx, detector);
+                inspect(&analysis, &lightning);
+                acc.record(&analysis, &lightning);
+                bar.inc(1);
+                acc
+            })
+            .reduce(RangeStats::default, |mut a, b| {
+                a.add(&b);
+                a
+            });
+        progress.block_done(bar);
+        stats.add(&block_stats);
+    }
+    progress.finish();
+    Ok(stats)
+}
+
+/// How many transactions of a `batch` were fetched and classified, and how many failed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct BatchSummary {
+    pub succeeded: usize,
+    pub failed: usize,
+}
+
+/// Fetch and classify each of `txids` in order, handing `on_result` the classification
+/// or the fetch error. A failed fetch doesn't stop the batch; an error from `on_result`
+/// does.
+pub async fn classify_batch<S: DataSource, E>(
+    client: &S,
+    txids: &[String],
+    tip: Option<&ChainTip>,
+    detector: &DetectorConfig,
+    verbosity: Verbosity,
+    mut on_result: impl FnMut(&str, Result<LightningClassification>) -> std::result::Result<(), E>,
+) -> std::result::Result<BatchSummary, E> {
+    let mut summary = BatchSummary::default();
+    for txid in txids {
+        let fetch = client.get_transaction(txid);
+        match verbosity.timed(format!("tx {txid}"), fetch).await {
+            Ok(tx) => {
+                summary.succeeded += 1;
+                on_result(txid, Ok(classify_at(&tx, tip, detector)))?;
+            }
+            Err(e) => {
+                summary.failed += 1;
+                on_result(txid, Err(e))?;
+            }
+        }
+    }
+    Ok(summary)
+}
+
+/// Classify every mempool transaction, handing each Lightning one at or above
+/// `min_confidence` to `on_hit` as soon as it is classified, since a full mempool takes
+/// a while to fetch. Returns how many were handed over; the first error ends the scan.
+pub async fn scan_mempool<S, E>(
+    client: &S,
+    tip: Option<&ChainTip>,
+    detector: &DetectorConfig,
+    min_confidence: Option<Confidence>,
+    verbosity: Verbosity,
+    mut on_hit: impl FnMut(&str, LightningClassification) -> std::result::Result<(), E>,
+) -> std::result::Result<usize, E>
+where
+    S: DataSource,
+    E: From<Error>,
+{
+    let txids = client.get_mempool_txids().await?;
+    verbosity.status(format!("Classifying {} mempool transactions...", txids.len()));
+    let mut found = 0;
+
+    for txid in &txids {
+        let fetch = client.get_transaction(txid);
+        let tx = match verbosity.timed(format!("tx {txid}"), fetch).await {
+            Ok(tx) => tx,
+            // Confirmed or evicted since the mempool was listed
+            Err(Error::NotFound(_)) => continue,
+            Err(e) => return Err(e.into()),
+        };
+        let lightning = classify_at(&tx, tip, detector);
+        if lightning.tx_type.is_none()
+            || min_confidence.is_some_and(|min| lightning.confidence < min)
+        {
+            continue;
+        }
+        found += 1;
+        on_hit(txid, lightning)?;
+    }
+    verbosity.status(format!(
+        "{} mempool transactions scanned, {found} Lightning-related",
+        txids.len()
+    ));
+    Ok(found)
+}
+
+/// Classify a block's transactions for `lightning block`, in block order: each against
+/// `tip`, confirmed against its funding output when `verify` is set, and commitments
+/// then linked to their same-block sweeps.
+pub async fn classify_block<S: DataSource + Sync>(
+    client: &S,
+    txs: &[ApiTransaction],
+    tip: Option<&ChainTip>,
+    detector: &DetectorConfig,
+    verify: bool,
+    progress: &ScanProgress,
+) -> anyhow::Result<Vec<(String, LightningClassification)>> {
+    let bar = progress.transactions(txs.len());
+    let mut results: Vec<_> = txs
+        .par_iter()
+        .map(|tx| (tx.txid.clone(), classify_at(tx, tip, detector)))
+        .inspect(|_| bar.inc(1))
+        .collect();
+    if verify {
+        for (tx, (_, lc)) in txs.iter().zip(results.iter_mut()) {
+            verify_commitment(client, tx, lc).await?;
+        }
+    }
+    progress.block_done(bar);
+    let classified = results.iter_mut().map(|(_, lc)| lc);
+    channel::link_same_block_sweeps(txs.iter().zip(classified));
+    Ok(results)
+}
+
+/// What `monitor` reports about a mempool transaction.
+#[derive(Debug, Clone)]
+pub struct MonitorHit {
+    pub timelock: TransactionAnalysis,
+    pub lightning: LightningClassification,
+    /// Alerts at or above the requested severity.
+    pub alerts: Vec<Alert>,
+}
+
+/// Analyze a mempool transaction against the tip at `tip_height`. `None` when it has no
+/// alert at or above `min_severity`, no Lightning type and no active timelock.
+pub fn monitor_transaction(
+    tx: &ApiTransaction,
+    tip_height: u64,
+    detector: &DetectorConfig,
+    config: &SecurityConfig,
+    min_severity: Severity,
+) -> Option<MonitorHit> {
+    let tip = ChainTip {
+        height: tip_height,
+        median_time_past: None,
+    };
+    let timelock = analyze_transaction_with_tip(tx, &tip);
+    let lightning = classify_lightning_with_config(tx, detector);
+    let alerts: Vec<_> = analyzer::analyze_transaction(&timelock, &lightning, tip_height, config)
+        .into_iter()
+        .filter(|a| a.severity >= min_severity)
+        .collect();
+
+    let interesting =
+        !alerts.is_empty() || lightning.tx_type.is_some() || timelock.summary.has_active_timelocks;
+    interesting.then_some(MonitorHit {
+        timelock,
+        lightning,
+        alerts,
+    })
+}
+
+/// What `watch` reports about a new block.
+#[derive(Debug, Clone, Default)]
+pub struct WatchedBlock {
+    /// Every transaction's classification, in block order.
+    pub lightning: Vec<(String, LightningClassification)>,
+    /// Timelock analyses in block order, empty unless asked for.
+    pub timelocks: Vec<TransactionAnalysis>,
+}
+
+/// Classify each of a new block's transactions and, with `timelocks`, analyze them.
+pub fn watch_block(txs: &[ApiTransaction], detector: &DetectorConfig, timelocks: bool) -> WatchedBlock {
+    let lightning = txs
+        .par_iter()
+        .map(|tx| (tx.txid.clone(), classify_lightning_with_config(tx, detector)))
+        .collect();
+    let timelocks = if timelocks {
+        txs.par_iter().map(analyze_transaction).collect()
+    } else {
+        Vec::new()
+    };
+    WatchedBlock {
+        lightning,
+        timelocks,
+    }
+}
@@ -1 +1,4 @@
+pub mod commands;
 pub mod output;
+pub mod progress;
+pub mod sort;
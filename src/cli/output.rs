@@ -1,15 +1,144 @@
+use std::cmp::Ordering as CmpOrdering;
+use std::collections::BTreeMap;
+use std::io::{self, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+
 use chrono::Local;
+use owo_colors::{OwoColorize, Style};
+use serde::Serialize;
 
-use crate::lightning::types::{Confidence, LightningClassification, LightningTxType};
+use crate::api::source::SyncStatus;
+use crate::api::types::ApiVin;
+use crate::lightning::types::{
+    ChannelCluster, ChannelType, ClassificationTrace, Confidence, DelaySummary, LightningClassification,
+    LightningTally, LightningTxType, OutputRole,
+};
 use crate::security::types::{Alert, DetectionType, Severity};
-use crate::timelock::types::{SequenceMeaning, TransactionAnalysis};
+use crate::stats::StatsReport;
+use crate::timelock::classify::{format_absolute, format_blocks_approx, format_duration_approx};
+use crate::timelock::types::{
+    ChainTip, RiskSeverity, ScriptTimelock, SequenceMeaning, SpendPath, TimelockDomain,
+    TimelockTally, TransactionAnalysis,
+};
+
+/// How results are written. Every command dispatches on one of these, whichever of
+/// `--format` or its older `--json`, `--ndjson` and `--csv` aliases picked it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Format {
+    #[default]
+    Text,
+    /// One pretty-printed JSON document.
+    Json,
+    /// One compact JSON object per line.
+    Ndjson,
+    /// A header, then one row per transaction.
+    Csv,
+}
+
+/// When to colorize text output.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColorChoice {
+    /// Color only when results go to a terminal and `NO_COLOR` is unset.
+    Auto,
+    Always,
+    Never,
+}
+
+static COLOR_ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// Enable or disable color for the text printers. Off until this is called.
+/// `terminal` says whether results are going to a terminal, which is all
+/// `Auto` looks at besides `NO_COLOR`.
+pub fn set_color(choice: ColorChoice, terminal: bool) {
+    let enabled = match choice {
+        ColorChoice::Always => true,
+        ColorChoice::Never => false,
+        ColorChoice::Auto => terminal && std::env::var_os("NO_COLOR").is_none_or(|v| v.is_empty()),
+    };
+    COLOR_ENABLED.store(enabled, Ordering::Relaxed);
+}
+
+fn paint(text: &str, style: Style) -> String {
+    if COLOR_ENABLED.load(Ordering::Relaxed) {
+        text.style(style).to_string()
+    } else {
+        text.to_string()
+    }
+}
+
+fn confidence_label(confidence: Confidence) -> String {
+    match confidence {
+        Confidence::None => "none".to_string(),
+        Confidence::Possible => paint("possible", Style::new().yellow()),
+        Confidence::HighlyLikely => paint("highly likely", Style::new().red().bold()),
+    }
+}
+
+/// `144 blocks (~1.0 days)`.
+fn blocks_label(blocks: u64) -> String {
+    format!("{blocks} blocks ({})", format_blocks_approx(blocks))
+}
+
+/// Where a block-height CLTV expiry stands relative to the chain tip.
+fn expiry_from_tip(expiry: u32, tip: &ChainTip) -> String {
+    let expiry = u64::from(expiry);
+    match expiry.cmp(&tip.height) {
+        CmpOrdering::Greater => {
+            let blocks = expiry - tip.height;
+            format!("expires in {blocks} blocks, {}", format_blocks_approx(blocks))
+        }
+        CmpOrdering::Less => {
+            let blocks = tip.height - expiry;
+            format!("expired {blocks} blocks ago, {}", format_blocks_approx(blocks))
+        }
+        CmpOrdering::Equal => "expires at the tip".to_string(),
+    }
+}
 
-pub fn print_transaction_analysis(analysis: &TransactionAnalysis) {
-    println!("Transaction: {}", analysis.txid);
-    println!("{}", "─".repeat(72));
+/// Where a timestamp CLTV expiry stands relative to the tip's median time past.
+fn expiry_time_from_tip(expiry: u32, median_time_past: u64) -> String {
+    let expiry = u64::from(expiry);
+    if expiry > median_time_past {
+        format!("expires in {}", format_duration_approx(expiry - median_time_past))
+    } else {
+        format!("expired {} ago", format_duration_approx(median_time_past - expiry))
+    }
+}
+
+fn active_timelocks_label(analysis: &TransactionAnalysis) -> String {
+    let mut parts = Vec::new();
+    if analysis.summary.nlocktime_active {
+        parts.push("nLockTime".to_string());
+    }
+    if analysis.summary.relative_timelock_count > 0 {
+        parts.push(format!("{} nSequence", analysis.summary.relative_timelock_count));
+    }
+    if analysis.summary.cltv_count > 0 {
+        parts.push(format!("{} CLTV", analysis.summary.cltv_count));
+    }
+    if analysis.summary.csv_count > 0 {
+        parts.push(format!("{} CSV", analysis.summary.csv_count));
+    }
+    paint(&parts.join(", "), Style::new().green().bold())
+}
+
+/// Print a timelock analysis. With `input_scripts` (the transaction's inputs), each
+/// input's witness and redeem script disassembly follows its sequence line.
+/// Print a transaction's timelocks. With the transaction's `vins`, each input also
+/// shows the value and type it spends when the source provided prevouts, and its
+/// scripts when `show_scripts` is set.
+pub fn print_transaction_analysis(
+    w: &mut dyn Write,
+    analysis: &TransactionAnalysis,
+    vins: Option<&[ApiVin]>,
+    show_scripts: bool,
+) -> io::Result<()> {
+    writeln!(w, "Transaction: {}", analysis.txid)?;
+    writeln!(w, "{}", "─".repeat(72))?;
 
     // nLockTime
-    println!(
+    writeln!(
+        w,
         "nLockTime:   {} {}",
         analysis.nlocktime.human_readable,
         if analysis.nlocktime.raw_value > 0 {
@@ -17,202 +146,483 @@ pub fn print_transaction_analysis(analysis: &TransactionAnalysis) {
         } else {
             String::new()
         }
-    );
-    println!();
+    )?;
+    writeln!(w)?;
 
     // Inputs / sequences
-    println!("Inputs ({}):", analysis.inputs.len());
+    writeln!(w, "Inputs ({}):", analysis.inputs.len())?;
     for input in &analysis.inputs {
         let meaning = match &input.meaning {
-            SequenceMeaning::Final => "final".to_string(),
+            SequenceMeaning::Final if input.raw_value == 0xFFFFFFFF => "final".to_string(),
+            SequenceMeaning::Final => "final (BIP 68 disabled, locktime enabled)".to_string(),
             SequenceMeaning::LocktimeEnabled => "locktime enabled".to_string(),
             SequenceMeaning::RbfEnabled => "RBF + locktime".to_string(),
             SequenceMeaning::RelativeTimelock => {
                 if let Some(ref rtl) = input.relative_timelock {
-                    format!("relative timelock: {}", rtl.human_readable)
+                    rtl.human_readable.clone()
                 } else {
                     "relative timelock".to_string()
                 }
             }
-            SequenceMeaning::NonStandard => "non-standard".to_string(),
         };
-        println!("  [{}] {} — {}", input.input_index, input.raw_hex, meaning);
+        let vin = vins.and_then(|vins| vins.get(input.input_index));
+        let spent = match vin.and_then(|vin| vin.prevout.as_ref()) {
+            Some(prevout) => {
+                format!(" (spends {} sats, {})", prevout.value, prevout.scriptpubkey_type)
+            }
+            None => String::new(),
+        };
+        let path = match input.spend_path {
+            Some(SpendPath::KeyPath) => " [taproot key path]",
+            Some(SpendPath::ScriptPath) => " [taproot script path]",
+            None => "",
+        };
+        writeln!(w, "  [{}] {} — {}{}{}", input.input_index, input.raw_hex, meaning, spent, path)?;
+        if let Some(vin) = vin.filter(|_| show_scripts) {
+            print_vin_scripts(w, vin, "      ")?;
+        }
     }
 
     // CLTV
     if !analysis.cltv_timelocks.is_empty() {
-        println!();
-        println!("OP_CHECKLOCKTIMEVERIFY ({}):", analysis.cltv_timelocks.len());
+        writeln!(w)?;
+        writeln!(w, "OP_CHECKLOCKTIMEVERIFY ({}):", analysis.cltv_timelocks.len())?;
         for tl in &analysis.cltv_timelocks {
-            println!(
+            writeln!(
+                w,
                 "  input[{}] {}: {} (raw: {})",
                 tl.input_index, tl.script_field, tl.human_readable, tl.raw_value
-            );
+            )?;
         }
     }
 
     // CSV
     if !analysis.csv_timelocks.is_empty() {
-        println!();
-        println!("OP_CHECKSEQUENCEVERIFY ({}):", analysis.csv_timelocks.len());
+        writeln!(w)?;
+        writeln!(w, "OP_CHECKSEQUENCEVERIFY ({}):", analysis.csv_timelocks.len())?;
         for tl in &analysis.csv_timelocks {
-            println!(
+            writeln!(
+                w,
                 "  input[{}] {}: {} (raw: {})",
                 tl.input_index, tl.script_field, tl.human_readable, tl.raw_value
-            );
+            )?;
         }
     }
 
     // Summary
-    println!();
+    writeln!(w)?;
     if analysis.summary.has_active_timelocks {
-        let mut parts = Vec::new();
-        if analysis.summary.nlocktime_active {
-            parts.push("nLockTime".to_string());
-        }
-        if analysis.summary.relative_timelock_count > 0 {
-            parts.push(format!("{} nSequence", analysis.summary.relative_timelock_count));
-        }
-        if analysis.summary.cltv_count > 0 {
-            parts.push(format!("{} CLTV", analysis.summary.cltv_count));
-        }
-        if analysis.summary.csv_count > 0 {
-            parts.push(format!("{} CSV", analysis.summary.csv_count));
-        }
-        println!("Active timelocks: {}", parts.join(", "));
+        writeln!(w, "Active timelocks: {}", active_timelocks_label(analysis))?;
     } else {
-        println!("No active timelocks.");
+        writeln!(w, "No active timelocks.")?;
+    }
+    if analysis.summary.nlocktime_ineffective {
+        writeln!(w, "  nLockTime is ineffective: every input sequence is final (0xFFFFFFFF)")?;
     }
+    for i in &analysis.summary.cltv_and_csv_inputs {
+        writeln!(w, "  input[{i}] mixes an absolute (CLTV) and a relative (CSV) timelock")?;
+    }
+
+    // Risk findings
+    if !analysis.risk.findings.is_empty() {
+        writeln!(w)?;
+        writeln!(w, "Risk ({}):", analysis.risk.findings.len())?;
+        for finding in &analysis.risk.findings {
+            let severity = match finding.severity {
+                RiskSeverity::High => "HIGH  ",
+                RiskSeverity::Medium => "MEDIUM",
+                RiskSeverity::Low => "LOW   ",
+                RiskSeverity::Info => "INFO  ",
+            };
+            let location = finding
+                .input_index
+                .map(|i| format!("input[{i}] "))
+                .unwrap_or_default();
+            writeln!(w, "  [{severity}] {location}{}", finding.reason)?;
+        }
+    }
+    Ok(())
+}
+
+/// With `tip`, CLTV expiries are shown relative to the chain tip.
+/// Print the witness and redeem script disassembly of each input that has one;
+/// prints nothing when no input does.
+pub fn print_input_scripts(w: &mut dyn Write, vins: &[ApiVin]) -> io::Result<()> {
+    if !vins.iter().any(has_script_asm) {
+        return Ok(());
+    }
+    writeln!(w)?;
+    writeln!(w, "Input scripts:")?;
+    for (i, vin) in vins.iter().enumerate().filter(|(_, v)| has_script_asm(v)) {
+        writeln!(w, "  [{i}]")?;
+        print_vin_scripts(w, vin, "      ")?;
+    }
+    Ok(())
+}
+
+fn has_script_asm(vin: &ApiVin) -> bool {
+    vin.inner_witnessscript_asm.is_some() || vin.inner_redeemscript_asm.is_some()
 }
 
-pub fn print_lightning_classification(txid: &str, lc: &LightningClassification) {
-    println!("Transaction: {txid}");
-    println!("{}", "─".repeat(72));
+fn print_vin_scripts(w: &mut dyn Write, vin: &ApiVin, indent: &str) -> io::Result<()> {
+    if let Some(asm) = &vin.inner_witnessscript_asm {
+        writeln!(w, "{indent}witness script: {asm}")?;
+    }
+    if let Some(asm) = &vin.inner_redeemscript_asm {
+        writeln!(w, "{indent}redeem script:  {asm}")?;
+    }
+    Ok(())
+}
+
+pub fn print_lightning_classification(
+    w: &mut dyn Write,
+    txid: &str,
+    lc: &LightningClassification,
+    tip: Option<&ChainTip>,
+) -> io::Result<()> {
+    writeln!(w, "Transaction: {txid}")?;
+    writeln!(w, "{}", "─".repeat(72))?;
 
     match lc.tx_type {
-        None => println!("Lightning: not identified"),
+        None => writeln!(w, "Lightning: not identified")?,
         Some(ref t) => {
             let type_str = match t {
                 LightningTxType::Commitment => "Commitment (force-close)",
                 LightningTxType::HtlcTimeout => "HTLC-timeout (refund)",
                 LightningTxType::HtlcSuccess => "HTLC-success (claim)",
+                LightningTxType::MutualClose => "Mutual close (cooperative)",
+                LightningTxType::Penalty => "Penalty (revoked commitment sweep)",
+                LightningTxType::ToLocalSweep => "to_local sweep (delayed self-sweep)",
+                LightningTxType::AnchorSweep => "Anchor sweep (CPFP fee bump)",
+                LightningTxType::Splice => "Splice (channel resize)",
             };
-            let conf = match lc.confidence {
-                Confidence::None => "none",
-                Confidence::Possible => "possible",
-                Confidence::HighlyLikely => "highly likely",
-            };
-            println!("Lightning:   {type_str} [{conf}]");
+            writeln!(w, "Lightning:   {type_str} [{}]", confidence_label(lc.confidence))?;
+        }
+    }
+    if !lc.reasons.is_empty() {
+        writeln!(w, "Reasons:")?;
+        for reason in &lc.reasons {
+            writeln!(w, "  - {reason}")?;
         }
     }
 
     // Commitment signals
     let s = &lc.commitment_signals;
     if s.locktime_match || s.sequence_match || s.has_anchor_outputs {
-        println!();
-        println!("Commitment signals:");
+        writeln!(w)?;
+        writeln!(w, "Commitment signals:")?;
         if s.locktime_match {
-            println!("  locktime in 0x20 range (Lightning encoding)");
+            writeln!(w, "  locktime in 0x20 range (Lightning encoding)")?;
         }
         if s.sequence_match {
-            println!("  sequence with 0x80 upper byte");
+            writeln!(w, "  sequence with 0x80 upper byte")?;
+        }
+        if s.is_taproot {
+            writeln!(w, "  all outputs P2TR (simple taproot channel)")?;
         }
         if s.has_anchor_outputs {
-            println!("  {} anchor output(s) (330 sats)", s.anchor_output_count);
+            writeln!(
+                w,
+                "  {} anchor output(s) ({} sats)",
+                s.anchor_output_count,
+                s.anchor_value.unwrap_or_default()
+            )?;
+        }
+        let channel_type = match s.channel_type {
+            ChannelType::Legacy => Some("legacy (pre-anchor)"),
+            ChannelType::Anchor => Some("anchor outputs"),
+            ChannelType::Taproot => Some("simple taproot"),
+            ChannelType::Unknown => None,
+        };
+        if let Some(channel_type) = channel_type {
+            writeln!(w, "  channel type: {channel_type}")?;
         }
     }
 
+    // Mutual close signals
+    let m = &lc.mutual_close_signals;
+    if m.two_of_two_input {
+        writeln!(w)?;
+        writeln!(w, "Mutual close signals:")?;
+        writeln!(w, "  single 2-of-2 multisig input (funding output spend)")?;
+        if m.locktime_zero && m.final_sequences {
+            writeln!(w, "  nLockTime 0 with final sequences")?;
+        }
+        writeln!(w, "  {} key-path output(s) (P2WPKH/P2TR)", m.key_path_output_count)?;
+    }
+
     // Extracted parameters
     let p = &lc.params;
     let has_params = p.commitment_number.is_some()
         || p.cltv_expiry.is_some()
+        || p.cltv_expiry_time.is_some()
         || p.preimage_revealed
+        || p.payment_hash.is_some()
         || !p.csv_delays.is_empty()
-        || p.htlc_output_count.is_some();
+        || p.to_self_delay.is_some()
+        || p.htlc_output_count.is_some()
+        || p.approx_channel_capacity.is_some()
+        || p.swept_output_count.is_some()
+        || p.parent_txid.is_some()
+        || p.funding_outpoint.is_some()
+        || p.same_block_commitment.is_some()
+        || !p.same_block_htlc_spends.is_empty()
+        || p.new_funding_value.is_some()
+        || p.possible_trimmed_htlcs;
 
     if has_params {
-        println!();
-        println!("Parameters:");
+        writeln!(w)?;
+        writeln!(w, "Parameters:")?;
         if let Some(cn) = p.commitment_number {
-            println!("  commitment number: {cn} (obscured)");
+            writeln!(w, "  commitment number: {}", format_obscured_commitment_number(cn))?;
+        }
+        if let Some(decoded) = p.commitment_number_decoded {
+            writeln!(w, "  commitment number: {decoded} (decoded)")?;
         }
         if let Some(count) = p.htlc_output_count {
-            println!("  HTLC outputs: {count}");
+            match (p.offered_htlc_count, p.received_htlc_count) {
+                (Some(offered), Some(received)) => {
+                    writeln!(w, "  HTLC outputs: {count} ({offered} offered, {received} received)")?
+                }
+                _ => writeln!(w, "  HTLC outputs: {count}")?,
+            }
+        }
+        if p.possible_trimmed_htlcs {
+            writeln!(w, "  possible trimmed HTLCs (high fee for its weight)")?;
+        }
+        if let Some(capacity) = p.approx_channel_capacity {
+            writeln!(w, "  channel capacity: ~{capacity} sats")?;
         }
         if let Some(expiry) = p.cltv_expiry {
-            println!("  CLTV expiry: block {expiry}");
+            match tip {
+                Some(tip) => {
+                    writeln!(w, "  CLTV expiry: block {expiry} ({})", expiry_from_tip(expiry, tip))?
+                }
+                None => writeln!(w, "  CLTV expiry: block {expiry}")?,
+            }
+        }
+        if let Some(expiry) = p.cltv_expiry_time {
+            let absolute = format_absolute(expiry as u64, TimelockDomain::Timestamp);
+            match tip.and_then(|t| t.median_time_past) {
+                Some(mtp) => {
+                    writeln!(w, "  CLTV expiry: {absolute} ({})", expiry_time_from_tip(expiry, mtp))?
+                }
+                None => writeln!(w, "  CLTV expiry: {absolute}")?,
+            }
+        }
+        if let Some(swept) = p.swept_output_count {
+            writeln!(w, "  revoked outputs swept: {swept}")?;
+        }
+        if let Some(ref parent) = p.parent_txid {
+            writeln!(w, "  parent commitment: {parent}")?;
+        }
+        if let Some(ref outpoint) = p.funding_outpoint {
+            writeln!(w, "  funding output: {outpoint} (verified)")?;
+        }
+        if let Some(ref commitment) = p.same_block_commitment {
+            writeln!(w, "  spends commitment {commitment} from the same block")?;
+        }
+        for spend in &p.same_block_htlc_spends {
+            writeln!(w, "  HTLC spent in the same block by {spend}")?;
+        }
+        if let Some(new) = p.new_funding_value {
+            match p.old_funding_value {
+                Some(old) => writeln!(w, "  funding value: {old} → {new} sats")?,
+                None => writeln!(w, "  new funding value: {new} sats")?,
+            }
         }
         if p.preimage_revealed {
-            if let Some(ref pre) = p.preimage {
-                println!("  preimage: {pre}");
+            let preimage_style = Style::new().magenta().bold();
+            if p.preimages.len() > 1 {
+                writeln!(w, "  preimages ({}):", p.preimages.len())?;
+                for pre in &p.preimages {
+                    writeln!(w, "    {}", paint(pre, preimage_style))?;
+                }
+            } else if let Some(ref pre) = p.preimage {
+                writeln!(w, "  preimage: {}", paint(pre, preimage_style))?;
             } else {
-                println!("  preimage: revealed");
+                writeln!(w, "  preimage: {}", paint("revealed", preimage_style))?;
             }
         }
+        if let Some(ref hash) = p.payment_hash {
+            writeln!(w, "  payment hash: {hash}")?;
+        }
         if !p.csv_delays.is_empty() {
-            let delays: Vec<String> = p.csv_delays.iter().map(|d| format!("{d} blocks")).collect();
-            println!("  CSV delays: {}", delays.join(", "));
+            let delays: Vec<String> = p.csv_delays.iter().map(|d| blocks_label(u64::from(*d))).collect();
+            writeln!(w, "  CSV delays: {}", delays.join(", "))?;
+        }
+        if let Some(delay) = p.to_self_delay {
+            writeln!(w, "  to_self_delay: {}", blocks_label(u64::from(delay)))?;
+        }
+    }
+
+    if p.htlc_inputs.len() > 1 {
+        writeln!(w)?;
+        writeln!(w, "HTLC inputs:")?;
+        for input in &p.htlc_inputs {
+            let kind = match input.tx_type {
+                LightningTxType::HtlcSuccess => "success (preimage)",
+                _ => "timeout",
+            };
+            let mut line = format!("  input[{}]: {kind}", input.input_index);
+            if let Some(expiry) = input.cltv_expiry {
+                line.push_str(&format!(", CLTV {expiry}"));
+                if let Some(tip) = tip {
+                    line.push_str(&format!(" ({})", expiry_from_tip(expiry, tip)));
+                }
+            }
+            if !input.csv_delays.is_empty() {
+                let delays: Vec<String> = input.csv_delays.iter().map(u16::to_string).collect();
+                line.push_str(&format!(", CSV {}", delays.join("/")));
+            }
+            writeln!(w, "{line}")?;
+        }
+    }
+
+    if !p.output_roles.is_empty() {
+        writeln!(w)?;
+        writeln!(w, "Outputs:")?;
+        for (i, role) in p.output_roles.iter().enumerate() {
+            let role_str = match role {
+                OutputRole::ToLocal => "to_local",
+                OutputRole::ToRemote => "to_remote",
+                OutputRole::Htlc => "HTLC",
+                OutputRole::Anchor => "anchor",
+                OutputRole::Taproot => "taproot (to_local, to_remote or HTLC)",
+                OutputRole::Other => "other",
+            };
+            writeln!(w, "  vout[{i}]: {role_str}")?;
         }
     }
+    Ok(())
 }
 
+/// The classifier's checks in order, for `lightning tx --explain`.
+pub fn print_classification_trace(
+    w: &mut dyn Write,
+    trace: &ClassificationTrace,
+) -> io::Result<()> {
+    writeln!(w, "Classification trace:")?;
+    for (i, step) in trace.steps.iter().enumerate() {
+        let outcome = if step.matched {
+            paint("match", Style::new().green().bold())
+        } else {
+            "no match".to_string()
+        };
+        writeln!(w, "  {}. {}: {outcome}", i + 1, step.check)?;
+        for detail in &step.details {
+            writeln!(w, "       {detail}")?;
+        }
+    }
+    if !trace.steps.iter().any(|s| s.matched) {
+        writeln!(w, "  no check matched: not a Lightning transaction")?;
+    }
+    Ok(())
+}
+
+/// `scanned` is the number of transactions in the block; `results` may be a filtered subset.
 pub fn print_lightning_block_summary(
+    w: &mut dyn Write,
     height: u64,
+    scanned: usize,
     results: &[(String, LightningClassification)],
-) {
+    tip: Option<&ChainTip>,
+) -> io::Result<()> {
     let lightning_txs: Vec<_> = results.iter().filter(|(_, lc)| lc.tx_type.is_some()).collect();
 
     let commitments = lightning_txs.iter().filter(|(_, lc)| lc.tx_type == Some(LightningTxType::Commitment)).count();
     let htlc_timeouts = lightning_txs.iter().filter(|(_, lc)| lc.tx_type == Some(LightningTxType::HtlcTimeout)).count();
     let htlc_successes = lightning_txs.iter().filter(|(_, lc)| lc.tx_type == Some(LightningTxType::HtlcSuccess)).count();
+    let mutual_closes = lightning_txs.iter().filter(|(_, lc)| lc.tx_type == Some(LightningTxType::MutualClose)).count();
+    let penalties = lightning_txs.iter().filter(|(_, lc)| lc.tx_type == Some(LightningTxType::Penalty)).count();
+    let to_local_sweeps = lightning_txs.iter().filter(|(_, lc)| lc.tx_type == Some(LightningTxType::ToLocalSweep)).count();
+    let anchor_sweeps = lightning_txs.iter().filter(|(_, lc)| lc.tx_type == Some(LightningTxType::AnchorSweep)).count();
+    let splices = lightning_txs.iter().filter(|(_, lc)| lc.tx_type == Some(LightningTxType::Splice)).count();
 
-    println!("Block {height} — Lightning Activity");
-    println!("{}", "═".repeat(72));
-    println!(
+    writeln!(w, "Block {height} — Lightning Activity")?;
+    writeln!(w, "{}", "═".repeat(72))?;
+    writeln!(
+        w,
         "{} transactions scanned, {} Lightning-related",
-        results.len(),
+        scanned,
         lightning_txs.len()
-    );
+    )?;
 
     if !lightning_txs.is_empty() {
-        println!(
-            "  {} commitment (force-close), {} HTLC-timeout, {} HTLC-success",
-            commitments, htlc_timeouts, htlc_successes
-        );
+        writeln!(
+            w,
+            "  {} commitment (force-close), {} HTLC-timeout, {} HTLC-success, {} mutual close, {} penalty, {} to_local sweep, {} anchor sweep, {} splice",
+            commitments, htlc_timeouts, htlc_successes, mutual_closes, penalties, to_local_sweeps, anchor_sweeps, splices
+        )?;
+        let mut delays = BTreeMap::new();
+        for delay in lightning_txs.iter().flat_map(|(_, lc)| &lc.params.csv_delays) {
+            *delays.entry(*delay).or_insert(0) += 1;
+        }
+        print_delay_summary(w, DelaySummary::from_distribution(&delays))?;
+        let immediate = lightning_txs.iter().filter(|(_, lc)| lc.params.same_block_commitment.is_some()).count();
+        if immediate > 0 {
+            writeln!(w, "  {immediate} HTLC transaction(s) sweep a commitment from this same block")?;
+        }
     }
-    println!();
+    writeln!(w)?;
 
     if lightning_txs.is_empty() {
-        println!("No Lightning transactions identified in this block.");
-        return;
+        writeln!(w, "No Lightning transactions identified in this block.")?;
+        return Ok(());
     }
 
     for (txid, lc) in &lightning_txs {
-        print_lightning_classification(txid, lc);
-        println!();
+        print_lightning_classification(w, txid, lc, tip)?;
+        writeln!(w)?;
+    }
+    Ok(())
+}
+
+/// The commitments of a block that HTLC transactions in it spend from, one line each.
+pub fn print_channel_clusters(w: &mut dyn Write, clusters: &[ChannelCluster]) -> io::Result<()> {
+    if clusters.is_empty() {
+        return Ok(());
+    }
+    writeln!(w, "Channels observed ({}):", clusters.len())?;
+    for cluster in clusters {
+        let count = |n: usize, one: &str, many: &str| match n {
+            1 => format!("1 {one}"),
+            n => format!("{n} {many}"),
+        };
+        writeln!(
+            w,
+            "  Channel (funding {}): 1 commitment, {}, {}",
+            cluster.funding_outpoint.as_deref().unwrap_or("unknown"),
+            count(cluster.htlc_timeouts.len(), "HTLC-timeout", "HTLC-timeouts"),
+            count(cluster.htlc_successes.len(), "HTLC-success", "HTLC-successes"),
+        )?;
+        writeln!(w, "    commitment {}", cluster.commitment_txid)?;
     }
+    writeln!(w)?;
+    Ok(())
 }
 
 pub fn print_monitor_hit(
+    w: &mut dyn Write,
     analysis: &TransactionAnalysis,
     lightning: &LightningClassification,
     alerts: &[Alert],
-) {
+) -> io::Result<()> {
     let now = Local::now().format("%H:%M:%S");
-    println!("[{now}] {}", analysis.txid);
+    writeln!(w, "[{now}] {}", analysis.txid)?;
 
     if let Some(ref t) = lightning.tx_type {
         let type_str = match t {
             LightningTxType::Commitment => "commitment (force-close)",
             LightningTxType::HtlcTimeout => "HTLC-timeout",
             LightningTxType::HtlcSuccess => "HTLC-success",
+            LightningTxType::MutualClose => "mutual close (cooperative)",
+            LightningTxType::Penalty => "penalty (revoked commitment sweep)",
+            LightningTxType::ToLocalSweep => "to_local sweep (delayed self-sweep)",
+            LightningTxType::AnchorSweep => "anchor sweep (CPFP fee bump)",
+            LightningTxType::Splice => "splice (channel resize)",
         };
-        let conf = match lightning.confidence {
-            Confidence::None => "none",
-            Confidence::Possible => "possible",
-            Confidence::HighlyLikely => "highly likely",
-        };
-        println!("  ⚡ Lightning: {type_str} [{conf}]");
+        writeln!(w, "  ⚡ Lightning: {type_str} [{}]", confidence_label(lightning.confidence))?;
     }
 
     for alert in alerts {
@@ -226,79 +636,248 @@ pub fn print_monitor_hit(
             DetectionType::ShortCltvDelta => "short-cltv-delta",
             DetectionType::HtlcClustering => "htlc-clustering",
             DetectionType::AnomalousSequence => "anomalous-sequence",
+            DetectionType::HtlcTimeoutMaturity => "htlc-timeout-maturity",
         };
-        println!("  [{severity_tag}] {detection}: {}", alert.description);
+        writeln!(w, "  [{severity_tag}] {detection}: {}", alert.description)?;
     }
 
     if analysis.summary.has_active_timelocks {
-        let mut parts = Vec::new();
-        if analysis.summary.nlocktime_active {
-            parts.push("nLockTime".to_string());
-        }
-        if analysis.summary.relative_timelock_count > 0 {
-            parts.push(format!("{} nSequence", analysis.summary.relative_timelock_count));
-        }
-        if analysis.summary.cltv_count > 0 {
-            parts.push(format!("{} CLTV", analysis.summary.cltv_count));
-        }
-        if analysis.summary.csv_count > 0 {
-            parts.push(format!("{} CSV", analysis.summary.csv_count));
-        }
-        println!("  timelocks: {}", parts.join(", "));
+        writeln!(w, "  timelocks: {}", active_timelocks_label(analysis))?;
+    }
+    for i in &analysis.summary.cltv_and_csv_inputs {
+        writeln!(w, "  input[{i}] mixes CLTV and CSV")?;
     }
 
-    println!();
+    writeln!(w)?;
+    Ok(())
 }
 
-pub fn print_block_summary(height: u64, analyses: &[TransactionAnalysis]) {
+pub fn print_block_summary(
+    w: &mut dyn Write,
+    height: u64,
+    analyses: &[TransactionAnalysis],
+) -> io::Result<()> {
     let total = analyses.len();
     let with_timelocks: Vec<_> = analyses.iter().filter(|a| a.summary.has_active_timelocks).collect();
 
-    println!("Block {height}");
-    println!("{}", "═".repeat(72));
-    println!(
+    writeln!(w, "Block {height}")?;
+    writeln!(w, "{}", "═".repeat(72))?;
+    writeln!(
+        w,
         "{total} transactions, {} with active timelocks",
         with_timelocks.len()
-    );
-    println!();
+    )?;
+    writeln!(w)?;
 
     if with_timelocks.is_empty() {
-        println!("No active timelocks found in this block.");
-        return;
+        writeln!(w, "No active timelocks found in this block.")?;
+        return Ok(());
     }
 
     for analysis in &with_timelocks {
-        print_transaction_analysis(analysis);
-        println!();
+        print_transaction_analysis(w, analysis, None, false)?;
+        writeln!(w)?;
     }
+    Ok(())
 }
 
-pub fn print_security_scan(start: u64, end: u64, alerts: &[Alert]) {
+pub fn print_block_range_totals(
+    w: &mut dyn Write,
+    start: u64,
+    end: u64,
+    blocks: &[(u64, TimelockTally)],
+) -> io::Result<()> {
+    let mut total = TimelockTally::default();
+    for (_, tally) in blocks {
+        total.add(tally);
+    }
+
+    writeln!(w, "Blocks {start}–{end} — Totals")?;
+    writeln!(w, "{}", "═".repeat(72))?;
+    for (height, tally) in blocks {
+        writeln!(
+            w,
+            "  {height}: {} transactions, {} with active timelocks",
+            tally.transactions, tally.with_timelocks
+        )?;
+    }
+    writeln!(w, "{}", "─".repeat(72))?;
+    writeln!(
+        w,
+        "{} blocks, {} transactions, {} with active timelocks",
+        blocks.len(),
+        total.transactions,
+        total.with_timelocks
+    )?;
+    Ok(())
+}
+
+pub fn print_lightning_range_totals(
+    w: &mut dyn Write,
+    start: u64,
+    end: u64,
+    blocks: &[(u64, LightningTally)],
+) -> io::Result<()> {
+    let mut total = LightningTally::default();
+    for (_, tally) in blocks {
+        total.add(tally);
+    }
+
+    writeln!(w, "Blocks {start}–{end} — Lightning Totals")?;
+    writeln!(w, "{}", "═".repeat(72))?;
+    for (height, tally) in blocks {
+        writeln!(
+            w,
+            "  {height}: {} transactions, {} Lightning-related",
+            tally.transactions,
+            tally.lightning()
+        )?;
+    }
+    writeln!(w, "{}", "─".repeat(72))?;
+    writeln!(
+        w,
+        "{} blocks, {} transactions scanned, {} Lightning-related",
+        blocks.len(),
+        total.transactions,
+        total.lightning()
+    )?;
+    writeln!(
+        w,
+        "  {} commitment (force-close), {} HTLC-timeout, {} HTLC-success, {} mutual close, {} penalty, {} to_local sweep, {} anchor sweep, {} splice",
+        total.commitments,
+        total.htlc_timeouts,
+        total.htlc_successes,
+        total.mutual_closes,
+        total.penalties,
+        total.to_local_sweeps,
+        total.anchor_sweeps,
+        total.splices
+    )?;
+    print_delay_summary(w, total.csv_delay_summary())?;
+    Ok(())
+}
+
+fn print_delay_summary(w: &mut dyn Write, summary: Option<DelaySummary>) -> io::Result<()> {
+    if let Some(s) = summary {
+        writeln!(
+            w,
+            "  CSV delays (blocks): {} seen, min {}, median {}, max {}, most common {}",
+            s.count, s.min, s.median, s.max, s.most_common
+        )?;
+    }
+    Ok(())
+}
+
+pub fn print_stats_report(w: &mut dyn Write, report: &StatsReport) -> io::Result<()> {
+    let l = &report.lightning;
+    writeln!(w, "Blocks {}–{} — Statistics", report.start_height, report.end_height)?;
+    writeln!(w, "{}", "═".repeat(72))?;
+    writeln!(
+        w,
+        "{} transactions, {} with active timelocks ({:.1}%)",
+        report.transactions, report.with_active_timelocks, report.active_timelock_pct
+    )?;
+    writeln!(
+        w,
+        "{} Lightning-related: {} commitment, {} HTLC-timeout, {} HTLC-success, {} mutual close, {} penalty, {} to_local sweep, {} anchor sweep, {} splice",
+        l.lightning(),
+        l.commitments,
+        l.htlc_timeouts,
+        l.htlc_successes,
+        l.mutual_closes,
+        l.penalties,
+        l.to_local_sweeps,
+        l.anchor_sweeps,
+        l.splices
+    )?;
+    match report.avg_htlc_outputs_per_commitment {
+        Some(avg) => writeln!(w, "Average HTLC outputs per commitment: {avg:.2}")?,
+        None => writeln!(w, "Average HTLC outputs per commitment: n/a")?,
+    }
+    writeln!(w, "Preimages revealed: {}", report.preimages_revealed)?;
+
+    print_histogram(w, "to_self_delay (blocks)", &report.to_self_delay_distribution)?;
+    print_histogram(w, "CSV delays (blocks)", &report.csv_delay_distribution)?;
+    Ok(())
+}
+
+const HISTOGRAM_WIDTH: usize = 40;
+
+fn print_histogram(
+    w: &mut dyn Write,
+    title: &str,
+    distribution: &BTreeMap<u16, usize>,
+) -> io::Result<()> {
+    writeln!(w)?;
+    writeln!(w, "{title}:")?;
+    if distribution.is_empty() {
+        writeln!(w, "  (none)")?;
+        return Ok(());
+    }
+    let max = distribution.values().copied().max().unwrap_or(1);
+    for (value, count) in distribution {
+        let bar = (count * HISTOGRAM_WIDTH).div_ceil(max);
+        writeln!(w, "  {value:>6} │{} {count}", "█".repeat(bar))?;
+    }
+    Ok(())
+}
+
+/// Data source, chain tip and sync state, for the `info` command.
+pub fn print_source_info(
+    w: &mut dyn Write,
+    source: &str,
+    tip_height: u64,
+    sync: Option<&SyncStatus>,
+) -> io::Result<()> {
+    writeln!(w, "Source:     {source}")?;
+    writeln!(w, "Tip height: {tip_height}")?;
+    let synced = match sync {
+        None => "unknown (the source doesn't report it)".to_string(),
+        Some(SyncStatus { synced: true, .. }) => paint("yes", Style::new().green()),
+        Some(SyncStatus { synced: false, progress: Some(progress) }) => paint(
+            &format!("no, initial block download {:.1}% done", progress * 100.0),
+            Style::new().yellow(),
+        ),
+        Some(SyncStatus { synced: false, progress: None }) => {
+            paint("no, initial block download in progress", Style::new().yellow())
+        }
+    };
+    writeln!(w, "Synced:     {synced}")?;
+    Ok(())
+}
+
+pub fn print_security_scan(
+    w: &mut dyn Write,
+    start: u64,
+    end: u64,
+    alerts: &[Alert],
+) -> io::Result<()> {
     let range = if start == end {
         format!("block {start}")
     } else {
         format!("blocks {start}–{end}")
     };
 
-    println!("Security Scan: {range}");
-    println!("{}", "═".repeat(72));
+    writeln!(w, "Security Scan: {range}")?;
+    writeln!(w, "{}", "═".repeat(72))?;
 
     let critical = alerts.iter().filter(|a| a.severity == Severity::Critical).count();
     let warning = alerts.iter().filter(|a| a.severity == Severity::Warning).count();
     let info = alerts.iter().filter(|a| a.severity == Severity::Informational).count();
 
-    println!(
+    writeln!(
+        w,
         "{} alerts: {} critical, {} warning, {} informational",
         alerts.len(),
         critical,
         warning,
         info
-    );
-    println!();
+    )?;
+    writeln!(w)?;
 
     if alerts.is_empty() {
-        println!("No security findings in {range}.");
-        return;
+        writeln!(w, "No security findings in {range}.")?;
+        return Ok(());
     }
 
     for alert in alerts {
@@ -312,24 +891,170 @@ pub fn print_security_scan(start: u64, end: u64, alerts: &[Alert]) {
             DetectionType::ShortCltvDelta => "short-cltv-delta",
             DetectionType::HtlcClustering => "htlc-clustering",
             DetectionType::AnomalousSequence => "anomalous-sequence",
+            DetectionType::HtlcTimeoutMaturity => "htlc-timeout-maturity",
         };
 
-        println!("[{severity_tag}] {detection}");
+        writeln!(w, "[{severity_tag}] {detection}")?;
         if !alert.txid.is_empty() {
-            print!("  tx: {}", alert.txid);
+            write!(w, "  tx: {}", alert.txid)?;
             if let Some(idx) = alert.input_index {
-                print!(" input[{idx}]");
+                write!(w, " input[{idx}]")?;
             }
-            println!();
+            writeln!(w)?;
         }
-        println!("  {}", alert.description);
+        writeln!(w, "  {}", alert.description)?;
         if let Some(ref reference) = alert.reference {
-            print!("  ref: {} ({}, {})", reference.name, reference.authors, reference.year);
+            write!(w, "  ref: {} ({}, {})", reference.name, reference.authors, reference.year)?;
             if let Some(ref url) = reference.url {
-                print!(" {url}");
+                write!(w, " {url}")?;
             }
-            println!();
+            writeln!(w)?;
         }
-        println!();
+        writeln!(w)?;
+    }
+    Ok(())
+}
+
+/// Write `value` as a single compact JSON line, for streaming output (`--ndjson`).
+pub fn write_ndjson<W: Write + ?Sized, T: Serialize>(writer: &mut W, value: &T) -> io::Result<()> {
+    serde_json::to_writer(&mut *writer, value)?;
+    writeln!(writer)
+}
+
+/// Write `value` pretty-printed for [`Format::Json`], or as one line for the others.
+pub fn write_json<T: Serialize>(w: &mut dyn Write, format: Format, value: &T) -> io::Result<()> {
+    if format != Format::Json {
+        return write_ndjson(w, value);
     }
+    serde_json::to_writer_pretty(&mut *w, value)?;
+    writeln!(w)
+}
+
+/// One `--csv` row of `lightning block` output. `None` fields render as empty cells.
+#[derive(Serialize)]
+struct LightningCsvRow<'a> {
+    txid: &'a str,
+    tx_type: Option<LightningTxType>,
+    confidence: Confidence,
+    commitment_number: Option<u64>,
+    htlc_output_count: Option<usize>,
+    cltv_expiry: Option<u32>,
+    csv_delays: String,
+    anchor_count: usize,
+}
+
+pub fn write_lightning_csv<W: Write>(
+    writer: &mut csv::Writer<W>,
+    txid: &str,
+    lc: &LightningClassification,
+) -> csv::Result<()> {
+    writer.serialize(LightningCsvRow {
+        txid,
+        tx_type: lc.tx_type,
+        confidence: lc.confidence,
+        commitment_number: lc.params.commitment_number,
+        htlc_output_count: lc.params.htlc_output_count,
+        cltv_expiry: lc.params.cltv_expiry,
+        csv_delays: join_values(&lc.params.csv_delays),
+        anchor_count: lc.commitment_signals.anchor_output_count,
+    })
+}
+
+/// One `--csv` row of `block` output.
+#[derive(Serialize)]
+struct TimelockCsvRow<'a> {
+    txid: &'a str,
+    nlocktime: u32,
+    nlocktime_active: bool,
+    relative_timelock_count: usize,
+    cltv_values: String,
+    csv_values: String,
+    max_risk: Option<RiskSeverity>,
+}
+
+pub fn write_timelock_csv<W: Write>(
+    writer: &mut csv::Writer<W>,
+    analysis: &TransactionAnalysis,
+) -> csv::Result<()> {
+    let raw_values = |tls: &[ScriptTimelock]| tls.iter().map(|tl| tl.raw_value).collect::<Vec<_>>();
+    writer.serialize(TimelockCsvRow {
+        txid: &analysis.txid,
+        nlocktime: analysis.nlocktime.raw_value,
+        nlocktime_active: analysis.summary.nlocktime_active,
+        relative_timelock_count: analysis.summary.relative_timelock_count,
+        cltv_values: join_values(&raw_values(&analysis.cltv_timelocks)),
+        csv_values: join_values(&raw_values(&analysis.csv_timelocks)),
+        max_risk: analysis.risk.max_severity,
+    })
+}
+
+/// Move the rows `csv` has buffered so far to `w`, so `--csv` output streams like the
+/// other row formats. The header goes out with the first rows only.
+pub fn flush_csv(w: &mut dyn Write, csv: &mut csv::Writer<Vec<u8>>) -> io::Result<()> {
+    csv.flush()?;
+    if csv.get_ref().is_empty() {
+        return Ok(());
+    }
+    let rest = csv::WriterBuilder::new().has_headers(false).from_writer(Vec::new());
+    let rows = std::mem::replace(csv, rest).into_inner().map_err(|e| e.into_error())?;
+    w.write_all(&rows)
+}
+
+/// `--oneline` output of `block`: `<txid> nlt=<nLockTime> cltv=<count> csv=<count>`.
+pub fn print_timelock_oneline(w: &mut dyn Write, analysis: &TransactionAnalysis) -> io::Result<()> {
+    writeln!(
+        w,
+        "{} nlt={} cltv={} csv={}",
+        analysis.txid,
+        analysis.nlocktime.raw_value,
+        analysis.summary.cltv_count,
+        analysis.summary.csv_count
+    )
+}
+
+/// `--oneline` output of `lightning block`: `<txid> <tx_type|-> <confidence>`, then
+/// `commitment=<n>`, `cltv=<n>` and `preimage` when they apply. Names are the JSON ones.
+pub fn print_lightning_oneline(
+    w: &mut dyn Write,
+    txid: &str,
+    lc: &LightningClassification,
+) -> io::Result<()> {
+    let mut line = format!(
+        "{txid} {} {}",
+        lc.tx_type.map_or("-".to_string(), |t| json_name(&t)),
+        json_name(&lc.confidence)
+    );
+    if let Some(n) = lc.params.commitment_number {
+        line.push_str(&format!(" commitment={n}"));
+    }
+    if let Some(expiry) = lc.params.cltv_expiry {
+        line.push_str(&format!(" cltv={expiry}"));
+    }
+    if lc.params.preimage_revealed {
+        line.push_str(" preimage");
+    }
+    writeln!(w, "{line}")
+}
+
+/// `0x0000123456ABCDEF (obscured) [seq=0x123456 lt=0xABCDEF]`: the obscured number in
+/// hex, split into the low 24 bits of the sequence and the locktime that encode it.
+fn format_obscured_commitment_number(cn: u64) -> String {
+    format!(
+        "0x{cn:016X} (obscured) [seq=0x{:06X} lt=0x{:06X}]",
+        cn >> 24,
+        cn & 0x00FF_FFFF
+    )
+}
+
+/// Name of a unit enum variant as it appears in JSON output, e.g. `highly_likely`.
+fn json_name<T: Serialize>(value: &T) -> String {
+    match serde_json::to_value(value) {
+        Ok(serde_json::Value::String(name)) => name,
+        _ => String::new(),
+    }
+}
+
+/// Multi-valued cells are `;`-joined so they never collide with the `,` delimiter.
+fn join_values<T: ToString>(values: &[T]) -> String {
+    values.iter().map(|v| v.to_string()).collect::<Vec<_>>().join(";")
 }
@@ -0,0 +1,137 @@
+use std::fmt::Display;
+use std::io::{self, IsTerminal};
+use std::time::Instant;
+
+use indicatif::{MultiProgress, ProgressBar, ProgressDrawTarget, ProgressStyle};
+
+/// How much progress chatter goes to stderr. Errors are printed at every level.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Verbosity {
+    /// No status lines or progress bars.
+    Quiet,
+    #[default]
+    Normal,
+    /// Also report how long each fetch took.
+    Verbose,
+}
+
+impl Verbosity {
+    pub fn from_flags(quiet: bool, verbose: bool) -> Self {
+        match (quiet, verbose) {
+            (true, _) => Self::Quiet,
+            (false, true) => Self::Verbose,
+            (false, false) => Self::Normal,
+        }
+    }
+
+    /// Print a status line to stderr unless quiet.
+    pub fn status(self, msg: impl Display) {
+        if self != Self::Quiet {
+            eprintln!("{msg}");
+        }
+    }
+
+    /// Await `fetch`, reporting on stderr how long fetching `what` took when verbose.
+    pub async fn timed<T>(self, what: impl Display, fetch: impl Future<Output = T>) -> T {
+        let started = Instant::now();
+        let result = fetch.await;
+        if self == Self::Verbose {
+            eprintln!("Fetched {what} in {:.2?}", started.elapsed());
+        }
+        result
+    }
+}
+
+/// Progress bars on stderr for block and range scans: one for the transactions of the
+/// current block and, over a range, one for the blocks.
+///
+/// Disabled bars are hidden and status lines fall back to plain stderr output, so the
+/// same calls work when stderr isn't a terminal or the output is machine-readable.
+pub struct ScanProgress {
+    multi: Option<MultiProgress>,
+    blocks: ProgressBar,
+    verbosity: Verbosity,
+}
+
+impl ScanProgress {
+    /// Bars are drawn when `enabled` and stderr is a terminal; with
+    /// [`Verbosity::Quiet`] nothing is drawn or printed at all.
+    pub fn new(start: u64, end: u64, enabled: bool, verbosity: Verbosity) -> Self {
+        if !enabled || verbosity == Verbosity::Quiet || !io::stderr().is_terminal() {
+            return Self {
+                multi: None,
+                blocks: ProgressBar::hidden(),
+                verbosity,
+            };
+        }
+
+        let multi = MultiProgress::with_draw_target(ProgressDrawTarget::stderr());
+        let blocks = if end > start {
+            multi.add(ProgressBar::new(end - start + 1).with_style(bar_style(
+                "[{bar:40}] {pos}/{len} blocks ({elapsed}, ETA {eta})",
+            )))
+        } else {
+            ProgressBar::hidden()
+        };
+        Self {
+            multi: Some(multi),
+            blocks,
+            verbosity,
+        }
+    }
+
+    /// Print a status line above the bars.
+    pub fn status(&self, msg: &str) {
+        match self.multi {
+            Some(ref multi) => {
+                let _ = multi.println(msg);
+            }
+            None => self.verbosity.status(msg),
+        }
+    }
+
+    /// Await `fetch` like [`Verbosity::timed`], printing the timing above the bars.
+    pub async fn timed<T>(&self, what: impl Display, fetch: impl Future<Output = T>) -> T {
+        let started = Instant::now();
+        let result = fetch.await;
+        if self.verbosity == Verbosity::Verbose {
+            self.status(&format!("Fetched {what} in {:.2?}", started.elapsed()));
+        }
+        result
+    }
+
+    /// Bar for the `len` transactions of one block; advance it with `inc(1)` and pass
+    /// it to [`ScanProgress::block_done`] when the block is finished.
+    pub fn transactions(&self, len: usize) -> ProgressBar {
+        match self.multi {
+            Some(ref multi) => multi.add(ProgressBar::new(len as u64).with_style(bar_style(
+                "[{bar:40}] {pos}/{len} transactions ({per_sec}, ETA {eta})",
+            ))),
+            None => ProgressBar::hidden(),
+        }
+    }
+
+    pub fn block_done(&self, transactions: ProgressBar) {
+        transactions.finish_and_clear();
+        self.blocks.inc(1);
+    }
+
+    /// Run `f` with the bars cleared, so whatever it prints isn't drawn over.
+    pub fn suspend<R>(&self, f: impl FnOnce() -> R) -> R {
+        match self.multi {
+            Some(ref multi) => multi.suspend(f),
+            None => f(),
+        }
+    }
+
+    /// Remove the bars before the final summary is printed.
+    pub fn finish(&self) {
+        self.blocks.finish_and_clear();
+    }
+}
+
+fn bar_style(template: &str) -> ProgressStyle {
+    ProgressStyle::with_template(template)
+        .expect("valid progress template")
+        .progress_chars("=> ")
+}
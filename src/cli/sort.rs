@@ -0,0 +1,66 @@
+use std::cmp::Ordering;
+
+use crate::lightning::types::LightningClassification;
+use crate::timelock::types::TransactionAnalysis;
+
+/// Order in which a block scan prints its transactions. Sorting is stable, so
+/// transactions that compare equal keep their block order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SortKey {
+    /// Block order.
+    #[default]
+    None,
+    /// Highest confidence first; for timelock analyses, highest risk severity first.
+    Confidence,
+    /// Commitments first, then the other Lightning types, then unclassified
+    /// transactions; for timelock analyses, transactions with active timelocks first.
+    Type,
+    /// Most HTLC outputs first; for timelock analyses, most CLTV and CSV script
+    /// timelocks first.
+    HtlcCount,
+}
+
+pub fn compare_lightning(
+    a: &LightningClassification,
+    b: &LightningClassification,
+    key: SortKey,
+) -> Ordering {
+    match key {
+        SortKey::None => Ordering::Equal,
+        SortKey::Confidence => b.confidence.cmp(&a.confidence),
+        SortKey::Type => match (a.tx_type, b.tx_type) {
+            (Some(a), Some(b)) => a.cmp(&b),
+            (a, b) => b.is_some().cmp(&a.is_some()),
+        },
+        SortKey::HtlcCount => {
+            let count = |lc: &LightningClassification| lc.params.htlc_output_count.unwrap_or(0);
+            count(b).cmp(&count(a))
+        }
+    }
+}
+
+pub fn compare_analyses(a: &TransactionAnalysis, b: &TransactionAnalysis, key: SortKey) -> Ordering {
+    match key {
+        SortKey::None => Ordering::Equal,
+        SortKey::Confidence => b.risk.max_severity.cmp(&a.risk.max_severity),
+        SortKey::Type => b.summary.has_active_timelocks.cmp(&a.summary.has_active_timelocks),
+        SortKey::HtlcCount => {
+            let count = |t: &TransactionAnalysis| t.summary.cltv_count + t.summary.csv_count;
+            count(b).cmp(&count(a))
+        }
+    }
+}
+
+/// Sort `(txid, classification)` pairs of a `lightning block` scan by `key`.
+pub fn sort_lightning(results: &mut [(String, LightningClassification)], key: SortKey) {
+    if key != SortKey::None {
+        results.sort_by(|(_, a), (_, b)| compare_lightning(a, b, key));
+    }
+}
+
+/// Sort the analyses of a `block` scan by `key`.
+pub fn sort_analyses(analyses: &mut [TransactionAnalysis], key: SortKey) {
+    if key != SortKey::None {
+        analyses.sort_by(|a, b| compare_analyses(a, b, key));
+    }
+}
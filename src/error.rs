@@ -0,0 +1,60 @@
+use std::fmt::Display;
+
+/// Why a [`DataSource`](crate::api::source::DataSource) call failed, so callers can
+/// tell a missing transaction from an unreachable backend without parsing messages.
+/// Each variant carries the full message, which is what `Display` prints.
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    /// The transaction, block or height doesn't exist on the source.
+    #[error("{0}")]
+    NotFound(String),
+    /// The source kept answering HTTP 429 until the retries ran out.
+    #[error("{0}")]
+    RateLimited(String),
+    /// Connection failure, timeout or server-side (5xx) error.
+    #[error("{0}")]
+    Network(String),
+    /// The response, or an id passed in, couldn't be parsed.
+    #[error("{0}")]
+    Decode(String),
+    /// The backend can't answer: the call is unsupported, authentication failed, or
+    /// the node is still starting up.
+    #[error("{0}")]
+    RpcUnavailable(String),
+}
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+impl Error {
+    /// Prefix the message with what was being done, keeping the kind.
+    pub fn context(self, context: impl Display) -> Self {
+        let wrap = |msg: String| format!("{context}: {msg}");
+        match self {
+            Self::NotFound(msg) => Self::NotFound(wrap(msg)),
+            Self::RateLimited(msg) => Self::RateLimited(wrap(msg)),
+            Self::Network(msg) => Self::Network(wrap(msg)),
+            Self::Decode(msg) => Self::Decode(wrap(msg)),
+            Self::RpcUnavailable(msg) => Self::RpcUnavailable(wrap(msg)),
+        }
+    }
+
+    /// Classify an error from a backend call that only surfaces `anyhow` errors: a
+    /// parse failure anywhere in the chain is `Decode`, anything else `fallback`.
+    pub(crate) fn classify(err: anyhow::Error, fallback: fn(String) -> Self) -> Self {
+        let msg = format!("{err:#}");
+        let is_decode = err.chain().any(|cause| {
+            cause.is::<serde_json::Error>()
+                || cause.is::<bitcoin::consensus::encode::Error>()
+                || cause.is::<bitcoin::consensus::encode::FromHexError>()
+                || cause.is::<bitcoin::hex::HexToArrayError>()
+                || cause.is::<std::num::ParseIntError>()
+        });
+        if is_decode { Self::Decode(msg) } else { fallback(msg) }
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(err: serde_json::Error) -> Self {
+        Self::Decode(err.to_string())
+    }
+}
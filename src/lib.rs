@@ -1,6 +1,16 @@
+pub mod analysis;
 pub mod api;
 pub mod cli;
+pub mod error;
 pub mod lightning;
 pub mod security;
+pub mod schema;
 pub mod server;
+pub mod stats;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
 pub mod timelock;
+pub mod util;
+
+pub use analysis::{CombinedAnalysis, analyze};
+pub use error::Error;
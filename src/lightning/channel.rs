@@ -0,0 +1,180 @@
+use std::collections::{BTreeMap, HashMap};
+
+use super::detector::{HtlcDirection, htlc_input_direction};
+use super::types::{
+    ChannelCluster, ChannelLink, LightningClassification, LightningTxType, LinkBasis,
+    LinkedCommitment,
+};
+use crate::api::types::{ApiTransaction, ApiVin};
+
+/// Low bits of an obscured commitment number that a channel's own commitments may
+/// differ in. Commitment numbers count up from 0 with each update, so they stay far
+/// below 2^24, and the 24 bits above are the obscuring factor's alone.
+const COMMITMENT_NUMBER_BITS: u32 = 24;
+
+/// Group commitment transactions that likely belong to the same channel, ordered by
+/// commitment number.
+///
+/// Commitments verified against the same funding outpoint are linked by it. The rest
+/// are linked when their obscured commitment numbers agree above the low 24 bits:
+/// every commitment of a channel, either side's, is obscured with the same 48-bit
+/// factor, so only the low bits change between them. Within a link, commitments are
+/// ordered by decoded commitment number where known, then by obscured number; the
+/// obscuring factor scrambles the low bits, so without the channel's basepoints the
+/// order of undecoded commitments is a best guess. Only groups of two or more are
+/// returned, and classifications that aren't commitments are ignored.
+pub fn correlate(commitments: &[LightningClassification]) -> Vec<ChannelLink> {
+    let mut by_outpoint: BTreeMap<&str, Vec<usize>> = BTreeMap::new();
+    let mut by_factor: BTreeMap<u64, Vec<usize>> = BTreeMap::new();
+
+    for (index, classification) in commitments.iter().enumerate() {
+        if classification.tx_type != Some(LightningTxType::Commitment) {
+            continue;
+        }
+        let params = &classification.params;
+        if let Some(ref outpoint) = params.funding_outpoint {
+            by_outpoint.entry(outpoint).or_default().push(index);
+        } else if let Some(number) = params.commitment_number {
+            by_factor
+                .entry(number >> COMMITMENT_NUMBER_BITS)
+                .or_default()
+                .push(index);
+        }
+    }
+
+    let outpoint_links = by_outpoint.into_iter().map(|(outpoint, indexes)| {
+        (LinkBasis::FundingOutpoint, Some(outpoint.to_string()), indexes)
+    });
+    let factor_links = by_factor
+        .into_values()
+        .map(|indexes| (LinkBasis::ObscuringFactor, None, indexes));
+
+    outpoint_links
+        .chain(factor_links)
+        .filter(|(_, _, indexes)| indexes.len() > 1)
+        .map(|(basis, funding_outpoint, indexes)| ChannelLink {
+            basis,
+            funding_outpoint,
+            commitments: ordered(commitments, indexes),
+        })
+        .collect()
+}
+
+fn ordered(commitments: &[LightningClassification], indexes: Vec<usize>) -> Vec<LinkedCommitment> {
+    let mut linked: Vec<LinkedCommitment> = indexes
+        .into_iter()
+        .map(|index| LinkedCommitment {
+            index,
+            commitment_number: commitments[index].params.commitment_number,
+            commitment_number_decoded: commitments[index].params.commitment_number_decoded,
+        })
+        .collect();
+    // Decoded commitments first, by real number, then the rest by obscured number
+    linked.sort_by_key(|c| {
+        (c.commitment_number_decoded.is_none(), c.commitment_number_decoded, c.commitment_number)
+    });
+    linked
+}
+
+/// Link each commitment among `classified` to the HTLC-timeout and HTLC-success
+/// transactions among them that spend its outputs, matched by the inputs' previous
+/// txid. Only commitments with at least one such spend are returned, in the order
+/// given. Anchor channels delay HTLC outputs by a block, so their second-stage spends
+/// are never in the commitment's own block.
+pub fn cluster_htlc_spends<'a>(
+    classified: impl IntoIterator<Item = (&'a ApiTransaction, &'a LightningClassification)>,
+) -> Vec<ChannelCluster> {
+    let mut clusters = Vec::new();
+    let mut by_commitment: HashMap<&str, usize> = HashMap::new();
+    let mut htlcs = Vec::new();
+
+    for (tx, classification) in classified {
+        match classification.tx_type {
+            Some(LightningTxType::Commitment) => {
+                by_commitment.insert(&tx.txid, clusters.len());
+                let funding_outpoint = classification.params.funding_outpoint.clone().or_else(|| {
+                    let vin = tx.vin.first()?;
+                    Some(format!("{}:{}", vin.txid.as_ref()?, vin.vout?))
+                });
+                clusters.push(ChannelCluster {
+                    funding_outpoint,
+                    commitment_txid: tx.txid.clone(),
+                    htlc_timeouts: Vec::new(),
+                    htlc_successes: Vec::new(),
+                });
+            }
+            Some(htlc @ (LightningTxType::HtlcTimeout | LightningTxType::HtlcSuccess)) => {
+                htlcs.push((tx, htlc));
+            }
+            _ => {}
+        }
+    }
+
+    for (tx, htlc) in htlcs {
+        // An aggregated claim may spend outputs of more than one commitment
+        let mut parents: Vec<usize> = tx
+            .vin
+            .iter()
+            .filter_map(|vin| by_commitment.get(vin.txid.as_deref()?).copied())
+            .collect();
+        parents.sort_unstable();
+        parents.dedup();
+        for index in parents {
+            let cluster = &mut clusters[index];
+            match htlc {
+                LightningTxType::HtlcTimeout => cluster.htlc_timeouts.push(tx.txid.clone()),
+                _ => cluster.htlc_successes.push(tx.txid.clone()),
+            }
+        }
+    }
+
+    clusters.retain(|c| !c.htlc_timeouts.is_empty() || !c.htlc_successes.is_empty());
+    clusters
+}
+
+/// Cross-reference each commitment in a block with the HTLC-timeout and HTLC-success
+/// transactions in the same block that spend it, as [`cluster_htlc_spends`] links
+/// them: the commitment's `same_block_htlc_spends` lists them, and each of them gets
+/// the commitment as `same_block_commitment` (the first one, for an aggregated claim
+/// spending several). Such an immediate sweep is only possible for pre-anchor
+/// channels, whose HTLC outputs carry no CSV delay. When the sweeps reveal the scripts
+/// of all the commitment's HTLC outputs, it also gets `offered_htlc_count` and
+/// `received_htlc_count`.
+pub fn link_same_block_sweeps<'a>(
+    block: impl IntoIterator<Item = (&'a ApiTransaction, &'a mut LightningClassification)>,
+) {
+    let mut block: Vec<_> = block.into_iter().collect();
+    let clusters = cluster_htlc_spends(block.iter().map(|(tx, lc)| (*tx, &**lc)));
+    let index: HashMap<&str, usize> = block
+        .iter()
+        .enumerate()
+        .map(|(i, (tx, _))| (tx.txid.as_str(), i))
+        .collect();
+
+    for cluster in clusters {
+        let mut offered = 0;
+        let mut received = 0;
+        for htlc in cluster.htlc_timeouts.iter().chain(&cluster.htlc_successes) {
+            let (tx, classification) = &mut block[index[htlc.as_str()]];
+            let params = &mut classification.params;
+            params.same_block_commitment.get_or_insert_with(|| cluster.commitment_txid.clone());
+            let spends_commitment =
+                |vin: &&ApiVin| vin.txid.as_deref() == Some(cluster.commitment_txid.as_str());
+            for vin in tx.vin.iter().filter(spends_commitment) {
+                match htlc_input_direction(vin) {
+                    Some(HtlcDirection::Offered) => offered += 1,
+                    Some(HtlcDirection::Received) => received += 1,
+                    None => {}
+                }
+            }
+        }
+        let commitment = &mut block[index[cluster.commitment_txid.as_str()]].1.params;
+        commitment.same_block_htlc_spends =
+            cluster.htlc_timeouts.into_iter().chain(cluster.htlc_successes).collect();
+        // The directions are only complete once every HTLC output has been swept
+        if commitment.htlc_output_count == Some(offered + received) {
+            commitment.offered_htlc_count = Some(offered);
+            commitment.received_htlc_count = Some(received);
+        }
+    }
+}
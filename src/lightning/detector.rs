@@ -1,40 +1,384 @@
-use crate::api::types::ApiTransaction;
+use std::borrow::Cow;
+use std::cmp::Ordering;
+use std::collections::HashMap;
 
+use bitcoin::hashes::{Hash, HashEngine, hash160, sha256};
+use bitcoin::hex::{DisplayHex, FromHex};
+use bitcoin::opcodes::all::{OP_CHECKSIG, OP_CSV, OP_ENDIF, OP_IFDUP, OP_NOTIF, OP_PUSHNUM_16};
+use bitcoin::script::{Builder, Instruction, PushBytesBuf};
+use bitcoin::{Network, ScriptBuf, Transaction, TxOut};
+
+use crate::api::raw::{api_transaction_from_bitcoin, api_transaction_with_prevouts};
+use crate::api::types::{ApiTransaction, ApiVin, ApiVout};
+use crate::timelock::extractor::decode_push_before;
+use crate::timelock::types::ChainTip;
+
+use super::heuristic::HeuristicMatch;
 use super::types::*;
 
-const ANCHOR_VALUE: u64 = 330;
+/// Script type of pay-to-anchor outputs (`OP_1 <0x4e73>`), used by zero-fee
+/// commitments at any value, usually 0.
+const PAY_TO_ANCHOR_TYPE: &str = "anchor";
+/// Largest weight still considered a small CPFP child (~500 vbytes).
+const ANCHOR_SWEEP_MAX_WEIGHT: u64 = 2_000;
+/// Byte sizes of a BOLT 3 HTLC witness script, with some slack: offered HTLCs are 133
+/// bytes and received ones 139 to 140 depending on the expiry push, plus 3 for the
+/// `1 OP_CSV` of anchor channels.
+const HTLC_SCRIPT_BYTES: std::ops::RangeInclusive<usize> = 128..=148;
 
 /// Classify a transaction as Lightning-related or not.
 pub fn classify_lightning(tx: &ApiTransaction) -> LightningClassification {
+    classify_lightning_with_config(tx, &DetectorConfig::default())
+}
+
+/// Classify a transaction with non-standard detection values, e.g. a regtest
+/// setup's anchor amount, or with extra heuristics registered.
+pub fn classify_lightning_with_config(
+    tx: &ApiTransaction,
+    config: &DetectorConfig,
+) -> LightningClassification {
+    classify_with_reasons(tx, config, &mut Tracer(None))
+}
+
+/// Classify a transaction like [`classify_lightning`], also recording each check the
+/// classifier ran and what it found.
+pub fn explain_lightning(tx: &ApiTransaction) -> (LightningClassification, ClassificationTrace) {
+    explain_lightning_with_config(tx, &DetectorConfig::default())
+}
+
+/// Explain a classification like [`explain_lightning`], with non-standard detection values.
+pub fn explain_lightning_with_config(
+    tx: &ApiTransaction,
+    config: &DetectorConfig,
+) -> (LightningClassification, ClassificationTrace) {
+    let mut trace = ClassificationTrace::default();
+    let classification = classify_with_reasons(tx, config, &mut Tracer(Some(&mut trace)));
+    (classification, trace)
+}
+
+fn classify_with_reasons(
+    tx: &ApiTransaction,
+    config: &DetectorConfig,
+    tracer: &mut Tracer,
+) -> LightningClassification {
+    let mut classification = classify(tx, config, tracer);
+    classification.reasons = reasons(tx, &classification, config);
+    apply_heuristics(tx, &mut classification, config, tracer);
+    classification
+}
+
+/// Let the first registered heuristic that matches override the built-in result.
+fn apply_heuristics(
+    tx: &ApiTransaction,
+    classification: &mut LightningClassification,
+    config: &DetectorConfig,
+    tracer: &mut Tracer,
+) {
+    if config.heuristics.is_empty() {
+        return;
+    }
+    let found = config
+        .heuristics
+        .iter()
+        .enumerate()
+        .find_map(|(index, heuristic)| Some((index, heuristic.evaluate(tx)?)));
+    tracer.step("registered heuristics", found.is_some(), || match &found {
+        Some((index, (_, confidence, _))) => vec![format!(
+            "heuristic {} of {} matched -> {}",
+            index + 1,
+            config.heuristics.len(),
+            confidence_name(*confidence)
+        )],
+        None => vec![format!("none of {} matched", config.heuristics.len())],
+    });
+    let Some((_, (tx_type, confidence, reasons))) = found else {
+        return;
+    };
+
+    if classification.tx_type != Some(tx_type) {
+        // The built-in signals stay, but parameters and reasons were for another type
+        classification.params = LightningParams::default();
+        classification.reasons.clear();
+    }
+    classification.tx_type = Some(tx_type);
+    classification.confidence = confidence;
+    for reason in reasons {
+        if !classification.reasons.contains(&reason) {
+            classification.reasons.push(reason);
+        }
+    }
+}
+
+/// The built-in commitment check on its own, for [`super::heuristic::CommitmentHeuristic`].
+pub(crate) fn commitment_match(
+    tx: &ApiTransaction,
+    config: &DetectorConfig,
+) -> Option<HeuristicMatch> {
+    if missing_inputs_or_outputs(tx).is_some() || tx.vin.iter().any(|v| v.is_coinbase) {
+        return None;
+    }
+    let signals = detect_commitment_signals(tx, config);
+    let confidence = commitment_confidence(&signals, config);
+    if confidence < Confidence::Possible {
+        return None;
+    }
+    let classification = LightningClassification {
+        tx_type: Some(LightningTxType::Commitment),
+        confidence,
+        params: extract_commitment_params(tx, &signals, config),
+        commitment_signals: signals,
+        ..not_lightning()
+    };
+    let reasons = reasons(tx, &classification, config);
+    Some((LightningTxType::Commitment, confidence, reasons))
+}
+
+/// The built-in HTLC check on its own, for [`super::heuristic::HtlcHeuristic`].
+pub(crate) fn htlc_match(tx: &ApiTransaction, config: &DetectorConfig) -> Option<HeuristicMatch> {
+    if missing_inputs_or_outputs(tx).is_some() || tx.vin.iter().any(|v| v.is_coinbase) {
+        return None;
+    }
+    let signals = detect_htlc_signals(tx);
+    let (tx_type, confidence, params) = classify_htlc(tx, &signals, config)?;
+    let classification = LightningClassification {
+        tx_type: Some(tx_type),
+        confidence,
+        htlc_signals: signals,
+        params,
+        ..not_lightning()
+    };
+    let reasons = reasons(tx, &classification, config);
+    Some((tx_type, confidence, reasons))
+}
+
+/// Classify a transaction like [`classify_lightning`], also judging an HTLC-timeout's
+/// CLTV expiry against `tip` (see [`DetectorConfig::with_tip`] and [`add_tip_reasons`]).
+pub fn classify_lightning_with_tip(tx: &ApiTransaction, tip: &ChainTip) -> LightningClassification {
+    let config = DetectorConfig::default().with_tip(tip.height);
+    let mut classification = classify_lightning_with_config(tx, &config);
+    add_tip_reasons(&mut classification, tip);
+    classification
+}
+
+/// Add the reasons that depend on the chain tip: whether an HTLC-timeout's nLockTime
+/// has matured. One still in the future can't confirm yet, so seeing it confirmed is
+/// suspicious.
+pub fn add_tip_reasons(classification: &mut LightningClassification, tip: &ChainTip) {
+    if classification.tx_type != Some(LightningTxType::HtlcTimeout) {
+        return;
+    }
+    let Some(expiry) = classification.params.cltv_expiry.map(u64::from) else {
+        return;
+    };
+    // nLockTime N is final in block N + 1, the next one when the tip is at N
+    let reason = if expiry > tip.height {
+        format!("HTLC-timeout locktime {} blocks in the future", expiry - tip.height)
+    } else {
+        "HTLC-timeout locktime already matured".to_string()
+    };
+    classification.reasons.push(reason);
+}
+
+/// Records trace steps when explaining; details are only built when recording.
+struct Tracer<'a>(Option<&'a mut ClassificationTrace>);
+
+impl Tracer<'_> {
+    fn step(&mut self, check: &str, matched: bool, details: impl FnOnce() -> Vec<String>) {
+        if let Some(trace) = self.0.as_deref_mut() {
+            trace.steps.push(TraceStep {
+                check: check.to_string(),
+                matched,
+                details: details(),
+            });
+        }
+    }
+}
+
+fn classify(
+    tx: &ApiTransaction,
+    config: &DetectorConfig,
+    tracer: &mut Tracer,
+) -> LightningClassification {
+    // Malformed or pruned data: the checks below assume inputs and outputs exist
+    let missing = missing_inputs_or_outputs(tx);
+    tracer.step("inputs and outputs", missing.is_some(), || {
+        vec![format!("{} inputs, {} outputs", tx.vin.len(), tx.vout.len())]
+    });
+    if missing.is_some() {
+        return not_lightning();
+    }
+
     // Skip coinbase transactions
-    if tx.vin.iter().any(|v| v.is_coinbase) {
+    let is_coinbase = tx.vin.iter().any(|v| v.is_coinbase);
+    tracer.step("coinbase", is_coinbase, || {
+        vec![format!("coinbase input: {}", yes_no(is_coinbase))]
+    });
+    if is_coinbase {
         return not_lightning();
     }
 
-    let commitment_signals = detect_commitment_signals(tx);
+    let commitment_signals = detect_commitment_signals(tx, config);
     let htlc_signals = detect_htlc_signals(tx);
+    let mutual_close_signals = detect_mutual_close_signals(tx, config);
 
     // Commitment detection takes priority over HTLC
-    let commitment_confidence = commitment_confidence(&commitment_signals);
-    if commitment_confidence >= Confidence::Possible {
-        let params = extract_commitment_params(tx, &commitment_signals);
+    let commitment_confidence = commitment_confidence(&commitment_signals, config);
+    let is_commitment = commitment_confidence >= Confidence::Possible;
+    tracer.step("commitment", is_commitment, || {
+        let mut details = commitment_trace(tx, &commitment_signals, config);
+        if is_commitment {
+            details.push(
+                concat!(
+                    "takes priority over the penalty, to_local sweep, anchor sweep, HTLC, ",
+                    "splice and mutual close checks"
+                )
+                .to_string(),
+            );
+        }
+        details
+    });
+    if is_commitment {
+        let params = extract_commitment_params(tx, &commitment_signals, config);
         return LightningClassification {
             tx_type: Some(LightningTxType::Commitment),
             confidence: commitment_confidence,
             commitment_signals,
             htlc_signals,
+            mutual_close_signals,
+            params,
+            reasons: Vec::new(),
+        };
+    }
+
+    // Penalty detection — must run before HTLC, since revoked outputs carry HTLC scripts
+    let penalty = classify_penalty(tx);
+    tracer.step("penalty", penalty.is_some(), || match &penalty {
+        Some((confidence, params)) => vec![
+            format!(
+                "{} revocation-path spends of the same commitment -> {}",
+                params.swept_output_count.unwrap_or_default(),
+                confidence_name(*confidence)
+            ),
+            "runs before the HTLC check, since revoked outputs carry HTLC scripts".to_string(),
+        ],
+        None => vec!["no input spends a revocation path".to_string()],
+    });
+    if let Some((confidence, params)) = penalty {
+        return LightningClassification {
+            tx_type: Some(LightningTxType::Penalty),
+            confidence,
+            commitment_signals,
+            htlc_signals,
+            mutual_close_signals,
+            params,
+            reasons: Vec::new(),
+        };
+    }
+
+    // to_local sweep detection — the delayed branch of the same script penalties spend
+    let to_local_sweep = classify_to_local_sweep(tx);
+    tracer.step("to_local sweep", to_local_sweep.is_some(), || match &to_local_sweep {
+        Some(params) => vec![format!(
+            "spends a to_local output through its CSV branch after {} blocks",
+            params.to_self_delay.unwrap_or_default()
+        )],
+        None => vec!["no input spends a matured to_local output".to_string()],
+    });
+    if let Some(params) = to_local_sweep {
+        return LightningClassification {
+            tx_type: Some(LightningTxType::ToLocalSweep),
+            confidence: Confidence::HighlyLikely,
+            commitment_signals,
+            htlc_signals,
+            mutual_close_signals,
+            params,
+            reasons: Vec::new(),
+        };
+    }
+
+    // Anchor sweep detection — before HTLC, since a CPFP child may carry an
+    // anti-fee-sniping block height in nLockTime
+    let anchor_sweep = classify_anchor_sweep(tx, config);
+    tracer.step("anchor sweep", anchor_sweep.is_some(), || {
+        vec![
+            format!(
+                "spends an anchor output: {}",
+                yes_no(tx.vin.iter().any(|v| is_anchor_spend(v, config)))
+            ),
+            format!("single output: {}", yes_no(tx.vout.len() == 1)),
+            format!("weight {} WU (at most {ANCHOR_SWEEP_MAX_WEIGHT})", tx.weight),
+        ]
+    });
+    if let Some(params) = anchor_sweep {
+        return LightningClassification {
+            tx_type: Some(LightningTxType::AnchorSweep),
+            confidence: Confidence::Possible,
+            commitment_signals,
+            htlc_signals,
+            mutual_close_signals,
             params,
+            reasons: Vec::new(),
         };
     }
 
     // HTLC detection
-    if let Some((htlc_type, confidence, params)) = classify_htlc(tx, &htlc_signals) {
+    let htlc = classify_htlc(tx, &htlc_signals, config);
+    tracer.step("HTLC", htlc.is_some(), || {
+        htlc_trace(tx, &htlc_signals, htlc.as_ref(), config)
+    });
+    if let Some((htlc_type, confidence, params)) = htlc {
         return LightningClassification {
             tx_type: Some(htlc_type),
             confidence,
             commitment_signals,
             htlc_signals,
+            mutual_close_signals,
             params,
+            reasons: Vec::new(),
+        };
+    }
+
+    // Splice detection — before mutual close, which also spends a funding output
+    let splice = classify_splice(tx);
+    tracer.step("splice", splice.is_some(), || match &splice {
+        Some((confidence, params)) => vec![
+            format!(
+                "funding output of {} sats replaced by one of {} sats -> {}",
+                params.old_funding_value.unwrap_or_default(),
+                params.new_funding_value.unwrap_or_default(),
+                confidence_name(*confidence)
+            ),
+            "runs before the mutual close check, which also spends a funding output".to_string(),
+        ],
+        None => vec!["no single funding spend creating a new funding output".to_string()],
+    });
+    if let Some((confidence, params)) = splice {
+        return LightningClassification {
+            tx_type: Some(LightningTxType::Splice),
+            confidence,
+            commitment_signals,
+            htlc_signals,
+            mutual_close_signals,
+            params,
+            reasons: Vec::new(),
+        };
+    }
+
+    // Mutual close detection
+    let mutual_close_confidence = mutual_close_confidence(&mutual_close_signals);
+    let is_mutual_close = mutual_close_confidence >= Confidence::Possible;
+    tracer.step("mutual close", is_mutual_close, || {
+        mutual_close_trace(&mutual_close_signals, mutual_close_confidence)
+    });
+    if is_mutual_close {
+        return LightningClassification {
+            tx_type: Some(LightningTxType::MutualClose),
+            confidence: mutual_close_confidence,
+            commitment_signals,
+            htlc_signals,
+            mutual_close_signals,
+            params: LightningParams::default(),
+            reasons: Vec::new(),
         };
     }
 
@@ -43,7 +387,52 @@ pub fn classify_lightning(tx: &ApiTransaction) -> LightningClassification {
         confidence: Confidence::None,
         commitment_signals,
         htlc_signals,
+        mutual_close_signals,
         params: LightningParams::default(),
+        reasons: Vec::new(),
+    }
+}
+
+/// Classify a transaction using known channel parameters.
+///
+/// Behaves like [`classify_lightning`], additionally decoding the commitment number of
+/// commitment transactions into `params.commitment_number_decoded`.
+pub fn classify_lightning_with_context(
+    tx: &ApiTransaction,
+    context: &ChannelContext,
+) -> LightningClassification {
+    let mut classification = classify_lightning(tx);
+    classification.params.commitment_number_decoded = classification
+        .params
+        .commitment_number
+        .map(|obscured| obscured ^ obscuring_factor(context));
+    classification
+}
+
+/// Classify a `bitcoin::Transaction` without building an `ApiTransaction` by hand.
+///
+/// `prevouts[i]` is the output spent by input `i`. Without them, witness scripts can't
+/// be recovered, so HTLC, penalty and anchor-sweep detection only see what the
+/// transaction itself carries.
+pub fn classify_lightning_tx(
+    tx: &Transaction,
+    prevouts: Option<&[TxOut]>,
+) -> LightningClassification {
+    // Addresses aren't used for classification, so the network doesn't matter
+    let api_tx = match prevouts {
+        Some(prevouts) => api_transaction_with_prevouts(tx, prevouts, Network::Bitcoin),
+        None => api_transaction_from_bitcoin(tx, Network::Bitcoin),
+    };
+    classify_lightning(&api_tx)
+}
+
+/// Why `tx` can't be a Lightning transaction of any kind, if it lacks inputs or outputs.
+fn missing_inputs_or_outputs(tx: &ApiTransaction) -> Option<&'static str> {
+    match (tx.vin.is_empty(), tx.vout.is_empty()) {
+        (true, true) => Some("transaction has no inputs or outputs"),
+        (true, false) => Some("transaction has no inputs"),
+        (false, true) => Some("transaction has no outputs"),
+        (false, false) => None,
     }
 }
 
@@ -53,62 +442,219 @@ fn not_lightning() -> LightningClassification {
         confidence: Confidence::None,
         commitment_signals: CommitmentSignals::default(),
         htlc_signals: HtlcSignals::default(),
+        mutual_close_signals: MutualCloseSignals::default(),
         params: LightningParams::default(),
+        reasons: Vec::new(),
     }
 }
 
 // ─── Commitment detection ────────────────────────────────────────────────────
 
-fn detect_commitment_signals(tx: &ApiTransaction) -> CommitmentSignals {
-    let locktime_match = is_lightning_locktime(tx.locktime);
-    let sequence_match = tx.vin.iter().any(|v| is_lightning_sequence(v.sequence));
-    let anchor_output_count = tx.vout.iter().filter(|o| o.value == ANCHOR_VALUE).count();
+fn detect_commitment_signals(tx: &ApiTransaction, config: &DetectorConfig) -> CommitmentSignals {
+    let locktime_match = is_lightning_locktime(tx.locktime, config);
+    let sequence_match = tx.vin.iter().any(|v| is_lightning_sequence(v.sequence, config));
+    let is_taproot = is_taproot_outputs(tx);
+
+    // A 330-sat P2TR output is an ordinary payment unless the commitment markers are
+    // present too, so taproot anchors only count alongside them. Pay-to-anchor outputs
+    // are used by any TRUC transaction, so they need the markers as well.
+    let markers = locktime_match || sequence_match;
+    let taproot_anchors = is_taproot && markers;
+    let anchor_spks = anchor_script_pubkeys(tx);
+    let anchors: Vec<&ApiVout> = tx
+        .vout
+        .iter()
+        .filter(|o| {
+            if o.scriptpubkey_type == PAY_TO_ANCHOR_TYPE {
+                markers
+            } else {
+                is_anchor_output(o, anchor_spks.as_deref(), config)
+                    || (taproot_anchors && is_taproot_anchor_output(o, config))
+            }
+        })
+        .collect();
+    let to_remote_delayed = tx
+        .vin
+        .iter()
+        .filter_map(|vin| vin.inner_witnessscript_asm.as_deref())
+        .any(is_to_remote_delayed_script);
+
+    let channel_type = if !markers {
+        ChannelType::Unknown
+    } else if is_taproot {
+        ChannelType::Taproot
+    } else if !anchors.is_empty() {
+        ChannelType::Anchor
+    } else if has_output_type(tx, "v0_p2wpkh") && has_output_type(tx, "v0_p2wsh") {
+        ChannelType::Legacy
+    } else {
+        ChannelType::Unknown
+    };
 
     CommitmentSignals {
         locktime_match,
         sequence_match,
-        has_anchor_outputs: anchor_output_count > 0,
-        anchor_output_count,
+        has_anchor_outputs: !anchors.is_empty(),
+        anchor_output_count: anchors.len(),
+        anchor_value: anchors.first().map(|o| o.value),
+        is_taproot,
+        to_remote_delayed,
+        channel_type,
+    }
+}
+
+fn has_output_type(tx: &ApiTransaction, script_type: &str) -> bool {
+    tx.vout.iter().any(|o| o.scriptpubkey_type == script_type)
+}
+
+/// Simple taproot channels pay every commitment output to a `v1_p2tr` key.
+fn is_taproot_outputs(tx: &ApiTransaction) -> bool {
+    !tx.vout.is_empty() && tx.vout.iter().all(|o| o.scriptpubkey_type == "v1_p2tr")
+}
+
+/// Taproot anchors are P2TR outputs at exactly the standard anchor value.
+fn is_taproot_anchor_output(output: &ApiVout, config: &DetectorConfig) -> bool {
+    output.scriptpubkey_type == "v1_p2tr" && output.value == config.anchor_value
+}
+
+/// Anchor outputs are P2WSH outputs paying the BOLT 3 anchor script of one of the
+/// funding pubkeys, whatever their value (see [`anchor_script_pubkeys`]), or
+/// pay-to-anchor outputs. Only when no input reveals the funding script does the value
+/// decide: any P2WSH output up to `anchor_max_value`, or, when the script type is
+/// unknown, exactly `anchor_value`.
+fn is_anchor_output(
+    output: &ApiVout,
+    anchor_spks: Option<&[String]>,
+    config: &DetectorConfig,
+) -> bool {
+    let template_match = || anchor_spks.is_some_and(|spks| spks.contains(&output.scriptpubkey));
+    match output.scriptpubkey_type.as_str() {
+        PAY_TO_ANCHOR_TYPE => true,
+        "v0_p2wsh" if anchor_spks.is_some() => template_match(),
+        "v0_p2wsh" => output.value <= config.anchor_max_value,
+        "" => template_match() || output.value == config.anchor_value,
+        _ => false,
     }
 }
 
+/// P2WSH scriptpubkeys (hex) of the two anchor outputs a commitment can have: the
+/// BOLT 3 anchor script built from each funding pubkey, which the commitment's input
+/// reveals in its 2-of-2 witness script. `None` when no input shows one.
+fn anchor_script_pubkeys(tx: &ApiTransaction) -> Option<Vec<String>> {
+    let funding_script = tx.vin.iter().find_map(|vin| {
+        let bytes = Vec::<u8>::from_hex(vin.witness.as_ref()?.last()?).ok()?;
+        let script = ScriptBuf::from(bytes);
+        is_two_of_two_script(&script.to_asm_string()).then_some(script)
+    })?;
+    let spks = funding_script
+        .instructions()
+        .filter_map(|instruction| match instruction {
+            Ok(Instruction::PushBytes(pubkey)) if pubkey.len() == 33 => Some(pubkey.to_owned()),
+            _ => None,
+        })
+        .map(|pubkey| {
+            let script_hash = anchor_script(pubkey).wscript_hash();
+            ScriptBuf::new_p2wsh(&script_hash).to_hex_string()
+        })
+        .collect();
+    Some(spks)
+}
+
+/// `<funding_pubkey> OP_CHECKSIG OP_IFDUP OP_NOTIF OP_16 OP_CSV OP_ENDIF`
+fn anchor_script(funding_pubkey: PushBytesBuf) -> ScriptBuf {
+    Builder::new()
+        .push_slice(funding_pubkey)
+        .push_opcode(OP_CHECKSIG)
+        .push_opcode(OP_IFDUP)
+        .push_opcode(OP_NOTIF)
+        .push_opcode(OP_PUSHNUM_16)
+        .push_opcode(OP_CSV)
+        .push_opcode(OP_ENDIF)
+        .into_script()
+}
+
+/// BOLT 3 anchor witness script:
+/// `<funding_pubkey> OP_CHECKSIG OP_IFDUP OP_NOTIF OP_16 OP_CSV OP_ENDIF`
+fn is_anchor_script(asm: &str) -> bool {
+    let tokens: Vec<&str> = asm
+        .split_whitespace()
+        .filter(|t| *t != "OP_PUSHBYTES_33")
+        .collect();
+
+    tokens.len() == 7
+        && tokens[0].len() == 66
+        && is_valid_hex(tokens[0])
+        && tokens[1] == "OP_CHECKSIG"
+        && tokens[2] == "OP_IFDUP"
+        && tokens[3] == "OP_NOTIF"
+        && matches!(tokens[4], "OP_16" | "OP_PUSHNUM_16" | "16")
+        && matches!(tokens[5], "OP_CSV" | "OP_CHECKSEQUENCEVERIFY")
+        && tokens[6] == "OP_ENDIF"
+}
+
+/// Anchor channels' to_remote script: `<remote_pubkey> OP_CHECKSIGVERIFY 1 OP_CSV`.
+fn is_to_remote_delayed_script(asm: &str) -> bool {
+    let tokens: Vec<&str> = asm
+        .split_whitespace()
+        .filter(|t| *t != "OP_PUSHBYTES_33")
+        .collect();
+
+    tokens.len() == 4
+        && tokens[0].len() == 66
+        && is_valid_hex(tokens[0])
+        && tokens[1] == "OP_CHECKSIGVERIFY"
+        && matches!(tokens[2], "OP_1" | "OP_PUSHNUM_1" | "OP_TRUE" | "1")
+        && matches!(tokens[3], "OP_CSV" | "OP_CHECKSEQUENCEVERIFY")
+}
+
 /// Lightning commitment transactions encode an obscured commitment number in locktime.
 /// The upper byte is 0x20, placing the value in range [0x20000000, 0x20FFFFFF].
-fn is_lightning_locktime(locktime: u32) -> bool {
-    (locktime >> 24) == 0x20
+fn is_lightning_locktime(locktime: u32, config: &DetectorConfig) -> bool {
+    (locktime >> 24) == u32::from(config.locktime_upper_byte)
 }
 
 /// Lightning commitment transaction inputs have sequence with upper byte 0x80.
-fn is_lightning_sequence(sequence: u32) -> bool {
-    (sequence >> 24) == 0x80
+fn is_lightning_sequence(sequence: u32, config: &DetectorConfig) -> bool {
+    (sequence >> 24) == u32::from(config.sequence_upper_byte)
 }
 
-fn commitment_confidence(signals: &CommitmentSignals) -> Confidence {
-    let mut score = 0;
-    if signals.locktime_match {
-        score += 1;
-    }
-    if signals.sequence_match {
-        score += 1;
-    }
-    if signals.has_anchor_outputs {
-        score += 1;
-    }
+/// Commitment signal weights. Anchor outputs count double: they are specific to
+/// Lightning, while an odd sequence (or, more rarely, locktime) can be coincidence.
+const LOCKTIME_WEIGHT: u32 = 1;
+const SEQUENCE_WEIGHT: u32 = 1;
+const ANCHOR_WEIGHT: u32 = 2;
+
+fn commitment_score(signals: &CommitmentSignals) -> u32 {
+    [
+        (signals.locktime_match, LOCKTIME_WEIGHT),
+        (signals.sequence_match, SEQUENCE_WEIGHT),
+        (signals.has_anchor_outputs, ANCHOR_WEIGHT),
+    ]
+    .into_iter()
+    .filter(|(matched, _)| *matched)
+    .map(|(_, weight)| weight)
+    .sum()
+}
 
-    match score {
-        0 => Confidence::None,
-        1 => Confidence::Possible,
-        _ => Confidence::HighlyLikely,
+fn commitment_confidence(signals: &CommitmentSignals, config: &DetectorConfig) -> Confidence {
+    match commitment_score(signals) {
+        score if score >= config.highly_likely_score => Confidence::HighlyLikely,
+        score if score >= config.possible_score => Confidence::Possible,
+        _ => Confidence::None,
     }
 }
 
-fn extract_commitment_params(tx: &ApiTransaction, signals: &CommitmentSignals) -> LightningParams {
+fn extract_commitment_params(
+    tx: &ApiTransaction,
+    signals: &CommitmentSignals,
+    config: &DetectorConfig,
+) -> LightningParams {
     let commitment_number = if signals.locktime_match && signals.sequence_match {
         let locktime_lower = (tx.locktime & 0x00FFFFFF) as u64;
         let seq_lower = tx
             .vin
             .iter()
-            .find(|v| is_lightning_sequence(v.sequence))
+            .find(|v| is_lightning_sequence(v.sequence, config))
             .map(|v| (v.sequence & 0x00FFFFFF) as u64)
             .unwrap_or(0);
         Some((seq_lower << 24) | locktime_lower)
@@ -116,48 +662,173 @@ fn extract_commitment_params(tx: &ApiTransaction, signals: &CommitmentSignals) -
         None
     };
 
-    // Count HTLC outputs: P2WSH outputs that aren't anchor outputs
-    let htlc_output_count = tx
-        .vout
-        .iter()
-        .filter(|o| o.scriptpubkey_type == "v0_p2wsh" && o.value != ANCHOR_VALUE)
-        .count();
-
-    // Subtract 1 for to_local (first non-anchor P2WSH) if present
-    let htlc_output_count = htlc_output_count.saturating_sub(1);
+    // Taproot outputs can't be told apart by type, so the HTLC count is unknown
+    let (output_roles, htlc_output_count) = if signals.is_taproot {
+        (taproot_output_roles(tx, config), None)
+    } else {
+        let roles = output_roles(tx, config);
+        let count = roles.iter().filter(|r| **r == OutputRole::Htlc).count();
+        (roles, Some(count))
+    };
 
     let csv_delays = extract_csv_delays_from_inputs(tx);
 
     LightningParams {
         commitment_number,
-        htlc_output_count: Some(htlc_output_count),
+        htlc_output_count,
+        output_roles,
         csv_delays,
+        to_self_delay: extract_to_self_delay(tx),
+        possible_trimmed_htlcs: is_possibly_trimmed(tx, htlc_output_count),
+        approx_channel_capacity: Some(approx_channel_capacity(tx)),
         ..Default::default()
     }
 }
 
+/// A commitment pays out the whole funding output: balances, HTLCs and the anchors,
+/// which come out of the opener's balance, with the rest (trimmed HTLCs included)
+/// left as fee.
+fn approx_channel_capacity(tx: &ApiTransaction) -> u64 {
+    if let [vin] = tx.vin.as_slice()
+        && let Some(ref prevout) = vin.prevout
+    {
+        return prevout.value;
+    }
+    tx.vout.iter().map(|o| o.value).sum::<u64>() + tx.fee.unwrap_or(0)
+}
+
+/// Fee rate above which a commitment's fee suggests trimmed HTLCs: 2.5 sat/WU
+/// (10 sat/vB). Anchor commitments pay a low fee and rely on CPFP, so anything well
+/// above that is most likely HTLC value below the dust limit folded into the fee.
+const TRIMMED_HTLC_MIN_FEE_RATE: f64 = 2.5;
+/// Most HTLC outputs a commitment can have and still be flagged: with many HTLCs
+/// already on-chain, a high fee is more likely just a high feerate.
+const TRIMMED_HTLC_MAX_HTLC_OUTPUTS: usize = 1;
+
+/// Needs `fee`; taproot commitments, whose HTLC count is unknown, are judged on fee alone.
+fn is_possibly_trimmed(tx: &ApiTransaction, htlc_output_count: Option<usize>) -> bool {
+    let Some(fee) = tx.fee else {
+        return false;
+    };
+    tx.weight > 0
+        && fee as f64 / tx.weight as f64 > TRIMMED_HTLC_MIN_FEE_RATE
+        && htlc_output_count.is_none_or(|count| count <= TRIMMED_HTLC_MAX_HTLC_OUTPUTS)
+}
+
+/// Which side of the channel offered an HTLC, as seen from the commitment's holder.
+#[derive(Clone, Copy, PartialEq)]
+pub(crate) enum HtlcDirection {
+    Offered,
+    Received,
+}
+
+/// Direction of the HTLC output `vin` spends, read from the witness script the spend
+/// reveals. A commitment's own outputs only show a P2WSH hash, so this is the only
+/// place the HTLC scripts appear on-chain.
+pub(crate) fn htlc_input_direction(vin: &ApiVin) -> Option<HtlcDirection> {
+    htlc_direction(vin.inner_witnessscript_asm.as_deref()?)
+}
+
+/// Offered and received HTLC outputs spent by `tx`'s inputs, or `None` when no input
+/// reveals an HTLC script.
+fn spent_htlc_directions(tx: &ApiTransaction) -> Option<(usize, usize)> {
+    let directions: Vec<HtlcDirection> = tx.vin.iter().filter_map(htlc_input_direction).collect();
+    if directions.is_empty() {
+        return None;
+    }
+    let offered = directions.iter().filter(|d| **d == HtlcDirection::Offered).count();
+    Some((offered, directions.len() - offered))
+}
+
+/// BOLT 3 HTLC scripts branch on `OP_SIZE 32 OP_EQUAL`. An offered HTLC continues
+/// with `OP_NOTIF` (the remote party claims with the preimage; the local timeout goes
+/// through the HTLC-timeout transaction's nLockTime). A received HTLC continues with
+/// `OP_IF` and carries the remote party's timeout as `OP_CHECKLOCKTIMEVERIFY`.
+fn htlc_direction(asm: &str) -> Option<HtlcDirection> {
+    let tokens: Vec<&str> = asm.split_whitespace().collect();
+    let size = tokens.iter().position(|t| *t == "OP_SIZE")?;
+    let has_cltv = tokens
+        .iter()
+        .any(|t| *t == "OP_CHECKLOCKTIMEVERIFY" || *t == "OP_CLTV");
+    match tokens[size..].iter().find(|t| matches!(**t, "OP_IF" | "OP_NOTIF")) {
+        Some(&"OP_NOTIF") if !has_cltv => Some(HtlcDirection::Offered),
+        Some(&"OP_IF") if has_cltv => Some(HtlcDirection::Received),
+        _ => None,
+    }
+}
+
+/// Assign a role to each commitment output: anchors first, then the first remaining
+/// P2WSH is to_local and any further P2WSH outputs are HTLCs.
+fn output_roles(tx: &ApiTransaction, config: &DetectorConfig) -> Vec<OutputRole> {
+    let mut seen_to_local = false;
+    let anchor_spks = anchor_script_pubkeys(tx);
+    tx.vout
+        .iter()
+        .map(|o| {
+            if is_anchor_output(o, anchor_spks.as_deref(), config) {
+                OutputRole::Anchor
+            } else if o.scriptpubkey_type == "v0_p2wsh" {
+                if seen_to_local {
+                    OutputRole::Htlc
+                } else {
+                    seen_to_local = true;
+                    OutputRole::ToLocal
+                }
+            } else if matches!(o.scriptpubkey_type.as_str(), "v0_p2wpkh" | "v1_p2tr") {
+                OutputRole::ToRemote
+            } else {
+                OutputRole::Other
+            }
+        })
+        .collect()
+}
+
+fn taproot_output_roles(tx: &ApiTransaction, config: &DetectorConfig) -> Vec<OutputRole> {
+    tx.vout
+        .iter()
+        .map(|o| {
+            if is_taproot_anchor_output(o, config) {
+                OutputRole::Anchor
+            } else {
+                OutputRole::Taproot
+            }
+        })
+        .collect()
+}
+
+/// BOLT 3: lower 48 bits of SHA256(opener payment_basepoint || accepter payment_basepoint).
+fn obscuring_factor(context: &ChannelContext) -> u64 {
+    let mut engine = sha256::Hash::engine();
+    engine.input(&context.opener_payment_basepoint.serialize());
+    engine.input(&context.accepter_payment_basepoint.serialize());
+    let hash = sha256::Hash::from_engine(engine);
+
+    hash.to_byte_array()[26..]
+        .iter()
+        .fold(0u64, |acc, &b| (acc << 8) | b as u64)
+}
+
 // ─── HTLC detection ─────────────────────────────────────────────────────────
 
 fn detect_htlc_signals(tx: &ApiTransaction) -> HtlcSignals {
-    let mut has_preimage = false;
-    let mut preimage = None;
+    let mut preimages = Vec::new();
+    let mut preimage_verified = false;
     let mut script_has_cltv = false;
     let mut script_has_csv = false;
+    let mut shaped_inputs = false;
 
     for vin in &tx.vin {
-        // Check witness for 32-byte preimage (64 hex chars, valid hex)
-        if let Some(ref witness) = vin.witness {
-            for elem in witness {
-                if elem.len() == 64 && is_valid_hex(elem) {
-                    has_preimage = true;
-                    preimage = Some(elem.clone());
-                    break;
-                }
+        shaped_inputs |= witness_htlc_shape(vin).is_some();
+        if let Some(found) = witness_preimage(vin) {
+            if preimages.is_empty() {
+                preimage_verified = !script_payment_hashes(vin).is_empty();
             }
+            preimages.push(found);
         }
 
-        // Check witness script for CLTV/CSV opcodes
-        if let Some(ref asm) = vin.inner_witnessscript_asm {
+        // Check spent scripts for CLTV/CSV opcodes. Anchor spends carry `OP_16 OP_CSV`
+        // but are not HTLCs.
+        for asm in input_scripts(vin).iter().filter(|asm| !is_anchor_script(asm)) {
             if asm.contains("OP_CHECKLOCKTIMEVERIFY") || asm.contains("OP_CLTV") {
                 script_has_cltv = true;
             }
@@ -169,10 +840,46 @@ fn detect_htlc_signals(tx: &ApiTransaction) -> HtlcSignals {
 
     HtlcSignals {
         locktime_value: tx.locktime,
-        has_preimage,
-        preimage,
+        has_preimage: !preimages.is_empty(),
+        preimage: preimages.first().cloned(),
+        preimages,
+        preimage_verified,
         script_has_cltv,
         script_has_csv,
+        witness_shape_only: shaped_inputs && !tx.vin.iter().any(has_htlc_script),
+    }
+}
+
+/// Decoded scripts revealed by an input: the P2WSH witness script, the P2SH redeem
+/// script (legacy and nested HTLCs), and the scriptSig itself. Without a decoded
+/// witness script, the spent output is used instead when the source provided it.
+fn input_scripts(vin: &ApiVin) -> Vec<Cow<'_, str>> {
+    let witness_script = match vin.inner_witnessscript_asm {
+        Some(ref asm) => Some(Cow::Borrowed(asm.as_str())),
+        None => prevout_script_asm(vin).map(Cow::Owned),
+    };
+    [witness_script]
+        .into_iter()
+        .chain(
+            [&vin.inner_redeemscript_asm, &vin.scriptsig_asm]
+                .into_iter()
+                .map(|asm| asm.as_deref().map(Cow::Borrowed)),
+        )
+        .flatten()
+        .collect()
+}
+
+/// Script recovered from the prevout: for P2WSH, the witness script is the last
+/// witness element; anything else is disassembled as is, which only matters for
+/// bare (non-template) scripts.
+fn prevout_script_asm(vin: &ApiVin) -> Option<String> {
+    let prevout = vin.prevout.as_ref()?;
+    if prevout.scriptpubkey_type == "v0_p2wsh" {
+        let last = vin.witness.as_ref()?.last()?;
+        let bytes = Vec::<u8>::from_hex(last).ok()?;
+        Some(ScriptBuf::from(bytes).to_asm_string())
+    } else {
+        Some(prevout.scriptpubkey_asm.clone())
     }
 }
 
@@ -183,71 +890,797 @@ fn is_valid_hex(s: &str) -> bool {
 fn classify_htlc(
     tx: &ApiTransaction,
     signals: &HtlcSignals,
+    config: &DetectorConfig,
 ) -> Option<(LightningTxType, Confidence, LightningParams)> {
-    let has_htlc_script = signals.script_has_cltv || signals.script_has_csv;
+    let htlc_inputs = classify_htlc_inputs(tx, config);
+    let htlc_type = dominant_htlc_type(&htlc_inputs)?;
 
-    if !has_htlc_script {
+    let confidence = match htlc_type {
+        _ if signals.witness_shape_only => Confidence::Possible,
+        // HTLC-success: preimage present, locktime = 0
+        LightningTxType::HtlcSuccess if tx.locktime == 0 => Confidence::HighlyLikely,
+        // HTLC-timeout: no preimage, locktime = block height or timestamp expiry
+        LightningTxType::HtlcTimeout
+            if !signals.has_preimage
+                && (is_realistic_expiry(tx.locktime, config)
+                    || is_timestamp_locktime(tx.locktime, config)) =>
+        {
+            Confidence::HighlyLikely
+        }
+        // Has HTLC-like script patterns but doesn't cleanly match either type, or the
+        // witness only looks like an HTLC spend
+        _ => Confidence::Possible,
+    };
+
+    let is_success = htlc_type == LightningTxType::HtlcSuccess;
+    let (offered_htlc_count, received_htlc_count) = spent_htlc_directions(tx).unzip();
+    let preimages: Vec<String> = if is_success {
+        htlc_inputs.iter().filter_map(|i| i.preimage.clone()).collect()
+    } else {
+        Vec::new()
+    };
+    let params = LightningParams {
+        preimage_revealed: is_success,
+        preimage: preimages.first().cloned(),
+        preimages,
+        payment_hash: htlc_inputs
+            .iter()
+            .filter(|i| i.tx_type == htlc_type)
+            .find_map(|i| i.payment_hash.clone()),
+        cltv_expiry: (!is_success && is_realistic_expiry(tx.locktime, config))
+            .then_some(tx.locktime),
+        cltv_expiry_time: (!is_success && is_timestamp_locktime(tx.locktime, config))
+            .then_some(tx.locktime),
+        csv_delays: extract_csv_delays_from_inputs(tx),
+        offered_htlc_count,
+        received_htlc_count,
+        htlc_inputs,
+        ..Default::default()
+    };
+    Some((htlc_type, confidence, params))
+}
+
+/// Classify every input whose scripts carry CLTV or CSV: a preimage in its witness
+/// makes it a success claim, anything else a timeout. Inputs whose scripts aren't
+/// visible are classified by the shape of their witness instead.
+fn classify_htlc_inputs(
+    tx: &ApiTransaction,
+    config: &DetectorConfig,
+) -> Vec<HtlcInputClassification> {
+    tx.vin
+        .iter()
+        .enumerate()
+        .filter_map(|(input_index, vin)| {
+            let preimage = witness_preimage(vin);
+            let tx_type = if !has_htlc_script(vin) {
+                witness_htlc_shape(vin)?
+            } else if preimage.is_some() {
+                LightningTxType::HtlcSuccess
+            } else {
+                LightningTxType::HtlcTimeout
+            };
+            let cltv_expiry = script_cltv_expiry(vin).or_else(|| {
+                let is_timeout = tx_type == LightningTxType::HtlcTimeout;
+                (is_timeout && is_realistic_expiry(tx.locktime, config)).then_some(tx.locktime)
+            });
+            Some(HtlcInputClassification {
+                input_index,
+                tx_type,
+                preimage,
+                payment_hash: script_payment_hashes(vin)
+                    .first()
+                    .map(|hash| hash.to_lower_hex_string()),
+                cltv_expiry,
+                csv_delays: csv_delays_in(vin),
+            })
+        })
+        .collect()
+}
+
+/// The most common input type; ties go to the first input.
+fn dominant_htlc_type(inputs: &[HtlcInputClassification]) -> Option<LightningTxType> {
+    let first = inputs.first()?.tx_type;
+    let successes = inputs
+        .iter()
+        .filter(|i| i.tx_type == LightningTxType::HtlcSuccess)
+        .count();
+    let timeouts = inputs.len() - successes;
+    Some(match successes.cmp(&timeouts) {
+        Ordering::Greater => LightningTxType::HtlcSuccess,
+        Ordering::Less => LightningTxType::HtlcTimeout,
+        Ordering::Equal => first,
+    })
+}
+
+/// HTLC spend type read from the witness stack alone, for an input whose scripts aren't
+/// visible (no decoded witness script and no prevout, as with raw hex). BOLT 3
+/// second-stage spends push `<> <sig> <sig> <preimage or empty> <script>`, and a
+/// counterparty's direct success claim `<sig> <preimage> <script>`. Its direct timeout
+/// claim, `<sig> <> <script>`, is left out: a to_local sweep looks the same.
+fn witness_htlc_shape(vin: &ApiVin) -> Option<LightningTxType> {
+    if vin.inner_witnessscript_asm.is_some() || vin.prevout.is_some() {
         return None;
     }
+    let (sigs, selector, script) = match vin.witness.as_deref()? {
+        [dummy, sig1, sig2, selector, script] if dummy.is_empty() => {
+            (vec![sig1, sig2], selector, script)
+        }
+        [sig, preimage, script] if preimage.len() == 64 => (vec![sig], preimage, script),
+        _ => return None,
+    };
+    // DER signatures (0x30 ...) are at most 73 bytes with the sighash flag
+    let is_signature = |elem: &String| elem.starts_with("30") && (18..=146).contains(&elem.len());
+    if !sigs.into_iter().all(is_signature)
+        || !HTLC_SCRIPT_BYTES.contains(&(script.len() / 2))
+        || !is_valid_hex(selector)
+    {
+        return None;
+    }
+    match selector.len() {
+        0 => Some(LightningTxType::HtlcTimeout),
+        64 => Some(LightningTxType::HtlcSuccess),
+        _ => None,
+    }
+}
 
-    let csv_delays = extract_csv_delays_from_inputs(tx);
+fn has_htlc_script(vin: &ApiVin) -> bool {
+    input_scripts(vin)
+        .iter()
+        .filter(|asm| !is_anchor_script(asm) && !is_to_remote_delayed_script(asm))
+        .any(|asm| {
+            ["OP_CHECKLOCKTIMEVERIFY", "OP_CLTV", "OP_CHECKSEQUENCEVERIFY", "OP_CSV"]
+                .iter()
+                .any(|op| asm.contains(op))
+        })
+}
 
-    if signals.has_preimage && tx.locktime == 0 {
-        // HTLC-success: preimage present, locktime = 0
-        let params = LightningParams {
-            preimage_revealed: true,
-            preimage: signals.preimage.clone(),
-            csv_delays,
-            ..Default::default()
-        };
-        Some((LightningTxType::HtlcSuccess, Confidence::HighlyLikely, params))
-    } else if !signals.has_preimage && is_realistic_block_height(tx.locktime) {
-        // HTLC-timeout: no preimage, locktime = realistic block height
-        let params = LightningParams {
-            cltv_expiry: Some(tx.locktime),
-            csv_delays,
-            ..Default::default()
-        };
-        Some((LightningTxType::HtlcTimeout, Confidence::HighlyLikely, params))
-    } else if has_htlc_script {
-        // Has HTLC-like script patterns but doesn't cleanly match either type
-        let params = LightningParams {
-            cltv_expiry: if is_realistic_block_height(tx.locktime) {
-                Some(tx.locktime)
-            } else {
-                None
-            },
-            csv_delays,
-            ..Default::default()
-        };
-        Some((LightningTxType::HtlcTimeout, Confidence::Possible, params))
+/// The witness element that hashes to a payment hash pushed by the input's scripts.
+/// When no script hash is visible, falls back to the first 32-byte (64 hex chars)
+/// element.
+fn witness_preimage(vin: &ApiVin) -> Option<String> {
+    let witness = vin.witness.as_ref()?;
+    let hashes = script_payment_hashes(vin);
+    if hashes.is_empty() {
+        return witness
+            .iter()
+            .find(|elem| elem.len() == 64 && is_valid_hex(elem))
+            .cloned();
+    }
+    witness
+        .iter()
+        .find(|elem| {
+            let Ok(bytes) = Vec::<u8>::from_hex(elem) else {
+                return false;
+            };
+            !bytes.is_empty()
+                && hashes.iter().any(|hash| match hash.len() {
+                    20 => hash160::Hash::hash(&bytes).as_byte_array() == hash.as_slice(),
+                    32 => sha256::Hash::hash(&bytes).as_byte_array() == hash.as_slice(),
+                    _ => false,
+                })
+        })
+        .cloned()
+}
+
+/// Hashes a preimage is checked against: the push after `OP_HASH160`
+/// (RIPEMD160(SHA256(x)), as in BOLT 3 HTLCs) or `OP_SHA256`. `OP_DUP OP_HASH160`
+/// commits to a public key, not a payment hash, and is skipped.
+fn script_payment_hashes(vin: &ApiVin) -> Vec<Vec<u8>> {
+    let mut hashes = Vec::new();
+    for asm in input_scripts(vin) {
+        let tokens: Vec<&str> = asm.split_whitespace().collect();
+        for (i, op) in tokens.iter().enumerate() {
+            let len = match *op {
+                "OP_HASH160" if i == 0 || tokens[i - 1] != "OP_DUP" => 20,
+                "OP_SHA256" => 32,
+                _ => continue,
+            };
+            // Esplora and rust-bitcoin asm put OP_PUSHBYTES_N before the data
+            let data = tokens[i + 1..]
+                .iter()
+                .find(|t| !t.starts_with("OP_PUSHBYTES_"));
+            if let Some(hash) = data.and_then(|t| Vec::<u8>::from_hex(t).ok())
+                && hash.len() == len
+            {
+                hashes.push(hash);
+            }
+        }
+    }
+    hashes
+}
+
+fn script_cltv_expiry(vin: &ApiVin) -> Option<u32> {
+    input_scripts(vin).iter().find_map(|asm| {
+        let tokens: Vec<&str> = asm.split_whitespace().collect();
+        tokens
+            .iter()
+            .position(|t| *t == "OP_CHECKLOCKTIMEVERIFY" || *t == "OP_CLTV")
+            .and_then(|i| decode_push_before(&tokens, i))
+            .and_then(|v| u32::try_from(v).ok())
+    })
+}
+
+/// nLockTime values below this are block heights, at or above are Unix timestamps.
+const LOCKTIME_THRESHOLD: u32 = 500_000_000;
+
+/// Check if a locktime value is a block height (not 0).
+fn is_block_height_locktime(locktime: u32) -> bool {
+    locktime > 0 && locktime < LOCKTIME_THRESHOLD
+}
+
+/// Check if a locktime value is a block height an HTLC could expire at: not past the
+/// bound set from the tip, when there is one.
+fn is_realistic_expiry(locktime: u32, config: &DetectorConfig) -> bool {
+    is_block_height_locktime(locktime)
+        && config.max_htlc_expiry.is_none_or(|max| u64::from(locktime) <= max)
+}
+
+/// Check if a locktime value is a Unix timestamp (not the Lightning commitment encoding).
+fn is_timestamp_locktime(locktime: u32, config: &DetectorConfig) -> bool {
+    locktime >= LOCKTIME_THRESHOLD && !is_lightning_locktime(locktime, config)
+}
+
+// ─── Penalty (justice) detection ────────────────────────────────────────────
+
+const SEQUENCE_DISABLE_FLAG: u32 = 1 << 31;
+
+fn classify_penalty(tx: &ApiTransaction) -> Option<(Confidence, LightningParams)> {
+    // Group revocation-path spends by the commitment they sweep
+    let mut swept_by_prev: HashMap<&str, usize> = HashMap::new();
+    for vin in tx.vin.iter().filter(|v| is_revocation_spend(v)) {
+        if let Some(ref prev_txid) = vin.txid {
+            *swept_by_prev.entry(prev_txid.as_str()).or_insert(0) += 1;
+        }
+    }
+
+    let swept = swept_by_prev.into_values().max()?;
+    let confidence = if swept >= 2 {
+        Confidence::HighlyLikely
     } else {
-        None
+        Confidence::Possible
+    };
+
+    let params = LightningParams {
+        swept_output_count: Some(swept),
+        csv_delays: extract_csv_delays_from_inputs(tx),
+        to_self_delay: extract_to_self_delay(tx),
+        ..Default::default()
+    };
+    Some((confidence, params))
+}
+
+/// A P2WSH spend whose script has an `OP_ELSE ... OP_CHECKSIG` branch guarded by CSV,
+/// but whose sequence disables relative timelocks — so the CSV branch cannot have been
+/// taken and the revocation branch was used instead.
+fn is_revocation_spend(vin: &ApiVin) -> bool {
+    let Some(ref asm) = vin.inner_witnessscript_asm else {
+        return false;
+    };
+
+    let tokens: Vec<&str> = asm.split_whitespace().collect();
+    let has_csv = tokens
+        .iter()
+        .any(|t| *t == "OP_CHECKSEQUENCEVERIFY" || *t == "OP_CSV");
+    let has_revocation_branch = tokens
+        .iter()
+        .position(|t| *t == "OP_ELSE")
+        .is_some_and(|else_idx| tokens[else_idx..].contains(&"OP_CHECKSIG"));
+
+    has_csv && has_revocation_branch && vin.sequence & SEQUENCE_DISABLE_FLAG != 0
+}
+
+// ─── to_local sweep (delayed self-sweep) detection ──────────────────────────
+
+const SEQUENCE_TYPE_FLAG: u32 = 1 << 22;
+
+fn classify_to_local_sweep(tx: &ApiTransaction) -> Option<LightningParams> {
+    let (vin, delay) = tx
+        .vin
+        .iter()
+        .find_map(|v| to_local_sweep_delay(v).map(|delay| (v, delay)))?;
+
+    Some(LightningParams {
+        parent_txid: vin.txid.clone(),
+        to_self_delay: Some(delay),
+        csv_delays: extract_csv_delays_from_inputs(tx),
+        ..Default::default()
+    })
+}
+
+/// The `to_self_delay` of an input spending a to_local script through its `OP_ELSE`
+/// branch: a block-based relative timelock of at least the delay, and an empty branch
+/// selector below the witness script.
+fn to_local_sweep_delay(vin: &ApiVin) -> Option<u16> {
+    let delay = to_local_delay(vin.inner_witnessscript_asm.as_deref()?)?;
+
+    let seq = vin.sequence;
+    if seq & (SEQUENCE_DISABLE_FLAG | SEQUENCE_TYPE_FLAG) != 0 || (seq & 0xFFFF) < u32::from(delay) {
+        return None;
+    }
+    if let Some(witness) = &vin.witness
+        && witness.len() >= 2
+        && !witness[witness.len() - 2].is_empty()
+    {
+        return None;
+    }
+
+    Some(delay)
+}
+
+// ─── Anchor sweep (CPFP) detection ──────────────────────────────────────────
+
+/// A small transaction with a single output that spends an anchor output: a node
+/// bumping the fee of its force-close via CPFP.
+fn classify_anchor_sweep(tx: &ApiTransaction, config: &DetectorConfig) -> Option<LightningParams> {
+    if tx.vout.len() != 1 || tx.weight > ANCHOR_SWEEP_MAX_WEIGHT {
+        return None;
+    }
+
+    let anchor = tx.vin.iter().find(|v| is_anchor_spend(v, config))?;
+    if tx.vout[0].value <= config.anchor_value {
+        return None;
+    }
+
+    Some(LightningParams {
+        parent_txid: anchor.txid.clone(),
+        ..Default::default()
+    })
+}
+
+/// An input spending a tiny output through the BOLT 3 anchor witness script, or a
+/// pay-to-anchor output.
+fn is_anchor_spend(vin: &ApiVin, config: &DetectorConfig) -> bool {
+    let Some(prevout) = &vin.prevout else {
+        return false;
+    };
+    if prevout.scriptpubkey_type == PAY_TO_ANCHOR_TYPE {
+        return true;
     }
+    prevout.value <= config.anchor_max_value
+        && vin
+            .inner_witnessscript_asm
+            .as_deref()
+            .is_some_and(is_anchor_script)
 }
 
-/// Check if a locktime value is a realistic block height (not Lightning encoding, not 0).
-fn is_realistic_block_height(locktime: u32) -> bool {
-    locktime > 0 && locktime < 500_000_000 && (locktime >> 24) != 0x20
+// ─── Splice detection ───────────────────────────────────────────────────────
+
+/// Smallest P2WSH output taken for a new funding output; smaller channels are
+/// refused by the major implementations.
+const SPLICE_MIN_FUNDING_VALUE: u64 = 20_000;
+
+/// One input spends a 2-of-2 funding output and a large P2WSH output with a different
+/// value takes its place; other inputs and outputs add or remove funds. The old value
+/// comes from the prevout, so without one this is left to mutual close detection.
+/// Highly likely when the new funding output is the largest output, since a
+/// cooperative close paying a P2WSH address looks the same otherwise.
+fn classify_splice(tx: &ApiTransaction) -> Option<(Confidence, LightningParams)> {
+    let mut funding_inputs = tx.vin.iter().filter(|v| is_two_of_two_spend(v));
+    let funding_input = funding_inputs.next()?;
+    if funding_inputs.next().is_some() {
+        return None;
+    }
+
+    let old_value = funding_input.prevout.as_ref()?.value;
+    let new_value = tx
+        .vout
+        .iter()
+        .filter(|o| o.scriptpubkey_type == "v0_p2wsh" && o.value >= SPLICE_MIN_FUNDING_VALUE)
+        .map(|o| o.value)
+        .filter(|value| *value != old_value)
+        .max()?;
+
+    let confidence = if tx.vout.iter().map(|o| o.value).max() == Some(new_value) {
+        Confidence::HighlyLikely
+    } else {
+        Confidence::Possible
+    };
+
+    let params = LightningParams {
+        old_funding_value: Some(old_value),
+        new_funding_value: Some(new_value),
+        ..Default::default()
+    };
+    Some((confidence, params))
+}
+
+// ─── Mutual close detection ─────────────────────────────────────────────────
+
+fn detect_mutual_close_signals(tx: &ApiTransaction, config: &DetectorConfig) -> MutualCloseSignals {
+    let two_of_two_input = tx.vin.len() == 1 && is_two_of_two_spend(&tx.vin[0]);
+    let key_path_output_count = tx
+        .vout
+        .iter()
+        .filter(|o| matches!(o.scriptpubkey_type.as_str(), "v0_p2wpkh" | "v1_p2tr"))
+        .count();
+    let anchor_spks = anchor_script_pubkeys(tx);
+    let has_anchor_or_htlc_outputs = tx.vout.iter().any(|o| {
+        is_anchor_output(o, anchor_spks.as_deref(), config) || o.scriptpubkey_type == "v0_p2wsh"
+    });
+
+    MutualCloseSignals {
+        two_of_two_input,
+        locktime_zero: tx.locktime == 0,
+        final_sequences: tx.vin.iter().all(|v| v.sequence == 0xFFFFFFFF),
+        key_path_output_count,
+        has_anchor_or_htlc_outputs,
+    }
+}
+
+/// A funding output spend: P2WSH witness of `<empty> <sig> <sig> <2-of-2 script>`.
+fn is_two_of_two_spend(vin: &ApiVin) -> bool {
+    if let Some(ref prevout) = vin.prevout
+        && prevout.scriptpubkey_type != "v0_p2wsh"
+    {
+        return false;
+    }
+
+    let signature_count = vin
+        .witness
+        .as_ref()
+        .map(|w| w.iter().filter(|e| is_der_signature(e)).count())
+        .unwrap_or(0);
+
+    let script_is_multisig = vin
+        .inner_witnessscript_asm
+        .as_deref()
+        .is_some_and(is_two_of_two_script);
+
+    signature_count == 2 && script_is_multisig
+}
+
+/// DER-encoded ECDSA signature plus sighash byte: 0x30 prefix, 70–73 bytes.
+fn is_der_signature(elem: &str) -> bool {
+    (140..=146).contains(&elem.len()) && elem.starts_with("30") && is_valid_hex(elem)
+}
+
+/// `OP_PUSHNUM_2 <pubkey> <pubkey> OP_PUSHNUM_2 OP_CHECKMULTISIG`
+pub(crate) fn is_two_of_two_script(asm: &str) -> bool {
+    let tokens: Vec<&str> = asm.split_whitespace().collect();
+    let n = tokens.len();
+    n >= 3
+        && tokens[0] == "OP_PUSHNUM_2"
+        && tokens[n - 2] == "OP_PUSHNUM_2"
+        && tokens[n - 1] == "OP_CHECKMULTISIG"
+}
+
+fn mutual_close_confidence(signals: &MutualCloseSignals) -> Confidence {
+    if !signals.two_of_two_input {
+        return Confidence::None;
+    }
+
+    if signals.locktime_zero
+        && signals.final_sequences
+        && signals.key_path_output_count == 2
+        && !signals.has_anchor_or_htlc_outputs
+    {
+        Confidence::HighlyLikely
+    } else {
+        Confidence::Possible
+    }
+}
+
+// ─── Classification trace ───────────────────────────────────────────────────
+
+fn yes_no(value: bool) -> &'static str {
+    if value { "yes" } else { "no" }
+}
+
+fn confidence_name(confidence: Confidence) -> &'static str {
+    match confidence {
+        Confidence::HighlyLikely => "highly likely",
+        Confidence::Possible => "possible",
+        Confidence::None => "none",
+    }
+}
+
+fn channel_type_name(channel_type: ChannelType) -> &'static str {
+    match channel_type {
+        ChannelType::Legacy => "legacy",
+        ChannelType::Anchor => "anchor",
+        ChannelType::Taproot => "taproot",
+        ChannelType::Unknown => "unknown",
+    }
+}
+
+/// Each commitment signal with its weight, then the score's confidence.
+fn commitment_trace(
+    tx: &ApiTransaction,
+    signals: &CommitmentSignals,
+    config: &DetectorConfig,
+) -> Vec<String> {
+    let weight = |matched: bool, weight: u32| {
+        if matched {
+            format!("yes (+{weight})")
+        } else {
+            "no".to_string()
+        }
+    };
+    let score = commitment_score(signals);
+    let highly_likely = config.highly_likely_score;
+    let mapping = match commitment_confidence(signals, config) {
+        Confidence::HighlyLikely => {
+            format!("score {score} (at least {highly_likely}) -> highly likely")
+        }
+        Confidence::Possible => format!("score {score} (below {highly_likely}) -> possible"),
+        Confidence::None => format!("score {score} -> not a commitment"),
+    };
+    vec![
+        format!(
+            "locktime 0x{:08x} has upper byte 0x{:02x}: {}",
+            tx.locktime,
+            config.locktime_upper_byte,
+            weight(signals.locktime_match, LOCKTIME_WEIGHT)
+        ),
+        format!(
+            "an input sequence has upper byte 0x{:02x}: {}",
+            config.sequence_upper_byte,
+            weight(signals.sequence_match, SEQUENCE_WEIGHT)
+        ),
+        format!(
+            "anchor outputs: {} {}",
+            signals.anchor_output_count,
+            weight(signals.has_anchor_outputs, ANCHOR_WEIGHT)
+        ),
+        format!(
+            "spends a delayed to_remote output: {}",
+            yes_no(signals.to_remote_delayed)
+        ),
+        format!("all outputs P2TR: {}", yes_no(signals.is_taproot)),
+        format!("channel type: {}", channel_type_name(signals.channel_type)),
+        mapping,
+    ]
+}
+
+fn htlc_trace(
+    tx: &ApiTransaction,
+    signals: &HtlcSignals,
+    result: Option<&(LightningTxType, Confidence, LightningParams)>,
+    config: &DetectorConfig,
+) -> Vec<String> {
+    let locktime = if tx.locktime == 0 {
+        "0".to_string()
+    } else if is_realistic_expiry(tx.locktime, config) {
+        format!("block height {}", tx.locktime)
+    } else if is_block_height_locktime(tx.locktime) {
+        format!("block height {}, too far past the chain tip", tx.locktime)
+    } else if is_timestamp_locktime(tx.locktime, config) {
+        format!("timestamp {}", tx.locktime)
+    } else {
+        format!("0x{:08x}", tx.locktime)
+    };
+    let preimage = match (signals.has_preimage, signals.preimage_verified) {
+        (true, true) => "found, matches the script's payment hash",
+        (true, false) => "32-byte element, no payment hash to check against",
+        (false, _) => "none",
+    };
+    let mut details = vec![
+        format!("script has CLTV: {}", yes_no(signals.script_has_cltv)),
+        format!("script has CSV: {}", yes_no(signals.script_has_csv)),
+        format!("HTLC witness shape only: {}", yes_no(signals.witness_shape_only)),
+        format!("witness preimage: {preimage}"),
+        format!("locktime: {locktime}"),
+    ];
+    match result {
+        Some((htlc_type, confidence, params)) => {
+            let successes = params
+                .htlc_inputs
+                .iter()
+                .filter(|i| i.tx_type == LightningTxType::HtlcSuccess)
+                .count();
+            let timeouts = params.htlc_inputs.len() - successes;
+            let type_name = if *htlc_type == LightningTxType::HtlcSuccess {
+                "HTLC-success"
+            } else {
+                "HTLC-timeout"
+            };
+            details.push(format!(
+                "HTLC inputs: {successes} success, {timeouts} timeout -> {type_name}, {}",
+                confidence_name(*confidence)
+            ));
+        }
+        None => details.push("no input spends an HTLC script".to_string()),
+    }
+    details
+}
+
+fn mutual_close_trace(signals: &MutualCloseSignals, confidence: Confidence) -> Vec<String> {
+    let mapping = match confidence {
+        Confidence::HighlyLikely => "2-of-2 spend with a clean close shape -> highly likely",
+        Confidence::Possible => "2-of-2 spend alone -> possible",
+        Confidence::None => "no 2-of-2 funding spend -> not a mutual close",
+    };
+    vec![
+        format!("single 2-of-2 funding input: {}", yes_no(signals.two_of_two_input)),
+        format!("locktime 0: {}", yes_no(signals.locktime_zero)),
+        format!("all sequences final: {}", yes_no(signals.final_sequences)),
+        format!("key-path outputs: {} (2 expected)", signals.key_path_output_count),
+        format!(
+            "anchor or HTLC outputs: {}",
+            yes_no(signals.has_anchor_or_htlc_outputs)
+        ),
+        mapping.to_string(),
+    ]
+}
+
+// ─── Classification reasons ─────────────────────────────────────────────────
+
+/// The signals that led to `classification.tx_type`, in plain words, or why a
+/// malformed transaction was skipped.
+fn reasons(
+    tx: &ApiTransaction,
+    classification: &LightningClassification,
+    config: &DetectorConfig,
+) -> Vec<String> {
+    if let Some(missing) = missing_inputs_or_outputs(tx) {
+        return vec![missing.to_string()];
+    }
+    let mut reasons = Vec::new();
+    let Some(tx_type) = classification.tx_type else {
+        return reasons;
+    };
+
+    match tx_type {
+        LightningTxType::Commitment => {
+            let s = &classification.commitment_signals;
+            if s.locktime_match {
+                reasons.push(format!("locktime upper byte 0x{:02x}", config.locktime_upper_byte));
+            }
+            if s.sequence_match {
+                reasons.push(format!("sequence upper byte 0x{:02x}", config.sequence_upper_byte));
+            }
+            if s.has_anchor_outputs {
+                reasons.push(format!("{} anchor outputs", s.anchor_output_count));
+            }
+            if s.is_taproot {
+                reasons.push("all outputs P2TR".to_string());
+            }
+            if s.channel_type == ChannelType::Legacy {
+                reasons.push("no anchors, undelayed P2WPKH to_remote (legacy channel)".to_string());
+            }
+            if classification.params.possible_trimmed_htlcs
+                && let Some(fee) = tx.fee
+            {
+                let rate = fee as f64 / tx.weight as f64;
+                reasons.push(format!("fee rate {rate:.2} sat/WU suggests trimmed HTLCs"));
+            }
+        }
+        LightningTxType::Penalty => {
+            if let Some(swept) = classification.params.swept_output_count {
+                reasons.push(format!("{swept} revocation-path spends of the same commitment"));
+            }
+        }
+        LightningTxType::ToLocalSweep => {
+            reasons.push("spends a to_local output through its CSV branch".to_string());
+            if let Some(delay) = classification.params.to_self_delay {
+                reasons.push(format!("sequence satisfies to_self_delay of {delay} blocks"));
+            }
+        }
+        LightningTxType::AnchorSweep => {
+            reasons.push("spends an anchor output".to_string());
+            reasons.push(format!("single output, {} WU", tx.weight));
+        }
+        LightningTxType::HtlcSuccess | LightningTxType::HtlcTimeout => {
+            let s = &classification.htlc_signals;
+            if s.preimage_verified {
+                reasons.push("witness preimage matches the script's payment hash".to_string());
+            } else if s.has_preimage {
+                reasons.push("32-byte preimage in witness".to_string());
+            }
+            if s.preimages.len() > 1 {
+                reasons.push(format!("{} preimages across inputs", s.preimages.len()));
+            }
+            if s.script_has_cltv {
+                reasons.push("script contains OP_CHECKLOCKTIMEVERIFY".to_string());
+            }
+            if s.script_has_csv {
+                reasons.push("script contains OP_CHECKSEQUENCEVERIFY".to_string());
+            }
+            if s.witness_shape_only {
+                reasons.push(
+                    "witness stack shaped like an HTLC spend (script not visible)".to_string(),
+                );
+            }
+            if tx.locktime == 0 {
+                reasons.push("nLockTime 0".to_string());
+            } else if is_block_height_locktime(tx.locktime) {
+                reasons.push(format!("nLockTime is block height {}", tx.locktime));
+                if !is_realistic_expiry(tx.locktime, config) {
+                    reasons.push(
+                        "nLockTime too far past the chain tip to be an HTLC expiry".to_string(),
+                    );
+                }
+            } else if is_timestamp_locktime(tx.locktime, config) {
+                reasons.push(format!("nLockTime is timestamp {}", tx.locktime));
+            }
+        }
+        LightningTxType::Splice => {
+            let p = &classification.params;
+            reasons.push("spends a 2-of-2 funding output".to_string());
+            if let (Some(old), Some(new)) = (p.old_funding_value, p.new_funding_value) {
+                reasons.push(format!("new {new}-sat P2WSH funding output (was {old} sats)"));
+            }
+        }
+        LightningTxType::MutualClose => {
+            let s = &classification.mutual_close_signals;
+            reasons.push("single 2-of-2 multisig input".to_string());
+            if s.locktime_zero {
+                reasons.push("nLockTime 0".to_string());
+            }
+            if s.final_sequences {
+                reasons.push("final sequences".to_string());
+            }
+            reasons.push(format!("{} key-path outputs", s.key_path_output_count));
+            if !s.has_anchor_or_htlc_outputs {
+                reasons.push("no anchor or HTLC outputs".to_string());
+            }
+        }
+    }
+
+    reasons
 }
 
 // ─── Parameter extraction helpers ───────────────────────────────────────────
 
 fn extract_csv_delays_from_inputs(tx: &ApiTransaction) -> Vec<u16> {
+    tx.vin.iter().flat_map(csv_delays_in).collect()
+}
+
+fn csv_delays_in(vin: &ApiVin) -> Vec<u16> {
     let mut delays = Vec::new();
 
-    for vin in &tx.vin {
-        if let Some(ref asm) = vin.inner_witnessscript_asm {
-            let tokens: Vec<&str> = asm.split_whitespace().collect();
-            for (i, token) in tokens.iter().enumerate() {
-                if (*token == "OP_CHECKSEQUENCEVERIFY" || *token == "OP_CSV") && i > 0 {
-                    if let Ok(val) = tokens[i - 1].parse::<u16>() {
-                        delays.push(val);
-                    }
-                }
+    for asm in input_scripts(vin) {
+        let tokens: Vec<&str> = asm.split_whitespace().collect();
+        for (i, token) in tokens.iter().enumerate() {
+            if (*token == "OP_CHECKSEQUENCEVERIFY" || *token == "OP_CSV")
+                && let Some(val) = decode_push_before(&tokens, i)
+                && let Some(delay) = csv_block_delay(val)
+            {
+                delays.push(delay);
             }
         }
     }
 
     delays
 }
+
+/// Block delay of a CSV operand under BIP 68: the low 16 bits, with every other bit but
+/// the two flags ignored. `None` when the operand disables the check or counts time.
+fn csv_block_delay(operand: u64) -> Option<u16> {
+    let flags = u64::from(SEQUENCE_DISABLE_FLAG | SEQUENCE_TYPE_FLAG);
+    (operand & flags == 0).then_some((operand & 0xFFFF) as u16)
+}
+
+/// Find the `to_self_delay` of a to_local script:
+/// `OP_IF <revocationpubkey> OP_ELSE <to_self_delay> OP_CSV OP_DROP <local_delayedpubkey> OP_ENDIF OP_CHECKSIG`.
+///
+/// Output scripts are checked first (bare or decoded scripts), then input witness scripts,
+/// which reveal the to_local template when a revoked or matured output is being swept.
+pub fn extract_to_self_delay(tx: &ApiTransaction) -> Option<u16> {
+    tx.vout
+        .iter()
+        .map(|o| o.scriptpubkey_asm.as_str())
+        .chain(tx.vin.iter().filter_map(|v| v.inner_witnessscript_asm.as_deref()))
+        .find_map(to_local_delay)
+}
+
+fn to_local_delay(asm: &str) -> Option<u16> {
+    if !asm.starts_with("OP_IF ") || !asm.ends_with(" OP_ENDIF OP_CHECKSIG") {
+        return None;
+    }
+
+    let tokens: Vec<&str> = asm.split_whitespace().collect();
+    let csv = tokens
+        .iter()
+        .position(|t| *t == "OP_CHECKSEQUENCEVERIFY" || *t == "OP_CSV")?;
+    if tokens.get(csv + 1) != Some(&"OP_DROP") {
+        return None;
+    }
+    // The delay push (one token, or two with an OP_PUSHBYTES_N prefix) follows OP_ELSE
+    let else_pos = tokens[..csv].iter().rposition(|t| *t == "OP_ELSE")?;
+    if csv - else_pos > 3 {
+        return None;
+    }
+
+    u16::try_from(decode_push_before(&tokens, csv)?).ok()
+}
@@ -0,0 +1,40 @@
+//! Detection rules registered on a [`DetectorConfig`] to extend the built-in classifier
+//! without forking it.
+
+use std::fmt::Debug;
+
+use super::detector;
+use super::types::{Confidence, DetectorConfig, LightningTxType};
+use crate::api::types::ApiTransaction;
+
+/// What a heuristic concluded about a transaction: its type, how sure it is, and why.
+pub type HeuristicMatch = (LightningTxType, Confidence, Vec<String>);
+
+/// A detection rule. Registered heuristics run after the built-in checks, in the order
+/// of `DetectorConfig::heuristics`, and the first to match decides the transaction's
+/// type and confidence. Its reasons are added to the built-in ones when both agree on
+/// the type, and replace them otherwise.
+pub trait LightningHeuristic: Debug + Send + Sync {
+    fn evaluate(&self, tx: &ApiTransaction) -> Option<HeuristicMatch>;
+}
+
+/// The built-in commitment check, with the default detection values. Register it ahead
+/// of a custom heuristic to keep commitments from being reclassified by it.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CommitmentHeuristic;
+
+impl LightningHeuristic for CommitmentHeuristic {
+    fn evaluate(&self, tx: &ApiTransaction) -> Option<HeuristicMatch> {
+        detector::commitment_match(tx, &DetectorConfig::default())
+    }
+}
+
+/// The built-in HTLC-timeout and HTLC-success check, with the default detection values.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct HtlcHeuristic;
+
+impl LightningHeuristic for HtlcHeuristic {
+    fn evaluate(&self, tx: &ApiTransaction) -> Option<HeuristicMatch> {
+        detector::htlc_match(tx, &DetectorConfig::default())
+    }
+}
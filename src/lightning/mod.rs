@@ -1,2 +1,5 @@
+pub mod channel;
 pub mod detector;
+pub mod heuristic;
 pub mod types;
+pub mod verify;
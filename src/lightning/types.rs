@@ -1,7 +1,14 @@
-use serde::Serialize;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use bitcoin::secp256k1::PublicKey;
+use schemars::JsonSchema;
+use serde::{Serialize, Serializer};
+
+use super::heuristic::LightningHeuristic;
 
 /// Confidence level for Lightning transaction identification.
-#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, JsonSchema)]
 #[serde(rename_all = "snake_case")]
 pub enum Confidence {
     /// No Lightning signals detected.
@@ -13,7 +20,8 @@ pub enum Confidence {
 }
 
 /// What type of Lightning transaction this is.
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+/// Variants are ordered as `--sort type` lists them, commitments first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, JsonSchema)]
 #[serde(rename_all = "snake_case")]
 pub enum LightningTxType {
     /// Force-close: spends funding output, creates to_local/to_remote/HTLC outputs.
@@ -22,59 +30,471 @@ pub enum LightningTxType {
     HtlcTimeout,
     /// Claim path: preimage revealed on-chain.
     HtlcSuccess,
+    /// Cooperative close: spends funding output directly to final key-path outputs.
+    MutualClose,
+    /// Justice transaction: sweeps a revoked commitment's outputs via the revocation key.
+    Penalty,
+    /// Delayed self-sweep: the force-closing party claims its to_local output once
+    /// `to_self_delay` has elapsed.
+    ToLocalSweep,
+    /// CPFP fee bump: spends a commitment's anchor output into a single larger output.
+    AnchorSweep,
+    /// Splice: spends a funding output and creates a new one with a different value.
+    Splice,
+}
+
+/// Values the Lightning detector matches against. The defaults follow BOLT 3;
+/// override them for test networks with non-standard channels.
+#[derive(Debug, Clone)]
+pub struct DetectorConfig {
+    /// Standard anchor output value, used when the script type is unknown.
+    pub anchor_value: u64,
+    /// Largest P2WSH value taken as an anchor without a funding script (the dust limit).
+    pub anchor_max_value: u64,
+
+    /// Upper byte of nLockTime carrying the obscured commitment number.
+    pub locktime_upper_byte: u8,
+    /// Upper byte of an input's nSequence carrying the obscured commitment number.
+    pub sequence_upper_byte: u8,
+
+    /// Minimum commitment signal score for `Possible`.
+    pub possible_score: u32,
+    /// Minimum commitment signal score for `HighlyLikely`: anchors plus a marker by default.
+    pub highly_likely_score: u32,
+
+    /// Highest nLockTime block height taken as a realistic HTLC-timeout expiry; `None`
+    /// accepts any height. Set from the network's tip with [`DetectorConfig::with_tip`].
+    pub max_htlc_expiry: Option<u64>,
+
+    /// Extra detection rules, tried in order after the built-in checks.
+    pub heuristics: Vec<Arc<dyn LightningHeuristic>>,
+}
+
+impl Default for DetectorConfig {
+    fn default() -> Self {
+        Self {
+            anchor_value: 330,
+            anchor_max_value: 546,
+            locktime_upper_byte: 0x20,
+            sequence_upper_byte: 0x80,
+            possible_score: 1,
+            highly_likely_score: 3,
+            max_htlc_expiry: None,
+            heuristics: Vec::new(),
+        }
+    }
+}
+
+/// How far past the tip an HTLC-timeout's expiry may lie. Nodes refuse HTLCs expiring
+/// more than about two weeks out, and an HTLC-timeout is signed along with its
+/// commitment, before it can be broadcast.
+const MAX_HTLC_EXPIRY_AHEAD: u64 = 2016;
+
+impl DetectorConfig {
+    /// Bound realistic HTLC-timeout expiries by the tip of the network being scanned,
+    /// so a locktime far beyond it (say, a mainnet height on signet) is only `Possible`.
+    pub fn with_tip(mut self, height: u64) -> Self {
+        self.max_htlc_expiry = Some(height.saturating_add(MAX_HTLC_EXPIRY_AHEAD));
+        self
+    }
+
+    /// Register `heuristic` after the ones already registered.
+    pub fn with_heuristic(mut self, heuristic: impl LightningHeuristic + 'static) -> Self {
+        self.heuristics.push(Arc::new(heuristic));
+        self
+    }
+
+    /// Fewer false positives: a lone marker is not enough for `Possible`, and
+    /// `HighlyLikely` needs anchors plus two more signals.
+    pub fn strict() -> Self {
+        Self {
+            possible_score: 2,
+            highly_likely_score: 4,
+            ..Self::default()
+        }
+    }
+
+    /// Fewer misses: anchors alone, or both markers without anchors, are enough for
+    /// `HighlyLikely`. A single marker is `Possible`, as with the defaults.
+    pub fn lenient() -> Self {
+        Self {
+            possible_score: 1,
+            highly_likely_score: 2,
+            ..Self::default()
+        }
+    }
 }
 
 /// Signals found when checking for commitment transaction patterns.
-#[derive(Debug, Clone, Default, Serialize)]
+#[derive(Debug, Clone, Default, Serialize, JsonSchema)]
 pub struct CommitmentSignals {
     /// Locktime is in the Lightning encoding range (upper byte 0x20).
     pub locktime_match: bool,
     /// At least one input has sequence with upper byte 0x80.
     pub sequence_match: bool,
-    /// At least one output has exactly 330 satoshis (anchor output).
+    /// At least one output is an anchor: a P2WSH paying the anchor script of a funding
+    /// key, or a tiny P2WSH (typically 330 satoshis) when the funding script isn't visible.
     pub has_anchor_outputs: bool,
     /// Number of anchor outputs found (0, 1, or 2).
     pub anchor_output_count: usize,
+    /// Value of the detected anchor output(s) in satoshis.
+    pub anchor_value: Option<u64>,
+    /// Every output is `v1_p2tr` (simple taproot channel).
+    pub is_taproot: bool,
+    /// An input's witness script is the anchor channels' to_remote script, delayed by
+    /// `1 OP_CHECKSEQUENCEVERIFY`: the transaction sweeps an anchor commitment's to_remote.
+    pub to_remote_delayed: bool,
+    /// Channel vintage implied by the outputs, when the commitment markers are present.
+    pub channel_type: ChannelType,
+}
+
+/// Kind of channel a commitment belongs to, inferred from its outputs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ChannelType {
+    /// Pre-anchor channel: no anchor outputs, a plain P2WPKH to_remote spendable right
+    /// away, and a P2WSH to_local or HTLC output.
+    Legacy,
+    /// Anchor outputs channel: anchor outputs next to the commitment markers.
+    Anchor,
+    /// Simple taproot channel: every output is P2TR.
+    Taproot,
+    /// No commitment markers, or outputs that fit none of the above (e.g. neither
+    /// anchors nor a P2WPKH to_remote, as when the remote balance was trimmed).
+    #[default]
+    Unknown,
+}
+
+/// Role of a commitment transaction output, inferred from its script type and value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum OutputRole {
+    /// First non-anchor P2WSH output: the broadcaster's delayed balance.
+    ToLocal,
+    /// P2WPKH/P2TR output paying the counterparty.
+    ToRemote,
+    /// Remaining P2WSH outputs. Offered and received HTLCs look identical until spent.
+    Htlc,
+    /// Tiny P2WSH (or, on taproot channels, 330-sat P2TR) output used for CPFP fee bumping.
+    Anchor,
+    /// Non-anchor output of a taproot channel: to_local, to_remote and HTLCs all look alike.
+    Taproot,
+    /// Any other output type.
+    Other,
 }
 
 /// Signals found when checking for HTLC second-stage transaction patterns.
-#[derive(Debug, Clone, Default, Serialize)]
+#[derive(Debug, Clone, Default, Serialize, JsonSchema)]
 pub struct HtlcSignals {
     /// nLockTime is a realistic block height (for timeout) or 0 (for success).
     pub locktime_value: u32,
-    /// Whether a 32-byte preimage was found in witness data.
+    /// Whether a preimage was found in witness data: an element hashing to the
+    /// script's payment hash, or any 32-byte element when no hash is visible.
     pub has_preimage: bool,
-    /// The preimage hex if found.
+    /// The first preimage hex if found.
     pub preimage: Option<String>,
+    /// Every preimage found, one per input that reveals one, in input order.
+    pub preimages: Vec<String>,
+    /// Whether the first preimage was checked against a payment hash in the script.
+    pub preimage_verified: bool,
     /// Whether OP_CHECKLOCKTIMEVERIFY was found in the witness script.
     pub script_has_cltv: bool,
     /// Whether OP_CHECKSEQUENCEVERIFY was found in the witness script.
     pub script_has_csv: bool,
+    /// No HTLC script was visible, but an input's witness stack has the shape of an
+    /// HTLC spend. A weaker signal, which caps confidence at `Possible`.
+    pub witness_shape_only: bool,
+}
+
+/// Signals found when checking for cooperative (mutual) close patterns.
+#[derive(Debug, Clone, Default, Serialize, JsonSchema)]
+pub struct MutualCloseSignals {
+    /// Single P2WSH input whose witness satisfies a 2-of-2 OP_CHECKMULTISIG.
+    pub two_of_two_input: bool,
+    /// nLockTime is 0.
+    pub locktime_zero: bool,
+    /// All inputs have sequence 0xFFFFFFFF.
+    pub final_sequences: bool,
+    /// Number of P2WPKH/P2TR (key-path) outputs.
+    pub key_path_output_count: usize,
+    /// At least one output looks like an anchor (330 sats) or HTLC/to_local (P2WSH).
+    pub has_anchor_or_htlc_outputs: bool,
 }
 
 /// Complete Lightning identification result for a transaction.
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone, Serialize, JsonSchema)]
 pub struct LightningClassification {
     pub tx_type: Option<LightningTxType>,
     pub confidence: Confidence,
     pub commitment_signals: CommitmentSignals,
     pub htlc_signals: HtlcSignals,
+    pub mutual_close_signals: MutualCloseSignals,
     pub params: LightningParams,
+    /// Human-readable evidence behind `tx_type`, e.g. "locktime upper byte 0x20", or
+    /// why a malformed transaction (no inputs or no outputs) wasn't classified.
+    pub reasons: Vec<String>,
+}
+
+/// Step-by-step record of how a classification was reached, from `explain_lightning`.
+#[derive(Debug, Clone, Default, Serialize, JsonSchema)]
+pub struct ClassificationTrace {
+    /// Checks in the order they ran. The first matching check decides the type; the
+    /// ones after it are skipped.
+    pub steps: Vec<TraceStep>,
+}
+
+/// One check the classifier ran.
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct TraceStep {
+    /// What was checked, e.g. "commitment".
+    pub check: String,
+    pub matched: bool,
+    /// The signals looked at and their values, in plain words.
+    pub details: Vec<String>,
 }
 
 /// Extracted Lightning-specific parameters.
-#[derive(Debug, Clone, Default, Serialize)]
+#[derive(Debug, Clone, Default, Serialize, JsonSchema)]
 pub struct LightningParams {
     /// Obscured commitment number (from locktime + sequence encoding).
     pub commitment_number: Option<u64>,
+    /// Real commitment number, only available when the channel's payment basepoints are known.
+    pub commitment_number_decoded: Option<u64>,
     /// Number of HTLC outputs on a commitment transaction.
     pub htlc_output_count: Option<usize>,
+    /// HTLC outputs offered by the commitment's holder (outgoing payments). The scripts
+    /// only appear in the witness of the spends, so an HTLC transaction counts the ones
+    /// it spends, and a commitment gets the count from `channel::link_same_block_sweeps`
+    /// once every one of its HTLC outputs is swept in the same block.
+    pub offered_htlc_count: Option<usize>,
+    /// HTLC outputs received by the commitment's holder (incoming payments), known in
+    /// the same cases as `offered_htlc_count`.
+    pub received_htlc_count: Option<usize>,
+    /// Role of each commitment output, indexed like `vout`.
+    pub output_roles: Vec<OutputRole>,
     /// CLTV expiry block height (from HTLC-timeout nLockTime).
     pub cltv_expiry: Option<u32>,
+    /// CLTV expiry Unix timestamp (from a timestamp-encoded HTLC-timeout nLockTime).
+    pub cltv_expiry_time: Option<u32>,
     /// CSV delay values found in output scripts.
     pub csv_delays: Vec<u16>,
+    /// `to_self_delay` from the to_local script of a commitment, when visible.
+    pub to_self_delay: Option<u16>,
     /// Whether a preimage was revealed (HTLC-success).
     pub preimage_revealed: bool,
-    /// The preimage itself if revealed.
+    /// The preimage itself if revealed; the first one when several are.
     pub preimage: Option<String>,
+    /// Every preimage revealed by the success claims, in input order. An aggregated
+    /// claim sweeping several HTLCs reveals one per input.
+    pub preimages: Vec<String>,
+    /// Hash the HTLC script checks the preimage against: RIPEMD160 of the payment hash
+    /// after `OP_HASH160` (BOLT 3), or the payment hash itself after `OP_SHA256`. The
+    /// same for the timeout and success spends of a payment.
+    pub payment_hash: Option<String>,
+    /// Number of inputs sweeping outputs of the same revoked commitment (penalty).
+    pub swept_output_count: Option<usize>,
+    /// Commitment transaction whose anchor output (anchor sweep) or to_local output
+    /// (to_local sweep) is spent.
+    pub parent_txid: Option<String>,
+    /// Per-input detail of HTLC transactions, which may claim several HTLCs at once.
+    pub htlc_inputs: Vec<HtlcInputClassification>,
+    /// Commitment fee is high for its weight with few HTLC outputs: HTLCs below the dust
+    /// limit were likely trimmed into the fee.
+    pub possible_trimmed_htlcs: bool,
+    /// Channel capacity implied by a commitment, in sats: the funding output's value
+    /// when the input carries its prevout, else the sum of all outputs (anchors
+    /// included) plus the fee. Without a known fee this undercounts by the fee.
+    pub approx_channel_capacity: Option<u64>,
+    /// `txid:vout` of the 2-of-2 funding output, once a commitment has been verified.
+    pub funding_outpoint: Option<String>,
+    /// Value of the funding output a splice spends.
+    pub old_funding_value: Option<u64>,
+    /// Value of the funding output a splice creates.
+    pub new_funding_value: Option<u64>,
+    /// Commitment in the same block whose outputs this HTLC transaction spends, i.e.
+    /// swept right after the force close. Set by `channel::link_same_block_sweeps`.
+    pub same_block_commitment: Option<String>,
+    /// HTLC transactions in the same block that spend this commitment's outputs. Set
+    /// by `channel::link_same_block_sweeps`.
+    pub same_block_htlc_spends: Vec<String>,
+}
+
+/// How a single input spending an HTLC output was classified.
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct HtlcInputClassification {
+    pub input_index: usize,
+    /// `HtlcSuccess` when the input reveals a preimage, `HtlcTimeout` otherwise.
+    pub tx_type: LightningTxType,
+    pub preimage: Option<String>,
+    /// Hash pushed after `OP_HASH160` or `OP_SHA256` in the input's script.
+    pub payment_hash: Option<String>,
+    /// CLTV expiry from the input's script, or the block-height nLockTime of a timeout.
+    pub cltv_expiry: Option<u32>,
+    /// CSV delays found in the input's scripts.
+    pub csv_delays: Vec<u16>,
+}
+
+/// Lightning transaction counts accumulated while scanning a block or a range of blocks.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct LightningTally {
+    pub transactions: usize,
+    pub commitments: usize,
+    pub htlc_timeouts: usize,
+    pub htlc_successes: usize,
+    pub mutual_closes: usize,
+    pub penalties: usize,
+    pub to_local_sweeps: usize,
+    pub anchor_sweeps: usize,
+    pub splices: usize,
+    /// CSV delay (blocks) → number of occurrences, serialized as its [`DelaySummary`].
+    #[serde(rename = "csv_delay_summary", serialize_with = "serialize_delay_summary")]
+    pub csv_delays: BTreeMap<u16, usize>,
+}
+
+impl LightningTally {
+    pub fn record(&mut self, lc: &LightningClassification) {
+        self.transactions += 1;
+        for delay in &lc.params.csv_delays {
+            *self.csv_delays.entry(*delay).or_insert(0) += 1;
+        }
+        match lc.tx_type {
+            Some(LightningTxType::Commitment) => self.commitments += 1,
+            Some(LightningTxType::HtlcTimeout) => self.htlc_timeouts += 1,
+            Some(LightningTxType::HtlcSuccess) => self.htlc_successes += 1,
+            Some(LightningTxType::MutualClose) => self.mutual_closes += 1,
+            Some(LightningTxType::Penalty) => self.penalties += 1,
+            Some(LightningTxType::ToLocalSweep) => self.to_local_sweeps += 1,
+            Some(LightningTxType::AnchorSweep) => self.anchor_sweeps += 1,
+            Some(LightningTxType::Splice) => self.splices += 1,
+            None => {}
+        }
+    }
+
+    pub fn add(&mut self, other: &LightningTally) {
+        self.transactions += other.transactions;
+        self.commitments += other.commitments;
+        self.htlc_timeouts += other.htlc_timeouts;
+        self.htlc_successes += other.htlc_successes;
+        self.mutual_closes += other.mutual_closes;
+        self.penalties += other.penalties;
+        self.to_local_sweeps += other.to_local_sweeps;
+        self.anchor_sweeps += other.anchor_sweeps;
+        self.splices += other.splices;
+        for (delay, count) in &other.csv_delays {
+            *self.csv_delays.entry(*delay).or_insert(0) += count;
+        }
+    }
+
+    pub fn csv_delay_summary(&self) -> Option<DelaySummary> {
+        DelaySummary::from_distribution(&self.csv_delays)
+    }
+
+    /// Number of transactions classified as any Lightning type.
+    pub fn lightning(&self) -> usize {
+        self.commitments
+            + self.htlc_timeouts
+            + self.htlc_successes
+            + self.mutual_closes
+            + self.penalties
+            + self.to_local_sweeps
+            + self.anchor_sweeps
+            + self.splices
+    }
+}
+
+fn serialize_delay_summary<S: Serializer>(
+    delays: &BTreeMap<u16, usize>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    DelaySummary::from_distribution(delays).serialize(serializer)
+}
+
+/// Spread of the CSV delays seen across a scan, in blocks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct DelaySummary {
+    /// Number of delays observed.
+    pub count: usize,
+    pub min: u16,
+    /// Lower median when the count is even.
+    pub median: u16,
+    pub max: u16,
+    /// Most frequent delay, the smallest one on a tie.
+    pub most_common: u16,
+}
+
+impl DelaySummary {
+    /// Summarize a delay → occurrences map; `None` when it holds no delays.
+    pub fn from_distribution(distribution: &BTreeMap<u16, usize>) -> Option<Self> {
+        let count: usize = distribution.values().sum();
+        if count == 0 {
+            return None;
+        }
+        let mut seen = 0;
+        let median = distribution.iter().find_map(|(delay, n)| {
+            seen += n;
+            (seen > (count - 1) / 2).then_some(*delay)
+        })?;
+        // max_by_key keeps the last maximum, so walk from the largest delay down
+        let most_common = distribution.iter().rev().max_by_key(|(_, n)| **n).map(|(d, _)| *d)?;
+
+        Some(Self {
+            count,
+            min: *distribution.keys().next()?,
+            median,
+            max: *distribution.keys().next_back()?,
+            most_common,
+        })
+    }
+}
+
+/// Channel parameters known to the caller, used to recover values that are obscured on-chain.
+#[derive(Debug, Clone)]
+pub struct ChannelContext {
+    /// `payment_basepoint` from the funder's `open_channel` message.
+    pub opener_payment_basepoint: PublicKey,
+    /// `payment_basepoint` from the fundee's `accept_channel` message.
+    pub accepter_payment_basepoint: PublicKey,
+}
+
+/// Why the commitments of a [`ChannelLink`] are taken to belong to the same channel.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum LinkBasis {
+    /// They were verified to spend the same funding output.
+    FundingOutpoint,
+    /// Their obscured commitment numbers share the upper bits, which come from the
+    /// channel's obscuring factor alone while commitment numbers stay small.
+    ObscuringFactor,
+}
+
+/// Commitment transactions likely from one channel, from `channel::correlate`.
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct ChannelLink {
+    pub basis: LinkBasis,
+    /// The shared funding output, for `LinkBasis::FundingOutpoint`.
+    pub funding_outpoint: Option<String>,
+    /// The linked commitments, oldest first.
+    pub commitments: Vec<LinkedCommitment>,
+}
+
+/// One commitment of a [`ChannelLink`].
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct LinkedCommitment {
+    /// Position in the slice passed to `correlate`.
+    pub index: usize,
+    /// Obscured commitment number, as encoded in the locktime and sequence.
+    pub commitment_number: Option<u64>,
+    /// Real commitment number, when it was decoded with the channel's basepoints.
+    pub commitment_number_decoded: Option<u64>,
+}
+
+/// A commitment and the HTLC transactions seen spending its outputs, from
+/// `channel::cluster_htlc_spends`.
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct ChannelCluster {
+    /// `txid:vout` of the funding output the commitment spends.
+    pub funding_outpoint: Option<String>,
+    pub commitment_txid: String,
+    pub htlc_timeouts: Vec<String>,
+    pub htlc_successes: Vec<String>,
 }
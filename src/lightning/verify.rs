@@ -0,0 +1,88 @@
+use anyhow::{Context, Result};
+use bitcoin::ScriptBuf;
+use bitcoin::hashes::{Hash, sha256};
+use bitcoin::hex::{DisplayHex, FromHex};
+
+use crate::api::source::DataSource;
+use crate::api::types::{ApiTransaction, ApiVin};
+
+use super::detector::is_two_of_two_script;
+use super::types::{Confidence, LightningClassification, LightningTxType};
+
+/// Confirm or refute a commitment classification by checking that its single input
+/// spends a 2-of-2 funding output.
+///
+/// A verified commitment becomes `HighlyLikely` and records its funding outpoint;
+/// anything else is downgraded to `Possible`. The spent output is fetched from
+/// `source` only when the input doesn't already carry its prevout. Other
+/// classifications are left untouched.
+pub async fn verify_commitment<S: DataSource>(
+    source: &S,
+    tx: &ApiTransaction,
+    classification: &mut LightningClassification,
+) -> Result<()> {
+    if classification.tx_type != Some(LightningTxType::Commitment) {
+        return Ok(());
+    }
+
+    let verified = match tx.vin.as_slice() {
+        [vin] => match (&vin.txid, vin.vout) {
+            (Some(txid), Some(vout)) => {
+                let spent_scriptpubkey = match vin.prevout {
+                    Some(ref prevout) => prevout.scriptpubkey.clone(),
+                    None => spent_output_script(source, txid, vout).await?,
+                };
+                spends_funding_output(vin, &spent_scriptpubkey).then(|| format!("{txid}:{vout}"))
+            }
+            _ => None,
+        },
+        _ => None,
+    };
+
+    match verified {
+        Some(outpoint) => {
+            classification.confidence = Confidence::HighlyLikely;
+            classification.params.funding_outpoint = Some(outpoint);
+            classification
+                .reasons
+                .push("input spends a 2-of-2 funding output".to_string());
+        }
+        None => {
+            classification.confidence = Confidence::Possible;
+            classification
+                .reasons
+                .push("input does not spend a 2-of-2 funding output".to_string());
+        }
+    }
+    Ok(())
+}
+
+async fn spent_output_script<S: DataSource>(source: &S, txid: &str, vout: u32) -> Result<String> {
+    let funding_tx = source
+        .get_transaction(txid)
+        .await
+        .with_context(|| format!("fetching funding transaction {txid}"))?;
+    funding_tx
+        .vout
+        .get(vout as usize)
+        .map(|o| o.scriptpubkey.clone())
+        .with_context(|| format!("funding transaction {txid} has no output {vout}"))
+}
+
+/// The input reveals a 2-of-2 multisig witness script that hashes to the spent P2WSH
+/// program.
+fn spends_funding_output(vin: &ApiVin, spent_scriptpubkey: &str) -> bool {
+    let Some(script) = vin
+        .witness
+        .as_ref()
+        .and_then(|w| w.last())
+        .and_then(|elem| Vec::<u8>::from_hex(elem).ok())
+    else {
+        return false;
+    };
+
+    let program = sha256::Hash::hash(&script).to_byte_array();
+    let expected = format!("0020{}", program.to_lower_hex_string());
+    spent_scriptpubkey.eq_ignore_ascii_case(&expected)
+        && is_two_of_two_script(&ScriptBuf::from(script).to_asm_string())
+}
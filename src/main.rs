@@ -1,43 +1,337 @@
 use std::collections::HashSet;
+use std::io::{IsTerminal, Write};
+use std::path::PathBuf;
+use std::process::ExitCode;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::time::Duration;
 
-use anyhow::Result;
-use clap::{Parser, Subcommand};
+use anyhow::{Context, Result, bail};
+use bitcoin::Network;
+use clap::{Parser, Subcommand, ValueEnum};
+use rand::SeedableRng;
+use rand::rngs::StdRng;
+use rayon::prelude::*;
+use serde::Serialize;
 use tokio::net::TcpListener;
 
+use cltv_scan::api::bitcoin_core_client::{BitcoinCoreClient, CoreAuth};
+use cltv_scan::api::block_file::BlockFileSource;
 use cltv_scan::api::cache::CachedClient;
-use cltv_scan::api::client::MempoolClient;
-use cltv_scan::api::source::DataSource;
-use cltv_scan::cli::output;
-use cltv_scan::lightning::detector::classify_lightning;
-use cltv_scan::lightning::types::LightningTxType;
-use cltv_scan::security::analyzer;
-use cltv_scan::security::types::{SecurityConfig, Severity};
+use cltv_scan::api::client::{
+    DEFAULT_RATE_LIMIT, DEFAULT_REQUEST_DELAY, MEMPOOL_SPACE_URL, MempoolClient,
+};
+use cltv_scan::api::disk_cache::{CachedSource, default_cache_dir};
+use cltv_scan::api::electrum_client::ElectrumClient;
+use cltv_scan::api::floresta_client::FlorestaClient;
+use cltv_scan::api::raw::decode_raw_transaction;
+use cltv_scan::api::source::{AnySource, DataSource, find_height_at_or_after, transaction_at};
+use cltv_scan::api::types::ApiTransaction;
+use cltv_scan::cli::commands::{self, BatchSummary, MonitorHit, WatchedBlock, classify_at};
+use cltv_scan::cli::output::{self, ColorChoice, Format};
+use cltv_scan::cli::progress::{ScanProgress, Verbosity};
+use cltv_scan::cli::sort::{self, SortKey};
+use cltv_scan::lightning::channel;
+use cltv_scan::lightning::detector::{add_tip_reasons, explain_lightning_with_config};
+use cltv_scan::lightning::types::{
+    ClassificationTrace, Confidence, DetectorConfig, LightningClassification, LightningTally,
+    LightningTxType,
+};
+use cltv_scan::lightning::verify::verify_commitment;
+use cltv_scan::util::{parse_block_position, parse_timestamp, parse_txid};
+use cltv_scan::schema;
+use cltv_scan::security::types::{Alert, SecurityConfig, Severity};
 use cltv_scan::server;
-use cltv_scan::timelock::extractor::analyze_transaction;
+use cltv_scan::server::metrics::{WatchMetrics, metrics_router};
+use cltv_scan::server::types::LightningTxEntry;
+use cltv_scan::timelock::extractor::{analyze_transaction, analyze_transaction_with_tip};
+use cltv_scan::timelock::types::{ChainTip, RiskSeverity, TimelockTally, TransactionAnalysis};
 
 #[derive(Parser)]
 #[command(name = "cltv-scan", about = "Bitcoin timelock vulnerability scanner")]
 struct Cli {
+    /// Data source backend
+    #[arg(long, global = true, value_enum, default_value_t = Source::Mempool)]
+    source: Source,
+    /// Bitcoin network
+    #[arg(long, global = true, value_enum, default_value_t = NetworkArg::Mainnet)]
+    network: NetworkArg,
+    /// Esplora-compatible API root (with --source mempool), e.g. http://localhost:3002/api
+    #[arg(long, global = true)]
+    api_url: Option<String>,
+    /// Proxy for API requests (with --source mempool), e.g. socks5h://127.0.0.1:9050
+    /// for Tor. Without it ALL_PROXY / HTTPS_PROXY are used
+    #[arg(long, global = true, value_name = "URL")]
+    proxy: Option<String>,
+    /// Most API requests per second, retries included (with --source mempool; 0 for no
+    /// limit)
+    #[arg(long, global = true, value_name = "N", default_value_t = DEFAULT_RATE_LIMIT)]
+    rate_limit: u32,
+    /// Don't read or write the on-disk transaction cache
+    #[arg(long, global = true)]
+    no_cache: bool,
+    /// On-disk transaction cache directory (default ~/.cache/cltv-scan)
+    #[arg(long, global = true, conflicts_with = "no_cache")]
+    cache_dir: Option<PathBuf>,
+    /// Per-request network timeout in seconds
+    #[arg(long, global = true, default_value_t = 30)]
+    timeout: u64,
+    /// Colorize text output
+    #[arg(long, global = true, value_enum, default_value_t = ColorArg::Auto)]
+    color: ColorArg,
+    /// Output format. Commands without a CSV or NDJSON form reject those
+    #[arg(long, global = true, value_enum, default_value_t = FormatArg::Text)]
+    format: FormatArg,
+    /// Write results to this file (created or truncated) instead of stdout. Progress
+    /// and errors still go to stderr
+    #[arg(long = "output", global = true, value_name = "PATH")]
+    output_file: Option<PathBuf>,
+    /// Worker threads for block analysis (0 = all cores, 1 = single-threaded)
+    #[arg(long, global = true, default_value_t = 0)]
+    jobs: usize,
+    /// Bitcoin Core RPC URL (with --source core)
+    #[arg(long, global = true, default_value = "http://127.0.0.1:8332")]
+    core_url: String,
+    /// Bitcoin Core RPC cookie file (with --source core, default ~/.bitcoin/.cookie)
+    #[arg(long, global = true)]
+    core_cookie: Option<PathBuf>,
+    /// Bitcoin Core RPC username (with --source core, instead of a cookie file)
+    #[arg(long, global = true, requires = "core_pass")]
+    core_user: Option<String>,
+    /// Bitcoin Core RPC password (with --source core)
+    #[arg(long, global = true, requires = "core_user")]
+    core_pass: Option<String>,
+    /// Electrum server address (with --source electrum): host:port, or tcp:// / ssl:// URL
+    #[arg(long, global = true, default_value = "127.0.0.1:50001")]
+    electrum_url: String,
+    /// Exit with status 3 when a result matches any of these conditions (comma-separated)
+    #[arg(long, global = true, value_enum, value_delimiter = ',')]
+    fail_on: Vec<FailOnArg>,
+    /// Don't print progress to stderr (errors are still shown)
+    #[arg(short, long, global = true, conflicts_with = "verbose")]
+    quiet: bool,
+    /// Also print how long each fetch took
+    #[arg(short, long, global = true)]
+    verbose: bool,
+    /// Require more commitment signals: no Possible on a lone marker, HighlyLikely only
+    /// with anchors plus two more signals
+    #[arg(long, global = true, conflicts_with = "lenient")]
+    strict: bool,
+    /// Accept fewer commitment signals: HighlyLikely on anchors alone or on both markers
+    #[arg(long, global = true)]
+    lenient: bool,
     #[command(subcommand)]
     command: Commands,
 }
 
+#[derive(Clone, Copy, ValueEnum)]
+enum Source {
+    /// mempool.space REST API
+    Mempool,
+    /// Bitcoin Core JSON-RPC
+    Core,
+    /// Electrum protocol server (ElectrumX, Fulcrum, electrs)
+    Electrum,
+    /// Embedded Floresta node (mainnet only)
+    Floresta,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum NetworkArg {
+    Mainnet,
+    Testnet,
+    Signet,
+}
+
+impl From<NetworkArg> for Network {
+    fn from(n: NetworkArg) -> Self {
+        match n {
+            NetworkArg::Mainnet => Network::Bitcoin,
+            NetworkArg::Testnet => Network::Testnet,
+            NetworkArg::Signet => Network::Signet,
+        }
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum ColorArg {
+    Auto,
+    Always,
+    Never,
+}
+
+impl From<ColorArg> for ColorChoice {
+    fn from(c: ColorArg) -> Self {
+        match c {
+            ColorArg::Auto => ColorChoice::Auto,
+            ColorArg::Always => ColorChoice::Always,
+            ColorArg::Never => ColorChoice::Never,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+enum FormatArg {
+    Text,
+    Json,
+    /// Newline-delimited JSON
+    Ndjson,
+    Csv,
+}
+
+impl From<FormatArg> for Format {
+    fn from(f: FormatArg) -> Self {
+        match f {
+            FormatArg::Text => Format::Text,
+            FormatArg::Json => Format::Json,
+            FormatArg::Ndjson => Format::Ndjson,
+            FormatArg::Csv => Format::Csv,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+enum FailOnArg {
+    /// Any Lightning classification
+    AnyLightning,
+    /// A commitment transaction
+    Commitment,
+    /// An HTLC success spend revealing a preimage
+    Preimage,
+    /// A timelock risk finding or security alert
+    Risk,
+}
+
+/// Exit status when a `--fail-on` condition matched. Not 2, which clap uses for
+/// usage errors.
+const EXIT_MATCHED: u8 = 3;
+
+/// Records whether any result matched a `--fail-on` condition. Shared across rayon
+/// workers, hence the atomic.
+struct FailOn {
+    conditions: Vec<FailOnArg>,
+    matched: AtomicBool,
+}
+
+impl FailOn {
+    fn new(conditions: Vec<FailOnArg>) -> Self {
+        Self {
+            conditions,
+            matched: AtomicBool::new(false),
+        }
+    }
+
+    fn check(&self, condition: FailOnArg, hit: bool) {
+        if hit && self.conditions.contains(&condition) {
+            self.matched.store(true, Ordering::Relaxed);
+        }
+    }
+
+    fn lightning(&self, lc: &LightningClassification) {
+        self.check(FailOnArg::AnyLightning, lc.tx_type.is_some());
+        self.check(FailOnArg::Commitment, lc.tx_type == Some(LightningTxType::Commitment));
+        self.check(FailOnArg::Preimage, lc.params.preimage_revealed);
+    }
+
+    fn analysis(&self, analysis: &TransactionAnalysis) {
+        self.check(FailOnArg::Risk, analysis.risk.max_severity > Some(RiskSeverity::Info));
+    }
+
+    fn alerts(&self, alerts: &[Alert]) {
+        self.check(FailOnArg::Risk, !alerts.is_empty());
+    }
+
+    fn exit_code(&self) -> ExitCode {
+        if self.matched.load(Ordering::Relaxed) {
+            ExitCode::from(EXIT_MATCHED)
+        } else {
+            ExitCode::SUCCESS
+        }
+    }
+}
+
 #[derive(Subcommand)]
 enum Commands {
     /// Analyze timelocks in a single transaction
     Tx {
         /// Transaction ID to analyze
-        txid: String,
-        /// Output as JSON
+        #[arg(required_unless_present_any = ["raw", "at"], value_parser = parse_txid)]
+        txid: Option<String>,
+        /// Raw transaction hex to decode locally instead of fetching by txid
+        #[arg(long, conflicts_with = "txid")]
+        raw: Option<String>,
+        /// Analyze the transaction at this position in a block (0 is the coinbase)
+        #[arg(
+            long,
+            value_name = "HEIGHT:INDEX",
+            value_parser = parse_block_position,
+            conflicts_with_all = ["txid", "raw"]
+        )]
+        at: Option<(u64, u32)>,
+        /// Output as JSON (same as --format json)
         #[arg(long)]
         json: bool,
+        /// Print each input's witness and redeem script disassembly
+        #[arg(long, conflicts_with = "json")]
+        show_scripts: bool,
     },
     /// Scan all transactions in a block for timelocks
     Block {
-        /// Block height to scan
+        /// Block height to scan (start of the range with --to)
+        #[arg(required_unless_present = "block_file")]
+        height: Option<u64>,
+        /// Last block height to scan (inclusive)
+        #[arg(long)]
+        to: Option<u64>,
+        /// Read the block from a local file instead of the data source: raw block hex
+        /// (`bitcoin-cli getblock <hash> 0`) or a serialized block from blk*.dat. The
+        /// height is taken from the coinbase unless given
+        #[arg(long, value_name = "PATH", conflicts_with = "to")]
+        block_file: Option<PathBuf>,
+        /// Output as JSON (same as --format json)
+        #[arg(long)]
+        json: bool,
+        /// Output as newline-delimited JSON, one transaction per line (same as
+        /// --format ndjson)
+        #[arg(long, conflicts_with = "json")]
+        ndjson: bool,
+        /// Leave transactions without active timelocks out of --json and --ndjson output,
+        /// as the text summary does
+        #[arg(long, conflicts_with_all = ["csv", "oneline"])]
+        with_timelocks_only: bool,
+        /// Output as CSV, one row per transaction (same as --format csv)
+        #[arg(long, conflicts_with_all = ["json", "ndjson"])]
+        csv: bool,
+        /// Print one grep-friendly line per transaction
+        #[arg(long, conflicts_with_all = ["json", "ndjson", "csv"])]
+        oneline: bool,
+        /// Order in which transactions are printed (summary counts are unaffected)
+        #[arg(long, value_enum, default_value = "none", conflicts_with = "ndjson")]
+        sort: SortArg,
+        /// Only analyze N transactions per block, picked uniformly at random
+        #[arg(long, value_name = "N")]
+        sample: Option<usize>,
+        /// Seed for --sample, to pick the same transactions again
+        #[arg(long, requires = "sample")]
+        seed: Option<u64>,
+    },
+    /// Aggregate Lightning and timelock statistics over a block range
+    Stats {
+        /// First block height
         height: u64,
-        /// Output as JSON
+        /// Last block height (inclusive)
+        #[arg(long)]
+        to: Option<u64>,
+        /// Output as JSON (same as --format json)
+        #[arg(long)]
+        json: bool,
+    },
+    /// Classify a list of transactions read from a file or stdin
+    Batch {
+        /// File with one txid per line (reads stdin when omitted)
+        #[arg(long)]
+        file: Option<PathBuf>,
+        /// Output as JSON (same as --format json)
         #[arg(long)]
         json: bool,
     },
@@ -51,8 +345,8 @@ enum Commands {
         /// Port to listen on
         #[arg(short, long, default_value_t = 3001)]
         port: u16,
-        /// mempool.space API base URL
-        #[arg(long, default_value = "https://mempool.space")]
+        /// mempool.space host; the --network API path is appended (overridden by --api-url)
+        #[arg(long, default_value = MEMPOOL_SPACE_URL)]
         mempool_url: String,
         /// Request delay in milliseconds (rate limiting)
         #[arg(long, default_value_t = 250)]
@@ -63,7 +357,8 @@ enum Commands {
         /// Polling interval in seconds
         #[arg(short, long, default_value_t = 10)]
         interval: u64,
-        /// Output as JSON
+        /// Output as newline-delimited JSON, one line per hit (same as --format json or
+        /// ndjson)
         #[arg(long)]
         json: bool,
         /// Minimum severity to display (info, warning, critical)
@@ -79,6 +374,48 @@ enum Commands {
         #[arg(long, default_value_t = 72)]
         cltv_info: u32,
     },
+    /// Classify every unconfirmed transaction in the mempool as Lightning-related
+    Mempool {
+        /// Output as newline-delimited JSON, one line per Lightning transaction (same as
+        /// --format json or ndjson)
+        #[arg(long)]
+        json: bool,
+        /// Only list classifications at or above this confidence
+        #[arg(long, value_enum)]
+        min_confidence: Option<MinConfidence>,
+    },
+    /// Poll for new blocks and scan each one for Lightning activity as it arrives
+    Watch {
+        /// Polling interval in seconds
+        #[arg(short, long, default_value_t = 30)]
+        interval: u64,
+        /// Output as newline-delimited JSON, one line per block (same as --format json
+        /// or ndjson)
+        #[arg(long)]
+        json: bool,
+        /// Don't report non-Lightning timelocks
+        #[arg(long)]
+        lightning_only: bool,
+        /// Serve Prometheus metrics at http://<host:port>/metrics
+        #[arg(long, value_name = "HOST:PORT")]
+        metrics_addr: Option<String>,
+        /// First scan the blocks mined since this time (Unix seconds or ISO 8601),
+        /// then keep watching
+        #[arg(long, value_name = "TIME", value_parser = parse_timestamp)]
+        since: Option<u64>,
+    },
+    /// Show the data source, its chain tip and whether it has finished syncing
+    Info {
+        /// Output as JSON (same as --format json)
+        #[arg(long)]
+        json: bool,
+    },
+    /// Print the JSON Schema of the --json output types
+    Schema {
+        /// Only print the schema of this type
+        #[arg(value_enum)]
+        output: Option<SchemaOutput>,
+    },
     /// Security scan for attack patterns and vulnerabilities
     Scan {
         /// Start block height
@@ -86,7 +423,7 @@ enum Commands {
         /// End block height (inclusive). Defaults to start (single block).
         #[arg(short, long)]
         end: Option<u64>,
-        /// Output as JSON
+        /// Output as JSON (same as --format json)
         #[arg(long)]
         json: bool,
         /// CLTV critical threshold (blocks remaining)
@@ -112,91 +449,772 @@ enum LightningCommands {
     /// Classify a single transaction as Lightning-related
     Tx {
         /// Transaction ID to classify
-        txid: String,
-        /// Output as JSON
+        #[arg(required_unless_present = "raw", value_parser = parse_txid)]
+        txid: Option<String>,
+        /// Raw transaction hex to decode locally instead of fetching by txid
+        #[arg(long, conflicts_with = "txid")]
+        raw: Option<String>,
+        /// Output as JSON (same as --format json)
         #[arg(long)]
         json: bool,
+        /// Print each input's witness and redeem script disassembly
+        #[arg(long, conflicts_with = "json")]
+        show_scripts: bool,
+        /// Confirm a commitment by checking that it spends a 2-of-2 funding output
+        #[arg(long, conflicts_with = "raw")]
+        verify: bool,
+        /// Show each check the classifier ran and what it found
+        #[arg(long)]
+        explain: bool,
     },
     /// Scan a block for Lightning Network activity
     Block {
-        /// Block height to scan
+        /// Block height to scan (start of the range with --to)
         height: u64,
-        /// Output as JSON
+        /// Last block height to scan (inclusive)
+        #[arg(long)]
+        to: Option<u64>,
+        /// Output as JSON (same as --format json)
         #[arg(long)]
         json: bool,
+        /// Output as newline-delimited JSON, one transaction per line (same as
+        /// --format ndjson)
+        #[arg(long, conflicts_with = "json")]
+        ndjson: bool,
+        /// Output as CSV, one row per transaction (same as --format csv)
+        #[arg(long, conflicts_with_all = ["json", "ndjson"])]
+        csv: bool,
+        /// Print one grep-friendly line per transaction
+        #[arg(long, conflicts_with_all = ["json", "ndjson", "csv"])]
+        oneline: bool,
+        /// Order in which transactions are printed (summary counts are unaffected)
+        #[arg(long, value_enum, default_value = "none", conflicts_with = "ndjson")]
+        sort: SortArg,
+        /// Only analyze N transactions per block, picked uniformly at random
+        #[arg(long, value_name = "N")]
+        sample: Option<usize>,
+        /// Seed for --sample, to pick the same transactions again
+        #[arg(long, requires = "sample")]
+        seed: Option<u64>,
+        /// Only list classifications at or above this confidence
+        #[arg(long, value_enum)]
+        min_confidence: Option<MinConfidence>,
+        /// Confirm commitments by checking that they spend a 2-of-2 funding output
+        #[arg(long)]
+        verify: bool,
     },
 }
 
+#[derive(Clone, Copy, ValueEnum)]
+enum SchemaOutput {
+    /// `tx` and `block` analyses
+    Analysis,
+    /// `lightning tx` and `lightning block` classifications
+    Lightning,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum MinConfidence {
+    Possible,
+    #[value(name = "highly_likely")]
+    HighlyLikely,
+}
+
+impl From<MinConfidence> for Confidence {
+    fn from(c: MinConfidence) -> Self {
+        match c {
+            MinConfidence::Possible => Confidence::Possible,
+            MinConfidence::HighlyLikely => Confidence::HighlyLikely,
+        }
+    }
+}
+
+impl Commands {
+    /// The output format: `--format`, or the command's own `--json`, `--ndjson` or
+    /// `--csv`, which are aliases for it. Refuses formats the command has no output in
+    /// and flags that only apply to another format.
+    fn format(&self, format: FormatArg) -> Result<Format> {
+        let format = match (self.format_alias(), format) {
+            (Some(alias), FormatArg::Text) => alias,
+            (Some(alias), format) if alias != format => {
+                bail!("--format conflicts with --json, --ndjson or --csv given alongside it")
+            }
+            (_, format) => format,
+        };
+        let supported = match self {
+            Commands::Block { .. } | Commands::Lightning { command: LightningCommands::Block { .. } } => {
+                true
+            }
+            Commands::Monitor { .. } | Commands::Mempool { .. } | Commands::Watch { .. } => {
+                format != FormatArg::Csv
+            }
+            Commands::Serve { .. } => format == FormatArg::Text,
+            _ => matches!(format, FormatArg::Text | FormatArg::Json),
+        };
+        if !supported {
+            let name = format.to_possible_value().expect("no skipped values");
+            bail!("this command has no --format {} output", name.get_name());
+        }
+
+        match self {
+            Commands::Tx { show_scripts: true, .. }
+            | Commands::Lightning {
+                command: LightningCommands::Tx { show_scripts: true, .. },
+            } if format != FormatArg::Text => {
+                bail!("--show-scripts only applies to text output");
+            }
+            Commands::Block { oneline, with_timelocks_only, sort, .. } => {
+                check_row_flags(format, *oneline, *with_timelocks_only, *sort)?
+            }
+            Commands::Lightning {
+                command: LightningCommands::Block { oneline, sort, .. },
+            } => check_row_flags(format, *oneline, false, *sort)?,
+            _ => {}
+        }
+        Ok(format.into())
+    }
+
+    /// The format the command's own `--json`, `--ndjson` or `--csv` flag picks, if set.
+    fn format_alias(&self) -> Option<FormatArg> {
+        let (json, ndjson, csv) = match *self {
+            Commands::Block { json, ndjson, csv, .. }
+            | Commands::Lightning {
+                command: LightningCommands::Block { json, ndjson, csv, .. },
+            } => (json, ndjson, csv),
+            Commands::Tx { json, .. }
+            | Commands::Lightning {
+                command: LightningCommands::Tx { json, .. },
+            }
+            | Commands::Stats { json, .. }
+            | Commands::Batch { json, .. }
+            | Commands::Monitor { json, .. }
+            | Commands::Mempool { json, .. }
+            | Commands::Watch { json, .. }
+            | Commands::Info { json }
+            | Commands::Scan { json, .. } => (json, false, false),
+            Commands::Serve { .. } | Commands::Schema { .. } => (false, false, false),
+        };
+        [(json, FormatArg::Json), (ndjson, FormatArg::Ndjson), (csv, FormatArg::Csv)]
+            .into_iter()
+            .find_map(|(set, format)| set.then_some(format))
+    }
+
+    /// Whether the command looks at fees or at the scripts of spent outputs, which
+    /// sources serving raw transactions (Electrum, Floresta) only fill in by fetching
+    /// each input's previous transaction.
+    fn needs_prevouts(&self) -> bool {
+        !matches!(self, Commands::Info { .. } | Commands::Schema { .. })
+    }
+}
+
+/// Refuse the `block` and `lightning block` flags that don't go with `format`, as
+/// clap does for the alias flags.
+fn check_row_flags(format: FormatArg, oneline: bool, with_timelocks_only: bool, sort: SortArg) -> Result<()> {
+    if oneline && format != FormatArg::Text {
+        bail!("--oneline only applies to text output");
+    }
+    if format == FormatArg::Csv && with_timelocks_only {
+        bail!("--with-timelocks-only doesn't apply to CSV output");
+    }
+    if format == FormatArg::Ndjson && !matches!(sort, SortArg::None) {
+        bail!("--format ndjson streams transactions as they are analyzed, so it can't be sorted");
+    }
+    Ok(())
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum SortArg {
+    /// Highest confidence first (`block`: highest risk severity first)
+    Confidence,
+    /// Commitments first, then the other Lightning types (`block`: active timelocks first)
+    Type,
+    /// Most HTLC outputs first (`block`: most CLTV and CSV script timelocks first)
+    HtlcCount,
+    /// Block order
+    None,
+}
+
+impl From<SortArg> for SortKey {
+    fn from(s: SortArg) -> Self {
+        match s {
+            SortArg::Confidence => SortKey::Confidence,
+            SortArg::Type => SortKey::Type,
+            SortArg::HtlcCount => SortKey::HtlcCount,
+            SortArg::None => SortKey::None,
+        }
+    }
+}
+
 #[tokio::main]
-async fn main() -> Result<()> {
+async fn main() -> Result<ExitCode> {
     let cli = Cli::parse();
-    let client = MempoolClient::default();
+    let format = cli.command.format(cli.format)?;
+    let network = Network::from(cli.network);
+    let mut out: Box<dyn Write + Send> = match &cli.output_file {
+        Some(path) => {
+            let file = std::fs::File::create(path)
+                .with_context(|| format!("creating {}", path.display()))?;
+            Box::new(std::io::LineWriter::new(file))
+        }
+        None => Box::new(std::io::stdout()),
+    };
+    let terminal = cli.output_file.is_none() && std::io::stdout().is_terminal();
+    output::set_color(cli.color.into(), terminal);
+    let out = out.as_mut();
+    let timeout = Duration::from_secs(cli.timeout);
+    rayon::ThreadPoolBuilder::new()
+        .num_threads(cli.jobs)
+        .build_global()
+        .context("configuring analysis thread pool")?;
+    let cache_dir = (!cli.no_cache).then(|| cli.cache_dir.clone().unwrap_or_else(default_cache_dir));
+    let fail_on = FailOn::new(cli.fail_on.clone());
+    let verbosity = Verbosity::from_flags(cli.quiet, cli.verbose);
+    let detector = match (cli.strict, cli.lenient) {
+        (true, _) => DetectorConfig::strict(),
+        (_, true) => DetectorConfig::lenient(),
+        _ => DetectorConfig::default(),
+    };
 
-    match cli.command {
-        Commands::Tx { txid, json } => {
-            let tx = client.get_transaction(&txid).await?;
+    // Raw hex needs no data source at all
+    match &cli.command {
+        Commands::Tx {
+            raw: Some(hex),
+            show_scripts,
+            ..
+        } => {
+            let tx = decode_raw_transaction(hex, network)?;
             let analysis = analyze_transaction(&tx);
-
-            if json {
-                println!("{}", serde_json::to_string_pretty(&analysis)?);
+            fail_on.analysis(&analysis);
+            if format == Format::Json {
+                output::write_json(out, format, &analysis)?;
+            } else {
+                output::print_transaction_analysis(out, &analysis, Some(&tx.vin), *show_scripts)?;
+            }
+            out.flush()?;
+            return Ok(fail_on.exit_code());
+        }
+        Commands::Lightning {
+            command: LightningCommands::Tx {
+                raw: Some(hex),
+                explain,
+                show_scripts,
+                ..
+            },
+        } => {
+            let tx = decode_raw_transaction(hex, network)?;
+            let (result, trace) = classify_explained(&tx, *explain, None, &detector);
+            fail_on.lightning(&result);
+            if format == Format::Json {
+                writeln!(out, "{}", lightning_json(&result, trace.as_ref())?)?;
             } else {
-                output::print_transaction_analysis(&analysis);
+                output::print_lightning_classification(out, &tx.txid, &result, None)?;
+                if *show_scripts {
+                    output::print_input_scripts(out, &tx.vin)?;
+                }
+                if let Some(trace) = &trace {
+                    writeln!(out)?;
+                    output::print_classification_trace(out, trace)?;
+                }
+            }
+            out.flush()?;
+            return Ok(fail_on.exit_code());
+        }
+        Commands::Block {
+            block_file: Some(path),
+            height,
+            ..
+        } => {
+            let source = BlockFileSource::open(path, *height, network)?;
+            let label = format!("block file: {}", path.display());
+            let context = RunContext {
+                source_label: &label,
+                out: &mut *out,
+                format,
+                fail_on: &fail_on,
+                verbosity,
+                detector: &detector,
+            };
+            run(cli.command, source, context).await?;
+            out.flush()?;
+            return Ok(fail_on.exit_code());
+        }
+        Commands::Schema { output } => {
+            let schema = match output {
+                None => schema::output_schemas(),
+                Some(SchemaOutput::Analysis) => schema::transaction_analysis_schema().to_value(),
+                Some(SchemaOutput::Lightning) => schema::lightning_classification_schema().to_value(),
+            };
+            output::write_json(out, Format::Json, &schema)?;
+            out.flush()?;
+            return Ok(ExitCode::SUCCESS);
+        }
+        _ => {}
+    }
+
+    let command = cli.command;
+    let (client, label): (AnySource, String) = match cli.source {
+        Source::Mempool => {
+            let (host, request_delay_ms) = match command {
+                Commands::Serve {
+                    ref mempool_url,
+                    request_delay_ms,
+                    ..
+                } => (mempool_url.as_str(), request_delay_ms),
+                _ => (MEMPOOL_SPACE_URL, 250),
+            };
+            let url = cli
+                .api_url
+                .clone()
+                .unwrap_or_else(|| MempoolClient::api_url(host, network));
+            let client = match cli.proxy.as_deref() {
+                Some(proxy) => MempoolClient::with_proxy(&url, proxy)?,
+                None => MempoolClient::new(&url, DEFAULT_REQUEST_DELAY),
+            };
+            let client = client
+                .with_request_delay(Duration::from_millis(request_delay_ms))
+                .with_rate_limit(cli.rate_limit)
+                .with_timeout(timeout)
+                .with_quiet(verbosity == Verbosity::Quiet);
+            (client.into(), format!("mempool.space: {url}"))
+        }
+        Source::Core => {
+            let auth = match (cli.core_user, cli.core_pass, cli.core_cookie) {
+                (Some(user), Some(pass), _) => CoreAuth::UserPass { user, pass },
+                (_, _, Some(cookie)) => CoreAuth::CookieFile(cookie),
+                _ => {
+                    let home = std::env::var("HOME").unwrap_or_default();
+                    CoreAuth::CookieFile(PathBuf::from(home).join(".bitcoin/.cookie"))
+                }
+            };
+            let client = BitcoinCoreClient::new(&cli.core_url, auth)?.with_timeout(timeout);
+            (client.into(), format!("Bitcoin Core RPC: {}", cli.core_url))
+        }
+        Source::Electrum => {
+            // Raw transactions carry no prevouts; fetch them so HTLC scripts are visible
+            let client = ElectrumClient::new(&cli.electrum_url, network)?
+                .with_timeout(timeout)
+                .with_prevouts(command.needs_prevouts());
+            (client.into(), format!("Electrum: {}", cli.electrum_url))
+        }
+        Source::Floresta => {
+            if network != Network::Bitcoin {
+                bail!("the embedded Floresta node only runs on mainnet");
             }
+            let client = FlorestaClient::default()
+                .with_timeout(timeout)
+                .with_prevouts(command.needs_prevouts());
+            (client.into(), "embedded Floresta node".to_string())
+        }
+    };
+    let context = RunContext {
+        source_label: &label,
+        out: &mut *out,
+        format,
+        fail_on: &fail_on,
+        verbosity,
+        detector: &detector,
+    };
+    // Behind the on-disk transaction cache unless it's disabled
+    match cache_dir {
+        Some(dir) => {
+            let client = CachedSource::new(client, dir).with_quiet(verbosity == Verbosity::Quiet);
+            run(command, client, context).await?;
         }
-        Commands::Block { height, json } => {
-            eprintln!("Fetching block {height}...");
-            let txs = client.get_all_block_txs(height).await?;
-            eprintln!("Analyzing {} transactions...", txs.len());
+        None => run(command, client, context).await?,
+    }
+    out.flush()?;
+    Ok(fail_on.exit_code())
+}
 
-            let analyses: Vec<_> = txs.iter().map(|tx| analyze_transaction(tx)).collect();
+/// What [`run`] needs besides the command and its data source.
+struct RunContext<'a> {
+    source_label: &'a str,
+    out: &'a mut (dyn Write + Send),
+    format: Format,
+    fail_on: &'a FailOn,
+    verbosity: Verbosity,
+    detector: &'a DetectorConfig,
+}
+
+async fn run<S: DataSource + Send + Sync + 'static>(
+    command: Commands,
+    client: S,
+    context: RunContext<'_>,
+) -> Result<()> {
+    let RunContext {
+        source_label,
+        out,
+        format,
+        fail_on,
+        verbosity,
+        detector,
+    } = context;
+    match command {
+        Commands::Tx {
+            txid,
+            show_scripts,
+            at,
+            ..
+        } => {
+            let tx = match at {
+                Some((height, index)) => {
+                    let fetch = transaction_at(&client, height, index);
+                    verbosity.timed(format!("tx {height}:{index}"), fetch).await?
+                }
+                None => {
+                    let txid = txid.context("txid is required")?;
+                    verbosity.timed(format!("tx {txid}"), client.get_transaction(&txid)).await?
+                }
+            };
+            let tip = ChainTip {
+                height: client.get_block_tip_height().await?,
+                median_time_past: None,
+            };
+            let analysis = analyze_transaction_with_tip(&tx, &tip);
+            fail_on.analysis(&analysis);
 
-            if json {
-                println!("{}", serde_json::to_string_pretty(&analyses)?);
+            if format == Format::Json {
+                output::write_json(out, format, &analysis)?;
             } else {
-                output::print_block_summary(height, &analyses);
+                output::print_transaction_analysis(out, &analysis, Some(&tx.vin), show_scripts)?;
+            }
+        }
+        Commands::Block {
+            height,
+            to,
+            with_timelocks_only,
+            oneline,
+            sort,
+            sample,
+            seed,
+            ..
+        } => {
+            let height = match height {
+                Some(height) => height,
+                // A block file serves just its own block
+                None => client.get_block_tip_height().await?,
+            };
+            let end = to.unwrap_or(height);
+            if end < height {
+                bail!("--to {end} is below the start height {height}");
+            }
+            let single = height == end;
+            let mut sampler = sample.map(|n| Sampler::new(n, seed));
+            let mut blocks = Vec::new();
+            let mut csv_out = (format == Format::Csv).then(|| csv::Writer::from_writer(Vec::new()));
+            let machine_readable = format != Format::Text || oneline;
+            let progress = ScanProgress::new(height, end, !machine_readable, verbosity);
+            let rows = matches!(format, Format::Ndjson | Format::Csv) || oneline;
+            // Rows need neither the whole block nor its order, so unless a sample or an
+            // ordering is asked for, each transaction is printed and dropped as it arrives
+            let streamed = rows && sampler.is_none() && SortKey::from(sort) == SortKey::None;
+
+            // One block at a time, so memory is bounded by the largest block
+            for h in height..=end {
+                if streamed {
+                    progress.status(&format!("Analyzing block {h}..."));
+                    client
+                        .for_each_block_tx(h, |tx| {
+                            let analysis = analyze_transaction(&tx);
+                            fail_on.analysis(&analysis);
+                            let csv_out = csv_out.as_mut();
+                            print_timelock_row(out, &analysis, format, with_timelocks_only, csv_out)
+                        })
+                        .await?;
+                    continue;
+                }
+
+                progress.status(&format!("Fetching block {h}..."));
+                let fetch = client.get_all_block_txs(h);
+                let mut txs = progress.timed(format!("block {h}"), fetch).await?;
+                if let Some(ref mut sampler) = sampler {
+                    txs = sampler.sample(txs);
+                }
+                progress.status(&format!("Analyzing {} transactions...", txs.len()));
+                let bar = progress.transactions(txs.len());
+
+                let mut analyses: Vec<_> = txs
+                    .par_iter()
+                    .map(analyze_transaction)
+                    .inspect(|analysis| {
+                        fail_on.analysis(analysis);
+                        bar.inc(1);
+                    })
+                    .collect();
+                progress.block_done(bar);
+                sort::sort_analyses(&mut analyses, sort.into());
+
+                if rows {
+                    for analysis in &analyses {
+                        let csv_out = csv_out.as_mut();
+                        print_timelock_row(out, analysis, format, with_timelocks_only, csv_out)?;
+                    }
+                    continue;
+                }
+
+                let mut tally = TimelockTally::default();
+                for analysis in &analyses {
+                    tally.record(analysis);
+                }
+
+                if single && format == Format::Json {
+                    if with_timelocks_only {
+                        analyses.retain(|analysis| analysis.summary.has_active_timelocks);
+                    }
+                    output::write_json(out, format, &analyses)?;
+                } else if format == Format::Text {
+                    progress.suspend(|| {
+                        output::print_block_summary(out, h, &analyses)?;
+                        writeln!(out)
+                    })?;
+                }
+                blocks.push((h, tally));
+            }
+            progress.finish();
+
+            if !single && !rows {
+                if format == Format::Json {
+                    let report = range_report(height, end, &blocks, TimelockTally::add);
+                    output::write_json(out, format, &report)?;
+                } else {
+                    output::print_block_range_totals(out, height, end, &blocks)?;
+                }
             }
         }
         Commands::Lightning { command } => match command {
-            LightningCommands::Tx { txid, json } => {
+            LightningCommands::Tx {
+                txid,
+                verify,
+                explain,
+                show_scripts,
+                ..
+            } => {
+                let txid = txid.context("txid is required")?;
                 let tx = client.get_transaction(&txid).await?;
-                let result = classify_lightning(&tx);
+                let tip = annotation_tip(&client).await;
+                let (mut result, trace) = classify_explained(&tx, explain, tip.as_ref(), detector);
+                if verify {
+                    verify_commitment(&client, &tx, &mut result).await?;
+                }
+                fail_on.lightning(&result);
 
-                if json {
-                    println!("{}", serde_json::to_string_pretty(&result)?);
+                if format == Format::Json {
+                    writeln!(out, "{}", lightning_json(&result, trace.as_ref())?)?;
                 } else {
-                    output::print_lightning_classification(&txid, &result);
+                    output::print_lightning_classification(out, &txid, &result, tip.as_ref())?;
+                    if show_scripts {
+                        output::print_input_scripts(out, &tx.vin)?;
+                    }
+                    if let Some(trace) = &trace {
+                        writeln!(out)?;
+                        output::print_classification_trace(out, trace)?;
+                    }
                 }
             }
-            LightningCommands::Block { height, json } => {
-                eprintln!("Fetching block {height}...");
-                let txs = client.get_all_block_txs(height).await?;
-                eprintln!("Classifying {} transactions...", txs.len());
-
-                let results: Vec<_> = txs
-                    .iter()
-                    .map(|tx| (tx.txid.clone(), classify_lightning(tx)))
-                    .collect();
+            LightningCommands::Block {
+                height,
+                to,
+                oneline,
+                sort,
+                sample,
+                seed,
+                min_confidence,
+                verify,
+                ..
+            } => {
+                let end = to.unwrap_or(height);
+                if end < height {
+                    bail!("--to {end} is below the start height {height}");
+                }
+                let single = height == end;
+                let mut sampler = sample.map(|n| Sampler::new(n, seed));
+                let machine_readable = format != Format::Text || oneline;
+                let tip = annotation_tip(&client).await;
+                let min_confidence = min_confidence.map(Confidence::from);
+                let passes = |lc: &LightningClassification| {
+                    min_confidence.is_none_or(|min| lc.confidence >= min)
+                };
+                let mut blocks = Vec::new();
+                let mut csv_out = (format == Format::Csv).then(|| csv::Writer::from_writer(Vec::new()));
+                let progress = ScanProgress::new(height, end, !machine_readable, verbosity);
 
-                if json {
-                    println!("{}", serde_json::to_string_pretty(&results)?);
-                } else {
-                    output::print_lightning_block_summary(height, &results);
+                for h in height..=end {
+                    progress.status(&format!("Fetching block {h}..."));
+                    let fetch = client.get_all_block_txs(h);
+                    let mut txs = progress.timed(format!("block {h}"), fetch).await?;
+                    if let Some(ref mut sampler) = sampler {
+                        txs = sampler.sample(txs);
+                    }
+                    progress.status(&format!("Classifying {} transactions...", txs.len()));
+
+                    if format == Format::Ndjson {
+                        for tx in &txs {
+                            let mut lightning = classify_at(tx, tip.as_ref(), detector);
+                            if verify {
+                                verify_commitment(&client, tx, &mut lightning).await?;
+                            }
+                            if !passes(&lightning) {
+                                continue;
+                            }
+                            fail_on.lightning(&lightning);
+                            let entry = serde_json::json!({
+                                "txid": tx.txid,
+                                "lightning": lightning,
+                            });
+                            output::write_ndjson(out, &entry)?;
+                        }
+                        continue;
+                    }
+
+                    // Linked before filtering, so a hidden commitment still anchors its HTLCs
+                    let classify =
+                        commands::classify_block(&client, &txs, tip.as_ref(), detector, verify, &progress);
+                    let mut results = classify.await?;
+                    let clusters = if machine_readable {
+                        Vec::new()
+                    } else {
+                        let classified = results.iter().map(|(_, lc)| lc);
+                        channel::cluster_htlc_spends(txs.iter().zip(classified))
+                    };
+                    // Verification can change confidence, so filter afterwards
+                    results.retain(|(_, lc)| passes(lc));
+                    for (_, lc) in &results {
+                        fail_on.lightning(lc);
+                    }
+                    sort::sort_lightning(&mut results, sort.into());
+
+                    if let Some(ref mut writer) = csv_out {
+                        for (txid, lc) in &results {
+                            output::write_lightning_csv(writer, txid, lc)?;
+                        }
+                        output::flush_csv(out, writer)?;
+                        continue;
+                    }
+                    if oneline {
+                        for (txid, lc) in &results {
+                            output::print_lightning_oneline(out, txid, lc)?;
+                        }
+                        continue;
+                    }
+
+                    let mut tally = LightningTally::default();
+                    for (_, lc) in &results {
+                        tally.record(lc);
+                    }
+                    // Filtered-out transactions were still scanned
+                    tally.transactions = txs.len();
+
+                    if single && format == Format::Json {
+                        output::write_json(out, format, &results)?;
+                    } else if format == Format::Text {
+                        progress.suspend(|| {
+                            let tip = tip.as_ref();
+                            output::print_lightning_block_summary(out, h, txs.len(), &results, tip)?;
+                            writeln!(out)?;
+                            output::print_channel_clusters(out, &clusters)
+                        })?;
+                    }
+                    blocks.push((h, tally));
+                }
+                progress.finish();
+
+                if !single && format != Format::Ndjson && format != Format::Csv && !oneline {
+                    if format == Format::Json {
+                        let report = range_report(height, end, &blocks, LightningTally::add);
+                        output::write_json(out, format, &report)?;
+                    } else {
+                        output::print_lightning_range_totals(out, height, end, &blocks)?;
+                    }
                 }
             }
         },
-        Commands::Serve {
-            port,
-            mempool_url,
-            request_delay_ms,
-        } => {
-            let client = MempoolClient::new(&mempool_url, Duration::from_millis(request_delay_ms));
+        Commands::Stats { height, to, .. } => {
+            let end = to.unwrap_or(height);
+            if end < height {
+                bail!("--to {end} is below the start height {height}");
+            }
+            let progress = ScanProgress::new(height, end, format == Format::Text, verbosity);
+            let stats = commands::range_stats(
+                &client,
+                height,
+                end,
+                detector,
+                &progress,
+                |analysis, lightning| {
+                    fail_on.analysis(analysis);
+                    fail_on.lightning(lightning);
+                },
+            )
+            .await?;
+
+            let report = stats.report(height, end);
+            if format == Format::Json {
+                output::write_json(out, format, &report)?;
+            } else {
+                output::print_stats_report(out, &report)?;
+            }
+        }
+        Commands::Batch { file, .. } => {
+            let input = match file {
+                Some(path) => std::fs::read_to_string(&path)
+                    .with_context(|| format!("reading {}", path.display()))?,
+                None => std::io::read_to_string(std::io::stdin())?,
+            };
+            let txids: Vec<String> = input
+                .lines()
+                .enumerate()
+                .filter(|(_, l)| !l.trim().is_empty())
+                .map(|(i, l)| parse_txid(l).with_context(|| format!("line {}", i + 1)))
+                .collect::<Result<_>>()?;
+            verbosity.status(format!("Classifying {} transactions...", txids.len()));
+            let tip = annotation_tip(&client).await;
+
+            let mut entries = Vec::new();
+            let summary = commands::classify_batch(
+                &client,
+                &txids,
+                tip.as_ref(),
+                detector,
+                verbosity,
+                |txid, result| {
+                    let classification = match result {
+                        Ok(classification) => classification,
+                        Err(e) => {
+                            eprintln!("error fetching tx {txid}: {e}");
+                            return Ok(());
+                        }
+                    };
+                    fail_on.lightning(&classification);
+
+                    if format == Format::Json {
+                        entries.push(LightningTxEntry {
+                            txid: txid.to_string(),
+                            classification,
+                        });
+                    } else {
+                        let tip = tip.as_ref();
+                        output::print_lightning_classification(out, txid, &classification, tip)?;
+                        writeln!(out)?;
+                    }
+                    anyhow::Ok(())
+                },
+            )
+            .await?;
+
+            if format == Format::Json {
+                output::write_json(out, format, &entries)?;
+            }
+            let BatchSummary { succeeded, failed } = summary;
+            verbosity.status(format!("{succeeded} succeeded, {failed} failed"));
+        }
+        Commands::Serve { port, .. } => {
             let cached = CachedClient::new(client, 10_000);
             let config = SecurityConfig::default();
-            let app = server::create_router(cached, config);
+            let app = server::create_router_with_detector(cached, config, detector.clone());
 
             let addr = format!("0.0.0.0:{port}");
             eprintln!("Starting server on {addr}");
-            eprintln!("  mempool.space: {mempool_url}");
+            eprintln!("  {source_label}");
             eprintln!("  Endpoints:");
             eprintln!("    GET /api/tx/{{txid}}");
             eprintln!("    GET /api/block/{{height}}?filter=timelocks&offset=0&limit=100");
@@ -210,11 +1228,11 @@ async fn main() -> Result<()> {
         }
         Commands::Monitor {
             interval,
-            json,
             min_severity,
             cltv_critical,
             cltv_warning,
             cltv_info,
+            ..
         } => {
             let min_sev = match min_severity.as_deref() {
                 Some("critical") => Severity::Critical,
@@ -228,8 +1246,7 @@ async fn main() -> Result<()> {
                 ..SecurityConfig::default()
             };
 
-            eprintln!("Monitoring mempool (every {interval}s, Ctrl+C to stop)...");
-            eprintln!();
+            verbosity.status(format!("Monitoring mempool (every {interval}s, Ctrl+C to stop)...\n"));
 
             let mut seen = HashSet::new();
             let poll_interval = Duration::from_secs(interval);
@@ -258,7 +1275,8 @@ async fn main() -> Result<()> {
                         continue;
                     }
 
-                    let tx = match client.get_transaction(txid).await {
+                    let fetch = client.get_transaction(txid);
+                    let tx = match verbosity.timed(format!("tx {txid}"), fetch).await {
                         Ok(t) => t,
                         Err(e) => {
                             eprintln!("error fetching tx {txid}: {e}");
@@ -266,39 +1284,27 @@ async fn main() -> Result<()> {
                         }
                     };
 
-                    let timelock = analyze_transaction(&tx);
-                    let lightning = classify_lightning(&tx);
-                    let alerts = analyzer::analyze_transaction(
-                        &timelock,
-                        &lightning,
-                        current_height,
-                        &config,
-                    );
-
-                    let alerts: Vec<_> = alerts
-                        .into_iter()
-                        .filter(|a| a.severity >= min_sev)
-                        .collect();
-
-                    let dominated =
-                        !alerts.is_empty()
-                        || lightning.tx_type.is_some()
-                        || timelock.summary.has_active_timelocks;
-
-                    if !dominated {
+                    let hit =
+                        commands::monitor_transaction(&tx, current_height, detector, &config, min_sev);
+                    let Some(MonitorHit {
+                        timelock,
+                        lightning,
+                        alerts,
+                    }) = hit
+                    else {
                         continue;
-                    }
+                    };
 
-                    if json {
+                    if matches!(format, Format::Json | Format::Ndjson) {
                         let entry = serde_json::json!({
                             "txid": txid,
                             "timelock": timelock,
                             "lightning": lightning,
                             "alerts": alerts,
                         });
-                        println!("{}", serde_json::to_string(&entry)?);
+                        output::write_ndjson(out, &entry)?;
                     } else {
-                        output::print_monitor_hit(&timelock, &lightning, &alerts);
+                        output::print_monitor_hit(out, &timelock, &lightning, &alerts)?;
                     }
                 }
 
@@ -310,15 +1316,153 @@ async fn main() -> Result<()> {
                 tokio::time::sleep(poll_interval).await;
             }
         }
+        Commands::Mempool { min_confidence, .. } => {
+            let tip = annotation_tip(&client).await;
+            let min_confidence = min_confidence.map(Confidence::from);
+            commands::scan_mempool(
+                &client,
+                tip.as_ref(),
+                detector,
+                min_confidence,
+                verbosity,
+                |txid, lightning| {
+                    fail_on.lightning(&lightning);
+                    if matches!(format, Format::Json | Format::Ndjson) {
+                        let entry = serde_json::json!({
+                            "txid": txid,
+                            "lightning": lightning,
+                        });
+                        output::write_ndjson(out, &entry)?;
+                    } else {
+                        let tip = tip.as_ref();
+                        output::print_lightning_classification(out, txid, &lightning, tip)?;
+                        writeln!(out)?;
+                    }
+                    anyhow::Ok(())
+                },
+            )
+            .await?;
+        }
+        Commands::Watch {
+            interval,
+            lightning_only,
+            metrics_addr,
+            since,
+            ..
+        } => {
+            let poll_interval = Duration::from_secs(interval);
+            let metrics = Arc::new(WatchMetrics::default());
+            if let Some(addr) = metrics_addr {
+                let listener = TcpListener::bind(&addr)
+                    .await
+                    .with_context(|| format!("binding metrics address {addr}"))?;
+                eprintln!("Serving metrics on http://{}/metrics", listener.local_addr()?);
+                let app = metrics_router(metrics.clone());
+                tokio::spawn(async move {
+                    if let Err(e) = axum::serve(listener, app).await {
+                        eprintln!("metrics server error: {e}");
+                    }
+                });
+            }
+            // Only blocks mined after startup are scanned, unless catching up from --since
+            let tip = client.get_block_tip_height().await?;
+            let first = match since {
+                Some(since) => find_height_at_or_after(&client, since).await?,
+                None => None,
+            };
+            if since.is_some() && first.is_none() {
+                verbosity.status("--since is after the tip block; nothing to catch up on");
+            }
+            let mut last_height = first.map_or(tip, |h| h.saturating_sub(1));
+            let mut catch_up = first.is_some();
+            match first {
+                Some(first) => verbosity.status(format!(
+                    "Catching up on blocks {first} to {tip}, then watching for new ones \
+                     (every {interval}s, Ctrl+C to stop)...\n"
+                )),
+                None => verbosity.status(format!(
+                    "Watching for blocks after {tip} (every {interval}s, Ctrl+C to stop)...\n"
+                )),
+            }
+
+            loop {
+                // The first round of a catch-up runs right away
+                if !std::mem::take(&mut catch_up) {
+                    tokio::time::sleep(poll_interval).await;
+                }
+
+                let tip = match client.get_block_tip_height().await {
+                    Ok(h) => h,
+                    Err(e) => {
+                        eprintln!("error fetching tip: {e}");
+                        continue;
+                    }
+                };
+                if tip < last_height {
+                    eprintln!("Tip went back from {last_height} to {tip} (reorg?); resuming from {tip}");
+                    last_height = tip;
+                    continue;
+                }
+
+                for h in last_height + 1..=tip {
+                    let fetch = client.get_all_block_txs(h);
+                    let txs = match verbosity.timed(format!("block {h}"), fetch).await {
+                        Ok(t) => t,
+                        Err(e) => {
+                            // Retried from this block on the next poll
+                            eprintln!("error fetching block {h}: {e}");
+                            break;
+                        }
+                    };
+                    let WatchedBlock {
+                        lightning: results,
+                        timelocks: analyses,
+                    } = commands::watch_block(&txs, detector, !lightning_only);
+                    metrics.record_block(h, &results);
+
+                    if matches!(format, Format::Json | Format::Ndjson) {
+                        let lightning: Vec<_> = results
+                            .into_iter()
+                            .filter(|(_, lc)| lc.tx_type.is_some())
+                            .map(|(txid, classification)| LightningTxEntry { txid, classification })
+                            .collect();
+                        let mut entry = serde_json::json!({
+                            "height": h,
+                            "transactions": txs.len(),
+                            "lightning": lightning,
+                        });
+                        if !lightning_only {
+                            let active: Vec<_> =
+                                analyses.iter().filter(|a| a.summary.has_active_timelocks).collect();
+                            entry["timelocks"] = serde_json::to_value(&active)?;
+                        }
+                        output::write_ndjson(out, &entry)?;
+                    } else {
+                        let chain_tip = ChainTip {
+                            height: tip,
+                            median_time_past: None,
+                        };
+                        let tip = Some(&chain_tip);
+                        output::print_lightning_block_summary(out, h, txs.len(), &results, tip)?;
+                        writeln!(out)?;
+                        if !lightning_only {
+                            output::print_block_summary(out, h, &analyses)?;
+                            writeln!(out)?;
+                        }
+                    }
+                    last_height = h;
+                }
+            }
+        }
         Commands::Scan {
             start,
             end,
-            json,
             cltv_critical,
             cltv_warning,
             cltv_info,
             cluster_window,
             cluster_threshold,
+            ..
         } => {
             let end = end.unwrap_or(start);
             let config = SecurityConfig {
@@ -330,48 +1474,162 @@ async fn main() -> Result<()> {
                 ..SecurityConfig::default()
             };
 
-            let current_height = client.get_block_tip_height().await?;
-            eprintln!("Current tip: block {current_height}");
+            let all_alerts =
+                commands::security_scan(&client, start, end, &config, detector, verbosity, |lc| {
+                    fail_on.lightning(lc)
+                })
+                .await?;
+            fail_on.alerts(&all_alerts);
 
-            let mut all_alerts = Vec::new();
-            let mut htlc_expiries = Vec::new();
+            if format == Format::Json {
+                output::write_json(out, format, &all_alerts)?;
+            } else {
+                output::print_security_scan(out, start, end, &all_alerts)?;
+            }
+        }
+        Commands::Info { .. } => {
+            let tip_height = client.get_block_tip_height().await?;
+            let sync = client.sync_status().await?;
+            if format == Format::Json {
+                let info = serde_json::json!({
+                    "source": source_label,
+                    "tip_height": tip_height,
+                    "synced": sync.map(|s| s.synced),
+                    "sync_progress": sync.and_then(|s| s.progress),
+                });
+                output::write_json(out, format, &info)?;
+            } else {
+                output::print_source_info(out, source_label, tip_height, sync.as_ref())?;
+            }
+        }
+        Commands::Schema { .. } => unreachable!("schema needs no data source"),
+    }
 
-            for height in start..=end {
-                eprintln!("Scanning block {height}...");
-                let txs = client.get_all_block_txs(height).await?;
-                eprintln!("  {} transactions", txs.len());
+    Ok(())
+}
 
-                for tx in &txs {
-                    let timelock = analyze_transaction(tx);
-                    let lightning = classify_lightning(tx);
+/// Classify `tx` like [`classify_at`], with the classifier's trace when `explain` is set.
+fn classify_explained(
+    tx: &ApiTransaction,
+    explain: bool,
+    tip: Option<&ChainTip>,
+    detector: &DetectorConfig,
+) -> (LightningClassification, Option<ClassificationTrace>) {
+    if explain {
+        let detector = match tip {
+            Some(tip) => detector.clone().with_tip(tip.height),
+            None => detector.clone(),
+        };
+        let (mut result, trace) = explain_lightning_with_config(tx, &detector);
+        if let Some(tip) = tip {
+            add_tip_reasons(&mut result, tip);
+        }
+        (result, Some(trace))
+    } else {
+        (classify_at(tx, tip, detector), None)
+    }
+}
 
-                    // Collect HTLC expiries for clustering analysis
-                    if lightning.tx_type == Some(LightningTxType::HtlcTimeout) {
-                        if let Some(expiry) = lightning.params.cltv_expiry {
-                            htlc_expiries.push(expiry);
-                        }
-                    }
+/// The classification as pretty JSON, with the trace under `trace` when explaining.
+fn lightning_json(
+    result: &LightningClassification,
+    trace: Option<&ClassificationTrace>,
+) -> Result<String> {
+    let mut value = serde_json::to_value(result)?;
+    if let Some(trace) = trace {
+        value["trace"] = serde_json::to_value(&trace.steps)?;
+    }
+    Ok(serde_json::to_string_pretty(&value)?)
+}
 
-                    let mut alerts =
-                        analyzer::analyze_transaction(&timelock, &lightning, current_height, &config);
-                    all_alerts.append(&mut alerts);
-                }
-            }
+/// Print one row of `block` output: an NDJSON line, a CSV record or a `--oneline` line.
+fn print_timelock_row(
+    out: &mut dyn Write,
+    analysis: &TransactionAnalysis,
+    format: Format,
+    with_timelocks_only: bool,
+    csv_out: Option<&mut csv::Writer<Vec<u8>>>,
+) -> Result<()> {
+    if with_timelocks_only && !analysis.summary.has_active_timelocks {
+        return Ok(());
+    }
+    match (format, csv_out) {
+        (Format::Ndjson, _) => output::write_ndjson(out, analysis)?,
+        (Format::Csv, Some(writer)) => {
+            output::write_timelock_csv(writer, analysis)?;
+            output::flush_csv(out, writer)?;
+        }
+        _ => output::print_timelock_oneline(out, analysis)?,
+    }
+    Ok(())
+}
+
+/// Chain tip that HTLC-timeout expiries are judged against and text output shows CLTV
+/// expiries relative to; both are left out when the tip can't be fetched.
+async fn annotation_tip<S: DataSource>(client: &S) -> Option<ChainTip> {
+    let height = client.get_block_tip_height().await.ok()?;
+    Some(ChainTip {
+        height,
+        median_time_past: None,
+    })
+}
 
-            // Cross-transaction clustering analysis
-            let mut cluster_alerts = analyzer::detect_htlc_clustering(&htlc_expiries, &config);
-            all_alerts.append(&mut cluster_alerts);
+/// Picks `--sample` transactions per block. Without `--seed` a random seed is drawn
+/// and printed, so the same sample can be taken again.
+struct Sampler {
+    size: usize,
+    rng: StdRng,
+}
 
-            // Sort by severity (critical first)
-            all_alerts.sort_by(|a, b| b.severity.cmp(&a.severity));
+impl Sampler {
+    fn new(size: usize, seed: Option<u64>) -> Self {
+        let seed = seed.unwrap_or_else(|| {
+            let seed = rand::random();
+            eprintln!("Sampling with --seed {seed}");
+            seed
+        });
+        Self {
+            size,
+            rng: StdRng::seed_from_u64(seed),
+        }
+    }
 
-            if json {
-                println!("{}", serde_json::to_string_pretty(&all_alerts)?);
-            } else {
-                output::print_security_scan(start, end, &all_alerts);
-            }
+    /// `size` transactions picked uniformly at random, kept in block order.
+    fn sample(&mut self, txs: Vec<ApiTransaction>) -> Vec<ApiTransaction> {
+        if txs.len() <= self.size {
+            return txs;
         }
+        let mut picked = rand::seq::index::sample(&mut self.rng, txs.len(), self.size).into_vec();
+        picked.sort_unstable();
+        let mut picked = picked.into_iter().peekable();
+        txs.into_iter()
+            .enumerate()
+            .filter(|(i, _)| picked.next_if_eq(i).is_some())
+            .map(|(_, tx)| tx)
+            .collect()
     }
+}
 
-    Ok(())
+/// JSON report for a block range: per-block tallies plus the grand total.
+fn range_report<T: Default + Serialize>(
+    start: u64,
+    end: u64,
+    blocks: &[(u64, T)],
+    add: fn(&mut T, &T),
+) -> serde_json::Value {
+    let mut total = T::default();
+    for (_, tally) in blocks {
+        add(&mut total, tally);
+    }
+    let per_block: Vec<_> = blocks
+        .iter()
+        .map(|(height, tally)| serde_json::json!({ "height": height, "counts": tally }))
+        .collect();
+
+    serde_json::json!({
+        "start_height": start,
+        "end_height": end,
+        "blocks": per_block,
+        "total": total,
+    })
 }
@@ -0,0 +1,23 @@
+use schemars::{Schema, schema_for};
+use serde_json::{Value, json};
+
+use crate::lightning::types::LightningClassification;
+use crate::timelock::types::TransactionAnalysis;
+
+/// JSON Schema of a single `--json` timelock analysis.
+pub fn transaction_analysis_schema() -> Schema {
+    schema_for!(TransactionAnalysis)
+}
+
+/// JSON Schema of a single `--json` Lightning classification.
+pub fn lightning_classification_schema() -> Schema {
+    schema_for!(LightningClassification)
+}
+
+/// Both schemas, keyed by output type.
+pub fn output_schemas() -> Value {
+    json!({
+        "transaction_analysis": transaction_analysis_schema(),
+        "lightning_classification": lightning_classification_schema(),
+    })
+}
@@ -15,6 +15,7 @@ pub fn analyze_transaction(
     detect_timelock_mixing(txid, timelock, &mut alerts);
     detect_short_cltv_delta(txid, timelock, current_height, config, &mut alerts);
     detect_anomalous_sequences(txid, timelock, lightning, config, &mut alerts);
+    detect_htlc_timeout_maturity(txid, timelock, lightning, current_height, &mut alerts);
 
     alerts
 }
@@ -228,6 +229,66 @@ fn detect_short_cltv_delta(
     }
 }
 
+// ─── HTLC-timeout maturity ───────────────────────────────────────────────────
+
+fn detect_htlc_timeout_maturity(
+    txid: &str,
+    timelock: &TransactionAnalysis,
+    lightning: &LightningClassification,
+    current_height: u64,
+    alerts: &mut Vec<Alert>,
+) {
+    if lightning.tx_type != Some(LightningTxType::HtlcTimeout) {
+        return;
+    }
+    // A height of 0 means the tip couldn't be fetched
+    let Some(expiry) = lightning.params.cltv_expiry.filter(|_| current_height > 0) else {
+        return;
+    };
+
+    let blocks_remaining = expiry as i64 - current_height as i64;
+    let confirmed = timelock.block_height.is_some();
+    // nLockTime N is final in block N + 1, the next one when the tip is at N
+    let (severity, description) = if blocks_remaining > 0 {
+        let consequence = if confirmed {
+            "It can't have confirmed yet: the transaction data or the tip is suspect."
+        } else {
+            "It can't confirm until then."
+        };
+        (
+            Severity::Warning,
+            format!(
+                "HTLC-timeout locktime {blocks_remaining} blocks in the future (block {expiry}). \
+                 {consequence}"
+            ),
+        )
+    } else {
+        (
+            Severity::Informational,
+            format!(
+                "HTLC-timeout locktime at block {expiry} already matured ({} blocks ago).",
+                -blocks_remaining
+            ),
+        )
+    };
+
+    alerts.push(Alert {
+        id: format!("htlc-timeout-maturity-{txid}-{expiry}"),
+        severity,
+        detection_type: DetectionType::HtlcTimeoutMaturity,
+        txid: txid.to_string(),
+        input_index: None,
+        description,
+        details: AlertDetails::HtlcTimeoutMaturity {
+            cltv_expiry: expiry,
+            current_height,
+            blocks_remaining,
+            confirmed,
+        },
+        reference: None,
+    });
+}
+
 // ─── Anomalous nSequence ─────────────────────────────────────────────────────
 
 const SEQUENCE_DISABLE_FLAG: u32 = 1 << 31;
@@ -15,6 +15,7 @@ pub enum DetectionType {
     ShortCltvDelta,
     HtlcClustering,
     AnomalousSequence,
+    HtlcTimeoutMaturity,
 }
 
 #[derive(Debug, Clone, Serialize)]
@@ -62,6 +63,12 @@ pub enum AlertDetails {
         raw_hex: String,
         anomaly: SequenceAnomaly,
     },
+    HtlcTimeoutMaturity {
+        cltv_expiry: u32,
+        current_height: u64,
+        blocks_remaining: i64,
+        confirmed: bool,
+    },
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
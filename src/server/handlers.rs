@@ -10,8 +10,8 @@ use axum::response::Json;
 use axum::response::sse::{Event, KeepAlive, KeepAliveStream, Sse};
 
 use crate::api::source::DataSource;
-use crate::lightning::detector::classify_lightning;
-use crate::lightning::types::LightningTxType;
+use crate::lightning::detector::classify_lightning_with_config;
+use crate::lightning::types::{DetectorConfig, LightningTxType};
 use crate::security::analyzer;
 use crate::security::types::{DetectionType, SecurityConfig, Severity};
 use crate::timelock::extractor::analyze_transaction;
@@ -23,6 +23,7 @@ pub type AppState<S> = Arc<ServerState<S>>;
 pub struct ServerState<S> {
     pub client: S,
     pub config: SecurityConfig,
+    pub detector: DetectorConfig,
 }
 
 pub async fn get_transaction<S: DataSource + Send + Sync>(
@@ -42,7 +43,7 @@ pub async fn get_transaction<S: DataSource + Send + Sync>(
         .unwrap_or(0);
 
     let timelock = analyze_transaction(&tx);
-    let lightning = classify_lightning(&tx);
+    let lightning = classify_lightning_with_config(&tx, &state.detector);
     let alerts = analyzer::analyze_transaction(&timelock, &lightning, tip, &state.config);
 
     Ok(Json(TxAnalysisResponse {
@@ -70,7 +71,7 @@ pub async fn get_block<S: DataSource + Send + Sync>(
         .iter()
         .map(|tx| {
             let timelock = analyze_transaction(tx);
-            let lightning = classify_lightning(tx);
+            let lightning = classify_lightning_with_config(tx, &state.detector);
             let alerts =
                 analyzer::analyze_transaction(&timelock, &lightning, tip, &state.config);
             TxAnalysisResponse {
@@ -128,7 +129,7 @@ pub async fn get_scan<S: DataSource + Send + Sync>(
 
         for tx in &txs {
             let timelock = analyze_transaction(tx);
-            let lightning = classify_lightning(tx);
+            let lightning = classify_lightning_with_config(tx, &state.detector);
 
             if lightning.tx_type == Some(LightningTxType::HtlcTimeout) {
                 if let Some(expiry) = lightning.params.cltv_expiry {
@@ -184,6 +185,11 @@ pub async fn get_lightning<S: DataSource + Send + Sync>(
     let mut commitments = 0;
     let mut htlc_timeouts = 0;
     let mut htlc_successes = 0;
+    let mut mutual_closes = 0;
+    let mut penalties = 0;
+    let mut to_local_sweeps = 0;
+    let mut anchor_sweeps = 0;
+    let mut splices = 0;
     let mut ln_txs = Vec::new();
     let mut expiry_counts: HashMap<u32, usize> = HashMap::new();
 
@@ -197,7 +203,7 @@ pub async fn get_lightning<S: DataSource + Send + Sync>(
         total_scanned += txs.len();
 
         for tx in &txs {
-            let classification = classify_lightning(tx);
+            let classification = classify_lightning_with_config(tx, &state.detector);
             match classification.tx_type {
                 Some(LightningTxType::Commitment) => {
                     commitments += 1;
@@ -223,6 +229,41 @@ pub async fn get_lightning<S: DataSource + Send + Sync>(
                         classification,
                     });
                 }
+                Some(LightningTxType::MutualClose) => {
+                    mutual_closes += 1;
+                    ln_txs.push(LightningTxEntry {
+                        txid: tx.txid.clone(),
+                        classification,
+                    });
+                }
+                Some(LightningTxType::Penalty) => {
+                    penalties += 1;
+                    ln_txs.push(LightningTxEntry {
+                        txid: tx.txid.clone(),
+                        classification,
+                    });
+                }
+                Some(LightningTxType::ToLocalSweep) => {
+                    to_local_sweeps += 1;
+                    ln_txs.push(LightningTxEntry {
+                        txid: tx.txid.clone(),
+                        classification,
+                    });
+                }
+                Some(LightningTxType::AnchorSweep) => {
+                    anchor_sweeps += 1;
+                    ln_txs.push(LightningTxEntry {
+                        txid: tx.txid.clone(),
+                        classification,
+                    });
+                }
+                Some(LightningTxType::Splice) => {
+                    splices += 1;
+                    ln_txs.push(LightningTxEntry {
+                        txid: tx.txid.clone(),
+                        classification,
+                    });
+                }
                 None => {}
             }
         }
@@ -244,6 +285,11 @@ pub async fn get_lightning<S: DataSource + Send + Sync>(
         commitments,
         htlc_timeouts,
         htlc_successes,
+        mutual_closes,
+        penalties,
+        to_local_sweeps,
+        anchor_sweeps,
+        splices,
         transactions: ln_txs,
         cltv_expiry_distribution,
     }))
@@ -278,7 +324,7 @@ pub async fn get_monitor<S: DataSource + Send + Sync + 'static>(
                     };
 
                     let timelock = analyze_transaction(&tx);
-                    let lightning = classify_lightning(&tx);
+                    let lightning = classify_lightning_with_config(&tx, &state.detector);
                     let alerts: Vec<_> = analyzer::analyze_transaction(
                         &timelock, &lightning, tip, &state.config,
                     )
@@ -335,6 +381,7 @@ fn parse_detection_type(s: &str) -> Option<DetectionType> {
         "short_cltv_delta" => Some(DetectionType::ShortCltvDelta),
         "htlc_clustering" => Some(DetectionType::HtlcClustering),
         "anomalous_sequence" => Some(DetectionType::AnomalousSequence),
+        "htlc_timeout_maturity" => Some(DetectionType::HtlcTimeoutMaturity),
         _ => None,
     }
 }
@@ -0,0 +1,81 @@
+use std::fmt::Write;
+use std::sync::{Arc, Mutex};
+
+use axum::Router;
+use axum::http::header::CONTENT_TYPE;
+use axum::response::IntoResponse;
+use axum::routing::get;
+
+use crate::lightning::types::{LightningClassification, LightningTally};
+
+#[derive(Default)]
+struct Counters {
+    blocks_scanned: u64,
+    last_height: Option<u64>,
+    lightning: LightningTally,
+}
+
+/// Counters kept by `watch`, exposed in the Prometheus text format.
+#[derive(Default)]
+pub struct WatchMetrics {
+    counters: Mutex<Counters>,
+}
+
+impl WatchMetrics {
+    /// Count a scanned block and its classified transactions.
+    pub fn record_block(&self, height: u64, results: &[(String, LightningClassification)]) {
+        let mut counters = self.counters.lock().unwrap();
+        counters.blocks_scanned += 1;
+        counters.last_height = Some(height);
+        for (_, lc) in results {
+            counters.lightning.record(lc);
+        }
+    }
+
+    /// Render every metric. The height gauge is left out until a block is scanned.
+    pub fn render(&self) -> String {
+        let counters = self.counters.lock().unwrap();
+        let tally = &counters.lightning;
+        let mut out = String::new();
+
+        out.push_str("# HELP cltv_scan_blocks_scanned_total Blocks scanned since startup.\n");
+        out.push_str("# TYPE cltv_scan_blocks_scanned_total counter\n");
+        let _ = writeln!(out, "cltv_scan_blocks_scanned_total {}", counters.blocks_scanned);
+
+        out.push_str("# HELP cltv_scan_lightning_transactions_total Lightning transactions detected, by type.\n");
+        out.push_str("# TYPE cltv_scan_lightning_transactions_total counter\n");
+        for (label, count) in [
+            ("commitment", tally.commitments),
+            ("htlc_timeout", tally.htlc_timeouts),
+            ("htlc_success", tally.htlc_successes),
+            ("mutual_close", tally.mutual_closes),
+            ("penalty", tally.penalties),
+            ("to_local_sweep", tally.to_local_sweeps),
+            ("anchor_sweep", tally.anchor_sweeps),
+            ("splice", tally.splices),
+        ] {
+            let _ = writeln!(out, "cltv_scan_lightning_transactions_total{{type=\"{label}\"}} {count}");
+        }
+
+        if let Some(height) = counters.last_height {
+            out.push_str("# HELP cltv_scan_last_scanned_height Height of the last scanned block.\n");
+            out.push_str("# TYPE cltv_scan_last_scanned_height gauge\n");
+            let _ = writeln!(out, "cltv_scan_last_scanned_height {height}");
+        }
+        out
+    }
+}
+
+/// Router serving `GET /metrics`.
+pub fn metrics_router(metrics: Arc<WatchMetrics>) -> Router {
+    Router::new().route(
+        "/metrics",
+        get(move || async move {
+            (
+                [(CONTENT_TYPE, "text/plain; version=0.0.4")],
+                metrics.render(),
+            )
+                .into_response()
+        }),
+    )
+}
@@ -1,4 +1,5 @@
 pub mod handlers;
+pub mod metrics;
 pub mod types;
 
 use std::sync::Arc;
@@ -8,6 +9,7 @@ use axum::Router;
 use tower_http::cors::{Any, CorsLayer};
 
 use crate::api::source::DataSource;
+use crate::lightning::types::DetectorConfig;
 use crate::security::types::SecurityConfig;
 
 use handlers::{AppState, ServerState};
@@ -16,7 +18,20 @@ pub fn create_router<S: DataSource + Send + Sync + 'static>(
     client: S,
     config: SecurityConfig,
 ) -> Router {
-    let state: AppState<S> = Arc::new(ServerState { client, config });
+    create_router_with_detector(client, config, DetectorConfig::default())
+}
+
+/// Like [`create_router`], classifying Lightning transactions with `detector`.
+pub fn create_router_with_detector<S: DataSource + Send + Sync + 'static>(
+    client: S,
+    config: SecurityConfig,
+    detector: DetectorConfig,
+) -> Router {
+    let state: AppState<S> = Arc::new(ServerState {
+        client,
+        config,
+        detector,
+    });
 
     let cors = CorsLayer::new()
         .allow_origin(Any)
@@ -40,6 +40,11 @@ pub struct LightningResponse {
     pub commitments: usize,
     pub htlc_timeouts: usize,
     pub htlc_successes: usize,
+    pub mutual_closes: usize,
+    pub penalties: usize,
+    pub to_local_sweeps: usize,
+    pub anchor_sweeps: usize,
+    pub splices: usize,
     pub transactions: Vec<LightningTxEntry>,
     pub cltv_expiry_distribution: Vec<ExpiryBucket>,
 }
@@ -0,0 +1,90 @@
+use std::collections::BTreeMap;
+
+use serde::Serialize;
+
+use crate::lightning::types::{LightningClassification, LightningTally, LightningTxType};
+use crate::timelock::types::TransactionAnalysis;
+
+/// Aggregate counters for a block range. Only counters are kept, so memory stays
+/// constant however many transactions are recorded.
+#[derive(Debug, Clone, Default)]
+pub struct RangeStats {
+    pub lightning: LightningTally,
+    pub with_active_timelocks: usize,
+    /// Commitments whose HTLC outputs could be counted (not taproot).
+    pub counted_commitments: usize,
+    pub htlc_outputs: usize,
+    pub to_self_delays: BTreeMap<u16, usize>,
+    pub preimages_revealed: usize,
+}
+
+impl RangeStats {
+    pub fn record(&mut self, analysis: &TransactionAnalysis, lc: &LightningClassification) {
+        self.lightning.record(lc);
+        if analysis.summary.has_active_timelocks {
+            self.with_active_timelocks += 1;
+        }
+        if lc.tx_type == Some(LightningTxType::Commitment)
+            && let Some(count) = lc.params.htlc_output_count
+        {
+            self.counted_commitments += 1;
+            self.htlc_outputs += count;
+        }
+        if let Some(delay) = lc.params.to_self_delay {
+            *self.to_self_delays.entry(delay).or_insert(0) += 1;
+        }
+        if lc.params.preimage_revealed {
+            self.preimages_revealed += 1;
+        }
+    }
+
+    pub fn add(&mut self, other: &RangeStats) {
+        self.lightning.add(&other.lightning);
+        self.with_active_timelocks += other.with_active_timelocks;
+        self.counted_commitments += other.counted_commitments;
+        self.htlc_outputs += other.htlc_outputs;
+        for (delay, count) in &other.to_self_delays {
+            *self.to_self_delays.entry(*delay).or_insert(0) += count;
+        }
+        self.preimages_revealed += other.preimages_revealed;
+    }
+
+    pub fn report(&self, start_height: u64, end_height: u64) -> StatsReport {
+        let transactions = self.lightning.transactions;
+        StatsReport {
+            start_height,
+            end_height,
+            transactions,
+            lightning: self.lightning.clone(),
+            avg_htlc_outputs_per_commitment: (self.counted_commitments > 0)
+                .then(|| self.htlc_outputs as f64 / self.counted_commitments as f64),
+            to_self_delay_distribution: self.to_self_delays.clone(),
+            csv_delay_distribution: self.lightning.csv_delays.clone(),
+            preimages_revealed: self.preimages_revealed,
+            with_active_timelocks: self.with_active_timelocks,
+            active_timelock_pct: if transactions == 0 {
+                0.0
+            } else {
+                self.with_active_timelocks as f64 * 100.0 / transactions as f64
+            },
+        }
+    }
+}
+
+/// Aggregate Lightning and timelock metrics for a block range.
+#[derive(Debug, Clone, Serialize)]
+pub struct StatsReport {
+    pub start_height: u64,
+    pub end_height: u64,
+    pub transactions: usize,
+    pub lightning: LightningTally,
+    /// `None` when no commitment with countable HTLC outputs was seen.
+    pub avg_htlc_outputs_per_commitment: Option<f64>,
+    /// to_self_delay (blocks) → number of transactions.
+    pub to_self_delay_distribution: BTreeMap<u16, usize>,
+    /// CSV delay (blocks) → number of occurrences in Lightning scripts.
+    pub csv_delay_distribution: BTreeMap<u16, usize>,
+    pub preimages_revealed: usize,
+    pub with_active_timelocks: usize,
+    pub active_timelock_pct: f64,
+}
@@ -0,0 +1,219 @@
+//! In-memory [`DataSource`] and transaction fixtures for driving the command logic in
+//! tests without a network or a node. Built with the `testing` feature.
+
+use std::collections::HashMap;
+
+use crate::api::source::{BLOCK_TXS_PAGE_SIZE, DataSource};
+use crate::api::types::{ApiStatus, ApiTransaction, ApiVin, ApiVout};
+use crate::error::{Error, Result};
+
+/// A data source serving the transactions and blocks it was built with. Block hashes
+/// are the height in hex, zero-padded to 64 characters, and the tip is the highest
+/// block unless set with [`MockSource::with_tip`].
+#[derive(Debug, Clone, Default)]
+pub struct MockSource {
+    pub transactions: HashMap<String, ApiTransaction>,
+    /// Txids of each block, in block order.
+    pub blocks: HashMap<u64, Vec<String>>,
+    /// Txids of the unconfirmed transactions, which `transactions` should also hold.
+    pub mempool: Vec<String>,
+    pub tip: Option<u64>,
+}
+
+impl MockSource {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Serve `tx` by txid, without placing it in a block.
+    pub fn with_transaction(mut self, tx: ApiTransaction) -> Self {
+        self.transactions.insert(tx.txid.clone(), tx);
+        self
+    }
+
+    /// Serve `txs` as the block at `height`, each marked confirmed in it.
+    pub fn with_block(mut self, height: u64, txs: Vec<ApiTransaction>) -> Self {
+        let txids = txs.iter().map(|tx| tx.txid.clone()).collect();
+        for mut tx in txs {
+            tx.status = confirmed_status(height);
+            self = self.with_transaction(tx);
+        }
+        self.blocks.insert(height, txids);
+        self
+    }
+
+    /// Serve `txs` as unconfirmed mempool transactions.
+    pub fn with_mempool(mut self, txs: Vec<ApiTransaction>) -> Self {
+        for mut tx in txs {
+            tx.status = unconfirmed_status();
+            self.mempool.push(tx.txid.clone());
+            self = self.with_transaction(tx);
+        }
+        self
+    }
+
+    pub fn with_tip(mut self, height: u64) -> Self {
+        self.tip = Some(height);
+        self
+    }
+
+    fn block_height(&self, hash: &str) -> Result<u64> {
+        u64::from_str_radix(hash, 16)
+            .ok()
+            .filter(|height| self.blocks.contains_key(height))
+            .ok_or_else(|| Error::NotFound(format!("block {hash}")))
+    }
+
+    fn block(&self, height: u64) -> Result<Vec<ApiTransaction>> {
+        let txids = self
+            .blocks
+            .get(&height)
+            .ok_or_else(|| Error::NotFound(format!("block at height {height}")))?;
+        Ok(txids.iter().map(|txid| self.transactions[txid].clone()).collect())
+    }
+}
+
+impl DataSource for MockSource {
+    async fn get_transaction(&self, txid: &str) -> Result<ApiTransaction> {
+        self.transactions
+            .get(txid)
+            .cloned()
+            .ok_or_else(|| Error::NotFound(format!("tx {txid}")))
+    }
+
+    async fn get_transaction_hex(&self, txid: &str) -> Result<String> {
+        Err(Error::NotFound(format!("raw tx {txid}")))
+    }
+
+    async fn get_block_txs(&self, hash: &str, start_index: u32) -> Result<Vec<ApiTransaction>> {
+        let txs = self.block(self.block_height(hash)?)?;
+        Ok(txs
+            .into_iter()
+            .skip(start_index as usize)
+            .take(BLOCK_TXS_PAGE_SIZE)
+            .collect())
+    }
+
+    async fn get_block_tip_height(&self) -> Result<u64> {
+        self.tip
+            .or_else(|| self.blocks.keys().max().copied())
+            .ok_or_else(|| Error::NotFound("no blocks".to_string()))
+    }
+
+    async fn get_block_hash(&self, height: u64) -> Result<String> {
+        if !self.blocks.contains_key(&height) {
+            return Err(Error::NotFound(format!("block at height {height}")));
+        }
+        Ok(format!("{height:064x}"))
+    }
+
+    async fn get_all_block_txs(&self, height: u64) -> Result<Vec<ApiTransaction>> {
+        self.block(height)
+    }
+
+    async fn get_mempool_recent_txids(&self) -> Result<Vec<String>> {
+        Ok(self.mempool.clone())
+    }
+
+    async fn get_mempool_txids(&self) -> Result<Vec<String>> {
+        Ok(self.mempool.clone())
+    }
+}
+
+// ─── Fixtures ────────────────────────────────────────────────────────────────
+
+pub fn confirmed_status(height: u64) -> ApiStatus {
+    ApiStatus {
+        confirmed: true,
+        block_height: Some(height),
+        block_hash: Some(format!("{height:064x}")),
+        block_time: Some(1_700_000_000),
+    }
+}
+
+pub fn unconfirmed_status() -> ApiStatus {
+    ApiStatus {
+        confirmed: false,
+        block_height: None,
+        block_hash: None,
+        block_time: None,
+    }
+}
+
+pub fn make_vout(value: u64, script_type: &str) -> ApiVout {
+    ApiVout {
+        scriptpubkey: "00".to_string(),
+        scriptpubkey_asm: "OP_0".to_string(),
+        scriptpubkey_type: script_type.to_string(),
+        scriptpubkey_address: None,
+        value,
+    }
+}
+
+pub fn make_vin(sequence: u32) -> ApiVin {
+    ApiVin {
+        txid: Some("aa".repeat(32)),
+        vout: Some(0),
+        prevout: None,
+        scriptsig: None,
+        scriptsig_asm: None,
+        inner_redeemscript_asm: None,
+        inner_witnessscript_asm: None,
+        witness: None,
+        is_coinbase: false,
+        sequence,
+    }
+}
+
+/// An unconfirmed transaction; [`MockSource::with_block`] confirms it.
+pub fn make_tx(txid: &str, locktime: u32, vins: Vec<ApiVin>, vouts: Vec<ApiVout>) -> ApiTransaction {
+    ApiTransaction {
+        txid: txid.to_string(),
+        version: 2,
+        locktime,
+        vin: vins,
+        vout: vouts,
+        size: 200,
+        weight: 800,
+        fee: Some(1000),
+        status: unconfirmed_status(),
+    }
+}
+
+/// A one-input, one-output payment with no timelocks.
+pub fn payment_tx(txid: &str) -> ApiTransaction {
+    make_tx(txid, 0, vec![make_vin(0xFFFFFFFF)], vec![make_vout(50_000, "v0_p2wpkh")])
+}
+
+/// An anchor-channel commitment (force close) with obscured commitment number `0x42`.
+pub fn commitment_tx(txid: &str) -> ApiTransaction {
+    make_tx(
+        txid,
+        0x2000_0042,
+        vec![make_vin(0x8000_0000)],
+        vec![
+            make_vout(100_000, "v0_p2wsh"),
+            make_vout(200_000, "v0_p2wpkh"),
+            make_vout(330, "v0_p2wsh"),
+            make_vout(330, "v0_p2wsh"),
+        ],
+    )
+}
+
+/// An HTLC-timeout spend whose nLockTime and script CLTV are both `expiry`.
+pub fn htlc_timeout_tx(txid: &str, expiry: u32) -> ApiTransaction {
+    let mut vin = make_vin(0);
+    vin.witness = Some(vec![String::new(), "3045".to_string()]);
+    vin.inner_witnessscript_asm =
+        Some(format!("OP_IF {expiry} OP_CHECKLOCKTIMEVERIFY OP_DROP OP_ENDIF OP_CHECKSIG"));
+    make_tx(txid, expiry, vec![vin], vec![make_vout(50_000, "v0_p2wpkh")])
+}
+
+/// An HTLC-success spend revealing `preimage` (64 hex characters).
+pub fn htlc_success_tx(txid: &str, preimage: &str) -> ApiTransaction {
+    let mut vin = make_vin(0);
+    vin.witness = Some(vec![preimage.to_string(), "3045".to_string()]);
+    vin.inner_witnessscript_asm =
+        Some("OP_SIZE 32 OP_EQUAL OP_IF OP_ELSE 1 OP_CHECKSEQUENCEVERIFY OP_DROP OP_ENDIF".to_string());
+    make_tx(txid, 0, vec![vin], vec![make_vout(50_000, "v0_p2wpkh")])
+}
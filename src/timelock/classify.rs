@@ -50,12 +50,13 @@ const SEQUENCE_TYPE_FLAG: u32 = 1 << 22;
 const SEQUENCE_LOCKTIME_MASK: u32 = 0x0000FFFF;
 
 /// Parse BIP 68 relative timelock from a sequence value.
-/// Returns None if bit 31 is set (relative timelock disabled).
+/// Returns None if bit 31 is set (relative timelock disabled), whatever the low bits hold.
 pub fn parse_relative_timelock(sequence: u32) -> Option<RelativeTimelock> {
     if sequence & SEQUENCE_DISABLE_FLAG != 0 {
         return None;
     }
 
+    // Type flag (bit 22) and value (low 16 bits) are independent fields
     let value = (sequence & SEQUENCE_LOCKTIME_MASK) as u16;
 
     if sequence & SEQUENCE_TYPE_FLAG != 0 {
@@ -65,7 +66,7 @@ pub fn parse_relative_timelock(sequence: u32) -> Option<RelativeTimelock> {
         Some(RelativeTimelock {
             domain: TimelockDomain::Timestamp,
             value,
-            human_readable: format!("{value} × 512s ({human})"),
+            human_readable: format!("relative: {total_seconds} seconds ({human})"),
         })
     } else {
         // Block-based
@@ -73,13 +74,13 @@ pub fn parse_relative_timelock(sequence: u32) -> Option<RelativeTimelock> {
         Some(RelativeTimelock {
             domain: TimelockDomain::BlockHeight,
             value,
-            human_readable: format!("{value} blocks ({human})"),
+            human_readable: format!("relative: {value} blocks ({human})"),
         })
     }
 }
 
 /// Approximate human-readable duration from seconds.
-fn format_duration_approx(seconds: u64) -> String {
+pub fn format_duration_approx(seconds: u64) -> String {
     if seconds < 3600 {
         format!("~{} min", seconds / 60)
     } else if seconds < 86400 {
@@ -90,7 +91,7 @@ fn format_duration_approx(seconds: u64) -> String {
 }
 
 /// Approximate human-readable duration from block count (~10 min/block).
-fn format_blocks_approx(blocks: u64) -> String {
+pub fn format_blocks_approx(blocks: u64) -> String {
     let minutes = blocks * 10;
     if minutes < 60 {
         format!("~{minutes} min")
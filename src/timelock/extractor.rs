@@ -1,9 +1,34 @@
+use bitcoin::ScriptBuf;
+use bitcoin::hex::FromHex;
+
 use super::classify::{classify_absolute, format_absolute, format_nlocktime, parse_relative_timelock};
+use super::risk::assess_risk;
 use super::types::*;
-use crate::api::types::ApiTransaction;
+use crate::api::types::{ApiTransaction, ApiVin};
+
+/// First byte of a witness element that BIP 341 reads as the annex, when it is last
+/// and there are at least two elements.
+const ANNEX_TAG: &str = "50";
+/// Leaf version of tapscript (BIP 342), in the control block's first byte with the
+/// output key's parity bit.
+const TAPSCRIPT_LEAF_VERSION: u8 = 0xc0;
+/// Deepest script tree a control block can prove a leaf in (BIP 341).
+const TAPROOT_CONTROL_MAX_NODE_COUNT: usize = 128;
 
 /// Analyze a transaction for all four timelock types.
 pub fn analyze_transaction(tx: &ApiTransaction) -> TransactionAnalysis {
+    analyze(tx, None)
+}
+
+/// Like [`analyze_transaction`], but nLockTime only counts as active while it is still
+/// in the future relative to `tip`: above the tip height, or at/after the tip's
+/// median-time-past for timestamps (BIP 113). Without an MTP, timestamp locktimes keep
+/// the tip-independent behavior.
+pub fn analyze_transaction_with_tip(tx: &ApiTransaction, tip: &ChainTip) -> TransactionAnalysis {
+    analyze(tx, Some(tip))
+}
+
+fn analyze(tx: &ApiTransaction, tip: Option<&ChainTip>) -> TransactionAnalysis {
     let nlocktime = extract_nlocktime(tx);
     let inputs = extract_sequences(tx);
     let cltv_timelocks = extract_script_timelocks(tx, TimelockOpcode::Cltv);
@@ -11,24 +36,54 @@ pub fn analyze_transaction(tx: &ApiTransaction) -> TransactionAnalysis {
 
     let relative_timelock_count = inputs.iter().filter(|i| i.relative_timelock.is_some()).count();
 
+    let mut cltv_and_csv_inputs: Vec<usize> = cltv_timelocks
+        .iter()
+        .map(|tl| tl.input_index)
+        .filter(|&i| csv_timelocks.iter().any(|tl| tl.input_index == i))
+        .collect();
+    cltv_and_csv_inputs.dedup();
+
+    let nlocktime_active = nlocktime.active
+        && nlocktime.raw_value > 0
+        && tip.is_none_or(|tip| locktime_in_future(&nlocktime, tip));
+
     let summary = AnalysisSummary {
-        has_active_timelocks: nlocktime.active && nlocktime.raw_value > 0
+        has_active_timelocks: nlocktime_active
             || relative_timelock_count > 0
             || !cltv_timelocks.is_empty()
             || !csv_timelocks.is_empty(),
-        nlocktime_active: nlocktime.active && nlocktime.raw_value > 0,
+        nlocktime_active,
+        nlocktime_ineffective: nlocktime.raw_value > 0 && !nlocktime.active,
         relative_timelock_count,
         cltv_count: cltv_timelocks.len(),
         csv_count: csv_timelocks.len(),
+        cltv_and_csv_inputs,
     };
 
-    TransactionAnalysis {
+    let mut analysis = TransactionAnalysis {
         txid: tx.txid.clone(),
+        block_height: tx.status.block_height,
         nlocktime,
         inputs,
         cltv_timelocks,
         csv_timelocks,
         summary,
+        risk: RiskReport::default(),
+    };
+    // Coinbase transactions can't be fee-sniped, so their nLockTime says nothing
+    let is_coinbase = tx.vin.iter().any(|v| v.is_coinbase);
+    analysis.risk = assess_risk(&analysis, tip.filter(|_| !is_coinbase));
+    analysis
+}
+
+/// Whether the locktime still prevents inclusion in the block after `tip`.
+fn locktime_in_future(nlocktime: &NLocktimeInfo, tip: &ChainTip) -> bool {
+    let value = nlocktime.raw_value as u64;
+    match nlocktime.domain {
+        // Valid in a block at height h once value < h; the next block is tip + 1
+        Some(TimelockDomain::BlockHeight) => value > tip.height,
+        Some(TimelockDomain::Timestamp) => tip.median_time_past.is_none_or(|mtp| value >= mtp),
+        None => false,
     }
 }
 
@@ -60,11 +115,11 @@ fn extract_sequences(tx: &ApiTransaction) -> Vec<SequenceInfo> {
             let relative_timelock = parse_relative_timelock(seq);
 
             let meaning = match seq {
-                0xFFFFFFFF => SequenceMeaning::Final,
                 0xFFFFFFFE => SequenceMeaning::LocktimeEnabled,
                 0xFFFFFFFD => SequenceMeaning::RbfEnabled,
                 _ if relative_timelock.is_some() => SequenceMeaning::RelativeTimelock,
-                _ => SequenceMeaning::NonStandard,
+                // Bit 31 set: BIP 68 disabled, even if the low bits look like a timelock
+                _ => SequenceMeaning::Final,
             };
 
             SequenceInfo {
@@ -73,11 +128,58 @@ fn extract_sequences(tx: &ApiTransaction) -> Vec<SequenceInfo> {
                 raw_hex: format!("0x{seq:08X}"),
                 meaning,
                 relative_timelock,
+                spend_path: spend_path(input),
             }
         })
         .collect()
 }
 
+/// How a taproot input was spent, read from its witness with any annex dropped: a
+/// lone 64- or 65-byte Schnorr signature is a key-path spend, and a tapscript control
+/// block (33 + 32n bytes) last is a script-path one. Inputs whose prevout is known
+/// not to be taproot get `None`.
+fn spend_path(input: &ApiVin) -> Option<SpendPath> {
+    if input.prevout.as_ref().is_some_and(|p| p.scriptpubkey_type != "v1_p2tr") {
+        return None;
+    }
+    match taproot_stack(input)? {
+        [sig] if matches!(sig.len(), 128 | 130) && Vec::<u8>::from_hex(sig).is_ok() => {
+            Some(SpendPath::KeyPath)
+        }
+        [.., _script, control] if is_control_block(control) => Some(SpendPath::ScriptPath),
+        _ => None,
+    }
+}
+
+/// The witness without its annex.
+fn taproot_stack(input: &ApiVin) -> Option<&[String]> {
+    let witness = input.witness.as_deref()?;
+    match witness {
+        [rest @ .., last] if !rest.is_empty() && last.starts_with(ANNEX_TAG) => Some(rest),
+        _ => Some(witness),
+    }
+}
+
+fn is_control_block(hex: &str) -> bool {
+    let Ok(bytes) = Vec::<u8>::from_hex(hex) else {
+        return false;
+    };
+    let nodes = bytes.len().checked_sub(33);
+    nodes.is_some_and(|n| n % 32 == 0 && n / 32 <= TAPROOT_CONTROL_MAX_NODE_COUNT)
+        && bytes[0] & 0xfe == TAPSCRIPT_LEAF_VERSION
+}
+
+/// Disassembled leaf script of a script-path taproot spend, which sources don't report
+/// as `inner_witnessscript_asm` the way they do for P2WSH.
+fn tapscript_asm(input: &ApiVin) -> Option<String> {
+    if spend_path(input)? != SpendPath::ScriptPath {
+        return None;
+    }
+    let stack = taproot_stack(input)?;
+    let script = Vec::<u8>::from_hex(&stack[stack.len() - 2]).ok()?;
+    Some(ScriptBuf::from(script).to_asm_string())
+}
+
 enum TimelockOpcode {
     Cltv,
     Csv,
@@ -103,10 +205,15 @@ fn extract_script_timelocks(tx: &ApiTransaction, opcode: TimelockOpcode) -> Vec<
     let mut results = Vec::new();
 
     for (input_idx, input) in tx.vin.iter().enumerate() {
+        let tapscript = match input.inner_witnessscript_asm {
+            Some(_) => None,
+            None => tapscript_asm(input),
+        };
         let script_fields: Vec<(&str, &Option<String>)> = vec![
             ("scriptsig_asm", &input.scriptsig_asm),
             ("inner_redeemscript_asm", &input.inner_redeemscript_asm),
             ("inner_witnessscript_asm", &input.inner_witnessscript_asm),
+            ("tapscript", &tapscript),
         ];
 
         for (field_name, field_value) in script_fields {
@@ -161,37 +268,68 @@ fn extract_timelock_from_asm(asm: &str, opcode: &TimelockOpcode) -> Vec<u64> {
 
     for (i, token) in tokens.iter().enumerate() {
         let is_match = opcode.patterns().iter().any(|p| token == p);
-        if is_match && i > 0 {
-            // The preceding token should be the numeric push
-            let prev = tokens[i - 1];
-            // Try parsing as decimal first, then as hex with OP_PUSHBYTES prefix stripped
-            if let Ok(v) = prev.parse::<u64>() {
-                values.push(v);
-            } else if let Ok(v) = parse_script_number(prev) {
-                values.push(v);
-            }
+        if is_match && let Some(v) = decode_push_before(&tokens, i) {
+            values.push(v);
         }
     }
 
     values
 }
 
+/// Maximum operand size accepted by OP_CHECKLOCKTIMEVERIFY / OP_CHECKSEQUENCEVERIFY.
+const MAX_TIMELOCK_PUSH_BYTES: usize = 5;
+
+/// Decode the script-number push immediately preceding `tokens[index]`.
+///
+/// ASM renderers disagree on how small pushes look: Bitcoin Core prints them as decimal
+/// (`144`), mempool.space prints the raw little-endian bytes (`OP_PUSHBYTES_2 9000`), and
+/// small integers appear as opcodes (`OP_PUSHNUM_16`). A bare token is read as decimal
+/// when it is all digits, as Core prints it, and as hex otherwise; after `OP_PUSHBYTES_N`
+/// it is always hex, so `OP_PUSHBYTES_2 9000` is 144 while a bare `9000` is 9000.
+pub fn decode_push_before(tokens: &[&str], index: usize) -> Option<u64> {
+    let prev = *tokens.get(index.checked_sub(1)?)?;
+
+    if let Some(n) = decode_small_int_opcode(prev) {
+        return Some(n);
+    }
+
+    let after_pushbytes = index >= 2 && tokens[index - 2].starts_with("OP_PUSHBYTES_");
+    if !after_pushbytes && let Ok(v) = prev.parse::<u64>() {
+        return Some(v);
+    }
+
+    parse_script_number(prev)
+}
+
+/// `OP_0`, `OP_1`..`OP_16` and their `OP_PUSHNUM_N` spellings.
+fn decode_small_int_opcode(token: &str) -> Option<u64> {
+    if token == "OP_0" || token == "OP_FALSE" {
+        return Some(0);
+    }
+    let n = token
+        .strip_prefix("OP_PUSHNUM_")
+        .or_else(|| token.strip_prefix("OP_"))?
+        .parse::<u64>()
+        .ok()?;
+    (1..=16).contains(&n).then_some(n)
+}
+
 /// Parse a hex-encoded script number (little-endian) as used in Bitcoin Script.
-fn parse_script_number(hex: &str) -> Result<u64, ()> {
+fn parse_script_number(hex: &str) -> Option<u64> {
+    if hex.is_empty() || !hex.len().is_multiple_of(2) || hex.len() / 2 > MAX_TIMELOCK_PUSH_BYTES {
+        return None;
+    }
+
     let bytes: Vec<u8> = (0..hex.len())
         .step_by(2)
         .map(|i| u8::from_str_radix(&hex[i..i + 2], 16))
         .collect::<Result<Vec<_>, _>>()
-        .map_err(|_| ())?;
-
-    if bytes.is_empty() {
-        return Err(());
-    }
+        .ok()?;
 
     // Bitcoin script numbers are little-endian with sign bit in the MSB of the last byte
-    let negative = bytes.last().map_or(false, |b| b & 0x80 != 0);
+    let negative = bytes.last().is_some_and(|b| b & 0x80 != 0);
     if negative {
-        return Err(()); // Timelock values shouldn't be negative
+        return None; // Timelock values shouldn't be negative
     }
 
     let mut value: u64 = 0;
@@ -199,5 +337,5 @@ fn parse_script_number(hex: &str) -> Result<u64, ()> {
         value |= (byte as u64) << (8 * i);
     }
 
-    Ok(value)
+    Some(value)
 }
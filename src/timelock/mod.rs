@@ -1,3 +1,4 @@
 pub mod classify;
 pub mod extractor;
+pub mod risk;
 pub mod types;
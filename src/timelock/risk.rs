@@ -0,0 +1,143 @@
+use super::types::*;
+
+/// BIP 68 disable flag: with bit 31 set, nSequence carries no relative timelock.
+const SEQUENCE_DISABLE_FLAG: u32 = 1 << 31;
+/// Bits of a CSV operand that BIP 68 reads: the disable and type flags and the delay.
+const CSV_MEANINGFUL_BITS: u64 = 0x8040_FFFF;
+
+/// How far below the signing height an nLockTime may be and still count as
+/// anti-fee-sniping: a transaction usually confirms within a couple of blocks.
+const FEE_SNIPING_WINDOW: u64 = 3;
+
+/// Flag timelock footguns in an analyzed transaction. With a chain tip, also report
+/// whether nLockTime follows the anti-fee-sniping convention.
+pub fn assess_risk(analysis: &TransactionAnalysis, tip: Option<&ChainTip>) -> RiskReport {
+    let mut findings = Vec::new();
+
+    let nlocktime = &analysis.nlocktime;
+    if nlocktime.raw_value > 0 && !nlocktime.active {
+        findings.push(RiskFinding {
+            kind: RiskKind::IneffectiveLocktime,
+            severity: RiskSeverity::Low,
+            input_index: None,
+            reason: format!(
+                "nLockTime {} has no effect: every input sequence is final",
+                nlocktime.raw_value
+            ),
+        });
+    }
+
+    if let Some(height) = analysis.block_height {
+        for tl in &analysis.cltv_timelocks {
+            if tl.domain == TimelockDomain::BlockHeight && tl.raw_value < height {
+                findings.push(RiskFinding {
+                    kind: RiskKind::ExpiredCltv,
+                    severity: RiskSeverity::Medium,
+                    input_index: Some(tl.input_index),
+                    reason: format!(
+                        "CLTV {} had already passed when confirmed at block {height} ({} blocks earlier)",
+                        tl.raw_value,
+                        height - tl.raw_value
+                    ),
+                });
+            }
+        }
+    }
+
+    for tl in &analysis.csv_timelocks {
+        // BIP 68: only the low 16 bits carry the delay
+        if tl.raw_value & 0xFFFF == 0 {
+            findings.push(RiskFinding {
+                kind: RiskKind::ZeroCsvDelay,
+                severity: RiskSeverity::High,
+                input_index: Some(tl.input_index),
+                reason: "CSV delay of zero enforces no relative timelock".to_string(),
+            });
+        }
+
+        if tl.raw_value & !CSV_MEANINGFUL_BITS != 0 {
+            findings.push(RiskFinding {
+                kind: RiskKind::OversizedCsv,
+                severity: RiskSeverity::Low,
+                input_index: Some(tl.input_index),
+                reason: format!(
+                    "CSV operand {} (0x{:X}) sets bits BIP 68 ignores; the delay is {}",
+                    tl.raw_value, tl.raw_value, tl.human_readable
+                ),
+            });
+        }
+
+        let sequence = analysis.inputs.iter().find(|i| i.input_index == tl.input_index);
+        if let Some(input) = sequence.filter(|i| i.raw_value & SEQUENCE_DISABLE_FLAG != 0) {
+            // BIP 112: such a spend only passes if the CSV argument is disabled too, or if
+            // the script took a branch without the CSV (say, a revocation path)
+            let (severity, reason) = if tl.raw_value & u64::from(SEQUENCE_DISABLE_FLAG) != 0 {
+                (
+                    RiskSeverity::High,
+                    format!(
+                        "CSV {} and nSequence {} both disable relative timelocks: \
+                         the delay is not enforced",
+                        tl.raw_value, input.raw_hex
+                    ),
+                )
+            } else {
+                (
+                    RiskSeverity::Info,
+                    format!(
+                        "CSV branch not executed: nSequence {} disables relative timelocks, \
+                         which CSV {} would reject",
+                        input.raw_hex, tl.raw_value
+                    ),
+                )
+            };
+            findings.push(RiskFinding {
+                kind: RiskKind::DisabledCsvSequence,
+                severity,
+                input_index: Some(tl.input_index),
+                reason,
+            });
+        }
+    }
+
+    if let Some(tip) = tip {
+        findings.extend(fee_sniping_finding(analysis, tip));
+    }
+
+    RiskReport {
+        max_severity: findings.iter().map(|f| f.severity).max(),
+        findings,
+    }
+}
+
+/// Wallets set nLockTime to the current height so a miner can't profitably re-mine the
+/// previous block with this transaction in it. Confirmed transactions are judged against
+/// their block, pending ones against the tip.
+fn fee_sniping_finding(analysis: &TransactionAnalysis, tip: &ChainTip) -> Option<RiskFinding> {
+    let height = analysis.block_height.unwrap_or(tip.height);
+    let nlocktime = &analysis.nlocktime;
+    let value = nlocktime.raw_value as u64;
+
+    let (kind, reason) = if value == 0 {
+        (
+            RiskKind::NoAntiFeeSniping,
+            "nLockTime is zero: no anti-fee-sniping height, unlike most wallet transactions"
+                .to_string(),
+        )
+    } else if nlocktime.active
+        && nlocktime.domain == Some(TimelockDomain::BlockHeight)
+        && value <= height
+        && height - value <= FEE_SNIPING_WINDOW
+    {
+        (
+            RiskKind::AntiFeeSniping,
+            format!(
+                "nLockTime {value} is within {FEE_SNIPING_WINDOW} blocks of height {height} \
+                 (anti-fee-sniping)"
+            ),
+        )
+    } else {
+        return None;
+    };
+
+    Some(RiskFinding { kind, severity: RiskSeverity::Info, input_index: None, reason })
+}
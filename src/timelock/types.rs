@@ -1,7 +1,8 @@
+use schemars::JsonSchema;
 use serde::Serialize;
 
 /// Domain of a timelock value: block height or Unix timestamp.
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, JsonSchema)]
 #[serde(rename_all = "snake_case")]
 pub enum TimelockDomain {
     BlockHeight,
@@ -9,7 +10,7 @@ pub enum TimelockDomain {
 }
 
 /// Classification of the nLockTime field.
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone, Serialize, JsonSchema)]
 pub struct NLocktimeInfo {
     pub raw_value: u32,
     pub domain: Option<TimelockDomain>,
@@ -18,8 +19,9 @@ pub struct NLocktimeInfo {
     pub human_readable: String,
 }
 
-/// Classification of a single input's nSequence field.
-#[derive(Debug, Clone, Serialize)]
+/// Classification of a single input's nSequence field, and of how it was spent when
+/// it spends a taproot output.
+#[derive(Debug, Clone, Serialize, JsonSchema)]
 pub struct SequenceInfo {
     pub input_index: usize,
     pub raw_value: u32,
@@ -28,12 +30,27 @@ pub struct SequenceInfo {
     pub meaning: SequenceMeaning,
     /// BIP 68 relative timelock, if encoded.
     pub relative_timelock: Option<RelativeTimelock>,
+    /// Taproot spend path, for an input spending a `v1_p2tr` output. Without the
+    /// prevout, set only when the witness can't be anything but a taproot spend.
+    pub spend_path: Option<SpendPath>,
 }
 
-#[derive(Debug, Clone, Serialize)]
+/// How a taproot output was spent (BIP 341).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum SpendPath {
+    /// A single Schnorr signature for the output key.
+    KeyPath,
+    /// A leaf script with its inputs and a control block. The leaf script may carry
+    /// CLTV and CSV, which are extracted like any other script's.
+    ScriptPath,
+}
+
+#[derive(Debug, Clone, Serialize, JsonSchema)]
 #[serde(rename_all = "snake_case")]
 pub enum SequenceMeaning {
-    /// 0xFFFFFFFF — final, disables nLockTime, no RBF.
+    /// 0xFFFFFFFF, or any value with bit 31 set — no BIP 68 relative timelock.
+    /// Only 0xFFFFFFFF also disables nLockTime (see `NLocktimeInfo::active`).
     Final,
     /// 0xFFFFFFFE — enables nLockTime, no RBF signaling.
     LocktimeEnabled,
@@ -41,11 +58,9 @@ pub enum SequenceMeaning {
     RbfEnabled,
     /// Has BIP 68 relative timelock.
     RelativeTimelock,
-    /// Non-standard value without BIP 68 encoding (bit 31 set but not a standard value).
-    NonStandard,
 }
 
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone, Serialize, JsonSchema)]
 pub struct RelativeTimelock {
     pub domain: TimelockDomain,
     pub value: u16,
@@ -53,7 +68,7 @@ pub struct RelativeTimelock {
 }
 
 /// A timelock opcode found in a script.
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone, Serialize, JsonSchema)]
 pub struct ScriptTimelock {
     pub input_index: usize,
     pub script_field: String,
@@ -63,22 +78,177 @@ pub struct ScriptTimelock {
     pub human_readable: String,
 }
 
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone, Serialize, JsonSchema)]
 pub struct AnalysisSummary {
     pub has_active_timelocks: bool,
     pub nlocktime_active: bool,
+    /// nLockTime is non-zero but every input's sequence is 0xFFFFFFFF, so consensus
+    /// never enforces it.
+    pub nlocktime_ineffective: bool,
     pub relative_timelock_count: usize,
     pub cltv_count: usize,
     pub csv_count: usize,
+    /// Inputs whose scripts carry both an absolute (CLTV) and a relative (CSV)
+    /// timelock, in input order.
+    pub cltv_and_csv_inputs: Vec<usize>,
+}
+
+/// Chain state that timelocks are judged against.
+#[derive(Debug, Clone, Copy)]
+pub struct ChainTip {
+    pub height: u64,
+    /// Median time past of the tip block, when the data source provides it.
+    pub median_time_past: Option<u64>,
+}
+
+/// How serious a timelock risk finding is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum RiskSeverity {
+    /// Worth knowing, not a problem on its own.
+    Info,
+    Low,
+    Medium,
+    High,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum RiskKind {
+    /// nLockTime is set but every input is final, so it is never enforced.
+    IneffectiveLocktime,
+    /// A block-height CLTV that had already passed when the transaction confirmed.
+    ExpiredCltv,
+    /// OP_CHECKSEQUENCEVERIFY with a zero delay enforces nothing.
+    ZeroCsvDelay,
+    /// The input's script has OP_CHECKSEQUENCEVERIFY but its nSequence has the
+    /// disable flag (bit 31) set. High when the CSV operand is disabled too, so no
+    /// relative timelock is enforced; otherwise the spend skipped the CSV branch.
+    DisabledCsvSequence,
+    /// nLockTime is set to about the height the transaction was signed at, as most
+    /// wallets do to discourage fee sniping.
+    AntiFeeSniping,
+    /// nLockTime is zero, which sets the transaction apart from most wallet spends.
+    NoAntiFeeSniping,
+    /// An OP_CHECKSEQUENCEVERIFY operand sets bits that BIP 68 ignores, such as a value
+    /// above 65535 blocks: only the low 16 bits and the two flags take effect.
+    OversizedCsv,
+}
+
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct RiskFinding {
+    pub kind: RiskKind,
+    pub severity: RiskSeverity,
+    pub input_index: Option<usize>,
+    pub reason: String,
+}
+
+/// Timelock footguns found in a transaction.
+#[derive(Debug, Clone, Default, Serialize, JsonSchema)]
+pub struct RiskReport {
+    /// Highest severity among the findings; `None` when there are none.
+    pub max_severity: Option<RiskSeverity>,
+    pub findings: Vec<RiskFinding>,
 }
 
 /// Complete timelock analysis for a single transaction.
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone, Serialize, JsonSchema)]
 pub struct TransactionAnalysis {
     pub txid: String,
+    /// Confirmation height, when known.
+    pub block_height: Option<u64>,
     pub nlocktime: NLocktimeInfo,
     pub inputs: Vec<SequenceInfo>,
     pub cltv_timelocks: Vec<ScriptTimelock>,
     pub csv_timelocks: Vec<ScriptTimelock>,
     pub summary: AnalysisSummary,
+    pub risk: RiskReport,
+}
+
+/// Which of the four timelock mechanisms a [`Timelock`] comes from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum TimelockKind {
+    NLocktime,
+    /// BIP 68 relative timelock in an input's nSequence.
+    Sequence,
+    Cltv,
+    Csv,
+}
+
+/// Where a timelock is set: the transaction's nLockTime field or one of its inputs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum TimelockLocation {
+    Transaction,
+    Input(usize),
+}
+
+/// One timelock of any kind, as listed by [`TransactionAnalysis::all_timelocks`].
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct Timelock {
+    pub kind: TimelockKind,
+    pub location: TimelockLocation,
+    pub raw_value: u64,
+    pub domain: TimelockDomain,
+    pub human_readable: String,
+}
+
+impl TransactionAnalysis {
+    /// Every timelock in the transaction: a non-zero nLockTime (enforced or not),
+    /// then BIP 68 relative timelocks, CLTVs and CSVs in input order.
+    pub fn all_timelocks(&self) -> Vec<Timelock> {
+        let nlocktime = self.nlocktime.domain.map(|domain| Timelock {
+            kind: TimelockKind::NLocktime,
+            location: TimelockLocation::Transaction,
+            raw_value: self.nlocktime.raw_value as u64,
+            domain,
+            human_readable: self.nlocktime.human_readable.clone(),
+        });
+        let sequences = self.inputs.iter().filter_map(|input| {
+            let relative = input.relative_timelock.as_ref()?;
+            Some(Timelock {
+                kind: TimelockKind::Sequence,
+                location: TimelockLocation::Input(input.input_index),
+                raw_value: input.raw_value as u64,
+                domain: relative.domain,
+                human_readable: relative.human_readable.clone(),
+            })
+        });
+        let scripts = self
+            .cltv_timelocks
+            .iter()
+            .map(|t| (TimelockKind::Cltv, t))
+            .chain(self.csv_timelocks.iter().map(|t| (TimelockKind::Csv, t)))
+            .map(|(kind, t)| Timelock {
+                kind,
+                location: TimelockLocation::Input(t.input_index),
+                raw_value: t.raw_value,
+                domain: t.domain,
+                human_readable: t.human_readable.clone(),
+            });
+
+        nlocktime.into_iter().chain(sequences).chain(scripts).collect()
+    }
+}
+
+/// Timelock counts accumulated while scanning a block or a range of blocks.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct TimelockTally {
+    pub transactions: usize,
+    pub with_timelocks: usize,
+}
+
+impl TimelockTally {
+    pub fn record(&mut self, analysis: &TransactionAnalysis) {
+        self.transactions += 1;
+        if analysis.summary.has_active_timelocks {
+            self.with_timelocks += 1;
+        }
+    }
+
+    pub fn add(&mut self, other: &TimelockTally) {
+        self.transactions += other.transactions;
+        self.with_timelocks += other.with_timelocks;
+    }
 }
@@ -0,0 +1,46 @@
+use anyhow::{Context, Result, bail};
+use chrono::{DateTime, NaiveDate, NaiveDateTime};
+
+/// Check that `s` looks like a txid (64 hex characters) before it is sent to a data
+/// source, which would otherwise fail with a less helpful error.
+pub fn parse_txid(s: &str) -> Result<String> {
+    let s = s.trim();
+    let len = s.chars().count();
+    if len != 64 {
+        bail!("invalid txid: expected 64 hex characters, got {len}");
+    }
+    if let Some(c) = s.chars().find(|c| !c.is_ascii_hexdigit()) {
+        bail!("invalid txid: {c:?} is not a hex character");
+    }
+    Ok(s.to_string())
+}
+
+/// Parse a transaction's position in a block, `<height>:<index>`.
+pub fn parse_block_position(s: &str) -> Result<(u64, u32)> {
+    let Some((height, index)) = s.trim().split_once(':') else {
+        bail!("invalid position {s:?}: expected <height>:<index>, e.g. 886000:5");
+    };
+    let height = height.parse().with_context(|| format!("invalid block height {height:?}"))?;
+    let index = index.parse().with_context(|| format!("invalid transaction index {index:?}"))?;
+    Ok((height, index))
+}
+
+/// Parse a point in time as Unix seconds: a plain number, an RFC 3339 timestamp
+/// (`2025-03-01T12:00:00Z`), or a UTC date with optional time (`2025-03-01`,
+/// `2025-03-01T12:00:00`).
+pub fn parse_timestamp(s: &str) -> Result<u64> {
+    let s = s.trim();
+    if s.bytes().all(|b| b.is_ascii_digit()) {
+        return s.parse().context("invalid Unix timestamp");
+    }
+    let datetime = if let Ok(dt) = DateTime::parse_from_rfc3339(s) {
+        dt.to_utc()
+    } else if let Ok(dt) = NaiveDateTime::parse_from_str(s, "%Y-%m-%dT%H:%M:%S") {
+        dt.and_utc()
+    } else if let Ok(date) = NaiveDate::parse_from_str(s, "%Y-%m-%d") {
+        date.and_hms_opt(0, 0, 0).context("invalid date")?.and_utc()
+    } else {
+        bail!("invalid timestamp {s:?}: expected Unix seconds or an ISO 8601 date");
+    };
+    u64::try_from(datetime.timestamp()).context("timestamp is before 1970")
+}
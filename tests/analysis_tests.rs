@@ -0,0 +1,35 @@
+use cltv_scan::analyze;
+use cltv_scan::lightning::types::{Confidence, LightningTxType};
+use cltv_scan::testing::*;
+use cltv_scan::timelock::types::RiskKind;
+
+// ─── Combined analysis ───────────────────────────────────────────────────────
+
+#[test]
+fn test_analyze_returns_both_results() {
+    let tx = commitment_tx("commit");
+    let result = analyze(&tx);
+
+    assert_eq!(result.timelock.txid, "commit");
+    assert_eq!(result.timelock.nlocktime.raw_value, 0x2000_0042);
+    assert_eq!(result.lightning.tx_type, Some(LightningTxType::Commitment));
+    assert_eq!(result.lightning.confidence, Confidence::HighlyLikely);
+
+    let json = serde_json::to_value(&result).unwrap();
+    assert_eq!(json["timelock"]["txid"], "commit");
+    assert_eq!(json["lightning"]["tx_type"], "commitment");
+}
+
+#[test]
+fn test_analyze_risk_report() {
+    // nLockTime set while every input is final, so it is never enforced
+    let tx = make_tx(
+        "unenforced",
+        886000,
+        vec![make_vin(0xFFFF_FFFF)],
+        vec![make_vout(1000, "v0_p2wpkh")],
+    );
+    let result = analyze(&tx);
+    assert!(result.risk().findings.iter().any(|f| f.kind == RiskKind::IneffectiveLocktime));
+    assert_eq!(result.lightning.tx_type, None);
+}
@@ -0,0 +1,949 @@
+use std::process::Stdio;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+use axum::Router;
+use axum::routing::{get, post};
+use tokio::io::{AsyncBufReadExt, BufReader};
+use tokio::net::TcpListener;
+use tokio::process::Command;
+
+use cltv_scan::api::types::*;
+use cltv_scan::testing::{confirmed_status, make_tx, make_vin, make_vout};
+
+// ─── Test helpers ────────────────────────────────────────────────────────────
+
+/// Mock esplora API serving a single block at height 886000, which is also the tip,
+/// and each of its transactions by txid, marked confirmed in it.
+async fn serve_block(txs: Vec<ApiTransaction>) -> String {
+    let txs: Vec<ApiTransaction> = txs
+        .into_iter()
+        .map(|tx| ApiTransaction { status: confirmed_status(886000), ..tx })
+        .collect();
+    let hash = "00".repeat(32);
+    let block_path = format!("/api/block/{hash}/txs/0");
+    let mut app = Router::new()
+        .route("/api/blocks/tip/height", get(|| async { "886000" }))
+        .route("/api/block-height/886000", get(move || async move { hash }));
+    for tx in &txs {
+        let tx = tx.clone();
+        app = app.route(&format!("/api/tx/{}", tx.txid), get(move || async move { axum::Json(tx) }));
+    }
+    let app = app.route(&block_path, get(move || async move { axum::Json(txs) }));
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+    format!("http://{addr}/api")
+}
+
+async fn run_cli(args: &[&str]) -> (bool, String) {
+    let output = Command::new(env!("CARGO_BIN_EXE_cltv-scan"))
+        .args(args)
+        .output()
+        .await
+        .unwrap();
+    (output.status.success(), String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+async fn run_cli_code(args: &[&str]) -> (Option<i32>, String) {
+    let output = Command::new(env!("CARGO_BIN_EXE_cltv-scan"))
+        .args(args)
+        .output()
+        .await
+        .unwrap();
+    (output.status.code(), String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+async fn run_cli_stderr(args: &[&str]) -> (bool, String) {
+    let output = Command::new(env!("CARGO_BIN_EXE_cltv-scan"))
+        .args(args)
+        .output()
+        .await
+        .unwrap();
+    (output.status.success(), String::from_utf8_lossy(&output.stderr).into_owned())
+}
+
+// ─── Lightning block --min-confidence ────────────────────────────────────────
+
+#[tokio::test]
+async fn test_lightning_block_min_confidence_filter() {
+    let highly_likely = make_tx(
+        &"11".repeat(32),
+        0x20000000 | 0x123456,
+        vec![make_vin(0x80000001)],
+        vec![make_vout(330, "v0_p2wsh"), make_vout(100_000, "v0_p2wsh")],
+    );
+    // Lightning-range locktime alone → possible
+    let possible = make_tx(
+        &"22".repeat(32),
+        0x20000000 | 0x654321,
+        vec![make_vin(0xFFFFFFFE)],
+        vec![make_vout(100_000, "v0_p2wpkh")],
+    );
+    let regular = make_tx(&"33".repeat(32), 0, vec![make_vin(0xFFFFFFFF)], vec![make_vout(10_000, "v0_p2wpkh")]);
+    let url = serve_block(vec![highly_likely, possible, regular]).await;
+
+    let (ok, stdout) = run_cli(&[
+        "--api-url", &url, "--no-cache", "lightning", "block", "886000", "--min-confidence", "highly_likely",
+    ])
+    .await;
+    assert!(ok);
+    // The scanned count is taken before filtering; the listing honors the filter
+    assert!(stdout.contains("3 transactions scanned, 1 Lightning-related"), "{stdout}");
+    assert!(stdout.contains(&"11".repeat(32)));
+    assert!(!stdout.contains(&"22".repeat(32)));
+
+    let (ok, stdout) = run_cli(&["--api-url", &url, "--no-cache", "lightning", "block", "886000"]).await;
+    assert!(ok);
+    assert!(stdout.contains("3 transactions scanned, 2 Lightning-related"), "{stdout}");
+    assert!(stdout.contains(&"22".repeat(32)));
+}
+
+#[tokio::test]
+async fn test_lightning_block_channel_clusters() {
+    let commitment = make_tx(
+        &"c1".repeat(32),
+        0x20000000 | 0x123456,
+        vec![make_vin(0x80000001)],
+        vec![make_vout(330, "v0_p2wsh"), make_vout(100_000, "v0_p2wsh")],
+    );
+    let mut htlc_vin = make_vin(0);
+    htlc_vin.txid = Some("c1".repeat(32));
+    htlc_vin.witness = Some(vec!["".to_string(), "3045".to_string()]);
+    htlc_vin.inner_witnessscript_asm =
+        Some("OP_IF 886000 OP_CHECKLOCKTIMEVERIFY OP_DROP OP_ENDIF OP_CHECKSIG".to_string());
+    let timeout = make_tx(&"a1".repeat(32), 886000, vec![htlc_vin], vec![make_vout(50_000, "v0_p2wpkh")]);
+    let url = serve_block(vec![commitment, timeout]).await;
+
+    let (ok, stdout) = run_cli(&["--api-url", &url, "--no-cache", "lightning", "block", "886000"]).await;
+    assert!(ok);
+    assert!(stdout.contains("Channels observed (1):"), "{stdout}");
+    let funding = format!("{}:0", "aa".repeat(32));
+    assert!(
+        stdout.contains(&format!(
+            "  Channel (funding {funding}): 1 commitment, 1 HTLC-timeout, 0 HTLC-successes"
+        )),
+        "{stdout}"
+    );
+}
+
+#[tokio::test]
+async fn test_lightning_block_same_block_sweep() {
+    let commitment = make_tx(
+        &"c1".repeat(32),
+        0x20000000 | 0x123456,
+        vec![make_vin(0x80000001)],
+        vec![make_vout(330, "v0_p2wsh"), make_vout(100_000, "v0_p2wsh")],
+    );
+    let mut htlc_vin = make_vin(0);
+    htlc_vin.txid = Some("c1".repeat(32));
+    htlc_vin.witness = Some(vec!["".to_string(), "3045".to_string()]);
+    htlc_vin.inner_witnessscript_asm =
+        Some("OP_IF 886000 OP_CHECKLOCKTIMEVERIFY OP_DROP OP_ENDIF OP_CHECKSIG".to_string());
+    let timeout = make_tx(&"a1".repeat(32), 886000, vec![htlc_vin], vec![make_vout(50_000, "v0_p2wpkh")]);
+    let url = serve_block(vec![commitment, timeout]).await;
+    let base = ["--api-url", url.as_str(), "--no-cache", "lightning", "block", "886000"];
+
+    let (ok, stdout) = run_cli(&base).await;
+    assert!(ok);
+    assert!(
+        stdout.contains("  1 HTLC transaction(s) sweep a commitment from this same block"),
+        "{stdout}"
+    );
+    let spends = format!("  spends commitment {} from the same block", "c1".repeat(32));
+    assert!(stdout.contains(&spends), "{stdout}");
+    let spent_by = format!("  HTLC spent in the same block by {}", "a1".repeat(32));
+    assert!(stdout.contains(&spent_by), "{stdout}");
+
+    let (ok, stdout) = run_cli(&[&base[..], &["--json"]].concat()).await;
+    assert!(ok);
+    let json: Vec<serde_json::Value> = serde_json::from_str(&stdout).unwrap();
+    let params = |i: usize| json[i][1]["params"].clone();
+    assert_eq!(params(0)["same_block_htlc_spends"][0], "a1".repeat(32));
+    assert_eq!(params(1)["same_block_commitment"], "c1".repeat(32));
+}
+
+#[tokio::test]
+async fn test_lightning_block_rejects_unknown_confidence() {
+    let (ok, _) = run_cli(&["lightning", "block", "886000", "--min-confidence", "certain"]).await;
+    assert!(!ok);
+}
+
+// ─── Txid validation ─────────────────────────────────────────────────────────
+
+#[tokio::test]
+async fn test_malformed_txid_rejected_before_fetch() {
+    // Nothing listens on the API URL, so reaching the network would fail differently
+    let (code, _) = run_cli_code(&["--api-url", "http://127.0.0.1:9/api", "tx", "abc"]).await;
+    assert_eq!(code, Some(2));
+    let (code, _) = run_cli_code(&["lightning", "tx", &"zz".repeat(32)]).await;
+    assert_eq!(code, Some(2));
+}
+
+// ─── Relative time annotations ──────────────────────────────────────────────
+
+#[tokio::test]
+async fn test_lightning_text_output_relative_times() {
+    let mut htlc_vin = make_vin(0);
+    htlc_vin.witness = Some(vec!["".to_string(), "3045".to_string()]);
+    htlc_vin.inner_witnessscript_asm = Some(
+        "OP_IF abc OP_ELSE 144 OP_CHECKSEQUENCEVERIFY OP_DROP OP_ENDIF".to_string(),
+    );
+    let htlc = make_tx(&"44".repeat(32), 886100, vec![htlc_vin], vec![make_vout(50_000, "v0_p2wsh")]);
+    let url = serve_block(vec![htlc]).await;
+
+    let (ok, stdout) = run_cli(&["--api-url", &url, "--no-cache", "lightning", "block", "886000"]).await;
+    assert!(ok);
+    assert!(stdout.contains("CLTV expiry: block 886100 (expires in 100 blocks, ~16.7 hours)"), "{stdout}");
+    assert!(stdout.contains("CSV delays: 144 blocks (~1.0 days)"), "{stdout}");
+
+    // JSON stays numeric
+    let (ok, stdout) = run_cli(&["--api-url", &url, "--no-cache", "lightning", "block", "886000", "--json"]).await;
+    assert!(ok);
+    let results: serde_json::Value = serde_json::from_str(&stdout).unwrap();
+    assert_eq!(results[0][1]["params"]["cltv_expiry"], 886100);
+    assert!(!stdout.contains("expires in"));
+}
+
+#[tokio::test]
+async fn test_lightning_text_output_commitment_number_hex() {
+    let commitment = make_tx(
+        &"11".repeat(32),
+        0x20000000 | 0xABCDEF,
+        vec![make_vin(0x80000000 | 0x123456)],
+        vec![make_vout(330, "v0_p2wsh"), make_vout(100_000, "v0_p2wsh")],
+    );
+    let url = serve_block(vec![commitment]).await;
+
+    let (ok, stdout) = run_cli(&["--api-url", &url, "--no-cache", "lightning", "block", "886000"]).await;
+    assert!(ok);
+    assert!(
+        stdout.contains("commitment number: 0x0000123456ABCDEF (obscured) [seq=0x123456 lt=0xABCDEF]"),
+        "{stdout}"
+    );
+
+    let (ok, stdout) = run_cli(&["--api-url", &url, "--no-cache", "lightning", "block", "886000", "--json"]).await;
+    assert!(ok);
+    let results: serde_json::Value = serde_json::from_str(&stdout).unwrap();
+    assert_eq!(results[0][1]["params"]["commitment_number"], 0x0000123456ABCDEFu64);
+}
+
+// ─── Timelocked transactions only ───────────────────────────────────────────
+
+#[tokio::test]
+async fn test_block_json_with_timelocks_only() {
+    let locked = make_tx(&"44".repeat(32), 886100, vec![make_vin(0xFFFFFFFD)], vec![make_vout(50_000, "v0_p2wpkh")]);
+    let regular = make_tx(&"33".repeat(32), 0, vec![make_vin(0xFFFFFFFF)], vec![make_vout(10_000, "v0_p2wpkh")]);
+    let url = serve_block(vec![locked, regular]).await;
+    let base = ["--api-url", url.as_str(), "--no-cache", "block", "886000"];
+
+    let (ok, stdout) = run_cli(&[&base[..], &["--json"]].concat()).await;
+    assert!(ok);
+    let all: Vec<serde_json::Value> = serde_json::from_str(&stdout).unwrap();
+    assert_eq!(all.len(), 2);
+
+    let (ok, stdout) = run_cli(&[&base[..], &["--json", "--with-timelocks-only"]].concat()).await;
+    assert!(ok);
+    let filtered: Vec<serde_json::Value> = serde_json::from_str(&stdout).unwrap();
+    assert_eq!(filtered.len(), 1);
+    assert_eq!(filtered[0]["txid"], "44".repeat(32));
+
+    let (ok, stdout) = run_cli(&[&base[..], &["--ndjson", "--with-timelocks-only"]].concat()).await;
+    assert!(ok);
+    assert_eq!(stdout.lines().count(), 1, "{stdout}");
+
+    let (ok, _) = run_cli(&[&base[..], &["--csv", "--with-timelocks-only"]].concat()).await;
+    assert!(!ok);
+}
+
+// ─── CSV output ──────────────────────────────────────────────────────────────
+
+#[tokio::test]
+async fn test_lightning_block_csv_output() {
+    let mut htlc_vin = make_vin(0);
+    htlc_vin.witness = Some(vec!["".to_string(), "3045".to_string()]);
+    htlc_vin.inner_witnessscript_asm = Some(
+        "OP_IF abc OP_ELSE 144 OP_CHECKSEQUENCEVERIFY OP_DROP 6 OP_CHECKSEQUENCEVERIFY OP_ENDIF".to_string(),
+    );
+    let htlc = make_tx(&"44".repeat(32), 886300, vec![htlc_vin], vec![make_vout(50_000, "v0_p2wsh")]);
+    let regular = make_tx(&"33".repeat(32), 0, vec![make_vin(0xFFFFFFFF)], vec![make_vout(10_000, "v0_p2wpkh")]);
+    let url = serve_block(vec![htlc, regular]).await;
+
+    let (ok, stdout) = run_cli(&["--api-url", &url, "--no-cache", "lightning", "block", "886000", "--csv"]).await;
+    assert!(ok);
+    let lines: Vec<&str> = stdout.lines().collect();
+    assert_eq!(
+        lines[0],
+        "txid,tx_type,confidence,commitment_number,htlc_output_count,cltv_expiry,csv_delays,anchor_count"
+    );
+    assert_eq!(lines.len(), 3);
+    assert!(lines[1].starts_with(&"44".repeat(32)));
+    assert!(lines[1].contains(",144;6,"), "{}", lines[1]);
+    // None fields are empty, not "null"
+    assert_eq!(lines[2], format!("{},,none,,,,,0", "33".repeat(32)));
+}
+
+#[tokio::test]
+async fn test_block_csv_output() {
+    let tx = make_tx(&"55".repeat(32), 885990, vec![make_vin(0xFFFFFFFD)], vec![make_vout(10_000, "v0_p2wpkh")]);
+    let other = make_tx(&"66".repeat(32), 0, vec![make_vin(0xFFFFFFFF)], vec![make_vout(10_000, "v0_p2wpkh")]);
+    let url = serve_block(vec![tx, other]).await;
+
+    let (ok, stdout) = run_cli(&["--api-url", &url, "--no-cache", "block", "886000", "--csv"]).await;
+    assert!(ok);
+    let lines: Vec<&str> = stdout.lines().collect();
+    assert_eq!(
+        lines[0],
+        "txid,nlocktime,nlocktime_active,relative_timelock_count,cltv_values,csv_values,max_risk"
+    );
+    assert_eq!(lines[1], format!("{},885990,true,0,,,", "55".repeat(32)));
+    // The header is written once, however the rows are flushed
+    assert_eq!(lines[2], format!("{},0,false,0,,,", "66".repeat(32)));
+    assert_eq!(lines.len(), 3);
+}
+
+#[tokio::test]
+async fn test_csv_conflicts_with_json() {
+    let (ok, _) = run_cli(&["block", "886000", "--csv", "--json"]).await;
+    assert!(!ok);
+}
+
+// ─── One-line output ─────────────────────────────────────────────────────────
+
+#[tokio::test]
+async fn test_oneline_output() {
+    let commitment = make_tx(
+        &"11".repeat(32),
+        0x20000000 | 0x123456,
+        vec![make_vin(0x80000001)],
+        vec![make_vout(330, "v0_p2wsh"), make_vout(100_000, "v0_p2wsh")],
+    );
+    let regular = make_tx(&"33".repeat(32), 885990, vec![make_vin(0xFFFFFFFD)], vec![make_vout(10_000, "v0_p2wpkh")]);
+    let url = serve_block(vec![commitment, regular]).await;
+    let base = ["--api-url", url.as_str(), "--no-cache"];
+
+    let (ok, stdout) = run_cli(&[&base[..], &["lightning", "block", "886000", "--oneline"]].concat()).await;
+    assert!(ok);
+    let lines: Vec<&str> = stdout.lines().collect();
+    assert_eq!(lines.len(), 2, "{stdout}");
+    assert!(
+        lines[0].starts_with(&format!("{} commitment highly_likely commitment=", "11".repeat(32))),
+        "{}",
+        lines[0]
+    );
+    assert_eq!(lines[1], format!("{} - none", "33".repeat(32)));
+
+    let (ok, stdout) = run_cli(&[&base[..], &["block", "886000", "--oneline"]].concat()).await;
+    assert!(ok);
+    assert!(stdout.contains(&format!("{} nlt=885990 cltv=0 csv=0\n", "33".repeat(32))), "{stdout}");
+
+    let (ok, _) = run_cli(&[&base[..], &["block", "886000", "--oneline", "--json"]].concat()).await;
+    assert!(!ok);
+}
+
+// ─── Sampling ────────────────────────────────────────────────────────────────
+
+#[tokio::test]
+async fn test_block_sample_is_seedable() {
+    let txs = ["11", "22", "33", "44", "55", "66"]
+        .iter()
+        .map(|id| make_tx(&id.repeat(32), 0, vec![make_vin(0xFFFFFFFF)], vec![make_vout(10_000, "v0_p2wpkh")]))
+        .collect();
+    let url = serve_block(txs).await;
+    let args = ["--api-url", url.as_str(), "--no-cache", "block", "886000", "--json", "--sample", "3", "--seed", "7"];
+
+    let (ok, first) = run_cli(&args).await;
+    assert!(ok);
+    let analyses: serde_json::Value = serde_json::from_str(&first).unwrap();
+    let txids: Vec<&str> = analyses.as_array().unwrap().iter().map(|a| a["txid"].as_str().unwrap()).collect();
+    assert_eq!(txids.len(), 3);
+    // Sampled transactions keep their block order
+    assert!(txids.windows(2).all(|w| w[0] < w[1]), "{txids:?}");
+
+    let (ok, second) = run_cli(&args).await;
+    assert!(ok);
+    assert_eq!(first, second);
+}
+
+#[tokio::test]
+async fn test_seed_requires_sample() {
+    let (ok, _) = run_cli(&["lightning", "block", "886000", "--seed", "7"]).await;
+    assert!(!ok);
+}
+
+// ─── Color output ────────────────────────────────────────────────────────────
+
+#[tokio::test]
+async fn test_color_flag() {
+    let commitment = make_tx(
+        &"11".repeat(32),
+        0x20000000 | 0x123456,
+        vec![make_vin(0x80000001)],
+        vec![make_vout(330, "v0_p2wsh"), make_vout(100_000, "v0_p2wsh")],
+    );
+    let url = serve_block(vec![commitment]).await;
+    let base = ["--api-url", url.as_str(), "--no-cache", "lightning", "block", "886000"];
+
+    let (ok, stdout) = run_cli(&[&base[..], &["--color", "always"]].concat()).await;
+    assert!(ok);
+    assert!(stdout.contains("\x1b["), "{stdout}");
+
+    // stdout is a pipe here, so auto means no color
+    let (ok, stdout) = run_cli(&base).await;
+    assert!(ok);
+    assert!(!stdout.contains("\x1b["), "{stdout}");
+
+    // Machine-readable output is never colored
+    let (ok, stdout) = run_cli(&[&base[..], &["--color", "always", "--csv"]].concat()).await;
+    assert!(ok);
+    assert!(!stdout.contains("\x1b["), "{stdout}");
+}
+
+// ─── Stats ───────────────────────────────────────────────────────────────────
+
+#[tokio::test]
+async fn test_stats_json_report() {
+    let commitment = make_tx(
+        &"11".repeat(32),
+        0x20000000 | 0x123456,
+        vec![make_vin(0x80000001)],
+        vec![make_vout(330, "v0_p2wsh"), make_vout(100_000, "v0_p2wsh"), make_vout(50_000, "v0_p2wsh")],
+    );
+    let regular = make_tx(&"33".repeat(32), 0, vec![make_vin(0xFFFFFFFF)], vec![make_vout(10_000, "v0_p2wpkh")]);
+    let url = serve_block(vec![commitment, regular]).await;
+
+    let (ok, stdout) = run_cli(&["--api-url", &url, "--no-cache", "stats", "886000", "--json"]).await;
+    assert!(ok);
+    let report: serde_json::Value = serde_json::from_str(&stdout).unwrap();
+    assert_eq!(report["transactions"], 2);
+    assert_eq!(report["lightning"]["commitments"], 1);
+    assert_eq!(report["avg_htlc_outputs_per_commitment"], 1.0);
+    assert_eq!(report["active_timelock_pct"], 50.0);
+
+    let (ok, stdout) = run_cli(&["--api-url", &url, "--no-cache", "stats", "886000"]).await;
+    assert!(ok);
+    assert!(stdout.contains("2 transactions, 1 with active timelocks (50.0%)"), "{stdout}");
+}
+
+// ─── Exit codes ──────────────────────────────────────────────────────────────
+
+#[tokio::test]
+async fn test_fail_on_exit_code() {
+    let commitment = make_tx(
+        &"11".repeat(32),
+        0x20000000 | 0x123456,
+        vec![make_vin(0x80000001)],
+        vec![make_vout(330, "v0_p2wsh"), make_vout(100_000, "v0_p2wsh")],
+    );
+    let url = serve_block(vec![commitment]).await;
+    let base = ["--api-url", url.as_str(), "--no-cache", "lightning", "block", "886000"];
+
+    // The report is still printed when a condition matches
+    let (code, stdout) = run_cli_code(&[&base[..], &["--fail-on", "commitment"]].concat()).await;
+    assert_eq!(code, Some(3));
+    assert!(stdout.contains(&"11".repeat(32)), "{stdout}");
+
+    let (code, _) = run_cli_code(&[&base[..], &["--fail-on", "preimage,risk"]].concat()).await;
+    assert_eq!(code, Some(0));
+
+    let (code, _) = run_cli_code(&base).await;
+    assert_eq!(code, Some(0));
+}
+
+#[tokio::test]
+async fn test_fail_on_rejects_unknown_condition() {
+    let (ok, _) = run_cli(&["lightning", "block", "886000", "--fail-on", "everything"]).await;
+    assert!(!ok);
+}
+
+// ─── Watch ───────────────────────────────────────────────────────────────────
+
+#[tokio::test]
+async fn test_watch_scans_new_blocks() {
+    let commitment = make_tx(
+        &"11".repeat(32),
+        0x20000000 | 0x123456,
+        vec![make_vin(0x80000001)],
+        vec![make_vout(330, "v0_p2wsh"), make_vout(100_000, "v0_p2wsh")],
+    );
+    let regular = make_tx(&"33".repeat(32), 0, vec![make_vin(0xFFFFFFFF)], vec![make_vout(10_000, "v0_p2wpkh")]);
+
+    // The tip is 885999 at startup, then advances to 886000
+    let polls = Arc::new(AtomicU64::new(0));
+    let hash = "00".repeat(32);
+    let block_path = format!("/api/block/{hash}/txs/0");
+    let app = Router::new()
+        .route(
+            "/api/blocks/tip/height",
+            get(move || async move { (885999 + polls.fetch_add(1, Ordering::SeqCst).min(1)).to_string() }),
+        )
+        .route("/api/block-height/886000", get(move || async move { hash }))
+        .route(&block_path, get(move || async move { axum::Json(vec![commitment, regular]) }));
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+    let mut child = Command::new(env!("CARGO_BIN_EXE_cltv-scan"))
+        .args(["--api-url", &format!("http://{addr}/api"), "--no-cache", "watch", "--interval", "1", "--json"])
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .kill_on_drop(true)
+        .spawn()
+        .unwrap();
+    let mut lines = BufReader::new(child.stdout.take().unwrap()).lines();
+    let line = tokio::time::timeout(Duration::from_secs(10), lines.next_line())
+        .await
+        .unwrap()
+        .unwrap()
+        .unwrap();
+    child.kill().await.unwrap();
+
+    let entry: serde_json::Value = serde_json::from_str(&line).unwrap();
+    assert_eq!(entry["height"], 886000);
+    assert_eq!(entry["transactions"], 2);
+    assert_eq!(entry["lightning"].as_array().unwrap().len(), 1);
+    assert_eq!(entry["lightning"][0]["txid"], "11".repeat(32));
+    assert!(entry["timelocks"].is_array());
+}
+
+// ─── JSON Schema ─────────────────────────────────────────────────────────────
+
+#[tokio::test]
+async fn test_schema_output() {
+    let (ok, stdout) = run_cli(&["schema"]).await;
+    assert!(ok);
+    let schemas: serde_json::Value = serde_json::from_str(&stdout).unwrap();
+    assert_eq!(schemas["transaction_analysis"]["title"], "TransactionAnalysis");
+    assert_eq!(schemas["lightning_classification"]["title"], "LightningClassification");
+
+    let (ok, stdout) = run_cli(&["schema", "lightning"]).await;
+    assert!(ok);
+    let schema: serde_json::Value = serde_json::from_str(&stdout).unwrap();
+    assert_eq!(schema["title"], "LightningClassification");
+    // Enum variants follow the serde renaming
+    assert!(stdout.contains("\"highly_likely\""), "{stdout}");
+    assert!(stdout.contains("\"anchor_sweep\""), "{stdout}");
+    assert!(!stdout.contains("\"HighlyLikely\""), "{stdout}");
+}
+
+// ─── Info ────────────────────────────────────────────────────────────────────
+
+/// Mock bitcoind JSON-RPC endpoint midway through initial block download.
+async fn serve_core_rpc() -> String {
+    let app = Router::new().route(
+        "/",
+        post(|axum::Json(request): axum::Json<serde_json::Value>| async move {
+            let result = match request["method"].as_str() {
+                Some("getblockcount") => serde_json::json!(886000),
+                Some("getblockchaininfo") => serde_json::json!({
+                    "initialblockdownload": true,
+                    "verificationprogress": 0.5,
+                }),
+                _ => serde_json::Value::Null,
+            };
+            axum::Json(serde_json::json!({ "result": result, "error": null, "id": "cltv-scan" }))
+        }),
+    );
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+    format!("http://{addr}")
+}
+
+#[tokio::test]
+async fn test_info_reports_tip_and_sync() {
+    let url = serve_block(Vec::new()).await;
+    let (ok, stdout) = run_cli(&["--api-url", &url, "--no-cache", "info", "--json"]).await;
+    assert!(ok);
+    let info: serde_json::Value = serde_json::from_str(&stdout).unwrap();
+    assert_eq!(info["tip_height"], 886000);
+    assert_eq!(info["synced"], serde_json::Value::Null);
+    assert!(info["source"].as_str().unwrap().contains(&url));
+
+    let rpc = serve_core_rpc().await;
+    let core_args = ["--source", "core", "--core-url", &rpc, "--core-user", "u", "--core-pass", "p"];
+    let (ok, stdout) = run_cli(&[&core_args[..], &["--no-cache", "info", "--json"]].concat()).await;
+    assert!(ok);
+    let info: serde_json::Value = serde_json::from_str(&stdout).unwrap();
+    assert_eq!(info["tip_height"], 886000);
+    assert_eq!(info["synced"], false);
+    assert_eq!(info["sync_progress"], 0.5);
+
+    let (ok, stdout) = run_cli(&[&core_args[..], &["--no-cache", "info"]].concat()).await;
+    assert!(ok);
+    assert!(stdout.contains("Tip height: 886000"), "{stdout}");
+    assert!(stdout.contains("no, initial block download 50.0% done"), "{stdout}");
+}
+
+// ─── Block files ─────────────────────────────────────────────────────────────
+
+#[tokio::test]
+async fn test_block_from_block_file() {
+    let genesis = bitcoin::blockdata::constants::genesis_block(bitcoin::Network::Bitcoin);
+    let path = std::env::temp_dir().join(format!("cltv-scan-test-genesis-{}.hex", std::process::id()));
+    std::fs::write(&path, bitcoin::consensus::encode::serialize_hex(&genesis)).unwrap();
+    let path = path.to_str().unwrap();
+
+    // Nothing listens on the API URL: the block file needs no data source
+    let api_url = "http://127.0.0.1:1/api";
+    let (ok, stdout) = run_cli(&["--api-url", api_url, "block", "0", "--block-file", path, "--json"]).await;
+    assert!(ok, "{stdout}");
+    let analyses: serde_json::Value = serde_json::from_str(&stdout).unwrap();
+    assert_eq!(
+        analyses[0]["txid"],
+        "4a5e1e4baab89f3a32518a88c31bc87f618f76673e2cc77ab2127b7afdeda33b"
+    );
+
+    // Genesis has no BIP 34 height to fall back on
+    let (ok, _) = run_cli(&["--api-url", api_url, "block", "--block-file", path]).await;
+    assert!(!ok);
+    std::fs::remove_file(path).unwrap();
+}
+
+// ─── Verbosity ───────────────────────────────────────────────────────────────
+
+#[tokio::test]
+async fn test_quiet_and_verbose_stderr() {
+    let tx = make_tx(&"55".repeat(32), 0, vec![make_vin(0xFFFFFFFF)], vec![make_vout(10_000, "v0_p2wpkh")]);
+    let url = serve_block(vec![tx]).await;
+    let base = ["--api-url", url.as_str(), "--no-cache", "block", "886000"];
+
+    let (ok, stderr) = run_cli_stderr(&base).await;
+    assert!(ok);
+    assert!(stderr.contains("Fetching block 886000..."), "{stderr}");
+
+    let (ok, stderr) = run_cli_stderr(&[&base[..], &["--quiet"]].concat()).await;
+    assert!(ok);
+    assert_eq!(stderr, "");
+
+    let (ok, stderr) = run_cli_stderr(&[&base[..], &["-v"]].concat()).await;
+    assert!(ok);
+    assert!(stderr.contains("Fetched block 886000 in "), "{stderr}");
+
+    // Errors still get through
+    let (ok, stderr) = run_cli_stderr(&["--api-url", "http://127.0.0.1:1/api", "-q", "block", "1"]).await;
+    assert!(!ok);
+    assert!(stderr.contains("Error"), "{stderr}");
+    assert!(!stderr.contains("retrying"), "{stderr}");
+
+    let (ok, _) = run_cli_stderr(&[&base[..], &["-q", "-v"]].concat()).await;
+    assert!(!ok);
+}
+
+// ─── Script disassembly ──────────────────────────────────────────────────────
+
+#[tokio::test]
+async fn test_show_scripts() {
+    let script = "OP_IF abc OP_ELSE 144 OP_CHECKSEQUENCEVERIFY OP_DROP OP_ENDIF";
+    let mut htlc_vin = make_vin(0);
+    htlc_vin.witness = Some(vec!["".to_string(), "3045".to_string()]);
+    htlc_vin.inner_witnessscript_asm = Some(script.to_string());
+    let htlc = make_tx(&"66".repeat(32), 886100, vec![make_vin(0xFFFFFFFF), htlc_vin], vec![make_vout(50_000, "v0_p2wsh")]);
+    let txid = htlc.txid.clone();
+    let url = serve_block(vec![htlc]).await;
+    let base = ["--api-url", url.as_str(), "--no-cache"];
+
+    let (ok, stdout) = run_cli(&[&base[..], &["tx", &txid, "--show-scripts"]].concat()).await;
+    assert!(ok);
+    assert!(stdout.contains(&format!("      witness script: {script}")), "{stdout}");
+    let (ok, stdout) = run_cli(&[&base[..], &["tx", &txid]].concat()).await;
+    assert!(ok);
+    assert!(!stdout.contains("witness script"), "{stdout}");
+
+    let (ok, stdout) = run_cli(&[&base[..], &["lightning", "tx", &txid, "--show-scripts"]].concat()).await;
+    assert!(ok);
+    assert!(stdout.contains(&format!("Input scripts:\n  [1]\n      witness script: {script}")), "{stdout}");
+
+    // Nothing to show for a decoded raw transaction, which carries no script asm
+    let raw = "01000000010000000000000000000000000000000000000000000000000000000000000000000000\
+               0000ffffffff0100000000000000000000000000";
+    let (ok, stdout) = run_cli(&["lightning", "tx", "--raw", raw, "--show-scripts"]).await;
+    assert!(ok, "{stdout}");
+    assert!(!stdout.contains("Input scripts"), "{stdout}");
+}
+
+// ─── Input values ────────────────────────────────────────────────────────────
+
+#[tokio::test]
+async fn test_tx_shows_spent_values() {
+    let mut anchor_vin = make_vin(0xFFFFFFFD);
+    anchor_vin.prevout = Some(ApiPrevout {
+        scriptpubkey: "0020".to_string(),
+        scriptpubkey_asm: "OP_0 OP_PUSHBYTES_32".to_string(),
+        scriptpubkey_type: "v0_p2wsh".to_string(),
+        scriptpubkey_address: None,
+        value: 330,
+    });
+    let tx = make_tx(&"99".repeat(32), 0, vec![anchor_vin, make_vin(0xFFFFFFFF)], vec![make_vout(10_000, "v0_p2wpkh")]);
+    let txid = tx.txid.clone();
+    let url = serve_block(vec![tx]).await;
+
+    let (ok, stdout) = run_cli(&["--api-url", &url, "--no-cache", "tx", &txid]).await;
+    assert!(ok);
+    assert!(stdout.contains("[0] 0xFFFFFFFD — RBF + locktime (spends 330 sats, v0_p2wsh)"), "{stdout}");
+    // No prevout, nothing added
+    assert!(stdout.contains("[1] 0xFFFFFFFF — final\n"), "{stdout}");
+}
+
+// ─── Ineffective nLockTime ───────────────────────────────────────────────────
+
+#[tokio::test]
+async fn test_tx_flags_ineffective_locktime() {
+    let tx = make_tx(&"ab".repeat(32), 885990, vec![make_vin(0xFFFFFFFF)], vec![make_vout(10_000, "v0_p2wpkh")]);
+    let txid = tx.txid.clone();
+    let url = serve_block(vec![tx]).await;
+    let base = ["--api-url", url.as_str(), "--no-cache", "tx", &txid];
+
+    let (ok, stdout) = run_cli(&base).await;
+    assert!(ok);
+    assert!(stdout.contains("nLockTime is ineffective: every input sequence is final"), "{stdout}");
+
+    let (ok, stdout) = run_cli(&[&base[..], &["--json"]].concat()).await;
+    assert!(ok);
+    let json: serde_json::Value = serde_json::from_str(&stdout).unwrap();
+    assert_eq!(json["summary"]["nlocktime_ineffective"], true);
+    assert_eq!(json["risk"]["findings"][0]["kind"], "ineffective_locktime");
+}
+
+// ─── Transaction by position ─────────────────────────────────────────────────
+
+#[tokio::test]
+async fn test_tx_at_block_position() {
+    let coinbase = make_tx(&"77".repeat(32), 0, vec![make_vin(0xFFFFFFFF)], vec![make_vout(10_000, "v0_p2wpkh")]);
+    let second = make_tx(&"88".repeat(32), 0, vec![make_vin(0xFFFFFFFF)], vec![make_vout(10_000, "v0_p2wpkh")]);
+    let url = serve_block(vec![coinbase, second]).await;
+    let base = ["--api-url", url.as_str(), "--no-cache", "tx", "--at"];
+
+    let (ok, stdout) = run_cli(&[&base[..], &["886000:1"]].concat()).await;
+    assert!(ok);
+    assert!(stdout.contains(&format!("Transaction: {}", "88".repeat(32))), "{stdout}");
+
+    let (ok, stderr) = run_cli_stderr(&[&base[..], &["886000:2"]].concat()).await;
+    assert!(!ok);
+    assert!(stderr.contains("block 886000 has 2 transactions, no index 2"), "{stderr}");
+
+    let (ok, _) = run_cli(&[&base[..], &["886000:1", &"88".repeat(32)]].concat()).await;
+    assert!(!ok);
+}
+
+// ─── Mempool scan ────────────────────────────────────────────────────────────
+
+/// Mock esplora API whose mempool lists `txs` plus a txid that is no longer there.
+async fn serve_mempool(txs: Vec<ApiTransaction>) -> String {
+    let mut txids: Vec<String> = txs.iter().map(|tx| tx.txid.clone()).collect();
+    txids.push("99".repeat(32));
+    let mut app = Router::new()
+        .route("/api/blocks/tip/height", get(|| async { "886000" }))
+        .route("/api/mempool/txids", get(move || async move { axum::Json(txids) }));
+    for tx in txs {
+        app = app.route(&format!("/api/tx/{}", tx.txid), get(move || async move { axum::Json(tx) }));
+    }
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+    format!("http://{addr}/api")
+}
+
+#[tokio::test]
+async fn test_mempool_scan() {
+    let unconfirmed =
+        |txid: &str, locktime, vin, vouts| make_tx(&txid.repeat(32), locktime, vec![vin], vouts);
+    let commitment = unconfirmed(
+        "11",
+        0x20000000 | 0x123456,
+        make_vin(0x80000001),
+        vec![make_vout(330, "v0_p2wsh"), make_vout(100_000, "v0_p2wsh")],
+    );
+    let possible = unconfirmed(
+        "22",
+        0x20000000 | 0x654321,
+        make_vin(0xFFFFFFFE),
+        vec![make_vout(100_000, "v0_p2wpkh")],
+    );
+    let regular = unconfirmed("33", 0, make_vin(0xFFFFFFFF), vec![make_vout(10_000, "v0_p2wpkh")]);
+    let url = serve_mempool(vec![commitment, possible, regular]).await;
+
+    let (ok, stdout) = run_cli(&["--api-url", &url, "--no-cache", "mempool", "--json"]).await;
+    assert!(ok);
+    let lines: Vec<serde_json::Value> =
+        stdout.lines().map(|l| serde_json::from_str(l).unwrap()).collect();
+    assert_eq!(lines.len(), 2, "{stdout}");
+    assert_eq!(lines[0]["txid"], "11".repeat(32));
+    assert_eq!(lines[0]["lightning"]["tx_type"], "commitment");
+    assert_eq!(lines[1]["txid"], "22".repeat(32));
+
+    let (ok, stdout) = run_cli(&[
+        "--api-url", &url, "--no-cache", "mempool", "--min-confidence", "highly_likely",
+    ])
+    .await;
+    assert!(ok);
+    assert!(stdout.contains(&"11".repeat(32)), "{stdout}");
+    assert!(!stdout.contains(&"22".repeat(32)));
+}
+
+#[tokio::test]
+async fn test_monitor_format_ndjson() {
+    let commitment = make_tx(
+        &"11".repeat(32),
+        0x20000000 | 0x123456,
+        vec![make_vin(0x80000001)],
+        vec![make_vout(330, "v0_p2wsh"), make_vout(100_000, "v0_p2wsh")],
+    );
+    let recent = serde_json::json!([{ "txid": commitment.txid }]);
+    let app = Router::new()
+        .route("/api/blocks/tip/height", get(|| async { "886000" }))
+        .route("/api/mempool/recent", get(move || async move { axum::Json(recent) }))
+        .route(&format!("/api/tx/{}", commitment.txid), get(move || async move { axum::Json(commitment) }));
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+    let mut child = Command::new(env!("CARGO_BIN_EXE_cltv-scan"))
+        .args(["--api-url", &format!("http://{addr}/api"), "--no-cache", "monitor", "--format", "ndjson"])
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .kill_on_drop(true)
+        .spawn()
+        .unwrap();
+    let mut lines = BufReader::new(child.stdout.take().unwrap()).lines();
+    let line = tokio::time::timeout(Duration::from_secs(10), lines.next_line())
+        .await
+        .unwrap()
+        .unwrap()
+        .unwrap();
+    child.kill().await.unwrap();
+
+    let entry: serde_json::Value = serde_json::from_str(&line).unwrap();
+    assert_eq!(entry["txid"], "11".repeat(32));
+    assert_eq!(entry["lightning"]["tx_type"], "commitment");
+}
+
+// ─── Confidence presets ──────────────────────────────────────────────────────
+
+#[tokio::test]
+async fn test_strict_and_lenient_flags() {
+    // Both commitment markers but no anchors
+    let tx = make_tx(
+        &"44".repeat(32),
+        0x20000042,
+        vec![make_vin(0x80000001)],
+        vec![make_vout(100_000, "v0_p2wsh"), make_vout(50_000, "v0_p2wpkh")],
+    );
+    let txid = tx.txid.clone();
+    let url = serve_block(vec![tx]).await;
+
+    let confidence = |preset| {
+        let (url, txid) = (url.clone(), txid.clone());
+        async move {
+            let args = ["--api-url", &url, "--no-cache", preset, "lightning", "tx", &txid, "--json"];
+            let (ok, stdout) = run_cli(&args).await;
+            assert!(ok);
+            let json: serde_json::Value = serde_json::from_str(&stdout).unwrap();
+            json["confidence"].as_str().unwrap().to_string()
+        }
+    };
+    assert_eq!(confidence("--strict").await, "possible");
+    assert_eq!(confidence("--lenient").await, "highly_likely");
+
+    let (ok, _) = run_cli(&["--strict", "--lenient", "lightning", "tx", &txid]).await;
+    assert!(!ok);
+}
+
+// ─── Output format ───────────────────────────────────────────────────────────
+
+#[tokio::test]
+async fn test_format_flag_matches_legacy_flags() {
+    let tx = make_tx(&"55".repeat(32), 885990, vec![make_vin(0xFFFFFFFD)], vec![make_vout(10_000, "v0_p2wpkh")]);
+    let txid = tx.txid.clone();
+    let url = serve_block(vec![tx]).await;
+    let base = ["--api-url", url.as_str(), "--no-cache", "block", "886000"];
+    let run = async |args: &[&str]| {
+        let (ok, stdout) = run_cli(&[&base[..], args].concat()).await;
+        assert!(ok, "{args:?}");
+        stdout
+    };
+
+    let json = run(&["--format", "json"]).await;
+    let parsed: Vec<serde_json::Value> = serde_json::from_str(&json).unwrap();
+    assert_eq!(parsed[0]["txid"], txid);
+    assert_eq!(json, run(&["--json"]).await);
+
+    let ndjson = run(&["--format", "ndjson"]).await;
+    assert_eq!(ndjson.lines().count(), 1);
+    serde_json::from_str::<serde_json::Value>(ndjson.trim_end()).unwrap();
+    assert_eq!(ndjson, run(&["--ndjson"]).await);
+
+    let csv = run(&["--format", "csv"]).await;
+    assert_eq!(csv.lines().nth(1), Some(format!("{txid},885990,true,0,,,").as_str()));
+    assert_eq!(csv, run(&["--csv"]).await);
+
+    assert_eq!(run(&["--format", "text"]).await, run(&[]).await);
+}
+
+#[tokio::test]
+async fn test_format_flag_on_tx_and_lightning() {
+    let tx = make_tx(&"66".repeat(32), 0x20000042, vec![make_vin(0x80000001)], vec![make_vout(330, "v0_p2wsh")]);
+    let txid = tx.txid.clone();
+    let url = serve_block(vec![tx]).await;
+
+    let (ok, stdout) = run_cli(&["--api-url", &url, "--no-cache", "--format", "json", "tx", &txid]).await;
+    assert!(ok);
+    let json: serde_json::Value = serde_json::from_str(&stdout).unwrap();
+    assert_eq!(json["txid"], txid);
+
+    // Global, so it may also follow the subcommand
+    let args = ["--api-url", &url, "--no-cache", "lightning", "tx", &txid, "--format", "json"];
+    let (ok, stdout) = run_cli(&args).await;
+    assert!(ok);
+    let json: serde_json::Value = serde_json::from_str(&stdout).unwrap();
+    assert!(json["confidence"].is_string());
+}
+
+#[tokio::test]
+async fn test_format_flag_rejects_unsupported_combinations() {
+    for args in [
+        &["stats", "886000", "--format", "csv"][..],
+        &["tx", &"55".repeat(32), "--format", "ndjson"],
+        &["block", "886000", "--format", "csv", "--json"],
+        &["block", "886000", "--format", "json", "--oneline"],
+        &["block", "886000", "--format", "ndjson", "--sort", "type"],
+        &["tx", &"55".repeat(32), "--format", "json", "--show-scripts"],
+        &["block", "886000", "--format", "yaml"],
+    ] {
+        let (ok, _) = run_cli(args).await;
+        assert!(!ok, "{args:?}");
+    }
+}
+
+// ─── Output file ─────────────────────────────────────────────────────────────
+
+#[tokio::test]
+async fn test_output_file_matches_stdout() {
+    let tx = make_tx(&"55".repeat(32), 885990, vec![make_vin(0xFFFFFFFD)], vec![make_vout(10_000, "v0_p2wpkh")]);
+    let url = serve_block(vec![tx]).await;
+    let path = std::env::temp_dir().join(format!("cltv-scan-test-output-{}", std::process::id()));
+
+    for format in ["text", "json", "ndjson", "csv"] {
+        let base = ["--api-url", url.as_str(), "--no-cache", "--format", format, "block", "886000"];
+        let (ok, expected) = run_cli(&base).await;
+        assert!(ok);
+
+        // Truncated, not appended to
+        std::fs::write(&path, "stale\n").unwrap();
+        let (ok, stdout) = run_cli(&[&base[..], &["--output", path.to_str().unwrap()]].concat()).await;
+        assert!(ok);
+        assert_eq!(stdout, "", "{format}");
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), expected, "{format}");
+    }
+    std::fs::remove_file(path).unwrap();
+}
+
+#[tokio::test]
+async fn test_output_write_error_fails() {
+    let (ok, _) = run_cli(&["--output", "/dev/full", "schema"]).await;
+    assert!(!ok);
+}
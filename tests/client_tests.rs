@@ -0,0 +1,891 @@
+use std::future::poll_fn;
+use std::pin::pin;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use axum::Router;
+use axum::http::StatusCode;
+use axum::routing::{get, post};
+use futures_core::Stream;
+use rand::SeedableRng;
+use rand::rngs::StdRng;
+use tokio::net::TcpListener;
+use tokio::time::Instant;
+
+use cltv_scan::error::{Error, Result};
+use cltv_scan::api::bitcoin_core_client::{BitcoinCoreClient, CoreAuth};
+use cltv_scan::api::client::{MempoolClient, backoff_delay};
+use cltv_scan::api::disk_cache::CachedSource;
+use cltv_scan::api::rate_limit::RateLimiter;
+use cltv_scan::api::source::{
+    BLOCK_TXS_PAGE_SIZE, DataSource, block_tx_pages, find_height_at_or_after, transaction_at,
+};
+use cltv_scan::api::types::*;
+use cltv_scan::testing::{confirmed_status, make_tx};
+
+// ─── Mock esplora server ─────────────────────────────────────────────────────
+
+/// Serve `/api/blocks/tip/height`, answering `failures` times with `status` before succeeding.
+/// Returns the API root and the number of requests seen.
+async fn flaky_server(failures: usize, status: StatusCode) -> (String, Arc<AtomicUsize>) {
+    let hits = Arc::new(AtomicUsize::new(0));
+    let counter = hits.clone();
+    let app = Router::new().route(
+        "/api/blocks/tip/height",
+        get(move || {
+            let counter = counter.clone();
+            async move {
+                if counter.fetch_add(1, Ordering::SeqCst) < failures {
+                    (status, String::new())
+                } else {
+                    (StatusCode::OK, "886000".to_string())
+                }
+            }
+        }),
+    );
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+    (format!("http://{addr}/api"), hits)
+}
+
+fn fast_client(url: &str) -> MempoolClient {
+    MempoolClient::new(url, Duration::ZERO).with_retry_base_delay(Duration::from_millis(1))
+}
+
+// ─── Retry policy ────────────────────────────────────────────────────────────
+
+#[tokio::test]
+async fn test_retries_transient_failures_then_succeeds() {
+    let (url, hits) = flaky_server(2, StatusCode::SERVICE_UNAVAILABLE).await;
+    let height = fast_client(&url).get_block_tip_height().await.unwrap();
+    assert_eq!(height, 886000);
+    assert_eq!(hits.load(Ordering::SeqCst), 3);
+}
+
+#[tokio::test]
+async fn test_retries_rate_limit() {
+    let (url, hits) = flaky_server(1, StatusCode::TOO_MANY_REQUESTS).await;
+    assert!(fast_client(&url).get_block_tip_height().await.is_ok());
+    assert_eq!(hits.load(Ordering::SeqCst), 2);
+}
+
+#[tokio::test]
+async fn test_gives_up_after_configured_attempts() {
+    let (url, hits) = flaky_server(10, StatusCode::BAD_GATEWAY).await;
+    let err = fast_client(&url)
+        .with_retry_attempts(3)
+        .get_block_tip_height()
+        .await
+        .unwrap_err();
+    assert!(err.to_string().contains("502"));
+    assert_eq!(hits.load(Ordering::SeqCst), 3);
+}
+
+#[tokio::test]
+async fn test_client_errors_not_retried() {
+    let (url, hits) = flaky_server(10, StatusCode::NOT_FOUND).await;
+    assert!(fast_client(&url).get_block_tip_height().await.is_err());
+    assert_eq!(hits.load(Ordering::SeqCst), 1);
+}
+
+#[test]
+fn test_backoff_doubles_within_jitter_band() {
+    let base = Duration::from_millis(100);
+    let mut rng = StdRng::seed_from_u64(7);
+    for retry in 1..=5 {
+        let ceiling = base * (1 << (retry - 1));
+        let delay = backoff_delay(base, retry, &mut rng);
+        assert!(delay >= ceiling / 2 && delay <= ceiling, "retry {retry}: {delay:?}");
+    }
+}
+
+#[test]
+fn test_backoff_jitter_comes_from_rng() {
+    let base = Duration::from_secs(1);
+    let delay = |seed| backoff_delay(base, 3, &mut StdRng::seed_from_u64(seed));
+    assert_eq!(delay(1), delay(1));
+    assert!((0..10).map(delay).any(|d| d != delay(0)));
+}
+
+// ─── Error kinds ─────────────────────────────────────────────────────────────
+
+#[tokio::test]
+async fn test_http_errors_map_to_error_kinds() {
+    let (url, _) = flaky_server(10, StatusCode::NOT_FOUND).await;
+    let err = fast_client(&url).get_block_tip_height().await.unwrap_err();
+    assert!(matches!(err, Error::NotFound(_)), "{err:?}");
+
+    let (url, _) = flaky_server(10, StatusCode::TOO_MANY_REQUESTS).await;
+    let err = fast_client(&url)
+        .with_retry_attempts(2)
+        .get_block_tip_height()
+        .await
+        .unwrap_err();
+    assert!(matches!(err, Error::RateLimited(_)), "{err:?}");
+
+    let (url, _) = flaky_server(10, StatusCode::BAD_GATEWAY).await;
+    let err = fast_client(&url)
+        .with_retry_attempts(1)
+        .get_block_tip_height()
+        .await
+        .unwrap_err();
+    assert!(matches!(err, Error::Network(_)), "{err:?}");
+}
+
+#[tokio::test]
+async fn test_malformed_response_is_decode_error() {
+    let app = Router::new()
+        .route("/api/blocks/tip/height", get(|| async { "not a height" }))
+        .route("/api/mempool/recent", get(|| async { "[{\"fee\": 1}]" }));
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+    let client = fast_client(&format!("http://{addr}/api"));
+
+    let err = client.get_block_tip_height().await.unwrap_err();
+    assert!(matches!(err, Error::Decode(_)), "{err:?}");
+    let err = client.get_mempool_recent_txids().await.unwrap_err();
+    assert!(matches!(err, Error::Decode(_)), "{err:?}");
+    assert!(err.to_string().starts_with("deserializing mempool recent transactions: "));
+}
+
+#[tokio::test]
+async fn test_unreachable_source_is_network_error() {
+    // Bind then drop, so the port is very likely closed
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    drop(listener);
+
+    let err = fast_client(&format!("http://{addr}/api"))
+        .with_retry_attempts(1)
+        .get_block_tip_height()
+        .await
+        .unwrap_err();
+    assert!(matches!(err, Error::Network(_)), "{err:?}");
+}
+
+// ─── Mempool listing ─────────────────────────────────────────────────────────
+
+#[tokio::test]
+async fn test_mempool_txids_lists_whole_mempool() {
+    let txids = vec!["11".repeat(32), "22".repeat(32)];
+    let listed = txids.clone();
+    let app = Router::new()
+        .route("/api/mempool/txids", get(move || async move { axum::Json(listed) }));
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+    let client = fast_client(&format!("http://{addr}/api"));
+    assert_eq!(client.get_mempool_txids().await.unwrap(), txids);
+}
+
+#[tokio::test]
+async fn test_mempool_txids_unsupported_by_default() {
+    let err = PagedSource::new(0, None).get_mempool_txids().await.unwrap_err();
+    assert!(matches!(err, Error::RpcUnavailable(_)), "{err:?}");
+}
+
+// ─── Block index ─────────────────────────────────────────────────────────────
+
+/// Mock bitcoind JSON-RPC endpoint knowing one confirmed coinbase transaction in
+/// block 885000, and a block of 30 coinbase-like transactions at any hash. Returns the
+/// RPC URL and the methods called, in order.
+async fn counting_core_rpc() -> (String, Arc<Mutex<Vec<String>>>) {
+    let calls = Arc::new(Mutex::new(Vec::new()));
+    let seen = calls.clone();
+    let app = Router::new().route(
+        "/",
+        post(move |axum::Json(request): axum::Json<serde_json::Value>| {
+            let seen = seen.clone();
+            async move {
+                let method = request["method"].as_str().unwrap_or_default().to_string();
+                seen.lock().unwrap().push(method.clone());
+                let result = match method.as_str() {
+                    "getblockhash" => serde_json::json!("ab".repeat(32)),
+                    "getblockheader" => serde_json::json!({ "height": 885000 }),
+                    "getrawtransaction" => serde_json::json!({
+                        "txid": "11".repeat(32),
+                        "version": 2,
+                        "size": 100,
+                        "weight": 400,
+                        "locktime": 0,
+                        "vin": [{ "coinbase": "00", "sequence": 0xFFFFFFFFu32 }],
+                        "vout": [],
+                        "blockhash": "ab".repeat(32),
+                        "confirmations": 1000,
+                    }),
+                    "getblock" => {
+                        let txs: Vec<_> = (0..30)
+                            .map(|i| {
+                                serde_json::json!({
+                                    "txid": format!("{i:02x}").repeat(32),
+                                    "version": 2,
+                                    "size": 100,
+                                    "weight": 400,
+                                    "locktime": 0,
+                                    "vin": [{ "coinbase": "00", "sequence": 0xFFFFFFFFu32 }],
+                                    "vout": [],
+                                })
+                            })
+                            .collect();
+                        serde_json::json!({ "height": 885000, "tx": txs })
+                    }
+                    _ => serde_json::Value::Null,
+                };
+                axum::Json(serde_json::json!({ "result": result, "error": null, "id": "cltv-scan" }))
+            }
+        }),
+    );
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+    (format!("http://{addr}"), calls)
+}
+
+#[tokio::test]
+async fn test_block_hash_lookups_cached() {
+    let (url, calls) = counting_core_rpc().await;
+    let client = BitcoinCoreClient::new(&url, CoreAuth::None).unwrap();
+
+    assert_eq!(client.get_block_hash(885000).await.unwrap(), "ab".repeat(32));
+    assert_eq!(client.get_block_hash(885000).await.unwrap(), "ab".repeat(32));
+    assert_eq!(*calls.lock().unwrap(), ["getblockhash"]);
+
+    // The height learned above also resolves the transaction's block hash
+    let tx = client.get_transaction(&"11".repeat(32)).await.unwrap();
+    assert_eq!(tx.status.block_height, Some(885000));
+    assert_eq!(*calls.lock().unwrap(), ["getblockhash", "getrawtransaction"]);
+}
+
+#[tokio::test]
+async fn test_block_height_lookups_cached() {
+    let (url, calls) = counting_core_rpc().await;
+    let client = BitcoinCoreClient::new(&url, CoreAuth::None).unwrap();
+
+    for _ in 0..2 {
+        let tx = client.get_transaction(&"11".repeat(32)).await.unwrap();
+        assert_eq!(tx.status.block_height, Some(885000));
+    }
+    assert_eq!(client.get_block_hash(885000).await.unwrap(), "ab".repeat(32));
+    assert_eq!(
+        *calls.lock().unwrap(),
+        ["getrawtransaction", "getblockheader", "getrawtransaction"]
+    );
+}
+
+// ─── Prevouts ────────────────────────────────────────────────────────────────
+
+#[tokio::test]
+async fn test_mempool_prevout_parsed_inline() {
+    // Trimmed esplora response: prevout comes inline with each input
+    let body = serde_json::json!({
+        "txid": "bb".repeat(32),
+        "version": 2,
+        "locktime": 886100,
+        "vin": [{
+            "txid": "aa".repeat(32),
+            "vout": 0,
+            "prevout": {
+                "scriptpubkey": format!("0020{}", "cd".repeat(32)),
+                "scriptpubkey_asm": format!("OP_0 OP_PUSHBYTES_32 {}", "cd".repeat(32)),
+                "scriptpubkey_type": "v0_p2wsh",
+                "scriptpubkey_address": "bc1qexample",
+                "value": 50000
+            },
+            "scriptsig": "",
+            "scriptsig_asm": "",
+            "witness": ["", "3045"],
+            "is_coinbase": false,
+            "sequence": 0
+        }],
+        "vout": [],
+        "size": 200,
+        "weight": 800,
+        "fee": 1000,
+        "status": { "confirmed": false }
+    });
+    let path = format!("/api/tx/{}", "bb".repeat(32));
+    let app = Router::new().route(&path, get(move || async move { axum::Json(body) }));
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+    let client = fast_client(&format!("http://{addr}/api"));
+    let tx = client.get_transaction(&"bb".repeat(32)).await.unwrap();
+    let prevout = tx.vin[0].prevout.as_ref().unwrap();
+    assert_eq!(prevout.value, 50_000);
+    assert_eq!(prevout.scriptpubkey_type, "v0_p2wsh");
+}
+
+// ─── On-disk cache ───────────────────────────────────────────────────────────
+
+/// DataSource that serves fixed transactions and counts how often it is asked.
+struct CountingSource {
+    txs: Vec<ApiTransaction>,
+    tx_fetches: Arc<AtomicUsize>,
+    block_fetches: Arc<AtomicUsize>,
+}
+
+impl CountingSource {
+    fn new(txs: Vec<ApiTransaction>) -> Self {
+        Self {
+            txs,
+            tx_fetches: Arc::new(AtomicUsize::new(0)),
+            block_fetches: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+}
+
+impl DataSource for CountingSource {
+    async fn get_transaction(&self, txid: &str) -> Result<ApiTransaction> {
+        self.tx_fetches.fetch_add(1, Ordering::SeqCst);
+        self.txs
+            .iter()
+            .find(|tx| tx.txid == txid)
+            .cloned()
+            .ok_or_else(|| Error::NotFound(format!("tx not found: {txid}")))
+    }
+
+    async fn get_transaction_hex(&self, _txid: &str) -> Result<String> {
+        Ok("00".to_string())
+    }
+
+    async fn get_block_txs(&self, _hash: &str, _start_index: u32) -> Result<Vec<ApiTransaction>> {
+        Ok(self.txs.clone())
+    }
+
+    async fn get_block_tip_height(&self) -> Result<u64> {
+        Ok(886000)
+    }
+
+    async fn get_block_hash(&self, _height: u64) -> Result<String> {
+        Ok("ab".repeat(32))
+    }
+
+    async fn get_all_block_txs(&self, _height: u64) -> Result<Vec<ApiTransaction>> {
+        self.block_fetches.fetch_add(1, Ordering::SeqCst);
+        Ok(self.txs.clone())
+    }
+
+    async fn get_mempool_recent_txids(&self) -> Result<Vec<String>> {
+        Ok(vec![])
+    }
+}
+
+/// An empty transaction with txid `txid_byte` repeated, confirmed at 886000 if `confirmed`.
+fn cache_tx(txid_byte: &str, confirmed: bool) -> ApiTransaction {
+    let tx = make_tx(&txid_byte.repeat(32), 0, vec![], vec![]);
+    if confirmed {
+        ApiTransaction { status: confirmed_status(886000), ..tx }
+    } else {
+        tx
+    }
+}
+
+fn temp_cache_dir(name: &str) -> std::path::PathBuf {
+    let dir = std::env::temp_dir().join(format!("cltv-scan-test-{name}-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    dir
+}
+
+#[tokio::test]
+async fn test_confirmed_tx_served_from_disk() {
+    let dir = temp_cache_dir("confirmed");
+    let txid = "aa".repeat(32);
+
+    let source = CountingSource::new(vec![cache_tx("aa", true)]);
+    let fetches = source.tx_fetches.clone();
+    let cached = CachedSource::new(source, &dir);
+    cached.get_transaction(&txid).await.unwrap();
+    cached.get_transaction(&txid).await.unwrap();
+    assert_eq!(fetches.load(Ordering::SeqCst), 1);
+
+    // A fresh wrapper over an empty source still finds it on disk
+    let reopened = CachedSource::new(CountingSource::new(vec![]), &dir);
+    let tx = reopened.get_transaction(&txid).await.unwrap();
+    assert_eq!(tx.txid, txid);
+    assert!(tx.status.confirmed);
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[tokio::test]
+async fn test_unconfirmed_tx_not_cached() {
+    let dir = temp_cache_dir("unconfirmed");
+    let txid = "bb".repeat(32);
+
+    let source = CountingSource::new(vec![cache_tx("bb", false)]);
+    let fetches = source.tx_fetches.clone();
+    let cached = CachedSource::new(source, &dir);
+    cached.get_transaction(&txid).await.unwrap();
+    cached.get_transaction(&txid).await.unwrap();
+    assert_eq!(fetches.load(Ordering::SeqCst), 2);
+
+    let reopened = CachedSource::new(CountingSource::new(vec![]), &dir);
+    assert!(reopened.get_transaction(&txid).await.is_err());
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[tokio::test]
+async fn test_block_txs_served_from_disk() {
+    let dir = temp_cache_dir("block");
+    let source = CountingSource::new(vec![cache_tx("cc", true), cache_tx("dd", true)]);
+    let block_fetches = source.block_fetches.clone();
+    let tx_fetches = source.tx_fetches.clone();
+    let cached = CachedSource::new(source, &dir);
+    let first = cached.get_all_block_txs(886000).await.unwrap();
+    let second = cached.get_all_block_txs(886000).await.unwrap();
+
+    assert_eq!(first.len(), 2);
+    assert_eq!(block_fetches.load(Ordering::SeqCst), 1);
+    // Block transactions are cached individually, too
+    cached.get_transaction(&"cc".repeat(32)).await.unwrap();
+    assert_eq!(tx_fetches.load(Ordering::SeqCst), 0);
+    assert_eq!(
+        second.iter().map(|tx| &tx.txid).collect::<Vec<_>>(),
+        first.iter().map(|tx| &tx.txid).collect::<Vec<_>>()
+    );
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[tokio::test]
+async fn test_block_walk_cached_as_it_streams() {
+    let dir = temp_cache_dir("block-walk");
+    let source = CountingSource::new(vec![cache_tx("ee", true), cache_tx("ff", true)]);
+    let block_fetches = source.block_fetches.clone();
+    let tx_fetches = source.tx_fetches.clone();
+    let cached = CachedSource::new(source, &dir);
+
+    let mut first = Vec::new();
+    cached
+        .for_each_block_tx(886000, |tx| {
+            first.push(tx.txid);
+            Ok::<_, Error>(())
+        })
+        .await
+        .unwrap();
+    let mut second = Vec::new();
+    cached
+        .for_each_block_tx(886000, |tx| {
+            second.push(tx.txid);
+            Ok::<_, Error>(())
+        })
+        .await
+        .unwrap();
+
+    assert_eq!(first, vec!["ee".repeat(32), "ff".repeat(32)]);
+    assert_eq!(second, first);
+    assert_eq!(block_fetches.load(Ordering::SeqCst), 1);
+    // The walk stored each transaction, and the block for get_all_block_txs
+    cached.get_transaction(&"ff".repeat(32)).await.unwrap();
+    assert_eq!(tx_fetches.load(Ordering::SeqCst), 0);
+    assert_eq!(cached.get_all_block_txs(886000).await.unwrap().len(), 2);
+    assert_eq!(block_fetches.load(Ordering::SeqCst), 1);
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+// ─── Block pagination ────────────────────────────────────────────────────────
+
+/// DataSource serving a block of `tx_count` transactions in 25-tx pages, recording
+/// each requested start index. Fails at `fail_at`, if set.
+struct PagedSource {
+    tx_count: usize,
+    fail_at: Option<u32>,
+    requests: Mutex<Vec<u32>>,
+}
+
+impl PagedSource {
+    fn new(tx_count: usize, fail_at: Option<u32>) -> Self {
+        Self {
+            tx_count,
+            fail_at,
+            requests: Mutex::new(Vec::new()),
+        }
+    }
+}
+
+impl DataSource for PagedSource {
+    async fn get_transaction(&self, txid: &str) -> Result<ApiTransaction> {
+        Err(Error::NotFound(format!("tx not found: {txid}")))
+    }
+
+    async fn get_transaction_hex(&self, _txid: &str) -> Result<String> {
+        Ok("00".to_string())
+    }
+
+    async fn get_block_txs(&self, _hash: &str, start_index: u32) -> Result<Vec<ApiTransaction>> {
+        self.requests.lock().unwrap().push(start_index);
+        if self.fail_at == Some(start_index) {
+            return Err(Error::Network(format!("page {start_index} unavailable")));
+        }
+        let start = (start_index as usize).min(self.tx_count);
+        let end = (start + BLOCK_TXS_PAGE_SIZE).min(self.tx_count);
+        Ok((start..end).map(|i| cache_tx(&format!("{:02x}", i % 256), true)).collect())
+    }
+
+    async fn get_block_tip_height(&self) -> Result<u64> {
+        Ok(886000)
+    }
+
+    async fn get_block_hash(&self, _height: u64) -> Result<String> {
+        Ok("ab".repeat(32))
+    }
+
+    async fn get_all_block_txs(&self, _height: u64) -> Result<Vec<ApiTransaction>> {
+        self.get_block_txs("", 0).await
+    }
+
+    async fn get_mempool_recent_txids(&self) -> Result<Vec<String>> {
+        Ok(vec![])
+    }
+}
+
+/// Drain a page stream into page sizes, stopping at the first error.
+async fn collect_pages(stream: impl Stream<Item = Result<Vec<ApiTransaction>>>) -> (Vec<usize>, Option<String>) {
+    let mut stream = pin!(stream);
+    let mut sizes = Vec::new();
+    while let Some(page) = poll_fn(|cx| stream.as_mut().poll_next(cx)).await {
+        match page {
+            Ok(page) => sizes.push(page.len()),
+            Err(e) => return (sizes, Some(e.to_string())),
+        }
+    }
+    (sizes, None)
+}
+
+#[tokio::test]
+async fn test_block_tx_pages_until_short_page() {
+    let source = PagedSource::new(60, None);
+    let (sizes, error) = collect_pages(block_tx_pages(&source, "ab")).await;
+    assert_eq!(sizes, vec![25, 25, 10]);
+    assert_eq!(error, None);
+    assert_eq!(*source.requests.lock().unwrap(), vec![0, 25, 50]);
+}
+
+#[tokio::test]
+async fn test_block_tx_pages_exact_multiple() {
+    // A full last page needs one more request to find the end; the empty page isn't yielded
+    let source = PagedSource::new(50, None);
+    let (sizes, _) = collect_pages(block_tx_pages(&source, "ab")).await;
+    assert_eq!(sizes, vec![25, 25]);
+    assert_eq!(*source.requests.lock().unwrap(), vec![0, 25, 50]);
+}
+
+#[tokio::test]
+async fn test_block_tx_pages_stops_at_error() {
+    let source = PagedSource::new(100, Some(50));
+    let (sizes, error) = collect_pages(block_tx_pages(&source, "ab")).await;
+    assert_eq!(sizes, vec![25, 25]);
+    assert_eq!(error.as_deref(), Some("page 50 unavailable"));
+    assert_eq!(*source.requests.lock().unwrap(), vec![0, 25, 50]);
+}
+
+#[tokio::test]
+async fn test_core_block_fetched_once_per_walk() {
+    let (url, calls) = counting_core_rpc().await;
+    let client = BitcoinCoreClient::new(&url, CoreAuth::None).unwrap();
+
+    let (sizes, error) = collect_pages(block_tx_pages(&client, &"ab".repeat(32))).await;
+    assert_eq!(sizes, vec![25, 5]);
+    assert_eq!(error, None);
+    assert_eq!(*calls.lock().unwrap(), ["getblock"]);
+
+    let mut txids = Vec::new();
+    client
+        .for_each_block_tx(885000, |tx| {
+            txids.push(tx.txid);
+            Ok::<_, Error>(())
+        })
+        .await
+        .unwrap();
+    assert_eq!(txids.len(), 30);
+    assert_eq!(txids[29], "1d".repeat(32));
+    // The first walk learned the block's height, so no getblockhash
+    assert_eq!(*calls.lock().unwrap(), ["getblock", "getblock"]);
+}
+
+#[tokio::test]
+async fn test_transaction_at_fetches_one_page() {
+    let source = PagedSource::new(60, None);
+    let tx = transaction_at(&source, 886000, 30).await.unwrap();
+    assert_eq!(tx.txid, "1e".repeat(32));
+    assert_eq!(*source.requests.lock().unwrap(), vec![25]);
+
+    let first = transaction_at(&source, 886000, 0).await.unwrap();
+    assert_eq!(first.txid, "00".repeat(32));
+}
+
+#[tokio::test]
+async fn test_transaction_at_out_of_range() {
+    let source = PagedSource::new(60, None);
+    let err = transaction_at(&source, 886000, 60).await.unwrap_err();
+    assert!(matches!(err, Error::NotFound(_)));
+    assert_eq!(err.to_string(), "block 886000 has 60 transactions, no index 60");
+
+    let err = transaction_at(&source, 886000, 100).await.unwrap_err();
+    assert_eq!(err.to_string(), "block 886000 has no transaction at index 100");
+}
+
+// ─── Streaming block transactions ────────────────────────────────────────────
+
+/// Mock esplora API serving block 886000 with `tx_count` transactions, paged like
+/// mempool.space.
+async fn serve_paged_block(tx_count: usize) -> String {
+    let hash = "ab".repeat(32);
+    let mut app = Router::new().route("/api/block-height/886000", get(|| async { "ab".repeat(32) }));
+    for start in (0..=tx_count).step_by(BLOCK_TXS_PAGE_SIZE) {
+        let end = (start + BLOCK_TXS_PAGE_SIZE).min(tx_count);
+        let page: Vec<_> = (start..end).map(|i| cache_tx(&format!("{i:02x}"), true)).collect();
+        app = app.route(
+            &format!("/api/block/{hash}/txs/{start}"),
+            get(move || async move { axum::Json(page) }),
+        );
+    }
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+    format!("http://{addr}/api")
+}
+
+#[tokio::test]
+async fn test_for_each_block_tx_sees_every_tx() {
+    let url = serve_paged_block(30).await;
+    let mut seen = Vec::new();
+    fast_client(&url)
+        .for_each_block_tx(886000, |tx| {
+            seen.push(tx.txid);
+            Ok::<_, Error>(())
+        })
+        .await
+        .unwrap();
+    let expected: Vec<_> = (0..30).map(|i| format!("{i:02x}").repeat(32)).collect();
+    assert_eq!(seen, expected);
+}
+
+#[tokio::test]
+async fn test_for_each_block_tx_stops_at_callback_error() {
+    let url = serve_paged_block(30).await;
+    let mut calls = 0;
+    let err = fast_client(&url)
+        .for_each_block_tx(886000, |_| {
+            calls += 1;
+            if calls == 3 {
+                Err(Error::Decode("stop".to_string()))
+            } else {
+                Ok(())
+            }
+        })
+        .await
+        .unwrap_err();
+    assert!(matches!(err, Error::Decode(_)), "{err:?}");
+    assert_eq!(calls, 3);
+}
+
+#[tokio::test]
+async fn test_for_each_block_tx_default_walks_whole_block() {
+    let mut count = 0;
+    PagedSource::new(10, None)
+        .for_each_block_tx(886000, |_| {
+            count += 1;
+            Ok::<_, Error>(())
+        })
+        .await
+        .unwrap();
+    assert_eq!(count, 10);
+}
+
+// ─── Block search by time ────────────────────────────────────────────────────
+
+/// DataSource whose block at height `h` was mined at `times[h]`, counting the blocks
+/// it was asked for.
+struct TimedSource {
+    times: Vec<u64>,
+    block_fetches: AtomicUsize,
+}
+
+impl TimedSource {
+    fn new(times: Vec<u64>) -> Self {
+        Self {
+            times,
+            block_fetches: AtomicUsize::new(0),
+        }
+    }
+}
+
+impl DataSource for TimedSource {
+    async fn get_transaction(&self, txid: &str) -> Result<ApiTransaction> {
+        Err(Error::NotFound(format!("tx not found: {txid}")))
+    }
+
+    async fn get_transaction_hex(&self, _txid: &str) -> Result<String> {
+        Ok("00".to_string())
+    }
+
+    async fn get_block_txs(&self, hash: &str, _start_index: u32) -> Result<Vec<ApiTransaction>> {
+        self.block_fetches.fetch_add(1, Ordering::SeqCst);
+        let height: usize = hash.parse().unwrap();
+        let mut tx = cache_tx("cc", true);
+        tx.status.block_time = Some(self.times[height]);
+        Ok(vec![tx])
+    }
+
+    async fn get_block_tip_height(&self) -> Result<u64> {
+        Ok(self.times.len() as u64 - 1)
+    }
+
+    async fn get_block_hash(&self, height: u64) -> Result<String> {
+        Ok(height.to_string())
+    }
+
+    async fn get_all_block_txs(&self, height: u64) -> Result<Vec<ApiTransaction>> {
+        self.get_block_txs(&height.to_string(), 0).await
+    }
+
+    async fn get_mempool_recent_txids(&self) -> Result<Vec<String>> {
+        Ok(vec![])
+    }
+}
+
+#[tokio::test]
+async fn test_find_height_at_or_after() {
+    // One block every 600 seconds from 1_000_000
+    let source = TimedSource::new((0..1000).map(|h| 1_000_000 + h * 600).collect());
+    assert_eq!(find_height_at_or_after(&source, 1_000_000).await.unwrap(), Some(0));
+    assert_eq!(find_height_at_or_after(&source, 0).await.unwrap(), Some(0));
+    assert_eq!(find_height_at_or_after(&source, 1_060_000).await.unwrap(), Some(100));
+    assert_eq!(find_height_at_or_after(&source, 1_060_001).await.unwrap(), Some(101));
+    assert_eq!(find_height_at_or_after(&source, 1_599_400).await.unwrap(), Some(999));
+}
+
+#[tokio::test]
+async fn test_find_height_uses_binary_search() {
+    let source = TimedSource::new((0..1000).map(|h| 1_000_000 + h * 600).collect());
+    find_height_at_or_after(&source, 1_300_000).await.unwrap();
+    // The tip, then about log2(1000) probes
+    assert!(source.block_fetches.load(Ordering::SeqCst) <= 12);
+}
+
+#[tokio::test]
+async fn test_find_height_in_the_future() {
+    let source = TimedSource::new(vec![1_000_000, 1_000_600, 1_001_200]);
+    assert_eq!(find_height_at_or_after(&source, 1_001_201).await.unwrap(), None);
+    assert_eq!(source.block_fetches.load(Ordering::SeqCst), 1);
+}
+
+// ─── Proxy ───────────────────────────────────────────────────────────────────
+
+#[tokio::test]
+async fn test_requests_go_through_proxy() {
+    // An HTTP proxy receives absolute-form requests, which the mock routes by path
+    let (proxy, hits) = flaky_server(0, StatusCode::OK).await;
+    let proxy = proxy.trim_end_matches("/api");
+    let client = MempoolClient::with_proxy("http://esplora.invalid/api", proxy).unwrap();
+    assert_eq!(client.get_block_tip_height().await.unwrap(), 886000);
+    assert_eq!(hits.load(Ordering::SeqCst), 1);
+}
+
+#[test]
+fn test_invalid_proxy_url() {
+    let err = MempoolClient::with_proxy("http://esplora.invalid/api", "not a url").err().unwrap();
+    assert!(matches!(err, Error::Decode(_)), "{err}");
+    assert!(err.to_string().starts_with("invalid proxy URL not a url"), "{err}");
+}
+
+// ─── Timeouts ────────────────────────────────────────────────────────────────
+
+#[tokio::test]
+async fn test_request_timeout_names_the_request() {
+    let app = Router::new().route(
+        "/api/block-height/886000",
+        get(|| async {
+            tokio::time::sleep(Duration::from_secs(5)).await;
+            "00".repeat(32)
+        }),
+    );
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+    let client = fast_client(&format!("http://{addr}/api"))
+        .with_retry_attempts(1)
+        .with_timeout(Duration::from_millis(100));
+    let err = client.get_block_hash(886000).await.unwrap_err().to_string();
+    assert!(err.contains("/block-height/886000"), "{err}");
+    assert!(err.contains("timed out"), "{err}");
+}
+
+// ─── Rate limiting ───────────────────────────────────────────────────────────
+
+#[tokio::test(start_paused = true)]
+async fn test_rate_limiter_paces_after_burst() {
+    let limiter = RateLimiter::new(10);
+    let start = Instant::now();
+    for _ in 0..10 {
+        limiter.acquire().await;
+    }
+    assert_eq!(start.elapsed(), Duration::ZERO);
+
+    // One token every 100ms after the burst, each wait jittered by at most 10ms
+    let mut last = start;
+    for i in 1..=20 {
+        limiter.acquire().await;
+        let now = Instant::now();
+        let due = start + Duration::from_millis(100 * i);
+        assert!(
+            now >= due && now <= due + Duration::from_millis(10),
+            "token {i} at {:?}",
+            now - start
+        );
+        assert!(now - last <= Duration::from_millis(110));
+        last = now;
+    }
+
+    // Idle for a second refills the bucket
+    tokio::time::sleep(Duration::from_secs(1)).await;
+    let refilled = Instant::now();
+    for _ in 0..10 {
+        limiter.acquire().await;
+    }
+    assert_eq!(refilled.elapsed(), Duration::ZERO);
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_rate_limiter_above_a_billion_per_second() {
+    // The interval would round down to zero
+    let limiter = RateLimiter::new(2_000_000_000);
+    for _ in 0..3 {
+        limiter.acquire().await;
+    }
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_rate_limiter_shared_by_concurrent_tasks() {
+    let limiter = Arc::new(RateLimiter::new(10));
+    let start = Instant::now();
+    let tasks: Vec<_> = (0..4)
+        .map(|_| {
+            let limiter = limiter.clone();
+            tokio::spawn(async move {
+                for _ in 0..10 {
+                    limiter.acquire().await;
+                }
+            })
+        })
+        .collect();
+    for task in tasks {
+        task.await.unwrap();
+    }
+    // 40 requests: a burst of 10, then 30 paced ones
+    let elapsed = start.elapsed();
+    assert!(elapsed >= Duration::from_secs(3), "{elapsed:?}");
+    assert!(elapsed <= Duration::from_millis(3100), "{elapsed:?}");
+}
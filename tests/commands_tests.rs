@@ -0,0 +1,285 @@
+use std::sync::Mutex;
+
+use cltv_scan::api::source::DataSource;
+use cltv_scan::cli::commands::{
+    BatchSummary, classify_batch, classify_block, monitor_transaction, range_stats, scan_mempool,
+    security_scan, watch_block,
+};
+use cltv_scan::cli::progress::{ScanProgress, Verbosity};
+use cltv_scan::error::Error;
+use cltv_scan::lightning::types::{Confidence, DetectorConfig, LightningTxType};
+use cltv_scan::security::types::{DetectionType, SecurityConfig, Severity};
+use cltv_scan::testing::*;
+use cltv_scan::timelock::types::ChainTip;
+
+// ─── Mock source ─────────────────────────────────────────────────────────────
+
+#[tokio::test]
+async fn test_mock_source_serves_blocks_in_pages() {
+    let txs: Vec<_> = (0..30).map(|i| payment_tx(&format!("tx{i:02}"))).collect();
+    let source = MockSource::new().with_block(886000, txs);
+
+    let hash = source.get_block_hash(886000).await.unwrap();
+    assert_eq!(source.get_block_txs(&hash, 0).await.unwrap().len(), 25);
+    assert_eq!(source.get_block_txs(&hash, 25).await.unwrap().len(), 5);
+    assert_eq!(source.get_all_block_txs(886000).await.unwrap().len(), 30);
+    assert_eq!(source.get_block_tip_height().await.unwrap(), 886000);
+
+    let tx = source.get_transaction("tx07").await.unwrap();
+    assert_eq!(tx.status.block_height, Some(886000));
+    assert!(matches!(source.get_all_block_txs(886001).await, Err(Error::NotFound(_))));
+}
+
+// ─── scan ────────────────────────────────────────────────────────────────────
+
+#[tokio::test]
+async fn test_security_scan_end_to_end() {
+    let source = MockSource::new()
+        .with_block(886000, vec![payment_tx("pay"), commitment_tx("commit")])
+        .with_block(886001, vec![htlc_timeout_tx("timeout", 886010)])
+        .with_tip(886005);
+
+    let seen = Mutex::new(Vec::new());
+    let alerts = security_scan(
+        &source,
+        886000,
+        886001,
+        &SecurityConfig::default(),
+        &DetectorConfig::default(),
+        Verbosity::Quiet,
+        |lc| seen.lock().unwrap().push(lc.tx_type),
+    )
+    .await
+    .unwrap();
+
+    let seen = seen.into_inner().unwrap();
+    assert_eq!(seen.len(), 3);
+    assert!(seen.contains(&Some(LightningTxType::Commitment)));
+    assert!(seen.contains(&Some(LightningTxType::HtlcTimeout)));
+
+    // The HTLC-timeout's CLTV is 5 blocks out and its locktime hasn't matured
+    assert!(alerts.iter().all(|a| a.txid == "timeout"));
+    assert_eq!(alerts[0].severity, Severity::Critical);
+    assert_eq!(alerts[0].detection_type, DetectionType::ShortCltvDelta);
+    let maturity = alerts
+        .iter()
+        .find(|a| a.detection_type == DetectionType::HtlcTimeoutMaturity)
+        .unwrap();
+    assert_eq!(maturity.severity, Severity::Warning);
+}
+
+#[tokio::test]
+async fn test_security_scan_missing_block_fails() {
+    let source = MockSource::new().with_block(886000, vec![payment_tx("pay")]);
+    let result = security_scan(
+        &source,
+        886000,
+        886001,
+        &SecurityConfig::default(),
+        &DetectorConfig::default(),
+        Verbosity::Quiet,
+        |_| {},
+    )
+    .await;
+    assert!(matches!(result, Err(Error::NotFound(_))));
+}
+
+// ─── stats ───────────────────────────────────────────────────────────────────
+
+#[tokio::test]
+async fn test_range_stats_end_to_end() {
+    let source = MockSource::new()
+        .with_block(886000, vec![payment_tx("pay"), commitment_tx("commit")])
+        .with_block(886001, vec![htlc_success_tx("success", &"ab".repeat(32))]);
+
+    let progress = ScanProgress::new(886000, 886001, false, Verbosity::Quiet);
+    let stats = range_stats(&source, 886000, 886001, &DetectorConfig::default(), &progress, |_, _| {})
+        .await
+        .unwrap();
+
+    let report = stats.report(886000, 886001);
+    assert_eq!(report.transactions, 3);
+    assert_eq!(report.lightning.commitments, 1);
+    assert_eq!(report.lightning.htlc_successes, 1);
+    assert_eq!(report.preimages_revealed, 1);
+}
+
+// ─── batch ───────────────────────────────────────────────────────────────────
+
+#[tokio::test]
+async fn test_classify_batch_reports_failures_and_keeps_going() {
+    let source = MockSource::new()
+        .with_transaction(commitment_tx("commit"))
+        .with_transaction(htlc_timeout_tx("timeout", 886100));
+    let txids = ["commit", "missing", "timeout"].map(String::from);
+    let tip = ChainTip {
+        height: 886000,
+        median_time_past: None,
+    };
+
+    let mut results = Vec::new();
+    let summary = classify_batch(
+        &source,
+        &txids,
+        Some(&tip),
+        &DetectorConfig::default(),
+        Verbosity::Quiet,
+        |txid, result| {
+            results.push((txid.to_string(), result));
+            Ok::<_, Error>(())
+        },
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(
+        summary,
+        BatchSummary {
+            succeeded: 2,
+            failed: 1
+        }
+    );
+    let order: Vec<_> = results.iter().map(|(txid, _)| txid.as_str()).collect();
+    assert_eq!(order, ["commit", "missing", "timeout"]);
+    assert!(matches!(results[1].1, Err(Error::NotFound(_))));
+
+    let timeout = results[2].1.as_ref().unwrap();
+    assert_eq!(timeout.tx_type, Some(LightningTxType::HtlcTimeout));
+    assert!(timeout.reasons.contains(&"HTLC-timeout locktime 100 blocks in the future".to_string()));
+}
+
+// ─── mempool ─────────────────────────────────────────────────────────────────
+
+#[tokio::test]
+async fn test_scan_mempool_end_to_end() {
+    let mut source = MockSource::new()
+        .with_block(886000, vec![payment_tx("confirmed")])
+        .with_mempool(vec![
+            payment_tx("pay"),
+            commitment_tx("commit"),
+            htlc_timeout_tx("timeout", 886100),
+        ]);
+    // Listed, then evicted before it was fetched
+    source.mempool.push("evicted".to_string());
+
+    let mut hits = Vec::new();
+    let detector = DetectorConfig::default();
+    let found = scan_mempool(&source, None, &detector, None, Verbosity::Quiet, |txid, lc| {
+        hits.push((txid.to_string(), lc.tx_type));
+        Ok::<_, Error>(())
+    })
+    .await
+    .unwrap();
+
+    assert_eq!(found, 2);
+    assert_eq!(
+        hits,
+        vec![
+            ("commit".to_string(), Some(LightningTxType::Commitment)),
+            ("timeout".to_string(), Some(LightningTxType::HtlcTimeout)),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn test_scan_mempool_min_confidence_and_callback_error() {
+    let source = MockSource::new().with_mempool(vec![
+        commitment_tx("commit"),
+        htlc_success_tx("success", &"ab".repeat(32)),
+    ]);
+
+    let mut hits = Vec::new();
+    let result = scan_mempool(
+        &source,
+        None,
+        &DetectorConfig::default(),
+        Some(Confidence::HighlyLikely),
+        Verbosity::Quiet,
+        |txid, _| {
+            hits.push(txid.to_string());
+            Err(Error::Decode("stop".to_string()))
+        },
+    )
+    .await;
+
+    assert!(matches!(result, Err(Error::Decode(_))));
+    assert_eq!(hits, ["commit"]);
+}
+
+#[tokio::test]
+async fn test_scan_mempool_strict_preset() {
+    // Locktime marker and anchors score 3, below the strict threshold of 4
+    let weak = make_tx(
+        "weak",
+        0x2000_0042,
+        vec![make_vin(0xFFFF_FFFD)],
+        vec![make_vout(100_000, "v0_p2wsh"), make_vout(330, "v0_p2wsh")],
+    );
+    let source = MockSource::new().with_mempool(vec![weak, commitment_tx("commit")]);
+
+    let mut hits = Vec::new();
+    let found = scan_mempool(
+        &source,
+        None,
+        &DetectorConfig::strict(),
+        Some(Confidence::HighlyLikely),
+        Verbosity::Quiet,
+        |txid, _| {
+            hits.push(txid.to_string());
+            Ok::<_, Error>(())
+        },
+    )
+    .await
+    .unwrap();
+    assert_eq!(found, 1);
+    assert_eq!(hits, ["commit"]);
+}
+
+// ─── lightning block ─────────────────────────────────────────────────────────
+
+#[tokio::test]
+async fn test_classify_block_links_same_block_sweeps() {
+    let mut timeout = htlc_timeout_tx("timeout", 886010);
+    timeout.vin[0].txid = Some("commit".to_string());
+    let txs = vec![payment_tx("pay"), commitment_tx("commit"), timeout];
+    let source = MockSource::new().with_block(886000, txs.clone());
+
+    let progress = ScanProgress::new(886000, 886000, false, Verbosity::Quiet);
+    let results = classify_block(&source, &txs, None, &DetectorConfig::default(), false, &progress)
+        .await
+        .unwrap();
+
+    let order: Vec<_> = results.iter().map(|(txid, _)| txid.as_str()).collect();
+    assert_eq!(order, ["pay", "commit", "timeout"]);
+    assert_eq!(results[1].1.tx_type, Some(LightningTxType::Commitment));
+    assert_eq!(results[1].1.params.same_block_htlc_spends, ["timeout"]);
+}
+
+// ─── monitor and watch ───────────────────────────────────────────────────────
+
+#[test]
+fn test_monitor_transaction_skips_plain_payments() {
+    let detector = DetectorConfig::default();
+    let config = SecurityConfig::default();
+    let tx = payment_tx("pay");
+    assert!(monitor_transaction(&tx, 886005, &detector, &config, Severity::Informational).is_none());
+
+    // The CLTV is 5 blocks past the tip: critical
+    let tx = htlc_timeout_tx("timeout", 886010);
+    let hit = monitor_transaction(&tx, 886005, &detector, &config, Severity::Critical).unwrap();
+    assert_eq!(hit.lightning.tx_type, Some(LightningTxType::HtlcTimeout));
+    assert!(!hit.alerts.is_empty());
+    assert!(hit.alerts.iter().all(|a| a.severity == Severity::Critical));
+}
+
+#[test]
+fn test_watch_block_timelocks_on_request() {
+    let txs = vec![payment_tx("pay"), commitment_tx("commit")];
+    let detector = DetectorConfig::default();
+
+    let block = watch_block(&txs, &detector, false);
+    assert_eq!(block.lightning.len(), 2);
+    assert_eq!(block.lightning[1].1.tx_type, Some(LightningTxType::Commitment));
+    assert!(block.timelocks.is_empty());
+    assert_eq!(watch_block(&txs, &detector, true).timelocks.len(), 2);
+}
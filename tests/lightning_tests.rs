@@ -1,6 +1,25 @@
+use bitcoin::hashes::{Hash, hash160, sha256};
+use bitcoin::hex::DisplayHex;
+use bitcoin::opcodes::all::*;
+use bitcoin::script::Builder;
+use bitcoin::transaction::Version;
+use bitcoin::{
+    Amount, Network, OutPoint, ScriptBuf, Sequence, Transaction, TxIn, TxOut, Witness, absolute,
+};
+
+use cltv_scan::api::raw::api_transaction_with_prevouts;
 use cltv_scan::api::types::*;
-use cltv_scan::lightning::detector::classify_lightning;
+use cltv_scan::cli::sort::{SortKey, sort_lightning};
+use cltv_scan::lightning::channel::{cluster_htlc_spends, correlate, link_same_block_sweeps};
+use cltv_scan::lightning::detector::{
+    classify_lightning, classify_lightning_with_config, classify_lightning_with_context,
+    classify_lightning_with_tip, explain_lightning, explain_lightning_with_config,
+};
+use cltv_scan::lightning::heuristic::{
+    CommitmentHeuristic, HeuristicMatch, HtlcHeuristic, LightningHeuristic,
+};
 use cltv_scan::lightning::types::*;
+use cltv_scan::timelock::types::ChainTip;
 
 // ─── Test helpers ────────────────────────────────────────────────────────────
 
@@ -107,13 +126,42 @@ fn test_commitment_without_anchors_is_possible() {
     );
     let result = classify_lightning(&tx);
     assert_eq!(result.tx_type, Some(LightningTxType::Commitment));
-    // locktime + sequence match = at least Possible, could be HighlyLikely
-    assert!(result.confidence >= Confidence::Possible);
+    // locktime + sequence without anchors stays Possible
+    assert_eq!(result.confidence, Confidence::Possible);
     assert!(result.commitment_signals.locktime_match);
     assert!(result.commitment_signals.sequence_match);
     assert!(!result.commitment_signals.has_anchor_outputs);
 }
 
+#[test]
+fn test_commitment_sequence_and_anchors_is_highly_likely() {
+    // Anchors count double, so one marker plus anchors is enough
+    let tx = make_tx(
+        0,
+        vec![make_vin(0x80000005)],
+        vec![
+            make_vout(100_000, "v0_p2wsh"),
+            make_vout(330, "v0_p2wsh"),
+            make_vout(330, "v0_p2wsh"),
+        ],
+    );
+    let result = classify_lightning(&tx);
+    assert_eq!(result.tx_type, Some(LightningTxType::Commitment));
+    assert_eq!(result.confidence, Confidence::HighlyLikely);
+}
+
+#[test]
+fn test_commitment_anchors_only_is_possible() {
+    let tx = make_tx(
+        0,
+        vec![make_vin(0xFFFFFFFD)],
+        vec![make_vout(100_000, "v0_p2wpkh"), make_vout(330, "v0_p2wsh")],
+    );
+    let result = classify_lightning(&tx);
+    assert_eq!(result.tx_type, Some(LightningTxType::Commitment));
+    assert_eq!(result.confidence, Confidence::Possible);
+}
+
 #[test]
 fn test_commitment_locktime_only_is_possible() {
     // Only locktime matches, sequence is standard → Possible at most
@@ -177,190 +225,2557 @@ fn test_commitment_number_extraction() {
     assert_eq!(result.params.commitment_number, Some(expected));
 }
 
-// ─── HTLC-timeout detection ─────────────────────────────────────────────────
+// ─── Channel type ────────────────────────────────────────────────────────────
 
 #[test]
-fn test_htlc_timeout_detection() {
-    // HTLC-timeout: nLockTime = realistic block height, no 32-byte preimage in witness
-    let locktime: u32 = 886100; // realistic block height
-    let mut vin = make_vin(0);
-    // Witness without a 32-byte (64 hex char) element
-    vin.witness = Some(vec![
-        "".to_string(),           // empty (no preimage)
-        "3045".to_string(),       // signature (not 64 chars)
-        "00".to_string(),         // some script element
-    ]);
-    vin.inner_witnessscript_asm = Some(
-        "OP_DUP OP_HASH160 abc123 OP_EQUALVERIFY OP_CHECKSIG OP_IF 886100 OP_CHECKLOCKTIMEVERIFY OP_DROP OP_ENDIF 1 OP_CHECKSEQUENCEVERIFY".to_string()
+fn test_legacy_channel_commitment() {
+    // No anchors, to_remote paid straight to P2WPKH
+    let tx = make_tx(
+        0x20000100,
+        vec![make_vin(0x80000005)],
+        vec![
+            make_vout(100_000, "v0_p2wsh"),
+            make_vout(200_000, "v0_p2wpkh"),
+            make_vout(20_000, "v0_p2wsh"),
+        ],
     );
+    let result = classify_lightning(&tx);
+    assert_eq!(result.tx_type, Some(LightningTxType::Commitment));
+    assert_eq!(result.commitment_signals.channel_type, ChannelType::Legacy);
+    assert!(result.reasons.iter().any(|r| r.contains("legacy channel")));
+}
+
+#[test]
+fn test_anchor_channel_commitment() {
     let tx = make_tx(
-        locktime,
-        vec![vin],
-        vec![make_vout(50_000, "v0_p2wsh")],
+        0x20000100,
+        vec![make_vin(0x80000005)],
+        vec![
+            make_vout(100_000, "v0_p2wsh"),
+            make_vout(200_000, "v0_p2wsh"),
+            make_vout(330, "v0_p2wsh"),
+            make_vout(330, "v0_p2wsh"),
+        ],
     );
     let result = classify_lightning(&tx);
-    assert_eq!(result.tx_type, Some(LightningTxType::HtlcTimeout));
-    assert!(!result.htlc_signals.has_preimage);
-    assert!(result.htlc_signals.script_has_cltv);
-    assert_eq!(result.params.cltv_expiry, Some(886100));
+    assert_eq!(result.commitment_signals.channel_type, ChannelType::Anchor);
+    assert!(!result.reasons.iter().any(|r| r.contains("legacy channel")));
 }
 
 #[test]
-fn test_htlc_timeout_no_preimage_empty_witness_element() {
-    // HTLC-timeout with empty string at preimage position
-    let locktime: u32 = 886200;
-    let mut vin = make_vin(0);
-    vin.witness = Some(vec![
-        "".to_string(),
-        "3044022000".to_string(),
-    ]);
-    vin.inner_witnessscript_asm = Some(
-        "OP_SIZE 32 OP_EQUAL OP_IF OP_HASH160 abc OP_ELSE 886200 OP_CHECKLOCKTIMEVERIFY OP_DROP OP_ENDIF 1 OP_CHECKSEQUENCEVERIFY".to_string()
+fn test_taproot_channel_commitment() {
+    let tx = make_tx(
+        0x20000100,
+        vec![make_vin(0x80000005)],
+        vec![
+            make_vout(100_000, "v1_p2tr"),
+            make_vout(200_000, "v1_p2tr"),
+            make_vout(330, "v1_p2tr"),
+        ],
     );
+    let result = classify_lightning(&tx);
+    assert_eq!(result.commitment_signals.channel_type, ChannelType::Taproot);
+}
+
+#[test]
+fn test_channel_type_unknown_without_markers_or_to_remote() {
+    let payment = make_tx(0, vec![make_vin(0xFFFFFFFF)], vec![make_vout(50_000, "v0_p2wpkh")]);
+    let result = classify_lightning(&payment);
+    assert_eq!(result.commitment_signals.channel_type, ChannelType::Unknown);
+
+    // Markers, but only P2WSH outputs: the remote balance may have been trimmed
     let tx = make_tx(
-        locktime,
-        vec![vin],
-        vec![make_vout(50_000, "v0_p2wsh")],
+        0x20000100,
+        vec![make_vin(0x80000005)],
+        vec![make_vout(100_000, "v0_p2wsh"), make_vout(20_000, "v0_p2wsh")],
     );
     let result = classify_lightning(&tx);
-    assert_eq!(result.tx_type, Some(LightningTxType::HtlcTimeout));
-    assert!(!result.htlc_signals.has_preimage);
+    assert_eq!(result.commitment_signals.channel_type, ChannelType::Unknown);
 }
 
-// ─── HTLC-success detection ─────────────────────────────────────────────────
+// ─── Simple taproot channel commitments ─────────────────────────────────────
 
 #[test]
-fn test_htlc_success_detection() {
-    // HTLC-success: nLockTime = 0, 32-byte preimage in witness
-    let preimage = "ab".repeat(32); // 64 hex chars = 32 bytes
-    let mut vin = make_vin(0);
-    vin.witness = Some(vec![
-        preimage.clone(),
-        "3045".to_string(),
-    ]);
-    vin.inner_witnessscript_asm = Some(
-        "OP_SIZE 32 OP_EQUAL OP_IF OP_HASH160 abc OP_EQUALVERIFY OP_CHECKSIG OP_ELSE 1 OP_CHECKSEQUENCEVERIFY OP_DROP OP_ENDIF".to_string()
-    );
+fn test_taproot_commitment_detected() {
     let tx = make_tx(
-        0, // locktime = 0 for HTLC-success
-        vec![vin],
-        vec![make_vout(50_000, "v0_p2wsh")],
+        0x20000042,
+        vec![make_vin(0x80000001)],
+        vec![
+            make_vout(100_000, "v1_p2tr"), // to_local
+            make_vout(200_000, "v1_p2tr"), // to_remote
+            make_vout(330, "v1_p2tr"),     // anchor
+            make_vout(330, "v1_p2tr"),     // anchor
+        ],
     );
     let result = classify_lightning(&tx);
-    assert_eq!(result.tx_type, Some(LightningTxType::HtlcSuccess));
-    assert!(result.htlc_signals.has_preimage);
-    assert_eq!(result.htlc_signals.preimage, Some(preimage));
-    assert!(result.params.preimage_revealed);
-    assert!(result.params.preimage.is_some());
+    assert_eq!(result.tx_type, Some(LightningTxType::Commitment));
+    assert_eq!(result.confidence, Confidence::HighlyLikely);
+    assert!(result.commitment_signals.is_taproot);
+    assert_eq!(result.commitment_signals.anchor_output_count, 2);
+    assert_eq!(
+        result.params.output_roles,
+        vec![OutputRole::Taproot, OutputRole::Taproot, OutputRole::Anchor, OutputRole::Anchor]
+    );
+    assert_eq!(result.params.htlc_output_count, None);
 }
 
 #[test]
-fn test_htlc_success_preimage_must_be_hex() {
-    // 64 chars but not valid hex → should NOT be detected as preimage
-    let not_hex = "zz".repeat(32);
-    let mut vin = make_vin(0);
-    vin.witness = Some(vec![not_hex, "3045".to_string()]);
-    vin.inner_witnessscript_asm = Some(
-        "OP_SIZE 32 OP_EQUAL OP_IF OP_HASH160 abc OP_EQUALVERIFY OP_ENDIF 1 OP_CHECKSEQUENCEVERIFY".to_string()
+fn test_taproot_commitment_sequence_only() {
+    // Sequence marker plus taproot anchors is enough on its own
+    let tx = make_tx(
+        0,
+        vec![make_vin(0x80000001)],
+        vec![make_vout(100_000, "v1_p2tr"), make_vout(330, "v1_p2tr")],
     );
-    let tx = make_tx(0, vec![vin], vec![make_vout(50_000, "v0_p2wsh")]);
     let result = classify_lightning(&tx);
-    assert!(!result.htlc_signals.has_preimage);
+    assert_eq!(result.tx_type, Some(LightningTxType::Commitment));
+    assert_eq!(result.confidence, Confidence::HighlyLikely);
+    assert!(result.commitment_signals.has_anchor_outputs);
 }
 
-// ─── HTLC CSV delay extraction ──────────────────────────────────────────────
-
 #[test]
-fn test_csv_delay_extraction_from_htlc() {
-    // Both HTLC-timeout and HTLC-success outputs have CSV-delayed scripts
-    // The CSV value = to_self_delay (revocation window)
-    let mut vin = make_vin(0);
-    vin.witness = Some(vec!["".to_string(), "3045".to_string()]);
-    vin.inner_witnessscript_asm = Some(
-        "OP_IF abc OP_ELSE 144 OP_CHECKSEQUENCEVERIFY OP_DROP def OP_ENDIF".to_string()
+fn test_330_sat_p2tr_output_alone_not_anchor() {
+    // Without commitment markers a 330-sat P2TR output is just a small payment
+    let tx = make_tx(
+        0,
+        vec![make_vin(0xFFFFFFFD)],
+        vec![make_vout(100_000, "v1_p2tr"), make_vout(330, "v1_p2tr")],
     );
-    let tx = make_tx(886300, vec![vin], vec![make_vout(50_000, "v0_p2wsh")]);
     let result = classify_lightning(&tx);
-    assert!(result.params.csv_delays.contains(&144));
+    assert!(result.commitment_signals.is_taproot);
+    assert!(!result.commitment_signals.has_anchor_outputs);
+    assert_eq!(result.tx_type, None);
 }
 
-// ─── Anchor output counting ─────────────────────────────────────────────────
+#[test]
+fn test_mixed_outputs_not_taproot() {
+    let tx = make_tx(
+        0x20000042,
+        vec![make_vin(0x80000001)],
+        vec![make_vout(100_000, "v0_p2wsh"), make_vout(200_000, "v1_p2tr")],
+    );
+    assert!(!classify_lightning(&tx).commitment_signals.is_taproot);
+}
+
+// ─── Detector configuration ─────────────────────────────────────────────────
 
 #[test]
-fn test_single_anchor_output() {
-    let locktime: u32 = 0x20000001;
-    let sequence: u32 = 0x80000001;
+fn test_anchor_value_follows_config() {
+    // A taproot commitment whose anchors pay 240 sats instead of 330
     let tx = make_tx(
-        locktime,
-        vec![make_vin(sequence)],
-        vec![
-            make_vout(100_000, "v0_p2wsh"),
-            make_vout(330, "v0_p2wsh"), // single anchor
-        ],
+        0,
+        vec![make_vin(0x80000001)],
+        vec![make_vout(100_000, "v1_p2tr"), make_vout(240, "v1_p2tr")],
     );
-    let result = classify_lightning(&tx);
+    let default = classify_lightning(&tx);
+    assert!(!default.commitment_signals.has_anchor_outputs);
+    assert_eq!(default.confidence, Confidence::Possible);
+
+    let config = DetectorConfig { anchor_value: 240, ..DetectorConfig::default() };
+    let result = classify_lightning_with_config(&tx, &config);
     assert!(result.commitment_signals.has_anchor_outputs);
-    assert_eq!(result.commitment_signals.anchor_output_count, 1);
+    assert_eq!(result.commitment_signals.anchor_value, Some(240));
+    assert_eq!(result.confidence, Confidence::HighlyLikely);
 }
 
-// ─── Edge cases ──────────────────────────────────────────────────────────────
-
 #[test]
-fn test_coinbase_tx_not_lightning() {
-    let mut vin = make_vin(0xFFFFFFFF);
-    vin.is_coinbase = true;
-    vin.txid = None;
+fn test_marker_bytes_follow_config() {
     let tx = make_tx(
+        0x21000042,
+        vec![make_vin(0x81000001)],
+        vec![make_vout(100_000, "v0_p2wsh"), make_vout(330, "v0_p2wsh")],
+    );
+    let default = classify_lightning(&tx);
+    assert!(!default.commitment_signals.locktime_match);
+    assert!(!default.commitment_signals.sequence_match);
+
+    let config = DetectorConfig {
+        locktime_upper_byte: 0x21,
+        sequence_upper_byte: 0x81,
+        ..DetectorConfig::default()
+    };
+    let result = classify_lightning_with_config(&tx, &config);
+    assert!(result.commitment_signals.locktime_match);
+    assert!(result.commitment_signals.sequence_match);
+    assert!(result.reasons.contains(&"locktime upper byte 0x21".to_string()));
+}
+
+#[test]
+fn test_default_config_matches_classify_lightning() {
+    let tx = bolt3_commitment_tx();
+    let result = classify_lightning_with_config(&tx, &DetectorConfig::default());
+    assert_eq!(result.confidence, classify_lightning(&tx).confidence);
+    assert_eq!(result.reasons, classify_lightning(&tx).reasons);
+}
+
+#[test]
+fn test_strict_and_lenient_presets() {
+    // Both markers, no anchors: score 2
+    let markers = make_tx(
         0x20000042,
-        vec![vin],
-        vec![make_vout(312_500_000, "v1_p2tr")],
+        vec![make_vin(0x80000001)],
+        vec![make_vout(100_000, "v0_p2wsh"), make_vout(50_000, "v0_p2wpkh")],
     );
-    let result = classify_lightning(&tx);
-    // Coinbase should not be classified as Lightning even if locktime happens to match
-    assert_eq!(result.tx_type, None);
+    let confidence = |tx, config| classify_lightning_with_config(tx, &config).confidence;
+    assert_eq!(confidence(&markers, DetectorConfig::strict()), Confidence::Possible);
+    assert_eq!(confidence(&markers, DetectorConfig::default()), Confidence::Possible);
+    assert_eq!(confidence(&markers, DetectorConfig::lenient()), Confidence::HighlyLikely);
+
+    // Locktime marker and anchors: score 3
+    let anchored = make_tx(
+        0x20000042,
+        vec![make_vin(0xFFFFFFFD)],
+        vec![make_vout(100_000, "v0_p2wsh"), make_vout(330, "v0_p2wsh")],
+    );
+    assert_eq!(confidence(&anchored, DetectorConfig::strict()), Confidence::Possible);
+    assert_eq!(confidence(&anchored, DetectorConfig::default()), Confidence::HighlyLikely);
+
+    // A lone sequence marker: score 1
+    let lone = make_tx(0, vec![make_vin(0x80000001)], vec![make_vout(100_000, "v0_p2wsh")]);
+    let strict = classify_lightning_with_config(&lone, &DetectorConfig::strict());
+    assert_eq!(strict.tx_type, None);
+    assert_eq!(confidence(&lone, DetectorConfig::lenient()), Confidence::Possible);
+}
+
+// ─── Registered heuristics ──────────────────────────────────────────────────
+
+/// Flags a single 240-sat P2TR output, a regtest setup's anchor, as a commitment.
+#[derive(Debug)]
+struct LoneAnchor;
+
+impl LightningHeuristic for LoneAnchor {
+    fn evaluate(&self, tx: &ApiTransaction) -> Option<HeuristicMatch> {
+        let anchors = tx.vout.iter().filter(|o| o.value == 240 && o.scriptpubkey_type == "v1_p2tr");
+        (anchors.count() == 1).then(|| {
+            let reasons = vec!["lone 240-sat anchor".to_string()];
+            (LightningTxType::Commitment, Confidence::Possible, reasons)
+        })
+    }
 }
 
 #[test]
-fn test_commitment_takes_priority_over_htlc() {
-    // A tx that has both commitment signals (locktime 0x20, sequence 0x80)
-    // and a witness with 32-byte element should be classified as commitment, not HTLC
-    let preimage = "cc".repeat(32);
-    let mut vin = make_vin(0x80000001);
-    vin.witness = Some(vec![preimage, "3045".to_string()]);
+fn test_custom_heuristic_classifies() {
+    let tx = make_tx(
+        0,
+        vec![make_vin(0xFFFFFFFD)],
+        vec![make_vout(100_000, "v0_p2wpkh"), make_vout(240, "v1_p2tr")],
+    );
+    assert_eq!(classify_lightning(&tx).tx_type, None);
+
+    let config = DetectorConfig::default().with_heuristic(LoneAnchor);
+    let result = classify_lightning_with_config(&tx, &config);
+    assert_eq!(result.tx_type, Some(LightningTxType::Commitment));
+    assert_eq!(result.confidence, Confidence::Possible);
+    assert_eq!(result.reasons, ["lone 240-sat anchor"]);
+
+    let (_, trace) = explain_lightning_with_config(&tx, &config);
+    let step = trace.steps.last().unwrap();
+    assert_eq!(step.check, "registered heuristics");
+    assert!(step.matched);
+    assert_eq!(step.details, ["heuristic 1 of 1 matched -> possible"]);
+}
+
+#[test]
+fn test_heuristics_run_in_priority_order() {
+    // Anchors and both markers: the built-in commitment check says highly likely
     let tx = make_tx(
         0x20000042,
-        vec![vin],
+        vec![make_vin(0x80000001)],
         vec![
             make_vout(100_000, "v0_p2wsh"),
-            make_vout(330, "v0_p2wsh"),
+            make_vout(240, "v1_p2tr"),
             make_vout(330, "v0_p2wsh"),
         ],
     );
-    let result = classify_lightning(&tx);
+    let builtin = classify_lightning(&tx);
+    assert_eq!(builtin.confidence, Confidence::HighlyLikely);
+
+    let custom_first = DetectorConfig::default()
+        .with_heuristic(LoneAnchor)
+        .with_heuristic(CommitmentHeuristic);
+    let result = classify_lightning_with_config(&tx, &custom_first);
+    assert_eq!(result.confidence, Confidence::Possible);
+    // Same type: the custom reason joins the built-in ones
+    assert_eq!(result.reasons.last().unwrap(), "lone 240-sat anchor");
+    assert_eq!(result.reasons.len(), builtin.reasons.len() + 1);
+    assert_eq!(result.params.commitment_number, builtin.params.commitment_number);
+
+    let builtin_first = DetectorConfig::default()
+        .with_heuristic(CommitmentHeuristic)
+        .with_heuristic(LoneAnchor);
+    let result = classify_lightning_with_config(&tx, &builtin_first);
+    assert_eq!(result.confidence, Confidence::HighlyLikely);
+    assert_eq!(result.reasons, builtin.reasons);
+}
+
+#[test]
+fn test_builtin_heuristics_match_classifier() {
+    let commitment = bolt3_commitment_tx();
+    let (tx_type, confidence, reasons) = CommitmentHeuristic.evaluate(&commitment).unwrap();
+    let builtin = classify_lightning(&commitment);
+    assert_eq!(Some(tx_type), builtin.tx_type);
+    assert_eq!(confidence, builtin.confidence);
+    assert_eq!(reasons, builtin.reasons);
+    assert!(HtlcHeuristic.evaluate(&commitment).is_none());
+
+    let htlc = make_tx(886100, vec![make_htlc_timeout_vin()], vec![make_vout(50_000, "v0_p2wsh")]);
+    let (tx_type, confidence, reasons) = HtlcHeuristic.evaluate(&htlc).unwrap();
+    let builtin = classify_lightning(&htlc);
+    assert_eq!(tx_type, LightningTxType::HtlcTimeout);
+    assert_eq!(confidence, builtin.confidence);
+    assert_eq!(reasons, builtin.reasons);
+    assert!(CommitmentHeuristic.evaluate(&htlc).is_none());
+}
+
+#[test]
+fn test_no_heuristic_match_keeps_builtin_result() {
+    let tx = bolt3_commitment_tx();
+    let config = DetectorConfig::default()
+        .with_heuristic(LoneAnchor)
+        .with_heuristic(HtlcHeuristic);
+    let result = classify_lightning_with_config(&tx, &config);
+    let builtin = classify_lightning(&tx);
+    assert_eq!(result.tx_type, builtin.tx_type);
+    assert_eq!(result.confidence, builtin.confidence);
+    assert_eq!(result.reasons, builtin.reasons);
+}
+
+// ─── Commitment number decoding (BOLT 3 test vectors) ───────────────────────
+
+fn bolt3_context() -> ChannelContext {
+    ChannelContext {
+        opener_payment_basepoint: "034f355bdcb7cc0af728ef3cceb9615d90684bb5b2ca5f859ab0f0b704075871aa"
+            .parse()
+            .unwrap(),
+        accepter_payment_basepoint: "032c0b7cf95324a07d05398b240174dc0c2be444d96b159aa6c7f7b1e668680991"
+            .parse()
+            .unwrap(),
+    }
+}
+
+fn bolt3_commitment_tx() -> ApiTransaction {
+    // BOLT 3 Appendix C: commitment number 42, obscuring factor 0x2bb038521914
+    make_tx(
+        542251326,
+        vec![make_vin(2150346808)],
+        vec![
+            make_vout(3_000_000, "v0_p2wpkh"),
+            make_vout(6_989_140, "v0_p2wsh"),
+        ],
+    )
+}
+
+#[test]
+fn test_commitment_number_decoded_with_bolt3_basepoints() {
+    let result = classify_lightning_with_context(&bolt3_commitment_tx(), &bolt3_context());
     assert_eq!(result.tx_type, Some(LightningTxType::Commitment));
+    assert_eq!(result.params.commitment_number, Some(0x2bb038521914 ^ 42));
+    assert_eq!(result.params.commitment_number_decoded, Some(42));
 }
 
 #[test]
-fn test_htlc_output_count_on_commitment() {
-    // Commitment with 3 HTLC outputs (P2WSH, not anchors, not to_remote P2WPKH)
-    let locktime: u32 = 0x20000001;
-    let sequence: u32 = 0x80000001;
+fn test_commitment_number_not_decoded_without_context() {
+    let result = classify_lightning(&bolt3_commitment_tx());
+    assert!(result.params.commitment_number.is_some());
+    assert_eq!(result.params.commitment_number_decoded, None);
+}
+
+#[test]
+fn test_commitment_number_decoding_requires_commitment() {
     let tx = make_tx(
-        locktime,
-        vec![make_vin(sequence)],
+        0,
+        vec![make_vin(0xFFFFFFFF)],
+        vec![make_vout(50_000, "v0_p2wpkh")],
+    );
+    let result = classify_lightning_with_context(&tx, &bolt3_context());
+    assert_eq!(result.params.commitment_number_decoded, None);
+}
+
+// ─── Channel correlation ────────────────────────────────────────────────────
+
+/// Commitment `number` of the BOLT 3 test channel, obscured with its factor.
+fn bolt3_commitment(number: u64) -> ApiTransaction {
+    let obscured = 0x2bb038521914 ^ number;
+    make_tx(
+        0x20000000 | (obscured & 0xFFFFFF) as u32,
+        vec![make_vin(0x80000000 | (obscured >> 24) as u32)],
         vec![
-            make_vout(100_000, "v0_p2wsh"),   // to_local
-            make_vout(200_000, "v0_p2wpkh"),  // to_remote
-            make_vout(330, "v0_p2wsh"),        // anchor
-            make_vout(330, "v0_p2wsh"),        // anchor
-            make_vout(50_000, "v0_p2wsh"),     // HTLC 1
-            make_vout(60_000, "v0_p2wsh"),     // HTLC 2
-            make_vout(70_000, "v0_p2wsh"),     // HTLC 3
+            make_vout(3_000_000, "v0_p2wpkh"),
+            make_vout(6_989_140, "v0_p2wsh"),
+            make_vout(330, "v0_p2wsh"),
+        ],
+    )
+}
+
+#[test]
+fn test_correlate_links_commitments_of_one_channel() {
+    let context = bolt3_context();
+    let other_channel = make_tx(
+        0x20123456,
+        vec![make_vin(0x80abcdef)],
+        vec![make_vout(100_000, "v0_p2wsh"), make_vout(330, "v0_p2wsh")],
+    );
+    let commitments = vec![
+        classify_lightning_with_context(&bolt3_commitment(43), &context),
+        classify_lightning(&other_channel),
+        classify_lightning_with_context(&bolt3_commitment(42), &context),
+    ];
+
+    let links = correlate(&commitments);
+    assert_eq!(links.len(), 1);
+    assert_eq!(links[0].basis, LinkBasis::ObscuringFactor);
+    assert_eq!(links[0].funding_outpoint, None);
+    let order: Vec<(usize, Option<u64>)> = links[0]
+        .commitments
+        .iter()
+        .map(|c| (c.index, c.commitment_number_decoded))
+        .collect();
+    assert_eq!(order, vec![(2, Some(42)), (0, Some(43))]);
+}
+
+#[test]
+fn test_correlate_without_basepoints() {
+    let commitments = vec![
+        classify_lightning(&bolt3_commitment(1)),
+        classify_lightning(&bolt3_commitment(1000)),
+    ];
+    let links = correlate(&commitments);
+    assert_eq!(links.len(), 1);
+    assert_eq!(links[0].commitments.len(), 2);
+    let numbers: Vec<u64> = links[0]
+        .commitments
+        .iter()
+        .map(|c| c.commitment_number.unwrap())
+        .collect();
+    assert!(numbers.windows(2).all(|w| w[0] < w[1]));
+}
+
+#[test]
+fn test_correlate_by_funding_outpoint() {
+    // Verified commitments are linked by their funding output even when their
+    // obscured numbers differ
+    let mut first = classify_lightning(&bolt3_commitment(7));
+    let mut second = classify_lightning(&bolt3_commitment(0xFFFFFFFF));
+    let outpoint = format!("{}:0", "aa".repeat(32));
+    first.params.funding_outpoint = Some(outpoint.clone());
+    second.params.funding_outpoint = Some(outpoint.clone());
+
+    let links = correlate(&[first, second]);
+    assert_eq!(links.len(), 1);
+    assert_eq!(links[0].basis, LinkBasis::FundingOutpoint);
+    assert_eq!(links[0].funding_outpoint, Some(outpoint));
+}
+
+#[test]
+fn test_correlate_ignores_single_and_non_commitments() {
+    let regular = make_tx(0, vec![make_vin(0xFFFFFFFF)], vec![make_vout(50_000, "v0_p2wpkh")]);
+    let commitments = vec![classify_lightning(&bolt3_commitment(5)), classify_lightning(&regular)];
+    assert!(correlate(&commitments).is_empty());
+}
+
+/// `tx` renamed to `txid`, with each input spending output `i` of `parent`.
+fn spending(mut tx: ApiTransaction, txid: &str, parent: &str) -> ApiTransaction {
+    tx.txid = txid.repeat(32);
+    for (i, vin) in tx.vin.iter_mut().enumerate() {
+        vin.txid = Some(parent.repeat(32));
+        vin.vout = Some(i as u32);
+    }
+    tx
+}
+
+#[test]
+fn test_cluster_htlc_spends_of_a_commitment() {
+    let commitment = spending(bolt3_commitment(42), "c1", "f0");
+    let other = spending(bolt3_commitment(43), "c2", "f1");
+    let timeout = |txid| {
+        let tx =
+            make_tx(886100, vec![make_htlc_timeout_vin()], vec![make_vout(50_000, "v0_p2wsh")]);
+        spending(tx, txid, "c1")
+    };
+    let mut success_vin = make_vin(0);
+    success_vin.witness = Some(vec!["ab".repeat(32), "3045".to_string()]);
+    success_vin.inner_witnessscript_asm =
+        Some("OP_SIZE 32 OP_EQUAL OP_IF OP_ELSE 1 OP_CSV OP_DROP OP_ENDIF".to_string());
+    let success = make_tx(0, vec![success_vin], vec![make_vout(50_000, "v0_p2wsh")]);
+    let success = spending(success, "d1", "c1");
+    // Spends an unseen commitment
+    let stray = spending(make_tx(886100, vec![make_htlc_timeout_vin()], vec![]), "e1", "99");
+
+    let txs = [commitment, timeout("a1"), other, success, timeout("a2"), stray];
+    let classified: Vec<_> = txs.iter().map(classify_lightning).collect();
+    let clusters = cluster_htlc_spends(txs.iter().zip(&classified));
+
+    // The other commitment has no HTLC spends, so it forms no cluster
+    assert_eq!(clusters.len(), 1);
+    let cluster = &clusters[0];
+    assert_eq!(cluster.commitment_txid, "c1".repeat(32));
+    assert_eq!(cluster.funding_outpoint, Some(format!("{}:0", "f0".repeat(32))));
+    assert_eq!(cluster.htlc_timeouts, ["a1".repeat(32), "a2".repeat(32)]);
+    assert_eq!(cluster.htlc_successes, ["d1".repeat(32)]);
+}
+
+#[test]
+fn test_cluster_prefers_verified_funding_outpoint() {
+    let commitment = spending(bolt3_commitment(42), "c1", "f0");
+    let timeout =
+        make_tx(886100, vec![make_htlc_timeout_vin()], vec![make_vout(50_000, "v0_p2wsh")]);
+    let timeout = spending(timeout, "a1", "c1");
+    let mut classified = vec![classify_lightning(&commitment), classify_lightning(&timeout)];
+    classified[0].params.funding_outpoint = Some("verified:1".to_string());
+
+    let txs = [commitment, timeout];
+    let clusters = cluster_htlc_spends(txs.iter().zip(&classified));
+    assert_eq!(clusters[0].funding_outpoint.as_deref(), Some("verified:1"));
+}
+
+#[test]
+fn test_link_same_block_sweeps() {
+    let commitment = spending(bolt3_commitment(42), "c1", "f0");
+    let timeout =
+        make_tx(886100, vec![make_htlc_timeout_vin()], vec![make_vout(50_000, "v0_p2wsh")]);
+    let timeout = spending(timeout, "a1", "c1");
+    let unrelated = spending(bolt3_commitment(43), "c2", "f1");
+    let txs = [timeout, commitment, unrelated];
+    let mut classified: Vec<_> = txs.iter().map(classify_lightning).collect();
+
+    link_same_block_sweeps(txs.iter().zip(&mut classified));
+
+    // The sweep may come before its commitment in block order
+    assert_eq!(classified[0].params.same_block_commitment, Some("c1".repeat(32)));
+    assert_eq!(classified[1].params.same_block_htlc_spends, ["a1".repeat(32)]);
+    assert!(classified[1].params.same_block_commitment.is_none());
+    assert!(classified[2].params.same_block_htlc_spends.is_empty());
+}
+
+// ─── HTLC-timeout detection ─────────────────────────────────────────────────
+
+#[test]
+fn test_htlc_timeout_detection() {
+    // HTLC-timeout: nLockTime = realistic block height, no 32-byte preimage in witness
+    let locktime: u32 = 886100; // realistic block height
+    let mut vin = make_vin(0);
+    // Witness without a 32-byte (64 hex char) element
+    vin.witness = Some(vec![
+        "".to_string(),           // empty (no preimage)
+        "3045".to_string(),       // signature (not 64 chars)
+        "00".to_string(),         // some script element
+    ]);
+    vin.inner_witnessscript_asm = Some(
+        "OP_DUP OP_HASH160 abc123 OP_EQUALVERIFY OP_CHECKSIG OP_IF 886100 OP_CHECKLOCKTIMEVERIFY OP_DROP OP_ENDIF 1 OP_CHECKSEQUENCEVERIFY".to_string()
+    );
+    let tx = make_tx(
+        locktime,
+        vec![vin],
+        vec![make_vout(50_000, "v0_p2wsh")],
+    );
+    let result = classify_lightning(&tx);
+    assert_eq!(result.tx_type, Some(LightningTxType::HtlcTimeout));
+    assert!(!result.htlc_signals.has_preimage);
+    assert!(result.htlc_signals.script_has_cltv);
+    assert_eq!(result.params.cltv_expiry, Some(886100));
+
+    let inputs = &result.params.htlc_inputs;
+    assert_eq!(inputs.len(), 1);
+    assert_eq!(inputs[0].tx_type, LightningTxType::HtlcTimeout);
+    assert_eq!(inputs[0].cltv_expiry, Some(886100));
+    assert_eq!(inputs[0].csv_delays, vec![1]);
+    assert_eq!(inputs[0].preimage, None);
+}
+
+#[test]
+fn test_htlc_timeout_no_preimage_empty_witness_element() {
+    // HTLC-timeout with empty string at preimage position
+    let locktime: u32 = 886200;
+    let mut vin = make_vin(0);
+    vin.witness = Some(vec![
+        "".to_string(),
+        "3044022000".to_string(),
+    ]);
+    vin.inner_witnessscript_asm = Some(
+        "OP_SIZE 32 OP_EQUAL OP_IF OP_HASH160 abc OP_ELSE 886200 OP_CHECKLOCKTIMEVERIFY OP_DROP OP_ENDIF 1 OP_CHECKSEQUENCEVERIFY".to_string()
+    );
+    let tx = make_tx(
+        locktime,
+        vec![vin],
+        vec![make_vout(50_000, "v0_p2wsh")],
+    );
+    let result = classify_lightning(&tx);
+    assert_eq!(result.tx_type, Some(LightningTxType::HtlcTimeout));
+    assert!(!result.htlc_signals.has_preimage);
+}
+
+fn make_htlc_timeout_vin() -> ApiVin {
+    let mut vin = make_vin(0);
+    vin.witness = Some(vec!["".to_string(), "3045".to_string()]);
+    vin.inner_witnessscript_asm = Some(
+        "OP_SIZE 32 OP_EQUAL OP_IF OP_HASH160 abc OP_ELSE OP_CHECKLOCKTIMEVERIFY OP_DROP OP_ENDIF".to_string()
+    );
+    vin
+}
+
+fn make_prevout(script_type: &str, scriptpubkey_asm: &str) -> ApiPrevout {
+    ApiPrevout {
+        scriptpubkey: "00".to_string(),
+        scriptpubkey_asm: scriptpubkey_asm.to_string(),
+        scriptpubkey_type: script_type.to_string(),
+        scriptpubkey_address: None,
+        value: 50_000,
+    }
+}
+
+#[test]
+fn test_htlc_timeout_cltv_from_prevout_witness_script() {
+    // No decoded witness script: `<886100> OP_CLTV OP_DROP <pubkey> OP_CHECKSIG` is
+    // recovered from the last witness element of the P2WSH spend
+    let script_hex = format!("0354850db1752102{}ac", "ab".repeat(32));
+    let mut vin = make_vin(0);
+    vin.witness = Some(vec!["".to_string(), "3045".to_string(), script_hex]);
+    vin.prevout = Some(make_prevout("v0_p2wsh", "OP_0 OP_PUSHBYTES_32 cd"));
+
+    let tx = make_tx(886100, vec![vin], vec![make_vout(50_000, "v0_p2wsh")]);
+    let result = classify_lightning(&tx);
+    assert!(result.htlc_signals.script_has_cltv);
+    assert_eq!(result.tx_type, Some(LightningTxType::HtlcTimeout));
+    assert_eq!(result.params.cltv_expiry, Some(886100));
+}
+
+#[test]
+fn test_htlc_timeout_cltv_from_bare_prevout_script() {
+    let mut vin = make_vin(0);
+    vin.witness = Some(vec![]);
+    vin.prevout = Some(make_prevout(
+        "unknown",
+        "OP_PUSHBYTES_3 54850d OP_CLTV OP_DROP OP_PUSHBYTES_33 02ab OP_CHECKSIG",
+    ));
+
+    let tx = make_tx(886100, vec![vin], vec![make_vout(50_000, "v0_p2wsh")]);
+    assert!(classify_lightning(&tx).htlc_signals.script_has_cltv);
+}
+
+#[test]
+fn test_p2wpkh_prevout_gives_no_script() {
+    let mut vin = make_vin(0);
+    vin.witness = Some(vec!["3045".to_string(), "02ab".to_string()]);
+    vin.prevout = Some(make_prevout("v0_p2wpkh", "OP_0 OP_PUSHBYTES_20 ab"));
+
+    let tx = make_tx(886100, vec![vin], vec![make_vout(50_000, "v0_p2wpkh")]);
+    let result = classify_lightning(&tx);
+    assert!(!result.htlc_signals.script_has_cltv);
+    assert_eq!(result.tx_type, None);
+}
+
+#[test]
+fn test_htlc_timeout_block_height_just_below_threshold() {
+    let tx = make_tx(499_999_999, vec![make_htlc_timeout_vin()], vec![make_vout(50_000, "v0_p2wsh")]);
+    let result = classify_lightning(&tx);
+    assert_eq!(result.tx_type, Some(LightningTxType::HtlcTimeout));
+    assert_eq!(result.params.cltv_expiry, Some(499_999_999));
+    assert_eq!(result.params.cltv_expiry_time, None);
+}
+
+#[test]
+fn test_htlc_timeout_timestamp_at_threshold() {
+    let tx = make_tx(500_000_000, vec![make_htlc_timeout_vin()], vec![make_vout(50_000, "v0_p2wsh")]);
+    let result = classify_lightning(&tx);
+    assert_eq!(result.tx_type, Some(LightningTxType::HtlcTimeout));
+    assert_eq!(result.confidence, Confidence::HighlyLikely);
+    assert_eq!(result.params.cltv_expiry, None);
+    assert_eq!(result.params.cltv_expiry_time, Some(500_000_000));
+}
+
+#[test]
+fn test_htlc_timeout_realistic_timestamp() {
+    // 2023-11-14 22:13:20 UTC
+    let tx = make_tx(1_700_000_000, vec![make_htlc_timeout_vin()], vec![make_vout(50_000, "v0_p2wsh")]);
+    let result = classify_lightning(&tx);
+    assert_eq!(result.tx_type, Some(LightningTxType::HtlcTimeout));
+    assert_eq!(result.params.cltv_expiry, None);
+    assert_eq!(result.params.cltv_expiry_time, Some(1_700_000_000));
+}
+
+fn htlc_timeout_reasons_at(tip_height: u64) -> Vec<String> {
+    let tx = make_tx(886100, vec![make_htlc_timeout_vin()], vec![make_vout(50_000, "v0_p2wsh")]);
+    let tip = ChainTip {
+        height: tip_height,
+        median_time_past: None,
+    };
+    classify_lightning_with_tip(&tx, &tip).reasons
+}
+
+#[test]
+fn test_htlc_timeout_locktime_in_future() {
+    let reasons = htlc_timeout_reasons_at(886000);
+    assert!(reasons.contains(&"HTLC-timeout locktime 100 blocks in the future".to_string()));
+}
+
+#[test]
+fn test_htlc_timeout_locktime_at_tip_matured() {
+    // Final in the next block
+    let reasons = htlc_timeout_reasons_at(886100);
+    assert!(reasons.contains(&"HTLC-timeout locktime already matured".to_string()));
+}
+
+#[test]
+fn test_htlc_timeout_locktime_in_past_matured() {
+    let reasons = htlc_timeout_reasons_at(886500);
+    assert!(reasons.contains(&"HTLC-timeout locktime already matured".to_string()));
+    assert!(!reasons.iter().any(|r| r.contains("in the future")));
+}
+
+#[test]
+fn test_tip_reasons_only_for_htlc_timeout() {
+    let mut vin = make_vin(0);
+    vin.witness = Some(vec!["ab".repeat(32), "3045".to_string()]);
+    vin.inner_witnessscript_asm = Some("OP_SIZE 32 OP_EQUAL OP_IF OP_ELSE 1 OP_CSV OP_ENDIF".to_string());
+    let tx = make_tx(0, vec![vin], vec![make_vout(50_000, "v0_p2wsh")]);
+    let tip = ChainTip {
+        height: 886000,
+        median_time_past: None,
+    };
+    assert_eq!(classify_lightning_with_tip(&tx, &tip).reasons, classify_lightning(&tx).reasons);
+}
+
+// ─── Realistic HTLC expiry heights ──────────────────────────────────────────
+
+fn classify_htlc_timeout_on(tip_height: u64) -> LightningClassification {
+    let tx = make_tx(886100, vec![make_htlc_timeout_vin()], vec![make_vout(50_000, "v0_p2wsh")]);
+    classify_lightning_with_config(&tx, &DetectorConfig::default().with_tip(tip_height))
+}
+
+#[test]
+fn test_htlc_expiry_within_mainnet_tip() {
+    let result = classify_htlc_timeout_on(886000);
+    assert_eq!(result.confidence, Confidence::HighlyLikely);
+    assert_eq!(result.params.cltv_expiry, Some(886100));
+    assert_eq!(result.params.htlc_inputs[0].cltv_expiry, Some(886100));
+}
+
+#[test]
+fn test_htlc_expiry_past_signet_tip() {
+    // A mainnet-sized height is absurd on a signet with a tip around 200000
+    let result = classify_htlc_timeout_on(200_000);
+    assert_eq!(result.tx_type, Some(LightningTxType::HtlcTimeout));
+    assert_eq!(result.confidence, Confidence::Possible);
+    assert_eq!(result.params.cltv_expiry, None);
+    assert_eq!(result.params.htlc_inputs[0].cltv_expiry, None);
+    assert!(
+        result
+            .reasons
+            .contains(&"nLockTime too far past the chain tip to be an HTLC expiry".to_string())
+    );
+}
+
+#[test]
+fn test_htlc_expiry_bound_allows_two_weeks_ahead() {
+    // 886100 is 2016 blocks past a tip of 884084, and one more past 884083
+    assert_eq!(classify_htlc_timeout_on(884_084).confidence, Confidence::HighlyLikely);
+    assert_eq!(classify_htlc_timeout_on(884_083).confidence, Confidence::Possible);
+}
+
+#[test]
+fn test_htlc_expiry_unbounded_without_tip() {
+    let tx = make_tx(886100, vec![make_htlc_timeout_vin()], vec![make_vout(50_000, "v0_p2wsh")]);
+    assert_eq!(DetectorConfig::default().max_htlc_expiry, None);
+    assert_eq!(classify_lightning(&tx).confidence, Confidence::HighlyLikely);
+    let testnet = ChainTip {
+        height: 4_500_000,
+        median_time_past: None,
+    };
+    assert_eq!(classify_lightning_with_tip(&tx, &testnet).confidence, Confidence::HighlyLikely);
+}
+
+// ─── HTLC-success detection ─────────────────────────────────────────────────
+
+#[test]
+fn test_htlc_success_detection() {
+    // HTLC-success: nLockTime = 0, 32-byte preimage in witness
+    let preimage = "ab".repeat(32); // 64 hex chars = 32 bytes
+    let mut vin = make_vin(0);
+    vin.witness = Some(vec![
+        preimage.clone(),
+        "3045".to_string(),
+    ]);
+    vin.inner_witnessscript_asm = Some(
+        "OP_SIZE 32 OP_EQUAL OP_IF OP_HASH160 abc OP_EQUALVERIFY OP_CHECKSIG OP_ELSE 1 OP_CHECKSEQUENCEVERIFY OP_DROP OP_ENDIF".to_string()
+    );
+    let tx = make_tx(
+        0, // locktime = 0 for HTLC-success
+        vec![vin],
+        vec![make_vout(50_000, "v0_p2wsh")],
+    );
+    let result = classify_lightning(&tx);
+    assert_eq!(result.tx_type, Some(LightningTxType::HtlcSuccess));
+    assert!(result.htlc_signals.has_preimage);
+    assert_eq!(result.htlc_signals.preimage, Some(preimage));
+    assert!(result.params.preimage_revealed);
+    assert!(result.params.preimage.is_some());
+
+    let inputs = &result.params.htlc_inputs;
+    assert_eq!(inputs.len(), 1);
+    assert_eq!(inputs[0].input_index, 0);
+    assert_eq!(inputs[0].tx_type, LightningTxType::HtlcSuccess);
+    assert_eq!(inputs[0].preimage, Some("ab".repeat(32)));
+    assert_eq!(inputs[0].cltv_expiry, None);
+}
+
+fn make_htlc_vin(preimage: Option<String>) -> ApiVin {
+    let mut vin = make_vin(0);
+    vin.witness = Some(preimage.into_iter().chain(["3045".to_string()]).collect());
+    vin.inner_witnessscript_asm =
+        Some("OP_HASH160 abc OP_EQUAL OP_IF OP_ELSE 886200 OP_CHECKLOCKTIMEVERIFY OP_ENDIF".to_string());
+    vin
+}
+
+#[test]
+fn test_htlc_inputs_of_mixed_kinds() {
+    // Aggregated sweep: two preimage claims and one expired HTLC, plus a wallet input
+    let tx = make_tx(
+        886300,
+        vec![
+            make_htlc_vin(None),
+            make_vin(0xFFFFFFFD),
+            make_htlc_vin(Some("cd".repeat(32))),
+            make_htlc_vin(Some("ef".repeat(32))),
+        ],
+        vec![make_vout(150_000, "v0_p2wpkh")],
+    );
+    let result = classify_lightning(&tx);
+
+    let inputs = &result.params.htlc_inputs;
+    let indexes: Vec<usize> = inputs.iter().map(|i| i.input_index).collect();
+    assert_eq!(indexes, vec![0, 2, 3]);
+    assert_eq!(inputs[0].tx_type, LightningTxType::HtlcTimeout);
+    assert_eq!(inputs[1].tx_type, LightningTxType::HtlcSuccess);
+    assert_eq!(inputs[2].preimage, Some("ef".repeat(32)));
+    assert!(inputs.iter().all(|i| i.cltv_expiry == Some(886200)));
+
+    // Success claims dominate; nLockTime isn't 0, so not a clean HTLC-success
+    assert_eq!(result.tx_type, Some(LightningTxType::HtlcSuccess));
+    assert_eq!(result.confidence, Confidence::Possible);
+    assert_eq!(result.params.preimage, Some("cd".repeat(32)));
+}
+
+#[test]
+fn test_htlc_success_multiple_preimages() {
+    // One claim sweeping two HTLCs, each revealing its own preimage
+    let tx = make_tx(
+        0,
+        vec![make_htlc_vin(Some("cd".repeat(32))), make_htlc_vin(Some("ef".repeat(32)))],
+        vec![make_vout(100_000, "v0_p2wpkh")],
+    );
+    let result = classify_lightning(&tx);
+    assert_eq!(result.tx_type, Some(LightningTxType::HtlcSuccess));
+    assert_eq!(result.confidence, Confidence::HighlyLikely);
+
+    let expected = vec!["cd".repeat(32), "ef".repeat(32)];
+    assert_eq!(result.htlc_signals.preimages, expected);
+    assert_eq!(result.htlc_signals.preimage, Some("cd".repeat(32)));
+    assert_eq!(result.params.preimages, expected);
+    assert_eq!(result.params.preimage, Some("cd".repeat(32)));
+    assert!(result.params.preimage_revealed);
+    assert!(result.reasons.iter().any(|r| r == "2 preimages across inputs"));
+}
+
+#[test]
+fn test_htlc_input_tie_goes_to_first() {
+    let tx = make_tx(
+        886300,
+        vec![make_htlc_vin(None), make_htlc_vin(Some("cd".repeat(32)))],
+        vec![make_vout(150_000, "v0_p2wpkh")],
+    );
+    let result = classify_lightning(&tx);
+    assert_eq!(result.tx_type, Some(LightningTxType::HtlcTimeout));
+    assert_eq!(result.params.htlc_inputs.len(), 2);
+}
+
+#[test]
+fn test_htlc_success_preimage_must_be_hex() {
+    // 64 chars but not valid hex → should NOT be detected as preimage
+    let not_hex = "zz".repeat(32);
+    let mut vin = make_vin(0);
+    vin.witness = Some(vec![not_hex, "3045".to_string()]);
+    vin.inner_witnessscript_asm = Some(
+        "OP_SIZE 32 OP_EQUAL OP_IF OP_HASH160 abc OP_EQUALVERIFY OP_ENDIF 1 OP_CHECKSEQUENCEVERIFY".to_string()
+    );
+    let tx = make_tx(0, vec![vin], vec![make_vout(50_000, "v0_p2wsh")]);
+    let result = classify_lightning(&tx);
+    assert!(!result.htlc_signals.has_preimage);
+}
+
+#[test]
+fn test_htlc_success_preimage_matches_payment_hash() {
+    // A 16-byte preimage is found because it hashes to the script's payment hash
+    let preimage = [0x42u8; 16];
+    let payment_hash = hash160::Hash::hash(&preimage).to_byte_array().to_lower_hex_string();
+    let mut vin = make_vin(0);
+    vin.witness = Some(vec![
+        "3045".to_string(),
+        preimage.to_lower_hex_string(),
+        "ff".repeat(40),
+    ]);
+    vin.inner_witnessscript_asm = Some(format!(
+        "OP_DUP OP_HASH160 OP_PUSHBYTES_20 {} OP_EQUAL OP_IF OP_CHECKSIG OP_ELSE OP_SIZE 32 OP_EQUAL OP_IF OP_HASH160 OP_PUSHBYTES_20 {payment_hash} OP_EQUALVERIFY OP_ENDIF 1 OP_CHECKSEQUENCEVERIFY OP_DROP OP_ENDIF",
+        "11".repeat(20)
+    ));
+    let tx = make_tx(0, vec![vin], vec![make_vout(50_000, "v0_p2wsh")]);
+    let result = classify_lightning(&tx);
+    assert_eq!(result.tx_type, Some(LightningTxType::HtlcSuccess));
+    assert!(result.htlc_signals.has_preimage);
+    assert!(result.htlc_signals.preimage_verified);
+    assert_eq!(result.htlc_signals.preimage, Some("42".repeat(16)));
+    assert_eq!(result.params.htlc_inputs[0].preimage, Some("42".repeat(16)));
+    assert!(result.reasons.contains(&"witness preimage matches the script's payment hash".to_string()));
+}
+
+#[test]
+fn test_htlc_success_sha256_payment_hash() {
+    let preimage = [0x07u8; 32];
+    let payment_hash = sha256::Hash::hash(&preimage).to_byte_array().to_lower_hex_string();
+    let mut vin = make_vin(0);
+    vin.witness = Some(vec!["3045".to_string(), preimage.to_lower_hex_string()]);
+    vin.inner_witnessscript_asm = Some(format!(
+        "OP_SHA256 {payment_hash} OP_EQUAL OP_IF abc OP_ELSE 144 OP_CHECKSEQUENCEVERIFY OP_DROP OP_ENDIF"
+    ));
+    let tx = make_tx(0, vec![vin], vec![make_vout(50_000, "v0_p2wsh")]);
+    let result = classify_lightning(&tx);
+    assert!(result.htlc_signals.preimage_verified);
+    assert_eq!(result.htlc_signals.preimage, Some("07".repeat(32)));
+}
+
+#[test]
+fn test_htlc_32_byte_element_not_matching_hash_is_not_preimage() {
+    // The script's payment hash is visible, so a 32-byte element that doesn't hash
+    // to it is not taken as the preimage
+    let payment_hash = hash160::Hash::hash(&[0x42u8; 32]).to_byte_array().to_lower_hex_string();
+    let mut vin = make_vin(0);
+    vin.witness = Some(vec!["3045".to_string(), "ab".repeat(32)]);
+    vin.inner_witnessscript_asm = Some(format!(
+        "OP_SIZE 32 OP_EQUAL OP_IF OP_HASH160 OP_PUSHBYTES_20 {payment_hash} OP_EQUALVERIFY OP_ENDIF 1 OP_CHECKSEQUENCEVERIFY"
+    ));
+    let tx = make_tx(0, vec![vin], vec![make_vout(50_000, "v0_p2wsh")]);
+    let result = classify_lightning(&tx);
+    assert!(!result.htlc_signals.has_preimage);
+    assert!(!result.htlc_signals.preimage_verified);
+    assert!(!result.params.preimage_revealed);
+}
+
+// ─── HTLC witness shape (scripts not visible) ───────────────────────────────
+
+/// A signature-sized DER element, as the witness holds it.
+fn witness_sig() -> String {
+    format!("3044{}01", "11".repeat(68))
+}
+
+/// An input with only `witness` to go on, ending in an HTLC-sized witness script.
+fn shaped_vin(mut witness: Vec<String>) -> ApiVin {
+    witness.push("76".repeat(136));
+    let mut vin = make_vin(0);
+    vin.witness = Some(witness);
+    vin
+}
+
+#[test]
+fn test_witness_shape_second_stage_timeout() {
+    let vin = shaped_vin(vec![String::new(), witness_sig(), witness_sig(), String::new()]);
+    let tx = make_tx(886100, vec![vin], vec![make_vout(50_000, "v0_p2wsh")]);
+    let result = classify_lightning(&tx);
+    assert_eq!(result.tx_type, Some(LightningTxType::HtlcTimeout));
+    // A full HTLC-timeout script with this locktime would be HighlyLikely
+    assert_eq!(result.confidence, Confidence::Possible);
+    assert!(result.htlc_signals.witness_shape_only);
+    assert_eq!(result.params.cltv_expiry, Some(886100));
+    assert!(result.reasons.contains(
+        &"witness stack shaped like an HTLC spend (script not visible)".to_string()
+    ));
+}
+
+#[test]
+fn test_witness_shape_success_claims() {
+    let preimage = "ab".repeat(32);
+    let second_stage =
+        shaped_vin(vec![String::new(), witness_sig(), witness_sig(), preimage.clone()]);
+    let direct = shaped_vin(vec![witness_sig(), preimage.clone()]);
+    for vin in [second_stage, direct] {
+        let tx = make_tx(0, vec![vin], vec![make_vout(50_000, "v0_p2wpkh")]);
+        let result = classify_lightning(&tx);
+        assert_eq!(result.tx_type, Some(LightningTxType::HtlcSuccess));
+        assert_eq!(result.confidence, Confidence::Possible);
+        assert_eq!(result.params.preimage.as_deref(), Some(preimage.as_str()));
+    }
+}
+
+#[test]
+fn test_witness_shape_rejects_lookalikes() {
+    let timeout_shape = || vec![String::new(), witness_sig(), witness_sig(), String::new()];
+    // A to_local sweep pushes <sig> <> <script> too
+    let to_local = shaped_vin(vec![witness_sig(), String::new()]);
+    // With the prevout known, the witness script is read instead
+    let mut with_prevout = shaped_vin(timeout_shape());
+    with_prevout.prevout = Some(make_prevout("v0_p2wsh", "OP_0 OP_PUSHBYTES_32 00"));
+    let mut short_script = shaped_vin(timeout_shape());
+    short_script.witness.as_mut().unwrap()[4] = "76".repeat(40);
+    let mut not_signatures = shaped_vin(timeout_shape());
+    not_signatures.witness.as_mut().unwrap()[1] = "02".repeat(33);
+
+    for vin in [to_local, with_prevout, short_script, not_signatures] {
+        let tx = make_tx(886100, vec![vin], vec![make_vout(50_000, "v0_p2wpkh")]);
+        let result = classify_lightning(&tx);
+        assert_eq!(result.tx_type, None, "{:?}", result.reasons);
+        assert!(!result.htlc_signals.witness_shape_only);
+    }
+}
+
+// ─── Payment hash extraction ────────────────────────────────────────────────
+
+#[test]
+fn test_payment_hash_from_hash160_script() {
+    // HTLC-timeout: no preimage, but the script still commits to the payment
+    let hash = "5b".repeat(20);
+    let mut vin = make_vin(0);
+    vin.witness = Some(vec!["".to_string(), "3045".to_string(), "3044".to_string(), "".to_string()]);
+    vin.inner_witnessscript_asm = Some(format!(
+        "OP_DUP OP_HASH160 OP_PUSHBYTES_20 {} OP_EQUAL OP_IF OP_CHECKSIG OP_ELSE OP_SIZE 32 OP_EQUAL OP_NOTIF OP_DROP 2 OP_SWAP 2 OP_CHECKMULTISIG OP_ELSE OP_HASH160 OP_PUSHBYTES_20 {hash} OP_EQUALVERIFY OP_CHECKSIG OP_ENDIF 1 OP_CHECKSEQUENCEVERIFY OP_DROP OP_ENDIF",
+        "11".repeat(20)
+    ));
+    let tx = make_tx(886000, vec![vin], vec![make_vout(50_000, "v0_p2wsh")]);
+    let result = classify_lightning(&tx);
+    assert_eq!(result.tx_type, Some(LightningTxType::HtlcTimeout));
+    // The revocation key hash after OP_DUP OP_HASH160 is skipped
+    assert_eq!(result.params.payment_hash, Some(hash.clone()));
+    assert_eq!(result.params.htlc_inputs[0].payment_hash, Some(hash));
+}
+
+#[test]
+fn test_payment_hash_from_sha256_script() {
+    let preimage = [0x07u8; 32];
+    let hash = sha256::Hash::hash(&preimage).to_byte_array().to_lower_hex_string();
+    let mut vin = make_vin(0);
+    vin.witness = Some(vec!["3045".to_string(), preimage.to_lower_hex_string()]);
+    vin.inner_witnessscript_asm = Some(format!(
+        "OP_SHA256 OP_PUSHBYTES_32 {hash} OP_EQUAL OP_IF abc OP_ELSE 144 OP_CHECKSEQUENCEVERIFY OP_DROP OP_ENDIF"
+    ));
+    let tx = make_tx(0, vec![vin], vec![make_vout(50_000, "v0_p2wsh")]);
+    let result = classify_lightning(&tx);
+    assert_eq!(result.tx_type, Some(LightningTxType::HtlcSuccess));
+    assert_eq!(result.params.payment_hash, Some(hash));
+}
+
+#[test]
+fn test_no_payment_hash_without_hash_opcode() {
+    let mut vin = make_vin(0);
+    vin.inner_witnessscript_asm = Some("OP_IF abc OP_ELSE 144 OP_CHECKSEQUENCEVERIFY OP_DROP OP_ENDIF".to_string());
+    let tx = make_tx(886000, vec![vin], vec![make_vout(50_000, "v0_p2wsh")]);
+    let result = classify_lightning(&tx);
+    assert!(result.tx_type.is_some());
+    assert_eq!(result.params.payment_hash, None);
+}
+
+// ─── HTLC CSV delay extraction ──────────────────────────────────────────────
+
+#[test]
+fn test_csv_delay_extraction_from_htlc() {
+    // Both HTLC-timeout and HTLC-success outputs have CSV-delayed scripts
+    // The CSV value = to_self_delay (revocation window)
+    let mut vin = make_vin(0);
+    vin.witness = Some(vec!["".to_string(), "3045".to_string()]);
+    vin.inner_witnessscript_asm = Some(
+        "OP_IF abc OP_ELSE 144 OP_CHECKSEQUENCEVERIFY OP_DROP def OP_ENDIF".to_string()
+    );
+    let tx = make_tx(886300, vec![vin], vec![make_vout(50_000, "v0_p2wsh")]);
+    let result = classify_lightning(&tx);
+    assert!(result.params.csv_delays.contains(&144));
+}
+
+fn csv_delays_for(script_asm: &str) -> Vec<u16> {
+    let mut vin = make_vin(0);
+    vin.witness = Some(vec!["".to_string(), "3045".to_string()]);
+    vin.inner_witnessscript_asm = Some(script_asm.to_string());
+    let tx = make_tx(886300, vec![vin], vec![make_vout(50_000, "v0_p2wsh")]);
+    classify_lightning(&tx).params.csv_delays
+}
+
+#[test]
+fn test_csv_delay_pushbytes_hex() {
+    // mempool.space renders 144 as its little-endian push `OP_PUSHBYTES_2 9000`
+    let delays = csv_delays_for("OP_IF abc OP_ELSE OP_PUSHBYTES_2 9000 OP_CSV OP_DROP def OP_ENDIF");
+    assert_eq!(delays, vec![144]);
+}
+
+#[test]
+fn test_csv_delay_bare_hex() {
+    let delays = csv_delays_for("OP_ELSE a005 OP_CHECKSEQUENCEVERIFY OP_DROP");
+    assert_eq!(delays, vec![1440]);
+}
+
+#[test]
+fn test_csv_delay_small_int_opcode() {
+    assert_eq!(csv_delays_for("OP_PUSHNUM_16 OP_CSV"), vec![16]);
+    assert_eq!(csv_delays_for("OP_1 OP_CSV"), vec![1]);
+}
+
+#[test]
+fn test_csv_delay_negative_push_ignored() {
+    // Sign bit set on the last byte → negative script number, not a delay
+    assert!(csv_delays_for("OP_PUSHBYTES_1 81 OP_CSV").is_empty());
+}
+
+#[test]
+fn test_csv_delay_oversized_push_masked() {
+    // 0x0186A0: BIP 68 ignores bit 16, so the delay is 0x86A0
+    assert_eq!(csv_delays_for("OP_PUSHBYTES_3 a08601 OP_CSV"), vec![0x86A0]);
+    assert_eq!(csv_delays_for("OP_PUSHBYTES_5 9000000001 OP_CSV"), vec![144]);
+}
+
+#[test]
+fn test_csv_delay_flagged_operands_ignored() {
+    // Time-based (bit 22) and disabled (bit 31) operands carry no block delay
+    assert!(csv_delays_for("OP_PUSHBYTES_3 900040 OP_CSV").is_empty());
+    assert!(csv_delays_for("OP_PUSHBYTES_5 9000008000 OP_CSV").is_empty());
+}
+
+#[test]
+fn test_p2sh_redeemscript_cltv_branch_detected() {
+    // Legacy P2SH HTLC: the script is revealed as inner_redeemscript_asm, with no witness
+    let mut vin = make_vin(0xFFFFFFFE);
+    vin.witness = None;
+    vin.inner_redeemscript_asm = Some(
+        "OP_IF OP_SHA256 OP_PUSHBYTES_32 aa OP_EQUALVERIFY OP_PUSHBYTES_33 02bb OP_ELSE \
+         OP_PUSHBYTES_3 40e201 OP_CHECKLOCKTIMEVERIFY OP_DROP OP_PUSHBYTES_2 9000 OP_CSV OP_DROP \
+         OP_PUSHBYTES_33 03cc OP_ENDIF OP_CHECKSIG"
+            .to_string(),
+    );
+    let tx = make_tx(123456, vec![vin], vec![make_vout(50_000, "p2pkh")]);
+    let result = classify_lightning(&tx);
+    assert!(result.htlc_signals.script_has_cltv);
+    assert!(result.htlc_signals.script_has_csv);
+    assert_eq!(result.params.csv_delays, vec![144]);
+}
+
+// ─── to_self_delay extraction ───────────────────────────────────────────────
+
+fn to_local_script(delay_push: &str) -> String {
+    format!(
+        "OP_IF OP_PUSHBYTES_33 02{} OP_ELSE {} OP_CSV OP_DROP OP_PUSHBYTES_33 03{} OP_ENDIF OP_CHECKSIG",
+        "a".repeat(64),
+        delay_push,
+        "b".repeat(64)
+    )
+}
+
+#[test]
+fn test_to_self_delay_from_to_local_output() {
+    let mut to_local = make_vout(500_000, "v0_p2wsh");
+    to_local.scriptpubkey_asm = to_local_script("OP_PUSHBYTES_2 9000");
+    let tx = make_tx(
+        0x20000000 | 0x123456,
+        vec![make_vin(0x80000000 | 0x000001)],
+        vec![to_local, make_vout(330, "v0_p2wsh")],
+    );
+    let result = classify_lightning(&tx);
+    assert_eq!(result.tx_type, Some(LightningTxType::Commitment));
+    assert_eq!(result.params.to_self_delay, Some(144));
+}
+
+#[test]
+fn test_to_self_delay_from_penalty_witness_script() {
+    let script = to_local_script("OP_PUSHBYTES_2 d007");
+    let tx = make_tx(
+        0,
+        vec![make_revocation_vin(&"c".repeat(64), 0, &script)],
+        vec![make_vout(490_000, "v0_p2wpkh")],
+    );
+    let result = classify_lightning(&tx);
+    assert_eq!(result.tx_type, Some(LightningTxType::Penalty));
+    assert_eq!(result.params.to_self_delay, Some(2000));
+}
+
+#[test]
+fn test_to_self_delay_absent_for_p2wsh_hash_only() {
+    // A P2WSH scriptpubkey only commits to the script hash; the delay isn't visible
+    let tx = make_tx(
+        0x20000000 | 0x123456,
+        vec![make_vin(0x80000000 | 0x000001)],
+        vec![make_vout(500_000, "v0_p2wsh")],
+    );
+    assert_eq!(classify_lightning(&tx).params.to_self_delay, None);
+}
+
+#[test]
+fn test_htlc_script_not_mistaken_for_to_local() {
+    let mut to_local = make_vout(500_000, "v0_p2wsh");
+    to_local.scriptpubkey_asm =
+        "OP_IF abc OP_ELSE 144 OP_CHECKSEQUENCEVERIFY OP_DROP def OP_ENDIF".to_string();
+    let tx = make_tx(0x20000000 | 0x123456, vec![make_vin(0x80000000 | 0x000001)], vec![to_local]);
+    assert_eq!(classify_lightning(&tx).params.to_self_delay, None);
+}
+
+// ─── Offered and received HTLC outputs ──────────────────────────────────────
+
+const HTLC_PREIMAGE: [u8; 32] = [0x42; 32];
+
+/// RIPEMD160 of the payment hash, as HTLC scripts commit to it.
+fn htlc_payment_hash() -> [u8; 20] {
+    hash160::Hash::hash(&HTLC_PREIMAGE).to_byte_array()
+}
+
+/// BOLT 3 offered HTLC output script (anchor variant).
+fn offered_htlc_script() -> ScriptBuf {
+    Builder::new()
+        .push_opcode(OP_DUP)
+        .push_opcode(OP_HASH160)
+        .push_slice([0x11; 20])
+        .push_opcode(OP_EQUAL)
+        .push_opcode(OP_IF)
+        .push_opcode(OP_CHECKSIG)
+        .push_opcode(OP_ELSE)
+        .push_slice([0x02; 33])
+        .push_opcode(OP_SWAP)
+        .push_opcode(OP_SIZE)
+        .push_int(32)
+        .push_opcode(OP_EQUAL)
+        .push_opcode(OP_NOTIF)
+        .push_opcode(OP_DROP)
+        .push_int(2)
+        .push_opcode(OP_SWAP)
+        .push_slice([0x03; 33])
+        .push_int(2)
+        .push_opcode(OP_CHECKMULTISIG)
+        .push_opcode(OP_ELSE)
+        .push_opcode(OP_HASH160)
+        .push_slice(htlc_payment_hash())
+        .push_opcode(OP_EQUALVERIFY)
+        .push_opcode(OP_CHECKSIG)
+        .push_opcode(OP_ENDIF)
+        .push_int(1)
+        .push_opcode(OP_CSV)
+        .push_opcode(OP_DROP)
+        .push_opcode(OP_ENDIF)
+        .into_script()
+}
+
+/// BOLT 3 received HTLC output script (anchor variant), expiring at block 886100.
+fn received_htlc_script() -> ScriptBuf {
+    Builder::new()
+        .push_opcode(OP_DUP)
+        .push_opcode(OP_HASH160)
+        .push_slice([0x11; 20])
+        .push_opcode(OP_EQUAL)
+        .push_opcode(OP_IF)
+        .push_opcode(OP_CHECKSIG)
+        .push_opcode(OP_ELSE)
+        .push_slice([0x02; 33])
+        .push_opcode(OP_SWAP)
+        .push_opcode(OP_SIZE)
+        .push_int(32)
+        .push_opcode(OP_EQUAL)
+        .push_opcode(OP_IF)
+        .push_opcode(OP_HASH160)
+        .push_slice(htlc_payment_hash())
+        .push_opcode(OP_EQUALVERIFY)
+        .push_int(2)
+        .push_opcode(OP_SWAP)
+        .push_slice([0x03; 33])
+        .push_int(2)
+        .push_opcode(OP_CHECKMULTISIG)
+        .push_opcode(OP_ELSE)
+        .push_opcode(OP_DROP)
+        .push_int(886100)
+        .push_opcode(OP_CLTV)
+        .push_opcode(OP_DROP)
+        .push_opcode(OP_CHECKSIG)
+        .push_opcode(OP_ENDIF)
+        .push_int(1)
+        .push_opcode(OP_CSV)
+        .push_opcode(OP_DROP)
+        .push_opcode(OP_ENDIF)
+        .into_script()
+}
+
+/// Second-stage HTLC transaction spending output `vout` of `parent`, which pays
+/// `script`, with the witness `<> <sig> <sig> <preimage or empty> <script>`. Decoded by
+/// `raw.rs` with the spent output, as the data sources do.
+fn htlc_spend(
+    parent: &str,
+    vout: u32,
+    script: &ScriptBuf,
+    preimage: &[u8],
+    locktime: u32,
+) -> ApiTransaction {
+    let signature = [[0x30].as_slice(), &[0x44; 70], &[0x01]].concat();
+    let input = TxIn {
+        previous_output: OutPoint::new(parent.parse().unwrap(), vout),
+        script_sig: ScriptBuf::new(),
+        sequence: Sequence(1),
+        witness: Witness::from_slice(&[
+            &[][..],
+            &signature,
+            &signature,
+            preimage,
+            script.as_bytes(),
+        ]),
+    };
+    let tx = Transaction {
+        version: Version::TWO,
+        lock_time: absolute::LockTime::from_consensus(locktime),
+        input: vec![input],
+        output: vec![TxOut {
+            value: Amount::from_sat(19_000),
+            script_pubkey: ScriptBuf::new_p2wpkh(&bitcoin::WPubkeyHash::from_byte_array([0x55; 20])),
+        }],
+    };
+    let prevout = TxOut {
+        value: Amount::from_sat(20_000),
+        script_pubkey: ScriptBuf::new_p2wsh(&script.wscript_hash()),
+    };
+    api_transaction_with_prevouts(&tx, &[prevout], Network::Bitcoin)
+}
+
+#[test]
+fn test_htlc_timeout_spends_offered_htlc() {
+    let tx = htlc_spend(&"c1".repeat(32), 1, &offered_htlc_script(), &[], 886100);
+    let result = classify_lightning(&tx);
+    assert_eq!(result.tx_type, Some(LightningTxType::HtlcTimeout));
+    assert_eq!(result.params.offered_htlc_count, Some(1));
+    assert_eq!(result.params.received_htlc_count, Some(0));
+}
+
+#[test]
+fn test_htlc_success_spends_received_htlc() {
+    let tx = htlc_spend(&"c1".repeat(32), 2, &received_htlc_script(), &HTLC_PREIMAGE, 0);
+    let result = classify_lightning(&tx);
+    assert_eq!(result.tx_type, Some(LightningTxType::HtlcSuccess));
+    assert_eq!(result.params.offered_htlc_count, Some(0));
+    assert_eq!(result.params.received_htlc_count, Some(1));
+}
+
+#[test]
+fn test_htlc_direction_unknown_without_scripts() {
+    // A commitment's outputs are P2WSH hashes: only the aggregate count is available
+    let tx = make_tx(
+        0x20000000 | 0x123456,
+        vec![make_vin(0x80000000 | 0x000001)],
+        vec![
+            make_vout(330, "v0_p2wsh"),
+            make_vout(500_000, "v0_p2wsh"),
+            make_vout(20_000, "v0_p2wsh"),
+        ],
+    );
+    let result = classify_lightning(&tx);
+    assert_eq!(result.params.htlc_output_count, Some(1));
+    assert_eq!(result.params.offered_htlc_count, None);
+    assert_eq!(result.params.received_htlc_count, None);
+}
+
+/// Pre-anchor commitment `c1` with one anchor, to_local and two HTLC outputs.
+fn commitment_with_two_htlcs() -> ApiTransaction {
+    let commitment = make_tx(
+        0x20000000 | 0x123456,
+        vec![make_vin(0x80000000 | 0x000001)],
+        vec![
+            make_vout(330, "v0_p2wsh"),
+            make_vout(500_000, "v0_p2wsh"),
+            make_vout(20_000, "v0_p2wsh"),
+            make_vout(30_000, "v0_p2wsh"),
+        ],
+    );
+    spending(commitment, "c1", "f0")
+}
+
+#[test]
+fn test_same_block_sweeps_give_commitment_htlc_directions() {
+    let txs = [
+        commitment_with_two_htlcs(),
+        htlc_spend(&"c1".repeat(32), 2, &offered_htlc_script(), &[], 886100),
+        htlc_spend(&"c1".repeat(32), 3, &received_htlc_script(), &HTLC_PREIMAGE, 0),
+    ];
+    let mut classified: Vec<_> = txs.iter().map(classify_lightning).collect();
+    assert_eq!(classified[0].params.offered_htlc_count, None);
+
+    link_same_block_sweeps(txs.iter().zip(&mut classified));
+    assert_eq!(classified[0].params.offered_htlc_count, Some(1));
+    assert_eq!(classified[0].params.received_htlc_count, Some(1));
+}
+
+#[test]
+fn test_commitment_htlc_directions_unknown_until_all_swept() {
+    let txs = [
+        commitment_with_two_htlcs(),
+        htlc_spend(&"c1".repeat(32), 2, &offered_htlc_script(), &[], 886100),
+    ];
+    let mut classified: Vec<_> = txs.iter().map(classify_lightning).collect();
+    link_same_block_sweeps(txs.iter().zip(&mut classified));
+    assert_eq!(classified[0].params.same_block_htlc_spends.len(), 1);
+    assert_eq!(classified[0].params.offered_htlc_count, None);
+    assert_eq!(classified[0].params.received_htlc_count, None);
+}
+
+// ─── Delayed to_remote (anchor channels) ────────────────────────────────────
+
+fn to_remote_script(delay: i64) -> ScriptBuf {
+    Builder::new()
+        .push_slice([0x02; 33])
+        .push_opcode(OP_CHECKSIGVERIFY)
+        .push_int(delay)
+        .push_opcode(OP_CSV)
+        .into_script()
+}
+
+/// Sweep of a commitment's to_remote output: the witness is the remote party's
+/// signature and the script, the input sequence satisfies the delay.
+fn to_remote_sweep(script: &ScriptBuf) -> ApiTransaction {
+    let signature = [[0x30].as_slice(), &[0x44; 70], &[0x01]].concat();
+    let tx = Transaction {
+        version: Version::TWO,
+        lock_time: absolute::LockTime::ZERO,
+        input: vec![TxIn {
+            previous_output: OutPoint::new("c1".repeat(32).parse().unwrap(), 1),
+            script_sig: ScriptBuf::new(),
+            sequence: Sequence(1),
+            witness: Witness::from_slice(&[signature.as_slice(), script.as_bytes()]),
+        }],
+        output: vec![TxOut {
+            value: Amount::from_sat(199_500),
+            script_pubkey: ScriptBuf::new_p2wpkh(&bitcoin::WPubkeyHash::from_byte_array([0x55; 20])),
+        }],
+    };
+    let prevout = TxOut {
+        value: Amount::from_sat(200_000),
+        script_pubkey: ScriptBuf::new_p2wsh(&script.wscript_hash()),
+    };
+    api_transaction_with_prevouts(&tx, &[prevout], Network::Bitcoin)
+}
+
+#[test]
+fn test_to_remote_sweep_sets_to_remote_delayed() {
+    let result = classify_lightning(&to_remote_sweep(&to_remote_script(1)));
+    assert!(result.commitment_signals.to_remote_delayed);
+    // The 1-block OP_CSV doesn't make the sweep an HTLC transaction
+    assert_eq!(result.tx_type, None);
+}
+
+#[test]
+fn test_to_remote_delayed_requires_one_block_csv() {
+    let result = classify_lightning(&to_remote_sweep(&to_remote_script(2)));
+    assert!(!result.commitment_signals.to_remote_delayed);
+}
+
+#[test]
+fn test_to_remote_delayed_unknown_without_scripts() {
+    // The commitment's P2WSH outputs only commit to their scripts' hashes
+    let tx = make_tx(
+        0x20000001,
+        vec![make_vin(0x80000001)],
+        vec![
+            make_vout(330, "v0_p2wsh"),
+            make_vout(200_000, "v0_p2wsh"),
+            make_vout(300_000, "v0_p2wsh"),
+        ],
+    );
+    let result = classify_lightning(&tx);
+    assert!(!result.commitment_signals.to_remote_delayed);
+    assert_eq!(result.confidence, Confidence::HighlyLikely);
+}
+
+// ─── Anchor output counting ─────────────────────────────────────────────────
+
+#[test]
+fn test_single_anchor_output() {
+    let locktime: u32 = 0x20000001;
+    let sequence: u32 = 0x80000001;
+    let tx = make_tx(
+        locktime,
+        vec![make_vin(sequence)],
+        vec![
+            make_vout(100_000, "v0_p2wsh"),
+            make_vout(330, "v0_p2wsh"), // single anchor
+        ],
+    );
+    let result = classify_lightning(&tx);
+    assert!(result.commitment_signals.has_anchor_outputs);
+    assert_eq!(result.commitment_signals.anchor_output_count, 1);
+}
+
+#[test]
+fn test_anchor_value_reported() {
+    let tx = make_tx(
+        0x20000001,
+        vec![make_vin(0x80000001)],
+        vec![
+            make_vout(100_000, "v0_p2wsh"),
+            make_vout(330, "v0_p2wsh"),
+            make_vout(330, "v0_p2wsh"),
+        ],
+    );
+    let result = classify_lightning(&tx);
+    assert_eq!(result.commitment_signals.anchor_value, Some(330));
+}
+
+const LOCAL_FUNDING_KEY: [u8; 33] = [0x02; 33];
+const REMOTE_FUNDING_KEY: [u8; 33] = [0x03; 33];
+
+/// Commitment input spending the 2-of-2 funding output, with the witness a real one
+/// carries, as the data sources and `raw.rs` decode it.
+fn funding_vin(sequence: u32) -> ApiVin {
+    let script = Builder::new()
+        .push_opcode(OP_PUSHNUM_2)
+        .push_slice(LOCAL_FUNDING_KEY)
+        .push_slice(REMOTE_FUNDING_KEY)
+        .push_opcode(OP_PUSHNUM_2)
+        .push_opcode(OP_CHECKMULTISIG)
+        .into_script();
+    let mut vin = make_vin(sequence);
+    let signature = format!("30{}01", "44".repeat(70));
+    vin.witness = Some(vec![String::new(), signature.clone(), signature, script.to_hex_string()]);
+    vin.inner_witnessscript_asm = Some(script.to_asm_string());
+    vin
+}
+
+/// P2WSH output paying `script`, which only its hash reveals.
+fn p2wsh_vout(value: u64, script: &ScriptBuf) -> ApiVout {
+    let spk = ScriptBuf::new_p2wsh(&script.wscript_hash());
+    ApiVout {
+        scriptpubkey: spk.to_hex_string(),
+        scriptpubkey_asm: spk.to_asm_string(),
+        scriptpubkey_type: "v0_p2wsh".to_string(),
+        scriptpubkey_address: None,
+        value,
+    }
+}
+
+/// BOLT 3 anchor output of `funding_key`.
+fn anchor_vout(value: u64, funding_key: [u8; 33]) -> ApiVout {
+    let script = Builder::new()
+        .push_slice(funding_key)
+        .push_opcode(OP_CHECKSIG)
+        .push_opcode(OP_IFDUP)
+        .push_opcode(OP_NOTIF)
+        .push_opcode(OP_PUSHNUM_16)
+        .push_opcode(OP_CSV)
+        .push_opcode(OP_ENDIF)
+        .into_script();
+    p2wsh_vout(value, &script)
+}
+
+#[test]
+fn test_anchors_matched_by_funding_keys() {
+    // With the funding script visible, only outputs paying an anchor script of its keys
+    // are anchors: a 330-sat HTLC output is not one
+    let tx = make_tx(
+        0x20000001,
+        vec![funding_vin(0x80000001)],
+        vec![
+            make_vout(100_000, "v0_p2wsh"),
+            anchor_vout(330, LOCAL_FUNDING_KEY),
+            anchor_vout(330, REMOTE_FUNDING_KEY),
+            make_vout(330, "v0_p2wsh"),
+        ],
+    );
+    let result = classify_lightning(&tx);
+    assert_eq!(result.commitment_signals.anchor_output_count, 2);
+    assert_eq!(result.confidence, Confidence::HighlyLikely);
+    assert_eq!(
+        result.params.output_roles,
+        vec![OutputRole::ToLocal, OutputRole::Anchor, OutputRole::Anchor, OutputRole::Htlc]
+    );
+}
+
+#[test]
+fn test_small_p2wsh_not_anchor_when_funding_script_visible() {
+    let tx = make_tx(
+        0x20000001,
+        vec![funding_vin(0x80000001)],
+        vec![make_vout(100_000, "v0_p2wsh"), make_vout(330, "v0_p2wsh")],
+    );
+    let result = classify_lightning(&tx);
+    assert!(!result.commitment_signals.has_anchor_outputs);
+    assert_eq!(result.params.htlc_output_count, Some(1));
+}
+
+#[test]
+fn test_non_standard_anchor_value_detected() {
+    // Tiny P2WSH outputs at a value other than 330 are still anchors
+    let tx = make_tx(
+        0x20000001,
+        vec![make_vin(0x80000001)],
+        vec![
+            make_vout(100_000, "v0_p2wsh"),
+            make_vout(240, "v0_p2wsh"),
+            make_vout(240, "v0_p2wsh"),
+        ],
+    );
+    let result = classify_lightning(&tx);
+    assert_eq!(result.commitment_signals.anchor_output_count, 2);
+    assert_eq!(result.commitment_signals.anchor_value, Some(240));
+}
+
+#[test]
+fn test_330_sat_key_path_output_not_anchor() {
+    // A 330-sat P2WPKH output is just a small payment, not an anchor
+    let tx = make_tx(
+        0,
+        vec![make_vin(0xFFFFFFFF)],
+        vec![make_vout(330, "v0_p2wpkh"), make_vout(50_000, "v0_p2wpkh")],
+    );
+    let result = classify_lightning(&tx);
+    assert!(!result.commitment_signals.has_anchor_outputs);
+    assert_eq!(result.tx_type, None);
+}
+
+#[test]
+fn test_anchor_falls_back_to_330_without_script_type() {
+    let tx = make_tx(
+        0,
+        vec![make_vin(0xFFFFFFFF)],
+        vec![make_vout(330, ""), make_vout(331, "")],
+    );
+    let result = classify_lightning(&tx);
+    assert_eq!(result.commitment_signals.anchor_output_count, 1);
+}
+
+#[test]
+fn test_zero_value_anchors_detected() {
+    let tx = make_tx(
+        0x20000001,
+        vec![make_vin(0x80000001)],
+        vec![
+            make_vout(100_000, "v0_p2wsh"),
+            make_vout(200_000, "v0_p2wpkh"),
+            make_vout(0, "v0_p2wsh"),
+            make_vout(0, "v0_p2wsh"),
+        ],
+    );
+    let result = classify_lightning(&tx);
+    assert_eq!(result.confidence, Confidence::HighlyLikely);
+    assert_eq!(result.commitment_signals.anchor_output_count, 2);
+    assert_eq!(result.commitment_signals.anchor_value, Some(0));
+    assert_eq!(result.params.htlc_output_count, Some(0));
+}
+
+#[test]
+fn test_anchor_recognized_by_script_hash_at_any_value() {
+    // The anchor's P2WSH hash matches the template of a funding key, so even a larger
+    // regtest anchor is recognized
+    let tx = make_tx(
+        0x20000001,
+        vec![funding_vin(0x80000001)],
+        vec![make_vout(100_000, "v0_p2wsh"), anchor_vout(1_000, LOCAL_FUNDING_KEY)],
+    );
+    let result = classify_lightning(&tx);
+    assert_eq!(result.commitment_signals.anchor_output_count, 1);
+    assert_eq!(result.commitment_signals.anchor_value, Some(1_000));
+    assert_eq!(result.confidence, Confidence::HighlyLikely);
+
+    // Without the funding script, the same output is too large to count
+    let tx = make_tx(
+        0x20000001,
+        vec![make_vin(0x80000001)],
+        vec![make_vout(100_000, "v0_p2wsh"), anchor_vout(1_000, LOCAL_FUNDING_KEY)],
+    );
+    assert_eq!(classify_lightning(&tx).commitment_signals.anchor_output_count, 0);
+}
+
+#[test]
+fn test_pay_to_anchor_output_needs_markers() {
+    let outputs = vec![make_vout(0, "anchor"), make_vout(200_000, "v0_p2wpkh")];
+
+    let tx = make_tx(0x20000001, vec![make_vin(0x80000001)], outputs.clone());
+    let result = classify_lightning(&tx);
+    assert_eq!(result.commitment_signals.anchor_output_count, 1);
+    assert_eq!(result.confidence, Confidence::HighlyLikely);
+
+    // Any TRUC transaction may carry a pay-to-anchor output
+    let tx = make_tx(0, vec![make_vin(0xFFFFFFFD)], outputs);
+    let result = classify_lightning(&tx);
+    assert!(!result.commitment_signals.has_anchor_outputs);
+}
+
+#[test]
+fn test_anchor_spend_not_htlc() {
+    // Spending an anchor reveals `<pubkey> OP_CHECKSIG OP_IFDUP OP_NOTIF OP_16 OP_CSV OP_ENDIF`
+    let mut vin = make_vin(0xFFFFFFFD);
+    vin.witness = Some(vec![format!("30{}01", "44".repeat(70)), "21".to_string()]);
+    vin.inner_witnessscript_asm = Some(format!(
+        "OP_PUSHBYTES_33 02{} OP_CHECKSIG OP_IFDUP OP_NOTIF OP_PUSHNUM_16 OP_CSV OP_ENDIF",
+        "ab".repeat(32)
+    ));
+    let tx = make_tx(886000, vec![vin], vec![make_vout(20_000, "v0_p2wpkh")]);
+    let result = classify_lightning(&tx);
+    assert!(!result.htlc_signals.script_has_csv);
+    assert_eq!(result.tx_type, None);
+}
+
+// ─── Trimmed HTLC detection ─────────────────────────────────────────────────
+
+/// Anchor commitment with a to_local output and `htlcs` HTLC outputs.
+fn commitment_with_fee(fee: Option<u64>, htlcs: usize) -> ApiTransaction {
+    let mut vouts = vec![
+        make_vout(100_000, "v0_p2wsh"),
+        make_vout(200_000, "v0_p2wpkh"),
+        make_vout(330, "v0_p2wsh"),
+        make_vout(330, "v0_p2wsh"),
+    ];
+    vouts.extend((0..htlcs).map(|_| make_vout(20_000, "v0_p2wsh")));
+    let mut tx = make_tx(0x20000042, vec![make_vin(0x80000001)], vouts);
+    tx.weight = 1_000;
+    tx.fee = fee;
+    tx
+}
+
+#[test]
+fn test_high_fee_commitment_possibly_trimmed() {
+    // 10 sat/WU, well above the 2.5 sat/WU threshold
+    let result = classify_lightning(&commitment_with_fee(Some(10_000), 0));
+    assert!(result.params.possible_trimmed_htlcs);
+    assert!(result.reasons.iter().any(|r| r.contains("trimmed HTLCs")));
+}
+
+#[test]
+fn test_low_fee_commitment_not_trimmed() {
+    // 0.5 sat/WU: an ordinary anchor commitment fee
+    let result = classify_lightning(&commitment_with_fee(Some(500), 0));
+    assert!(!result.params.possible_trimmed_htlcs);
+}
+
+#[test]
+fn test_high_fee_with_many_htlcs_not_trimmed() {
+    let result = classify_lightning(&commitment_with_fee(Some(10_000), 3));
+    assert_eq!(result.params.htlc_output_count, Some(3));
+    assert!(!result.params.possible_trimmed_htlcs);
+}
+
+#[test]
+fn test_trimmed_htlcs_need_fee() {
+    let result = classify_lightning(&commitment_with_fee(None, 0));
+    assert!(!result.params.possible_trimmed_htlcs);
+}
+
+// ─── Channel capacity ───────────────────────────────────────────────────────
+
+#[test]
+fn test_channel_capacity_from_outputs_and_fee() {
+    // 100_000 to_local + 200_000 to_remote + 2 × 330 anchors + 2 × 20_000 HTLCs + fee
+    let result = classify_lightning(&commitment_with_fee(Some(1_500), 2));
+    assert_eq!(result.tx_type, Some(LightningTxType::Commitment));
+    assert_eq!(result.params.approx_channel_capacity, Some(342_160));
+}
+
+#[test]
+fn test_channel_capacity_without_fee_counts_outputs() {
+    let result = classify_lightning(&commitment_with_fee(None, 0));
+    assert_eq!(result.params.approx_channel_capacity, Some(300_660));
+}
+
+#[test]
+fn test_channel_capacity_from_funding_prevout() {
+    let mut tx = commitment_with_fee(None, 1);
+    tx.vin[0].prevout = Some(ApiPrevout {
+        value: 1_000_000,
+        ..make_prevout("v0_p2wsh", "OP_0 OP_PUSHBYTES_32 ab")
+    });
+    assert_eq!(classify_lightning(&tx).params.approx_channel_capacity, Some(1_000_000));
+}
+
+#[test]
+fn test_channel_capacity_only_for_commitments() {
+    let tx = make_tx(0, vec![make_vin(0xFFFFFFFF)], vec![make_vout(50_000, "v0_p2wpkh")]);
+    assert_eq!(classify_lightning(&tx).params.approx_channel_capacity, None);
+}
+
+// ─── Anchor sweep (CPFP) detection ──────────────────────────────────────────
+
+fn make_anchor_vin(parent_txid: &str, value: u64) -> ApiVin {
+    let mut vin = make_vin(0xFFFFFFFD);
+    vin.txid = Some(parent_txid.to_string());
+    vin.prevout = Some(ApiPrevout {
+        scriptpubkey: "00".to_string(),
+        scriptpubkey_asm: "OP_0".to_string(),
+        scriptpubkey_type: "v0_p2wsh".to_string(),
+        scriptpubkey_address: None,
+        value,
+    });
+    vin.witness = Some(vec![format!("30{}01", "44".repeat(70)), "21".to_string()]);
+    vin.inner_witnessscript_asm = Some(format!(
+        "OP_PUSHBYTES_33 02{} OP_CHECKSIG OP_IFDUP OP_NOTIF OP_PUSHNUM_16 OP_CSV OP_ENDIF",
+        "ab".repeat(32)
+    ));
+    vin
+}
+
+#[test]
+fn test_anchor_sweep_detected() {
+    let parent = "cc".repeat(32);
+    let tx = make_tx(
+        886000,
+        vec![make_anchor_vin(&parent, 330), make_vin(0xFFFFFFFD)],
+        vec![make_vout(49_000, "v0_p2wpkh")],
+    );
+    let result = classify_lightning(&tx);
+    assert_eq!(result.tx_type, Some(LightningTxType::AnchorSweep));
+    assert_eq!(result.confidence, Confidence::Possible);
+    assert_eq!(result.params.parent_txid, Some(parent));
+}
+
+#[test]
+fn test_anchor_sweep_non_standard_anchor_value() {
+    let tx = make_tx(
+        0,
+        vec![make_anchor_vin(&"cc".repeat(32), 240), make_vin(0xFFFFFFFD)],
+        vec![make_vout(49_000, "v0_p2wpkh")],
+    );
+    assert_eq!(classify_lightning(&tx).tx_type, Some(LightningTxType::AnchorSweep));
+}
+
+#[test]
+fn test_anchor_sweep_requires_anchor_value() {
+    let tx = make_tx(0, vec![make_anchor_vin(&"cc".repeat(32), 10_000)], vec![make_vout(9_000, "v0_p2wpkh")]);
+    assert_eq!(classify_lightning(&tx).tx_type, None);
+}
+
+#[test]
+fn test_anchor_sweep_requires_single_output() {
+    let tx = make_tx(
+        0,
+        vec![make_anchor_vin(&"cc".repeat(32), 330), make_vin(0xFFFFFFFD)],
+        vec![make_vout(30_000, "v0_p2wpkh"), make_vout(19_000, "v0_p2wpkh")],
+    );
+    assert_eq!(classify_lightning(&tx).tx_type, None);
+}
+
+#[test]
+fn test_anchor_sweep_rejects_large_tx() {
+    let mut tx = make_tx(0, vec![make_anchor_vin(&"cc".repeat(32), 330)], vec![make_vout(49_000, "v0_p2wpkh")]);
+    tx.weight = 20_000;
+    assert_eq!(classify_lightning(&tx).tx_type, None);
+}
+
+// ─── Malformed transactions ─────────────────────────────────────────────────
+
+#[test]
+fn test_no_inputs_is_not_lightning() {
+    // Lightning locktime and anchor-like outputs, but no inputs to spend
+    let tx = make_tx(
+        0x20000042,
+        vec![],
+        vec![make_vout(100_000, "v0_p2wsh"), make_vout(330, "v0_p2wsh")],
+    );
+    let result = classify_lightning(&tx);
+    assert_eq!(result.tx_type, None);
+    assert_eq!(result.confidence, Confidence::None);
+    assert!(!result.commitment_signals.locktime_match);
+    assert_eq!(result.reasons, vec!["transaction has no inputs"]);
+
+    let (_, trace) = explain_lightning(&tx);
+    assert_eq!(trace_checks(&trace), vec![("inputs and outputs", true)]);
+    assert_eq!(trace.steps[0].details, vec!["0 inputs, 2 outputs"]);
+}
+
+#[test]
+fn test_no_outputs_is_not_lightning() {
+    let tx = make_tx(0x20000042, vec![make_vin(0x80000001)], vec![]);
+    let result = classify_lightning(&tx);
+    assert_eq!(result.tx_type, None);
+    assert_eq!(result.reasons, vec!["transaction has no outputs"]);
+
+    let empty = make_tx(0, vec![], vec![]);
+    assert_eq!(classify_lightning(&empty).reasons, vec!["transaction has no inputs or outputs"]);
+}
+
+// ─── Penalty (justice) detection ────────────────────────────────────────────
+
+fn make_revocation_vin(prev_txid: &str, vout: u32, script: &str) -> ApiVin {
+    let mut vin = make_vin(0xFFFFFFFF);
+    vin.txid = Some(prev_txid.to_string());
+    vin.vout = Some(vout);
+    vin.witness = Some(vec![format!("30{}01", "44".repeat(70)), "01".to_string(), "63".to_string()]);
+    vin.inner_witnessscript_asm = Some(script.to_string());
+    vin
+}
+
+const TO_LOCAL_SCRIPT: &str =
+    "OP_IF OP_PUSHBYTES_33 02aa OP_ELSE 144 OP_CHECKSEQUENCEVERIFY OP_DROP OP_PUSHBYTES_33 03bb OP_ENDIF OP_CHECKSIG";
+const OFFERED_HTLC_SCRIPT: &str =
+    "OP_DUP OP_HASH160 abc OP_EQUAL OP_IF OP_CHECKSIG OP_ELSE 02cc OP_SWAP OP_SIZE 32 OP_EQUAL OP_NOTIF OP_DROP 2 OP_SWAP 03dd 2 OP_CHECKMULTISIG OP_ELSE OP_HASH160 def OP_EQUALVERIFY OP_CHECKSIG OP_ENDIF 1 OP_CHECKSEQUENCEVERIFY OP_DROP OP_ENDIF";
+
+#[test]
+fn test_penalty_sweep_multiple_outputs() {
+    // Justice tx sweeping to_local + two HTLC outputs of the same revoked commitment
+    let revoked = "cd".repeat(32);
+    let tx = make_tx(
+        0,
+        vec![
+            make_revocation_vin(&revoked, 0, TO_LOCAL_SCRIPT),
+            make_revocation_vin(&revoked, 2, OFFERED_HTLC_SCRIPT),
+            make_revocation_vin(&revoked, 3, OFFERED_HTLC_SCRIPT),
+        ],
+        vec![make_vout(900_000, "v0_p2wpkh")],
+    );
+    let result = classify_lightning(&tx);
+    assert_eq!(result.tx_type, Some(LightningTxType::Penalty));
+    assert_eq!(result.confidence, Confidence::HighlyLikely);
+    assert_eq!(result.params.swept_output_count, Some(3));
+}
+
+#[test]
+fn test_penalty_single_to_local_sweep_is_possible() {
+    let tx = make_tx(
+        0,
+        vec![make_revocation_vin(&"cd".repeat(32), 0, TO_LOCAL_SCRIPT)],
+        vec![make_vout(500_000, "v0_p2wpkh")],
+    );
+    let result = classify_lightning(&tx);
+    assert_eq!(result.tx_type, Some(LightningTxType::Penalty));
+    assert_eq!(result.confidence, Confidence::Possible);
+    assert_eq!(result.params.swept_output_count, Some(1));
+}
+
+#[test]
+fn test_penalty_counts_largest_group_per_commitment() {
+    // Two revocation spends from one commitment, one from another
+    let tx = make_tx(
+        0,
+        vec![
+            make_revocation_vin(&"cd".repeat(32), 0, TO_LOCAL_SCRIPT),
+            make_revocation_vin(&"cd".repeat(32), 1, OFFERED_HTLC_SCRIPT),
+            make_revocation_vin(&"ef".repeat(32), 0, TO_LOCAL_SCRIPT),
+        ],
+        vec![make_vout(900_000, "v0_p2wpkh")],
+    );
+    let result = classify_lightning(&tx);
+    assert_eq!(result.params.swept_output_count, Some(2));
+}
+
+#[test]
+fn test_delayed_to_local_sweep_not_penalty() {
+    // Sequence satisfies the CSV delay → owner's delayed sweep, not a revocation
+    let revoked = "cd".repeat(32);
+    let mut first = make_revocation_vin(&revoked, 0, TO_LOCAL_SCRIPT);
+    first.sequence = 144;
+    let mut second = make_revocation_vin(&revoked, 1, TO_LOCAL_SCRIPT);
+    second.sequence = 144;
+    let tx = make_tx(0, vec![first, second], vec![make_vout(900_000, "v0_p2wpkh")]);
+    let result = classify_lightning(&tx);
+    assert_ne!(result.tx_type, Some(LightningTxType::Penalty));
+    assert_eq!(result.params.swept_output_count, None);
+}
+
+// ─── to_local sweep (delayed self-sweep) detection ──────────────────────────
+
+fn make_to_local_sweep_vin(prev_txid: &str, sequence: u32) -> ApiVin {
+    let mut vin = make_vin(sequence);
+    vin.txid = Some(prev_txid.to_string());
+    vin.vout = Some(0);
+    // Signature, empty selector for the OP_ELSE branch, witness script
+    vin.witness = Some(vec![format!("30{}01", "44".repeat(70)), "".to_string(), "63".to_string()]);
+    vin.inner_witnessscript_asm = Some(TO_LOCAL_SCRIPT.to_string());
+    vin
+}
+
+#[test]
+fn test_to_local_sweep_after_delay() {
+    let commitment = "cd".repeat(32);
+    let tx = make_tx(
+        0,
+        vec![make_to_local_sweep_vin(&commitment, 144)],
+        vec![make_vout(490_000, "v0_p2wpkh")],
+    );
+    let result = classify_lightning(&tx);
+    assert_eq!(result.tx_type, Some(LightningTxType::ToLocalSweep));
+    assert_eq!(result.confidence, Confidence::HighlyLikely);
+    assert_eq!(result.params.to_self_delay, Some(144));
+    assert_eq!(result.params.parent_txid, Some(commitment));
+    assert_eq!(result.params.csv_delays, vec![144]);
+    assert!(result.reasons.iter().any(|r| r.contains("to_self_delay of 144 blocks")));
+}
+
+#[test]
+fn test_to_local_sweep_with_pushed_delay() {
+    let mut vin = make_to_local_sweep_vin(&"cd".repeat(32), 2016);
+    vin.inner_witnessscript_asm = Some(to_local_script("OP_PUSHBYTES_2 d007"));
+    let tx = make_tx(0, vec![vin], vec![make_vout(490_000, "v0_p2wpkh")]);
+    let result = classify_lightning(&tx);
+    assert_eq!(result.tx_type, Some(LightningTxType::ToLocalSweep));
+    assert_eq!(result.params.to_self_delay, Some(2000));
+}
+
+#[test]
+fn test_to_local_sweep_needs_sequence_past_delay() {
+    let tx = make_tx(
+        0,
+        vec![make_to_local_sweep_vin(&"cd".repeat(32), 143)],
+        vec![make_vout(490_000, "v0_p2wpkh")],
+    );
+    assert_ne!(classify_lightning(&tx).tx_type, Some(LightningTxType::ToLocalSweep));
+}
+
+#[test]
+fn test_to_local_sweep_ignores_time_based_sequence() {
+    let tx = make_tx(
+        0,
+        vec![make_to_local_sweep_vin(&"cd".repeat(32), (1 << 22) | 144)],
+        vec![make_vout(490_000, "v0_p2wpkh")],
+    );
+    assert_ne!(classify_lightning(&tx).tx_type, Some(LightningTxType::ToLocalSweep));
+}
+
+#[test]
+fn test_to_local_revocation_branch_is_penalty_not_sweep() {
+    let mut vin = make_to_local_sweep_vin(&"cd".repeat(32), 0xFFFFFFFF);
+    vin.witness = Some(vec![format!("30{}01", "44".repeat(70)), "01".to_string(), "63".to_string()]);
+    let tx = make_tx(0, vec![vin], vec![make_vout(490_000, "v0_p2wpkh")]);
+    assert_eq!(classify_lightning(&tx).tx_type, Some(LightningTxType::Penalty));
+}
+
+#[test]
+fn test_lightning_tally_counts_to_local_sweeps() {
+    let tx = make_tx(
+        0,
+        vec![make_to_local_sweep_vin(&"cd".repeat(32), 144)],
+        vec![make_vout(490_000, "v0_p2wpkh")],
+    );
+    let mut tally = LightningTally::default();
+    tally.record(&classify_lightning(&tx));
+    assert_eq!(tally.to_local_sweeps, 1);
+    assert_eq!(tally.lightning(), 1);
+}
+
+// ─── Mutual close detection ─────────────────────────────────────────────────
+
+fn make_funding_spend_vin(sequence: u32) -> ApiVin {
+    let mut vin = make_vin(sequence);
+    vin.witness = Some(vec![
+        String::new(),
+        format!("30{}01", "44".repeat(70)),
+        format!("30{}01", "45".repeat(70)),
+        "5221".to_string(),
+    ]);
+    vin.inner_witnessscript_asm = Some(format!(
+        "OP_PUSHNUM_2 OP_PUSHBYTES_33 02{} OP_PUSHBYTES_33 03{} OP_PUSHNUM_2 OP_CHECKMULTISIG",
+        "11".repeat(32),
+        "22".repeat(32)
+    ));
+    vin
+}
+
+#[test]
+fn test_mutual_close_highly_likely() {
+    // 2-of-2 funding spend, locktime 0, final sequence, two key-path outputs
+    let tx = make_tx(
+        0,
+        vec![make_funding_spend_vin(0xFFFFFFFF)],
+        vec![make_vout(400_000, "v0_p2wpkh"), make_vout(600_000, "v1_p2tr")],
+    );
+    let result = classify_lightning(&tx);
+    assert_eq!(result.tx_type, Some(LightningTxType::MutualClose));
+    assert_eq!(result.confidence, Confidence::HighlyLikely);
+    assert!(result.mutual_close_signals.two_of_two_input);
+    assert_eq!(result.mutual_close_signals.key_path_output_count, 2);
+}
+
+#[test]
+fn test_mutual_close_single_output_is_possible() {
+    // One side had no balance → single output
+    let tx = make_tx(
+        0,
+        vec![make_funding_spend_vin(0xFFFFFFFF)],
+        vec![make_vout(1_000_000, "v0_p2wpkh")],
+    );
+    let result = classify_lightning(&tx);
+    assert_eq!(result.tx_type, Some(LightningTxType::MutualClose));
+    assert_eq!(result.confidence, Confidence::Possible);
+}
+
+#[test]
+fn test_mutual_close_with_p2wsh_output_is_possible() {
+    let tx = make_tx(
+        0,
+        vec![make_funding_spend_vin(0xFFFFFFFF)],
+        vec![make_vout(400_000, "v0_p2wpkh"), make_vout(600_000, "v0_p2wsh")],
+    );
+    let result = classify_lightning(&tx);
+    assert_eq!(result.tx_type, Some(LightningTxType::MutualClose));
+    assert_eq!(result.confidence, Confidence::Possible);
+    assert!(result.mutual_close_signals.has_anchor_or_htlc_outputs);
+}
+
+#[test]
+fn test_multisig_without_signatures_not_mutual_close() {
+    // Witness script is 2-of-2 but witness doesn't carry two signatures
+    let mut vin = make_funding_spend_vin(0xFFFFFFFF);
+    vin.witness = Some(vec![String::new(), "5221".to_string()]);
+    let tx = make_tx(
+        0,
+        vec![vin],
+        vec![make_vout(400_000, "v0_p2wpkh"), make_vout(600_000, "v0_p2wpkh")],
+    );
+    let result = classify_lightning(&tx);
+    assert_eq!(result.tx_type, None);
+    assert!(!result.mutual_close_signals.two_of_two_input);
+}
+
+#[test]
+fn test_force_close_of_funding_output_is_commitment() {
+    // Same 2-of-2 funding spend but with commitment encoding → commitment wins
+    let tx = make_tx(
+        0x20000042,
+        vec![make_funding_spend_vin(0x80000001)],
+        vec![
+            make_vout(100_000, "v0_p2wsh"),
+            make_vout(200_000, "v0_p2wpkh"),
+            make_vout(330, "v0_p2wsh"),
+            make_vout(330, "v0_p2wsh"),
+        ],
+    );
+    let result = classify_lightning(&tx);
+    assert_eq!(result.tx_type, Some(LightningTxType::Commitment));
+}
+
+// ─── Splice detection ───────────────────────────────────────────────────────
+
+fn make_splice_vin(funding_value: u64) -> ApiVin {
+    let mut vin = make_funding_spend_vin(0xFFFFFFFD);
+    vin.prevout = Some(ApiPrevout {
+        value: funding_value,
+        ..make_prevout("v0_p2wsh", "OP_0 OP_PUSHBYTES_32 aa")
+    });
+    vin
+}
+
+#[test]
+fn test_splice_in_detection() {
+    // Funding output plus a wallet input → larger funding output and change
+    let tx = make_tx(
+        886000,
+        vec![make_splice_vin(1_000_000), make_vin(0xFFFFFFFD)],
+        vec![make_vout(1_500_000, "v0_p2wsh"), make_vout(20_000, "v0_p2wpkh")],
+    );
+    let result = classify_lightning(&tx);
+    assert_eq!(result.tx_type, Some(LightningTxType::Splice));
+    assert_eq!(result.confidence, Confidence::HighlyLikely);
+    assert_eq!(result.params.old_funding_value, Some(1_000_000));
+    assert_eq!(result.params.new_funding_value, Some(1_500_000));
+    assert!(result.reasons.contains(&"new 1500000-sat P2WSH funding output (was 1000000 sats)".to_string()));
+}
+
+#[test]
+fn test_splice_out_detection() {
+    // A payment out of the channel next to the smaller funding output
+    let tx = make_tx(
+        0,
+        vec![make_splice_vin(1_000_000)],
+        vec![make_vout(700_000, "v0_p2wsh"), make_vout(299_000, "v1_p2tr")],
+    );
+    let result = classify_lightning(&tx);
+    assert_eq!(result.tx_type, Some(LightningTxType::Splice));
+    assert_eq!(result.params.new_funding_value, Some(700_000));
+}
+
+#[test]
+fn test_splice_smaller_than_other_output_is_possible() {
+    let tx = make_tx(
+        0,
+        vec![make_splice_vin(1_000_000)],
+        vec![make_vout(300_000, "v0_p2wsh"), make_vout(699_000, "v0_p2wpkh")],
+    );
+    let result = classify_lightning(&tx);
+    assert_eq!(result.tx_type, Some(LightningTxType::Splice));
+    assert_eq!(result.confidence, Confidence::Possible);
+}
+
+#[test]
+fn test_funding_spend_to_key_path_outputs_not_splice() {
+    let tx = make_tx(
+        0,
+        vec![make_splice_vin(1_000_000)],
+        vec![make_vout(400_000, "v0_p2wpkh"), make_vout(599_000, "v1_p2tr")],
+    );
+    let result = classify_lightning(&tx);
+    assert_eq!(result.tx_type, Some(LightningTxType::MutualClose));
+}
+
+#[test]
+fn test_small_p2wsh_output_not_splice() {
+    let tx = make_tx(
+        0,
+        vec![make_splice_vin(1_000_000)],
+        vec![make_vout(10_000, "v0_p2wsh"), make_vout(989_000, "v0_p2wpkh")],
+    );
+    let result = classify_lightning(&tx);
+    assert_ne!(result.tx_type, Some(LightningTxType::Splice));
+}
+
+// ─── Classification reasons ─────────────────────────────────────────────────
+
+#[test]
+fn test_commitment_reasons() {
+    let tx = make_tx(
+        0x20000042,
+        vec![make_vin(0x80000001)],
+        vec![
+            make_vout(100_000, "v0_p2wsh"),
+            make_vout(200_000, "v0_p2wpkh"),
+            make_vout(330, "v0_p2wsh"),
+            make_vout(330, "v0_p2wsh"),
+        ],
+    );
+    let result = classify_lightning(&tx);
+    assert_eq!(
+        result.reasons,
+        vec![
+            "locktime upper byte 0x20",
+            "sequence upper byte 0x80",
+            "2 anchor outputs",
+        ]
+    );
+}
+
+#[test]
+fn test_htlc_success_reasons() {
+    let mut vin = make_vin(0);
+    vin.witness = Some(vec!["ab".repeat(32), "3045".to_string()]);
+    vin.inner_witnessscript_asm =
+        Some("OP_HASH160 abc OP_EQUAL OP_IF OP_ELSE 500000 OP_CHECKLOCKTIMEVERIFY OP_ENDIF".to_string());
+    let tx = make_tx(0, vec![vin], vec![make_vout(50_000, "v0_p2wsh")]);
+    let result = classify_lightning(&tx);
+    assert_eq!(result.tx_type, Some(LightningTxType::HtlcSuccess));
+    assert!(result.reasons.contains(&"32-byte preimage in witness".to_string()));
+    assert!(result.reasons.contains(&"script contains OP_CHECKLOCKTIMEVERIFY".to_string()));
+    assert!(result.reasons.contains(&"nLockTime 0".to_string()));
+}
+
+#[test]
+fn test_not_lightning_has_no_reasons() {
+    let tx = make_tx(0, vec![make_vin(0xFFFFFFFF)], vec![make_vout(50_000, "v0_p2wpkh")]);
+    let result = classify_lightning(&tx);
+    assert!(result.reasons.is_empty());
+}
+
+#[test]
+fn test_reasons_serialized() {
+    let tx = make_tx(0x20000042, vec![make_vin(0xFFFFFFFF)], vec![make_vout(50_000, "v0_p2wpkh")]);
+    let json = serde_json::to_value(classify_lightning(&tx)).unwrap();
+    assert_eq!(json["reasons"], serde_json::json!(["locktime upper byte 0x20"]));
+}
+
+// ─── Classification trace ───────────────────────────────────────────────────
+
+fn trace_checks(trace: &ClassificationTrace) -> Vec<(&str, bool)> {
+    trace.steps.iter().map(|s| (s.check.as_str(), s.matched)).collect()
+}
+
+#[test]
+fn test_explain_commitment_stops_at_first_match() {
+    let tx = make_tx(
+        0x20000042,
+        vec![make_vin(0x80000001)],
+        vec![make_vout(100_000, "v0_p2wsh"), make_vout(330, "v0_p2wsh")],
+    );
+    let (result, trace) = explain_lightning(&tx);
+    assert_eq!(result.tx_type, Some(LightningTxType::Commitment));
+    assert_eq!(
+        trace_checks(&trace),
+        vec![("inputs and outputs", false), ("coinbase", false), ("commitment", true)]
+    );
+
+    let details = &trace.steps[2].details;
+    assert!(details.contains(&"locktime 0x20000042 has upper byte 0x20: yes (+1)".to_string()));
+    assert!(details.contains(&"anchor outputs: 1 yes (+2)".to_string()));
+    assert!(details.contains(&"score 4 (at least 3) -> highly likely".to_string()));
+    assert!(details.last().unwrap().starts_with("takes priority over"));
+}
+
+#[test]
+fn test_explain_htlc_timeout_trace() {
+    let mut vin = make_vin(0);
+    vin.witness = Some(vec!["".to_string(), "3045".to_string()]);
+    vin.inner_witnessscript_asm =
+        Some("OP_IF abc OP_ELSE 886100 OP_CHECKLOCKTIMEVERIFY OP_DROP OP_ENDIF".to_string());
+    let tx = make_tx(886100, vec![vin], vec![make_vout(50_000, "v0_p2wsh")]);
+    let (result, trace) = explain_lightning(&tx);
+    assert_eq!(result.tx_type, Some(LightningTxType::HtlcTimeout));
+    assert_eq!(
+        trace_checks(&trace),
+        vec![
+            ("inputs and outputs", false),
+            ("coinbase", false),
+            ("commitment", false),
+            ("penalty", false),
+            ("to_local sweep", false),
+            ("anchor sweep", false),
+            ("HTLC", true),
+        ]
+    );
+    assert!(trace.steps[2].details.contains(&"score 0 -> not a commitment".to_string()));
+    let htlc = &trace.steps[6].details;
+    assert!(htlc.contains(&"locktime: block height 886100".to_string()));
+    assert!(htlc.contains(&"HTLC inputs: 0 success, 1 timeout -> HTLC-timeout, highly likely".to_string()));
+}
+
+#[test]
+fn test_explain_not_lightning_runs_every_check() {
+    let tx = make_tx(0, vec![make_vin(0xFFFFFFFF)], vec![make_vout(50_000, "v0_p2wpkh")]);
+    let (result, trace) = explain_lightning(&tx);
+    assert_eq!(result.tx_type, None);
+    assert_eq!(trace.steps.len(), 9);
+    assert!(trace.steps.iter().all(|s| !s.matched));
+}
+
+#[test]
+fn test_explain_matches_classify() {
+    let tx = make_tx(
+        0x20000042,
+        vec![make_vin(0xFFFFFFFF)],
+        vec![make_vout(50_000, "v0_p2wpkh")],
+    );
+    let (explained, _) = explain_lightning(&tx);
+    let classified = classify_lightning(&tx);
+    assert_eq!(explained.tx_type, classified.tx_type);
+    assert_eq!(explained.confidence, classified.confidence);
+    assert_eq!(explained.reasons, classified.reasons);
+}
+
+// ─── Range tallies ──────────────────────────────────────────────────────────
+
+#[test]
+fn test_lightning_tally_across_blocks() {
+    let commitment = make_tx(0x20000000 | 0x123456, vec![make_vin(0x80000001)], vec![make_vout(330, "v0_p2wsh")]);
+    let regular = make_tx(0, vec![make_vin(0xFFFFFFFF)], vec![make_vout(10_000, "v0_p2wpkh")]);
+
+    let mut first = LightningTally::default();
+    first.record(&classify_lightning(&commitment));
+    first.record(&classify_lightning(&regular));
+    let mut second = LightningTally::default();
+    second.record(&classify_lightning(&commitment));
+
+    let mut total = LightningTally::default();
+    total.add(&first);
+    total.add(&second);
+    assert_eq!(total.transactions, 3);
+    assert_eq!(total.commitments, 2);
+    assert_eq!(total.lightning(), 2);
+}
+
+#[test]
+fn test_lightning_tally_counts_anchor_sweeps() {
+    let sweep = make_tx(0, vec![make_anchor_vin(&"cc".repeat(32), 330)], vec![make_vout(49_000, "v0_p2wpkh")]);
+    let mut tally = LightningTally::default();
+    tally.record(&classify_lightning(&sweep));
+    assert_eq!(tally.anchor_sweeps, 1);
+    assert_eq!(tally.lightning(), 1);
+}
+
+#[test]
+fn test_lightning_tally_csv_delay_summary() {
+    let tx = make_tx(0, vec![make_vin(0xFFFFFFFF)], vec![make_vout(10_000, "v0_p2wpkh")]);
+    let with_delays = |delays: &[u16]| {
+        let mut lc = classify_lightning(&tx);
+        lc.params.csv_delays = delays.to_vec();
+        lc
+    };
+
+    let mut first = LightningTally::default();
+    assert_eq!(first.csv_delay_summary(), None);
+    first.record(&with_delays(&[144, 1]));
+    first.record(&with_delays(&[2016]));
+    let mut second = LightningTally::default();
+    second.record(&with_delays(&[144, 6, 1]));
+    first.add(&second);
+
+    // Delays 1, 1, 6, 144, 144, 2016: the tie between 1 and 144 goes to the smaller
+    let summary = first.csv_delay_summary().unwrap();
+    assert_eq!(
+        summary,
+        DelaySummary { count: 6, min: 1, median: 6, max: 2016, most_common: 1 }
+    );
+
+    let json = serde_json::to_value(&first).unwrap();
+    assert_eq!(json["csv_delay_summary"]["median"], 6);
+}
+
+#[test]
+fn test_delay_summary_odd_count() {
+    let distribution = [(144, 2), (2016, 1)].into_iter().collect();
+    let summary = DelaySummary::from_distribution(&distribution).unwrap();
+    assert_eq!(summary.median, 144);
+    assert_eq!(summary.most_common, 144);
+}
+
+// ─── Edge cases ──────────────────────────────────────────────────────────────
+
+#[test]
+fn test_coinbase_tx_not_lightning() {
+    let mut vin = make_vin(0xFFFFFFFF);
+    vin.is_coinbase = true;
+    vin.txid = None;
+    let tx = make_tx(
+        0x20000042,
+        vec![vin],
+        vec![make_vout(312_500_000, "v1_p2tr")],
+    );
+    let result = classify_lightning(&tx);
+    // Coinbase should not be classified as Lightning even if locktime happens to match
+    assert_eq!(result.tx_type, None);
+}
+
+#[test]
+fn test_commitment_takes_priority_over_htlc() {
+    // A tx that has both commitment signals (locktime 0x20, sequence 0x80)
+    // and a witness with 32-byte element should be classified as commitment, not HTLC
+    let preimage = "cc".repeat(32);
+    let mut vin = make_vin(0x80000001);
+    vin.witness = Some(vec![preimage, "3045".to_string()]);
+    let tx = make_tx(
+        0x20000042,
+        vec![vin],
+        vec![
+            make_vout(100_000, "v0_p2wsh"),
+            make_vout(330, "v0_p2wsh"),
+            make_vout(330, "v0_p2wsh"),
+        ],
+    );
+    let result = classify_lightning(&tx);
+    assert_eq!(result.tx_type, Some(LightningTxType::Commitment));
+}
+
+#[test]
+fn test_htlc_output_count_on_commitment() {
+    // Commitment with 3 HTLC outputs (P2WSH, not anchors, not to_remote P2WPKH)
+    let locktime: u32 = 0x20000001;
+    let sequence: u32 = 0x80000001;
+    let tx = make_tx(
+        locktime,
+        vec![make_vin(sequence)],
+        vec![
+            make_vout(100_000, "v0_p2wsh"),   // to_local
+            make_vout(200_000, "v0_p2wpkh"),  // to_remote
+            make_vout(330, "v0_p2wsh"),        // anchor
+            make_vout(330, "v0_p2wsh"),        // anchor
+            make_vout(50_000, "v0_p2wsh"),     // HTLC 1
+            make_vout(60_000, "v0_p2wsh"),     // HTLC 2
+            make_vout(70_000, "v0_p2wsh"),     // HTLC 3
         ],
     );
     let result = classify_lightning(&tx);
     assert_eq!(result.tx_type, Some(LightningTxType::Commitment));
     // HTLC outputs = P2WSH outputs that aren't anchors (330 sats) or to_local (first P2WSH)
-    // This is heuristic — the exact count depends on implementation logic
-    assert!(result.params.htlc_output_count.is_some());
+    assert_eq!(result.params.htlc_output_count, Some(3));
+    assert_eq!(
+        result.params.output_roles,
+        vec![
+            OutputRole::ToLocal,
+            OutputRole::ToRemote,
+            OutputRole::Anchor,
+            OutputRole::Anchor,
+            OutputRole::Htlc,
+            OutputRole::Htlc,
+            OutputRole::Htlc,
+        ]
+    );
+}
+
+#[test]
+fn test_output_roles_follow_vout_order() {
+    // Anchors listed first don't shift which P2WSH counts as to_local
+    let tx = make_tx(
+        0x20000001,
+        vec![make_vin(0x80000001)],
+        vec![
+            make_vout(330, "v0_p2wsh"),
+            make_vout(50_000, "v0_p2wsh"),
+            make_vout(200_000, "v1_p2tr"),
+            make_vout(60_000, "v0_p2wsh"),
+            make_vout(0, "op_return"),
+        ],
+    );
+    let result = classify_lightning(&tx);
+    assert_eq!(
+        result.params.output_roles,
+        vec![
+            OutputRole::Anchor,
+            OutputRole::ToLocal,
+            OutputRole::ToRemote,
+            OutputRole::Htlc,
+            OutputRole::Other,
+        ]
+    );
+    assert_eq!(result.params.htlc_output_count, Some(1));
+}
+
+#[test]
+fn test_output_roles_only_on_commitments() {
+    let tx = make_tx(0, vec![make_vin(0xFFFFFFFF)], vec![make_vout(50_000, "v0_p2wsh")]);
+    assert!(classify_lightning(&tx).params.output_roles.is_empty());
+}
+
+// ─── Sorting ────────────────────────────────────────────────────────────────
+
+fn sample_results() -> Vec<(String, LightningClassification)> {
+    let tx = make_tx(0, vec![make_vin(0xFFFFFFFF)], vec![make_vout(10_000, "v0_p2wpkh")]);
+    let classified = |tx_type, confidence, htlcs| {
+        let mut lc = classify_lightning(&tx);
+        lc.tx_type = tx_type;
+        lc.confidence = confidence;
+        lc.params.htlc_output_count = htlcs;
+        lc
+    };
+    vec![
+        ("none".to_string(), classified(None, Confidence::None, None)),
+        (
+            "sweep".to_string(),
+            classified(Some(LightningTxType::AnchorSweep), Confidence::Possible, None),
+        ),
+        (
+            "commitment".to_string(),
+            classified(Some(LightningTxType::Commitment), Confidence::HighlyLikely, Some(2)),
+        ),
+        (
+            "timeout".to_string(),
+            classified(Some(LightningTxType::HtlcTimeout), Confidence::HighlyLikely, None),
+        ),
+        (
+            "big_commitment".to_string(),
+            classified(Some(LightningTxType::Commitment), Confidence::Possible, Some(5)),
+        ),
+    ]
+}
+
+fn sorted_txids(key: SortKey) -> Vec<String> {
+    let mut results = sample_results();
+    sort_lightning(&mut results, key);
+    results.into_iter().map(|(txid, _)| txid).collect()
+}
+
+#[test]
+fn test_sort_lightning_results() {
+    assert_eq!(
+        sorted_txids(SortKey::None),
+        ["none", "sweep", "commitment", "timeout", "big_commitment"]
+    );
+    // Stable: equal keys keep block order
+    assert_eq!(
+        sorted_txids(SortKey::Confidence),
+        ["commitment", "timeout", "sweep", "big_commitment", "none"]
+    );
+    assert_eq!(
+        sorted_txids(SortKey::Type),
+        ["commitment", "big_commitment", "timeout", "sweep", "none"]
+    );
+    assert_eq!(
+        sorted_txids(SortKey::HtlcCount),
+        ["big_commitment", "commitment", "none", "sweep", "timeout"]
+    );
+}
+
+#[test]
+fn test_sort_leaves_tally_unchanged() {
+    let tally = |results: &[(String, LightningClassification)]| {
+        let mut tally = LightningTally::default();
+        for (_, lc) in results {
+            tally.record(lc);
+        }
+        serde_json::to_value(tally).unwrap()
+    };
+    let mut results = sample_results();
+    let before = tally(&results);
+    sort_lightning(&mut results, SortKey::Type);
+    assert_eq!(tally(&results), before);
 }
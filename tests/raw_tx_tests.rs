@@ -0,0 +1,258 @@
+use bitcoin::blockdata::constants::genesis_block;
+use bitcoin::consensus::encode::{serialize, serialize_hex};
+use bitcoin::hashes::Hash;
+use bitcoin::script::{Builder, PushBytesBuf};
+use bitcoin::transaction::Version;
+use bitcoin::{
+    Amount, Block, Network, OutPoint, ScriptBuf, Sequence, Transaction, TxIn, TxMerkleNode, TxOut,
+    Txid, Witness, WScriptHash, absolute, block, opcodes,
+};
+use cltv_scan::Error;
+use cltv_scan::api::block_file::BlockFileSource;
+use cltv_scan::api::raw::{api_transaction_with_prevouts, decode_raw_transaction};
+use cltv_scan::api::source::DataSource;
+use cltv_scan::lightning::detector::{classify_lightning, classify_lightning_tx};
+use cltv_scan::lightning::types::LightningTxType;
+use cltv_scan::timelock::extractor::analyze_transaction;
+
+// BIP 143 native P2WPKH example: one legacy P2PK input, one P2WPKH input, two P2PKH outputs
+const BIP143_P2WPKH_TX: &str = "01000000000102fff7f7881a8099afa6940d42d1e7f6362bec38171ea3edf433541db4e4ad969f00000000494830450221008b9d1dc26ba6a9cb62127b02742fa9d754cd3bebf337f7a55d114c8e5cdd30be022040529b194ba3f9281a99f2b1c0a19c0489bc22ede944ccf4ecbab4cc618ef3ed01eeffffffef51e1b804cc89d182d279655c3aa89e815b1b309fe287d9b2b55d57b90ec68a0100000000ffffffff02202cb206000000001976a9148280b37df378db99f66f85c95a783a76ac7a6d5988ac9093510d000000001976a9143bde42dbee7e4dbe6a21b2d50ce2f0167faa815988ac000247304402203609e17b84f6a7d30c80bfa610b5b4542f32a8a0d5447a12fb1366d7f01cc44a0220573a954c4518331561406f90300e8f3358f51928d43c212a8caed02de67eebee0121025476c2e83188368da1ff3e292e7acafcdb3566bb0ad253f62fc70f07aeee635711000000";
+
+#[test]
+fn test_decode_segwit_tx() {
+    let tx = decode_raw_transaction(BIP143_P2WPKH_TX, Network::Bitcoin).unwrap();
+
+    assert_eq!(tx.version, 1);
+    assert_eq!(tx.locktime, 17);
+    assert_eq!(tx.vin.len(), 2);
+    assert_eq!(tx.vout.len(), 2);
+    assert!(tx.fee.is_none());
+    assert!(!tx.status.confirmed);
+
+    // Little-endian outpoint hash is displayed reversed
+    assert_eq!(
+        tx.vin[0].txid.as_deref(),
+        Some("9f96ade4b41d5433f4eda31e1738ec2b36f6e7d1420d94a6af99801a88f7f7ff")
+    );
+    assert_eq!(tx.vin[0].vout, Some(0));
+    assert_eq!(tx.vin[0].sequence, 0xffffffee);
+    assert!(tx.vin[0].witness.as_ref().unwrap().is_empty());
+    assert!(!tx.vin[0].is_coinbase);
+
+    assert_eq!(tx.vin[1].vout, Some(1));
+    assert_eq!(tx.vin[1].sequence, 0xffffffff);
+    let witness = tx.vin[1].witness.as_ref().unwrap();
+    assert_eq!(witness.len(), 2);
+    assert!(witness[0].starts_with("304402203609e17b"));
+    assert!(witness[1].starts_with("025476c2e831"));
+    assert!(tx.vin[1].inner_witnessscript_asm.is_none());
+
+    assert_eq!(tx.vout[0].value, 112_340_000);
+    assert_eq!(tx.vout[1].value, 223_450_000);
+    assert_eq!(tx.vout[0].scriptpubkey_type, "p2pkh");
+    assert_eq!(
+        tx.vout[0].scriptpubkey,
+        "76a9148280b37df378db99f66f85c95a783a76ac7a6d5988ac"
+    );
+    assert!(tx.vout[0].scriptpubkey_asm.starts_with("OP_DUP OP_HASH160"));
+    assert!(tx.vout[0].scriptpubkey_address.as_deref().unwrap().starts_with('1'));
+}
+
+#[test]
+fn test_decode_uses_network_address_encoding() {
+    let tx = decode_raw_transaction(BIP143_P2WPKH_TX, Network::Testnet).unwrap();
+    let addr = tx.vout[0].scriptpubkey_address.as_deref().unwrap();
+    assert!(addr.starts_with('m') || addr.starts_with('n'));
+}
+
+#[test]
+fn test_raw_tx_runs_through_analysis() {
+    let tx = decode_raw_transaction(BIP143_P2WPKH_TX, Network::Bitcoin).unwrap();
+
+    let analysis = analyze_transaction(&tx);
+    assert_eq!(analysis.txid, tx.txid);
+    assert_eq!(analysis.nlocktime.raw_value, 17);
+    // The first input's 0xffffffee sequence enables nLockTime
+    assert!(analysis.nlocktime.active);
+
+    let lightning = classify_lightning(&tx);
+    assert!(lightning.tx_type.is_none());
+}
+
+#[test]
+fn test_decode_rejects_invalid_hex() {
+    assert!(decode_raw_transaction("zz", Network::Bitcoin).is_err());
+    assert!(decode_raw_transaction("0100", Network::Bitcoin).is_err());
+}
+
+// ─── Classifying rust-bitcoin transactions ──────────────────────────────────
+
+/// BOLT 3 anchor script: `<pubkey> OP_CHECKSIG OP_IFDUP OP_NOTIF OP_16 OP_CSV OP_ENDIF`
+fn anchor_script() -> ScriptBuf {
+    let mut pubkey = vec![0x02];
+    pubkey.extend([0xab; 32]);
+    Builder::new()
+        .push_slice(PushBytesBuf::try_from(pubkey).unwrap())
+        .push_opcode(opcodes::all::OP_CHECKSIG)
+        .push_opcode(opcodes::all::OP_IFDUP)
+        .push_opcode(opcodes::all::OP_NOTIF)
+        .push_opcode(opcodes::all::OP_PUSHNUM_16)
+        .push_opcode(opcodes::all::OP_CSV)
+        .push_opcode(opcodes::all::OP_ENDIF)
+        .into_script()
+}
+
+/// Anchor spend plus one wallet input into a single P2WPKH output.
+fn anchor_sweep() -> (Transaction, Vec<TxOut>) {
+    let anchor_script = anchor_script();
+    let mut signature = vec![0x30; 71];
+    signature.push(0x01);
+
+    let anchor_input = TxIn {
+        previous_output: OutPoint::new(Txid::from_byte_array([0xcc; 32]), 2),
+        script_sig: ScriptBuf::new(),
+        sequence: Sequence::ENABLE_RBF_NO_LOCKTIME,
+        witness: Witness::from_slice(&[signature.clone(), anchor_script.to_bytes()]),
+    };
+    let wallet_input = TxIn {
+        previous_output: OutPoint::new(Txid::from_byte_array([0xdd; 32]), 0),
+        script_sig: ScriptBuf::new(),
+        sequence: Sequence::ENABLE_RBF_NO_LOCKTIME,
+        witness: Witness::from_slice(&[signature, vec![0x02; 33]]),
+    };
+    let tx = Transaction {
+        version: Version::TWO,
+        lock_time: absolute::LockTime::ZERO,
+        input: vec![anchor_input, wallet_input],
+        output: vec![TxOut {
+            value: Amount::from_sat(49_000),
+            script_pubkey: ScriptBuf::new_p2wpkh(&bitcoin::WPubkeyHash::from_byte_array([0x11; 20])),
+        }],
+    };
+    let prevouts = vec![
+        TxOut {
+            value: Amount::from_sat(330),
+            script_pubkey: ScriptBuf::new_p2wsh(&WScriptHash::hash(anchor_script.as_bytes())),
+        },
+        TxOut {
+            value: Amount::from_sat(50_000),
+            script_pubkey: ScriptBuf::new_p2wpkh(&bitcoin::WPubkeyHash::from_byte_array([0x22; 20])),
+        },
+    ];
+    (tx, prevouts)
+}
+
+#[test]
+fn test_prevouts_fill_witness_script_and_fee() {
+    let (tx, prevouts) = anchor_sweep();
+    let api_tx = api_transaction_with_prevouts(&tx, &prevouts, Network::Bitcoin);
+
+    assert_eq!(api_tx.fee, Some(1_330));
+    assert_eq!(api_tx.vin[0].prevout.as_ref().unwrap().scriptpubkey_type, "v0_p2wsh");
+    assert_eq!(api_tx.vin[0].prevout.as_ref().unwrap().value, 330);
+    let asm = api_tx.vin[0].inner_witnessscript_asm.as_deref().unwrap();
+    assert!(asm.ends_with("OP_CHECKSIG OP_IFDUP OP_NOTIF OP_PUSHNUM_16 OP_CSV OP_ENDIF"));
+    // The P2WPKH input's last witness element is a pubkey, not a script
+    assert!(api_tx.vin[1].inner_witnessscript_asm.is_none());
+}
+
+#[test]
+fn test_pay_to_anchor_sweep() {
+    // Zero-fee commitments use a 0-sat pay-to-anchor output, spent with an empty witness
+    let (mut tx, mut prevouts) = anchor_sweep();
+    tx.input[0].witness = Witness::new();
+    prevouts[0] = TxOut {
+        value: Amount::ZERO,
+        script_pubkey: ScriptBuf::from_bytes(vec![0x51, 0x02, 0x4e, 0x73]),
+    };
+    let api_tx = api_transaction_with_prevouts(&tx, &prevouts, Network::Bitcoin);
+    assert_eq!(api_tx.vin[0].prevout.as_ref().unwrap().scriptpubkey_type, "anchor");
+
+    let result = classify_lightning_tx(&tx, Some(&prevouts));
+    assert_eq!(result.tx_type, Some(LightningTxType::AnchorSweep));
+}
+
+#[test]
+fn test_classify_lightning_tx_with_prevouts() {
+    let (tx, prevouts) = anchor_sweep();
+    let result = classify_lightning_tx(&tx, Some(&prevouts));
+    assert_eq!(result.tx_type, Some(LightningTxType::AnchorSweep));
+    assert_eq!(result.params.parent_txid, Some("cc".repeat(32)));
+}
+
+#[test]
+fn test_classify_lightning_tx_without_prevouts() {
+    // Without prevouts the anchor input can't be recognized
+    let (tx, _) = anchor_sweep();
+    assert_eq!(classify_lightning_tx(&tx, None).tx_type, None);
+
+    // Commitment markers live in the transaction itself
+    let mut commitment = tx.clone();
+    commitment.lock_time = absolute::LockTime::from_consensus(0x20000042);
+    commitment.input[0].sequence = Sequence(0x80000001);
+    assert_eq!(
+        classify_lightning_tx(&commitment, None).tx_type,
+        Some(LightningTxType::Commitment)
+    );
+}
+
+// ─── Block files ─────────────────────────────────────────────────────────────
+
+const GENESIS_COINBASE_TXID: &str =
+    "4a5e1e4baab89f3a32518a88c31bc87f618f76673e2cc77ab2127b7afdeda33b";
+
+/// The genesis block with a BIP 34 height pushed in its coinbase.
+fn bip34_block(height: i64) -> Block {
+    let mut block = genesis_block(Network::Bitcoin);
+    block.header.version = block::Version::TWO;
+    block.txdata[0].input[0].script_sig = Builder::new().push_int(height).into_script();
+    block.header.merkle_root = block.compute_merkle_root().unwrap();
+    block
+}
+
+#[test]
+fn test_block_file_from_hex() {
+    let hex = serialize_hex(&genesis_block(Network::Bitcoin));
+    let source = BlockFileSource::from_bytes(hex.as_bytes(), Some(0), Network::Bitcoin).unwrap();
+    assert_eq!(source.height(), 0);
+
+    // Genesis predates BIP 34, so its height must be given
+    assert!(BlockFileSource::from_bytes(hex.as_bytes(), None, Network::Bitcoin).is_err());
+}
+
+#[tokio::test]
+async fn test_block_file_serves_its_transactions() {
+    let hex = serialize_hex(&genesis_block(Network::Bitcoin));
+    let source = BlockFileSource::from_bytes(hex.as_bytes(), Some(0), Network::Bitcoin).unwrap();
+
+    let txs = source.get_all_block_txs(0).await.unwrap();
+    assert_eq!(txs.len(), 1);
+    assert_eq!(txs[0].txid, GENESIS_COINBASE_TXID);
+    assert!(txs[0].vin[0].is_coinbase);
+    assert_eq!(txs[0].status.block_height, Some(0));
+    assert_eq!(txs[0].status.block_time, Some(1231006505));
+    assert_eq!(analyze_transaction(&txs[0]).nlocktime.raw_value, 0);
+
+    assert!(matches!(source.get_all_block_txs(1).await, Err(Error::NotFound(_))));
+    assert!(source.get_transaction(GENESIS_COINBASE_TXID).await.is_ok());
+}
+
+#[test]
+fn test_block_file_dat_record_with_bip34_height() {
+    let block = bip34_block(886000);
+    let bytes = serialize(&block);
+    let mut record = Network::Bitcoin.magic().to_bytes().to_vec();
+    record.extend((bytes.len() as u32).to_le_bytes());
+    record.extend(&bytes);
+
+    let source = BlockFileSource::from_bytes(&record, None, Network::Bitcoin).unwrap();
+    assert_eq!(source.height(), 886000);
+}
+
+#[test]
+fn test_block_file_rejects_bad_merkle_root() {
+    let mut block = bip34_block(886000);
+    block.header.merkle_root = TxMerkleNode::all_zeros();
+    let hex = serialize_hex(&block);
+    assert!(BlockFileSource::from_bytes(hex.as_bytes(), None, Network::Bitcoin).is_err());
+}
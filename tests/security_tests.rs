@@ -1,8 +1,15 @@
 use cltv_scan::api::types::*;
+use cltv_scan::cli::sort::{SortKey, sort_analyses};
 use cltv_scan::lightning::detector::classify_lightning;
 use cltv_scan::security::analyzer::{analyze_transaction, detect_htlc_clustering};
 use cltv_scan::security::types::*;
 use cltv_scan::timelock::extractor::analyze_transaction as extract_timelocks;
+use cltv_scan::timelock::extractor::analyze_transaction_with_tip as extract_timelocks_with_tip;
+use cltv_scan::timelock::classify::parse_relative_timelock;
+use cltv_scan::timelock::types::{
+    ChainTip, RiskKind, RiskSeverity, SequenceMeaning, SpendPath, TimelockDomain, TimelockKind,
+    TimelockLocation,
+};
 
 // ─── Test helpers ────────────────────────────────────────────────────────────
 
@@ -64,6 +71,498 @@ fn run_analysis(tx: &ApiTransaction, current_height: u64) -> Vec<Alert> {
     analyze_transaction(&timelock, &lightning, current_height, &default_config())
 }
 
+// ─── Script number decoding ──────────────────────────────────────────────────
+
+#[test]
+fn test_cltv_multi_byte_hex_push() {
+    let mut vin = make_vin(0xFFFFFFFE);
+    vin.inner_witnessscript_asm = Some(
+        "OP_PUSHBYTES_3 40e201 OP_CHECKLOCKTIMEVERIFY OP_DROP".to_string(),
+    );
+    let tx = make_tx(0, vec![vin], vec![make_vout(10_000, "v0_p2wsh")]);
+    let analysis = extract_timelocks(&tx);
+    assert_eq!(analysis.cltv_timelocks.len(), 1);
+    assert_eq!(analysis.cltv_timelocks[0].raw_value, 123456);
+}
+
+#[test]
+fn test_cltv_decimal_push_still_parsed() {
+    let mut vin = make_vin(0xFFFFFFFE);
+    vin.inner_witnessscript_asm = Some("886000 OP_CHECKLOCKTIMEVERIFY".to_string());
+    let tx = make_tx(0, vec![vin], vec![make_vout(10_000, "v0_p2wsh")]);
+    assert_eq!(extract_timelocks(&tx).cltv_timelocks[0].raw_value, 886000);
+}
+
+#[test]
+fn test_csv_all_digit_hex_push_after_pushbytes() {
+    let mut vin = make_vin(144);
+    vin.inner_witnessscript_asm = Some("OP_PUSHBYTES_2 9000 OP_CHECKSEQUENCEVERIFY".to_string());
+    let tx = make_tx(0, vec![vin], vec![make_vout(10_000, "v0_p2wsh")]);
+    assert_eq!(extract_timelocks(&tx).csv_timelocks[0].raw_value, 144);
+}
+
+#[test]
+fn test_csv_bare_push_with_hex_letters() {
+    // 0x05a0 little-endian
+    let mut vin = make_vin(1440);
+    vin.inner_witnessscript_asm = Some("a005 OP_CHECKSEQUENCEVERIFY".to_string());
+    let tx = make_tx(0, vec![vin], vec![make_vout(10_000, "v0_p2wsh")]);
+    assert_eq!(extract_timelocks(&tx).csv_timelocks[0].raw_value, 1440);
+}
+
+#[test]
+fn test_csv_bare_even_length_decimal_push() {
+    // Core's asm: a bare `1008` is the number, not the bytes 10 08
+    let mut vin = make_vin(1008);
+    vin.inner_witnessscript_asm = Some("1008 OP_CHECKSEQUENCEVERIFY".to_string());
+    let tx = make_tx(0, vec![vin], vec![make_vout(10_000, "v0_p2wsh")]);
+    assert_eq!(extract_timelocks(&tx).csv_timelocks[0].raw_value, 1008);
+}
+
+#[test]
+fn test_cltv_from_p2sh_redeemscript() {
+    let mut vin = make_vin(0xFFFFFFFE);
+    vin.inner_redeemscript_asm = Some(
+        "OP_IF OP_PUSHBYTES_33 02aa OP_ELSE OP_PUSHBYTES_3 40e201 OP_CLTV OP_DROP OP_PUSHBYTES_33 03bb OP_ENDIF OP_CHECKSIG"
+            .to_string(),
+    );
+    let tx = make_tx(0, vec![vin], vec![make_vout(10_000, "p2sh")]);
+    let analysis = extract_timelocks(&tx);
+    assert_eq!(analysis.cltv_timelocks.len(), 1);
+    assert_eq!(analysis.cltv_timelocks[0].script_field, "inner_redeemscript_asm");
+    assert_eq!(analysis.cltv_timelocks[0].raw_value, 123456);
+}
+
+// ─── BIP 68 sequence decoding ────────────────────────────────────────────────
+
+#[test]
+fn test_bip68_block_based() {
+    let rtl = parse_relative_timelock(144).unwrap();
+    assert_eq!(rtl.domain, TimelockDomain::BlockHeight);
+    assert_eq!(rtl.value, 144);
+    assert!(rtl.human_readable.starts_with("relative: 144 blocks"));
+}
+
+#[test]
+fn test_bip68_time_based() {
+    // Bit 22 set: 20 units × 512s = 10240 seconds
+    let rtl = parse_relative_timelock((1 << 22) | 20).unwrap();
+    assert_eq!(rtl.domain, TimelockDomain::Timestamp);
+    assert_eq!(rtl.value, 20);
+    assert_eq!(rtl.human_readable, "relative: 10240 seconds (~2.8 hours)");
+}
+
+#[test]
+fn test_bip68_type_flag_boundary() {
+    // Bit 21 is not the type flag; bits between 16 and 22 are ignored
+    let below = parse_relative_timelock((1 << 21) | 10).unwrap();
+    assert_eq!(below.domain, TimelockDomain::BlockHeight);
+    assert_eq!(below.value, 10);
+
+    let max_blocks = parse_relative_timelock(0xFFFF).unwrap();
+    assert_eq!(max_blocks.value, u16::MAX);
+    assert_eq!(max_blocks.domain, TimelockDomain::BlockHeight);
+}
+
+#[test]
+fn test_bip68_disable_flag_is_final() {
+    // Bit 31 set: the low bits look like 144 blocks but BIP 68 doesn't apply
+    let seq = (1 << 31) | 144;
+    assert!(parse_relative_timelock(seq).is_none());
+    assert!(parse_relative_timelock(0x7FFFFFFF).is_some());
+
+    let tx = make_tx(0, vec![make_vin(seq)], vec![make_vout(10_000, "v0_p2wpkh")]);
+    let analysis = extract_timelocks(&tx);
+    assert!(matches!(analysis.inputs[0].meaning, SequenceMeaning::Final));
+    assert!(analysis.inputs[0].relative_timelock.is_none());
+    // Unlike 0xFFFFFFFF, it still leaves nLockTime enforced
+    assert!(analysis.nlocktime.active);
+}
+
+// ─── nLockTime relative to the chain tip ─────────────────────────────────────
+
+fn tip(height: u64, median_time_past: Option<u64>) -> ChainTip {
+    ChainTip { height, median_time_past }
+}
+
+#[test]
+fn test_height_locktime_active_only_above_tip() {
+    let tx = make_tx(886000, vec![make_vin(0xFFFFFFFD)], vec![make_vout(10_000, "v0_p2wpkh")]);
+
+    // Without a tip, any enforced locktime counts
+    assert!(extract_timelocks(&tx).summary.nlocktime_active);
+
+    let future = extract_timelocks_with_tip(&tx, &tip(885999, None));
+    assert!(future.summary.nlocktime_active);
+    assert!(future.summary.has_active_timelocks);
+
+    // Locktime == tip height: already valid for the next block
+    let reached = extract_timelocks_with_tip(&tx, &tip(886000, None));
+    assert!(!reached.summary.nlocktime_active);
+    assert!(!reached.summary.has_active_timelocks);
+}
+
+#[test]
+fn test_timestamp_locktime_compared_to_mtp() {
+    let tx = make_tx(1_700_000_000, vec![make_vin(0xFFFFFFFE)], vec![make_vout(10_000, "v0_p2wpkh")]);
+
+    assert!(extract_timelocks_with_tip(&tx, &tip(886000, Some(1_699_999_999))).summary.nlocktime_active);
+    assert!(extract_timelocks_with_tip(&tx, &tip(886000, Some(1_700_000_000))).summary.nlocktime_active);
+    assert!(!extract_timelocks_with_tip(&tx, &tip(886000, Some(1_700_000_001))).summary.nlocktime_active);
+    // Unknown MTP: fall back to the tip-independent answer
+    assert!(extract_timelocks_with_tip(&tx, &tip(886000, None)).summary.nlocktime_active);
+}
+
+#[test]
+fn test_disabled_locktime_inactive_with_tip() {
+    let tx = make_tx(886000, vec![make_vin(0xFFFFFFFF)], vec![make_vout(10_000, "v0_p2wpkh")]);
+    assert!(!extract_timelocks_with_tip(&tx, &tip(800000, None)).summary.nlocktime_active);
+}
+
+// ─── Ineffective nLockTime ───────────────────────────────────────────────────
+
+#[test]
+fn test_locktime_ineffective_with_all_final_inputs() {
+    let vins = vec![make_vin(0xFFFFFFFF), make_vin(0xFFFFFFFF)];
+    let analysis = extract_timelocks(&make_tx(885990, vins, vec![make_vout(10_000, "v0_p2wpkh")]));
+    assert!(analysis.summary.nlocktime_ineffective);
+    assert!(!analysis.summary.nlocktime_active);
+    assert_eq!(analysis.risk.findings[0].kind, RiskKind::IneffectiveLocktime);
+}
+
+#[test]
+fn test_locktime_effective_with_one_non_final_input() {
+    // 0x80000000 disables BIP 68 but still enables nLockTime
+    for sequence in [0xFFFFFFFE, 0xFFFFFFFD, 0x80000000] {
+        let vins = vec![make_vin(0xFFFFFFFF), make_vin(sequence)];
+        let tx = make_tx(885990, vins, vec![make_vout(10_000, "v0_p2wpkh")]);
+        let analysis = extract_timelocks(&tx);
+        assert!(!analysis.summary.nlocktime_ineffective, "sequence {sequence:#x}");
+        assert!(analysis.summary.nlocktime_active);
+    }
+}
+
+#[test]
+fn test_zero_locktime_not_ineffective() {
+    let tx = make_tx(0, vec![make_vin(0xFFFFFFFF)], vec![make_vout(10_000, "v0_p2wpkh")]);
+    assert!(!extract_timelocks(&tx).summary.nlocktime_ineffective);
+}
+
+// ─── Taproot spend paths ─────────────────────────────────────────────────────
+
+fn spending_output(script_type: &str, witness: Vec<String>) -> ApiVin {
+    let mut vin = make_vin(0xFFFFFFFD);
+    vin.prevout = Some(ApiPrevout {
+        scriptpubkey: "00".to_string(),
+        scriptpubkey_asm: "OP_0".to_string(),
+        scriptpubkey_type: script_type.to_string(),
+        scriptpubkey_address: None,
+        value: 50_000,
+    });
+    vin.witness = Some(witness);
+    vin
+}
+
+fn spend_paths(vins: Vec<ApiVin>) -> Vec<Option<SpendPath>> {
+    let tx = make_tx(0, vins, vec![make_vout(10_000, "v0_p2wpkh")]);
+    extract_timelocks(&tx).inputs.iter().map(|i| i.spend_path).collect()
+}
+
+/// `<144> OP_CSV OP_DROP <886100> OP_CLTV OP_DROP <key> OP_CHECKSIG`, as witness hex.
+fn timelocked_leaf() -> String {
+    format!("029000b275{}20{}ac", "0354850db175", "11".repeat(32))
+}
+
+#[test]
+fn test_taproot_key_path_spend() {
+    let schnorr = "33".repeat(64);
+    let with_sighash = format!("{schnorr}01");
+    let with_annex = vec![schnorr.clone(), "50aa".to_string()];
+    let vins = vec![
+        spending_output("v1_p2tr", vec![schnorr.clone()]),
+        spending_output("v1_p2tr", vec![with_sighash]),
+        spending_output("v1_p2tr", with_annex),
+    ];
+    assert_eq!(spend_paths(vins), [Some(SpendPath::KeyPath); 3]);
+}
+
+#[test]
+fn test_taproot_script_path_spend_carries_script_timelocks() {
+    let control = format!("c1{}{}", "22".repeat(32), "44".repeat(32));
+    let witness = vec!["33".repeat(64), timelocked_leaf(), control];
+    // Without the prevout, the control block alone marks it as taproot
+    let mut vin = spending_output("v1_p2tr", witness);
+    vin.prevout = None;
+    let tx = make_tx(0, vec![vin], vec![make_vout(10_000, "v0_p2wpkh")]);
+
+    let analysis = extract_timelocks(&tx);
+    assert_eq!(analysis.inputs[0].spend_path, Some(SpendPath::ScriptPath));
+    assert_eq!(analysis.cltv_timelocks.len(), 1);
+    assert_eq!(analysis.cltv_timelocks[0].script_field, "tapscript");
+    assert_eq!(analysis.cltv_timelocks[0].raw_value, 886100);
+    assert_eq!(analysis.csv_timelocks[0].raw_value, 144);
+    assert_eq!(analysis.summary.cltv_and_csv_inputs, [0]);
+}
+
+#[test]
+fn test_non_taproot_inputs_have_no_spend_path() {
+    let der_sig = format!("3044{}01", "11".repeat(68));
+    let pubkey = format!("02{}", "22".repeat(32));
+    // Control-block sized, but a P2WPKH key doesn't start with a leaf version
+    let p2wpkh = spending_output("v0_p2wpkh", vec![der_sig.clone(), pubkey.clone()]);
+    let mut p2wpkh_no_prevout = p2wpkh.clone();
+    p2wpkh_no_prevout.prevout = None;
+    // A 64-byte lone element only means a key-path spend for a taproot output
+    let not_taproot = spending_output("v0_p2wsh", vec!["33".repeat(64)]);
+    let control = format!("c0{}", "22".repeat(32));
+    let mut decoded = spending_output("v1_p2tr", vec![der_sig, timelocked_leaf(), control]);
+    // A decoded script is read once, not again from the witness
+    decoded.inner_witnessscript_asm = Some("886100 OP_CHECKLOCKTIMEVERIFY".to_string());
+
+    let paths = spend_paths(vec![p2wpkh, p2wpkh_no_prevout, not_taproot, decoded.clone()]);
+    assert_eq!(paths, [None, None, None, Some(SpendPath::ScriptPath)]);
+    let tx = make_tx(0, vec![decoded], vec![make_vout(10_000, "v0_p2wpkh")]);
+    let analysis = extract_timelocks(&tx);
+    assert_eq!(analysis.cltv_timelocks.len(), 1);
+    assert_eq!(analysis.cltv_timelocks[0].script_field, "inner_witnessscript_asm");
+}
+
+// ─── Timelock risk assessment ────────────────────────────────────────────────
+
+#[test]
+fn test_risk_locktime_with_all_final_sequences() {
+    let tx = make_tx(885990, vec![make_vin(0xFFFFFFFF)], vec![make_vout(10_000, "v0_p2wpkh")]);
+    let risk = extract_timelocks(&tx).risk;
+    assert_eq!(risk.findings.len(), 1);
+    assert_eq!(risk.findings[0].kind, RiskKind::IneffectiveLocktime);
+    assert_eq!(risk.max_severity, Some(RiskSeverity::Low));
+}
+
+#[test]
+fn test_risk_enforced_locktime_not_flagged() {
+    let tx = make_tx(885990, vec![make_vin(0xFFFFFFFD)], vec![make_vout(10_000, "v0_p2wpkh")]);
+    let risk = extract_timelocks(&tx).risk;
+    assert!(risk.findings.is_empty());
+    assert_eq!(risk.max_severity, None);
+}
+
+#[test]
+fn test_risk_cltv_already_passed_at_confirmation() {
+    let mut vin = make_vin(0xFFFFFFFE);
+    vin.inner_witnessscript_asm = Some("885000 OP_CHECKLOCKTIMEVERIFY OP_DROP".to_string());
+    let tx = make_tx(886000, vec![vin], vec![make_vout(10_000, "v0_p2wsh")]);
+    let risk = extract_timelocks(&tx).risk;
+    let finding = risk.findings.iter().find(|f| f.kind == RiskKind::ExpiredCltv).unwrap();
+    assert_eq!(finding.input_index, Some(0));
+    assert_eq!(finding.severity, RiskSeverity::Medium);
+}
+
+#[test]
+fn test_risk_cltv_without_block_height_not_flagged() {
+    let mut vin = make_vin(0xFFFFFFFE);
+    vin.inner_witnessscript_asm = Some("885000 OP_CHECKLOCKTIMEVERIFY OP_DROP".to_string());
+    let mut tx = make_tx(886000, vec![vin], vec![make_vout(10_000, "v0_p2wsh")]);
+    tx.status.block_height = None;
+    let risk = extract_timelocks(&tx).risk;
+    assert!(!risk.findings.iter().any(|f| f.kind == RiskKind::ExpiredCltv));
+}
+
+#[test]
+fn test_risk_zero_csv_delay() {
+    let mut vin = make_vin(0);
+    vin.inner_witnessscript_asm = Some("OP_0 OP_CHECKSEQUENCEVERIFY OP_DROP".to_string());
+    let tx = make_tx(0, vec![vin], vec![make_vout(10_000, "v0_p2wsh")]);
+    let risk = extract_timelocks(&tx).risk;
+    assert_eq!(risk.findings.len(), 1);
+    assert_eq!(risk.findings[0].kind, RiskKind::ZeroCsvDelay);
+    assert_eq!(risk.max_severity, Some(RiskSeverity::High));
+}
+
+#[test]
+fn test_risk_oversized_csv_operand() {
+    // 0x0186A0 = 100000: bit 16 is ignored by BIP 68, leaving 0x86A0 = 34464 blocks
+    let mut vin = make_vin(0x0000_86A0);
+    vin.inner_witnessscript_asm =
+        Some("OP_PUSHBYTES_3 a08601 OP_CHECKSEQUENCEVERIFY OP_DROP".to_string());
+    let tx = make_tx(0, vec![vin], vec![make_vout(10_000, "v0_p2wsh")]);
+    let analysis = extract_timelocks(&tx);
+    assert_eq!(analysis.csv_timelocks[0].raw_value, 100_000);
+    let risk = analysis.risk;
+    assert_eq!(risk.findings.len(), 1);
+    assert_eq!(risk.findings[0].kind, RiskKind::OversizedCsv);
+    assert_eq!(risk.findings[0].input_index, Some(0));
+    assert!(risk.findings[0].reason.contains("34464 blocks"), "{}", risk.findings[0].reason);
+    assert_eq!(risk.max_severity, Some(RiskSeverity::Low));
+}
+
+#[test]
+fn test_risk_csv_operand_above_32_bits() {
+    let mut vin = make_vin(0x0000_0090);
+    vin.inner_witnessscript_asm =
+        Some("OP_PUSHBYTES_5 9000000001 OP_CHECKSEQUENCEVERIFY OP_DROP".to_string());
+    let tx = make_tx(0, vec![vin], vec![make_vout(10_000, "v0_p2wsh")]);
+    let risk = extract_timelocks(&tx).risk;
+    assert_eq!(risk.findings.len(), 1);
+    assert_eq!(risk.findings[0].kind, RiskKind::OversizedCsv);
+}
+
+#[test]
+fn test_risk_time_based_csv_not_oversized() {
+    let mut vin = make_vin(0x0040_0090);
+    vin.inner_witnessscript_asm = Some("4194448 OP_CHECKSEQUENCEVERIFY OP_DROP".to_string());
+    let tx = make_tx(0, vec![vin], vec![make_vout(10_000, "v0_p2wsh")]);
+    assert!(extract_timelocks(&tx).risk.findings.is_empty());
+}
+
+#[test]
+fn test_risk_csv_with_disabled_sequence() {
+    // Low bits say 144 blocks, but bit 31 of both the operand and nSequence turns the
+    // relative timelock off
+    let mut vin = make_vin(0x8000_0090);
+    vin.inner_witnessscript_asm =
+        Some("OP_PUSHBYTES_5 9000008000 OP_CHECKSEQUENCEVERIFY OP_DROP".to_string());
+    let tx = make_tx(0, vec![vin], vec![make_vout(10_000, "v0_p2wsh")]);
+    let risk = extract_timelocks(&tx).risk;
+    assert_eq!(risk.findings.len(), 1);
+    assert_eq!(risk.findings[0].kind, RiskKind::DisabledCsvSequence);
+    assert_eq!(risk.findings[0].input_index, Some(0));
+    assert_eq!(risk.max_severity, Some(RiskSeverity::High));
+}
+
+#[test]
+fn test_risk_revocation_spend_not_high() {
+    // A penalty spends to_local through the revocation branch, skipping the CSV
+    let mut vin = make_vin(0xFFFF_FFFD);
+    vin.inner_witnessscript_asm = Some(format!(
+        "OP_IF OP_PUSHBYTES_33 02{k} OP_ELSE OP_PUSHBYTES_2 9000 OP_CSV OP_DROP \
+         OP_PUSHBYTES_33 03{k} OP_ENDIF OP_CHECKSIG",
+        k = "11".repeat(32)
+    ));
+    let tx = make_tx(0, vec![vin], vec![make_vout(10_000, "v0_p2wpkh")]);
+    let risk = extract_timelocks(&tx).risk;
+    assert_eq!(risk.findings.len(), 1);
+    assert_eq!(risk.findings[0].kind, RiskKind::DisabledCsvSequence);
+    assert_eq!(risk.findings[0].severity, RiskSeverity::Info);
+    assert!(risk.findings[0].reason.contains("CSV branch not executed"));
+    assert_eq!(risk.max_severity, Some(RiskSeverity::Info));
+}
+
+#[test]
+fn test_risk_csv_with_enforced_sequence_not_flagged() {
+    let mut vin = make_vin(0x0000_0090);
+    vin.inner_witnessscript_asm = Some("144 OP_CHECKSEQUENCEVERIFY OP_DROP".to_string());
+    let tx = make_tx(0, vec![vin], vec![make_vout(10_000, "v0_p2wsh")]);
+    assert!(extract_timelocks(&tx).risk.findings.is_empty());
+}
+
+#[test]
+fn test_anti_fee_sniping_present() {
+    // Signed at the tip before block 886000, RBF enabled
+    let tx = make_tx(885999, vec![make_vin(0xFFFFFFFD)], vec![make_vout(10_000, "v0_p2wpkh")]);
+    let risk = extract_timelocks_with_tip(&tx, &tip(886100, None)).risk;
+    assert_eq!(risk.findings.len(), 1);
+    assert_eq!(risk.findings[0].kind, RiskKind::AntiFeeSniping);
+    assert_eq!(risk.max_severity, Some(RiskSeverity::Info));
+}
+
+#[test]
+fn test_anti_fee_sniping_pending_tx_uses_tip() {
+    let mut tx = make_tx(886100, vec![make_vin(0xFFFFFFFD)], vec![make_vout(10_000, "v0_p2wpkh")]);
+    tx.status.block_height = None;
+    let risk = extract_timelocks_with_tip(&tx, &tip(886100, None)).risk;
+    assert!(risk.findings.iter().any(|f| f.kind == RiskKind::AntiFeeSniping));
+}
+
+#[test]
+fn test_anti_fee_sniping_absent() {
+    let tx = make_tx(0, vec![make_vin(0xFFFFFFFD)], vec![make_vout(10_000, "v0_p2wpkh")]);
+    let risk = extract_timelocks_with_tip(&tx, &tip(886100, None)).risk;
+    assert_eq!(risk.findings.len(), 1);
+    assert_eq!(risk.findings[0].kind, RiskKind::NoAntiFeeSniping);
+    assert_eq!(risk.findings[0].severity, RiskSeverity::Info);
+
+    // Needs the chain tip
+    assert!(extract_timelocks(&tx).risk.findings.is_empty());
+}
+
+#[test]
+fn test_anti_fee_sniping_not_reported_for_old_locktime() {
+    let tx = make_tx(885990, vec![make_vin(0xFFFFFFFD)], vec![make_vout(10_000, "v0_p2wpkh")]);
+    assert!(extract_timelocks_with_tip(&tx, &tip(886100, None)).risk.findings.is_empty());
+}
+
+// ─── Flattened timelock list ─────────────────────────────────────────────────
+
+#[test]
+fn test_all_timelocks_matches_categories() {
+    let mut script_vin = make_vin(144);
+    script_vin.inner_witnessscript_asm = Some(
+        "886000 OP_CHECKLOCKTIMEVERIFY OP_DROP 144 OP_CHECKSEQUENCEVERIFY".to_string(),
+    );
+    let tx = make_tx(
+        885990,
+        vec![make_vin(0xFFFFFFFD), script_vin],
+        vec![make_vout(50_000, "v0_p2wsh")],
+    );
+    let analysis = extract_timelocks(&tx);
+    let all = analysis.all_timelocks();
+
+    let summary = &analysis.summary;
+    assert_eq!(
+        all.len(),
+        1 + summary.relative_timelock_count + summary.cltv_count + summary.csv_count
+    );
+    assert_eq!(all.len(), 4);
+
+    assert_eq!(all[0].kind, TimelockKind::NLocktime);
+    assert_eq!(all[0].location, TimelockLocation::Transaction);
+    assert_eq!(all[0].raw_value, 885990);
+    assert_eq!(all[1].kind, TimelockKind::Sequence);
+    assert_eq!(all[1].location, TimelockLocation::Input(1));
+    assert_eq!(all[1].domain, TimelockDomain::BlockHeight);
+    assert_eq!(all[2].kind, TimelockKind::Cltv);
+    assert_eq!(all[2].raw_value, 886000);
+    assert_eq!(all[3].kind, TimelockKind::Csv);
+    assert_eq!(all[3].location, TimelockLocation::Input(1));
+}
+
+#[test]
+fn test_all_timelocks_skips_zero_locktime_and_final_sequences() {
+    let tx = make_tx(0, vec![make_vin(0xFFFFFFFF)], vec![make_vout(10_000, "v0_p2wpkh")]);
+    assert!(extract_timelocks(&tx).all_timelocks().is_empty());
+}
+
+// ─── CLTV and CSV on one input ───────────────────────────────────────────────
+
+#[test]
+fn test_cltv_and_csv_on_same_input_flagged() {
+    let mut cltv_vin = make_vin(0xFFFFFFFD);
+    cltv_vin.inner_witnessscript_asm =
+        Some("886000 OP_CHECKLOCKTIMEVERIFY OP_DROP OP_CHECKSIG".to_string());
+    let mut both_vin = make_vin(144);
+    both_vin.inner_witnessscript_asm = Some(
+        "OP_IF 886000 OP_CHECKLOCKTIMEVERIFY OP_ELSE 144 OP_CHECKSEQUENCEVERIFY OP_ENDIF OP_DROP"
+            .to_string(),
+    );
+    let tx = make_tx(885990, vec![cltv_vin, both_vin], vec![make_vout(50_000, "v0_p2wsh")]);
+    let summary = extract_timelocks(&tx).summary;
+    assert_eq!(summary.cltv_count, 2);
+    assert_eq!(summary.csv_count, 1);
+    assert_eq!(summary.cltv_and_csv_inputs, vec![1]);
+}
+
+#[test]
+fn test_cltv_and_csv_on_different_inputs_not_flagged() {
+    let mut cltv_vin = make_vin(0xFFFFFFFD);
+    cltv_vin.inner_witnessscript_asm =
+        Some("886000 OP_CHECKLOCKTIMEVERIFY OP_DROP OP_CHECKSIG".to_string());
+    let mut csv_vin = make_vin(144);
+    csv_vin.inner_witnessscript_asm =
+        Some("144 OP_CHECKSEQUENCEVERIFY OP_DROP OP_CHECKSIG".to_string());
+    let tx = make_tx(885990, vec![cltv_vin, csv_vin], vec![make_vout(50_000, "v0_p2wsh")]);
+    assert!(extract_timelocks(&tx).summary.cltv_and_csv_inputs.is_empty());
+}
+
 // ═══════════════════════════════════════════════════════════════════════════
 // Goal 1: Timelock mixing detection
 // ═══════════════════════════════════════════════════════════════════════════
@@ -439,6 +938,87 @@ fn test_lightning_sequence_0x80_no_anomaly() {
     assert!(seq_alerts.is_empty());
 }
 
+// ─── HTLC-timeout maturity ───────────────────────────────────────────────────
+
+fn htlc_timeout_tx(confirmed: bool) -> ApiTransaction {
+    let mut vin = make_vin(0);
+    vin.witness = Some(vec!["".to_string(), "3045".to_string()]);
+    vin.inner_witnessscript_asm = Some(
+        "OP_IF 886200 OP_CHECKLOCKTIMEVERIFY OP_DROP OP_ENDIF OP_CHECKSIG".to_string(),
+    );
+    let mut tx = make_tx(886200, vec![vin], vec![make_vout(50_000, "v0_p2wpkh")]);
+    if !confirmed {
+        tx.status = ApiStatus {
+            confirmed: false,
+            block_height: None,
+            block_hash: None,
+            block_time: None,
+        };
+    }
+    tx
+}
+
+fn maturity_alerts(tx: &ApiTransaction, current_height: u64) -> Vec<Alert> {
+    run_analysis(tx, current_height)
+        .into_iter()
+        .filter(|a| a.detection_type == DetectionType::HtlcTimeoutMaturity)
+        .collect()
+}
+
+#[test]
+fn test_htlc_timeout_locktime_in_future_flagged() {
+    let alerts = maturity_alerts(&htlc_timeout_tx(false), 886100);
+    assert_eq!(alerts.len(), 1);
+    assert_eq!(alerts[0].severity, Severity::Warning);
+    assert!(alerts[0].description.starts_with("HTLC-timeout locktime 100 blocks in the future"));
+    assert!(alerts[0].description.contains("can't confirm until then"));
+    match &alerts[0].details {
+        AlertDetails::HtlcTimeoutMaturity {
+            cltv_expiry,
+            blocks_remaining,
+            confirmed,
+            ..
+        } => {
+            assert_eq!(*cltv_expiry, 886200);
+            assert_eq!(*blocks_remaining, 100);
+            assert!(!confirmed);
+        }
+        other => panic!("unexpected details: {other:?}"),
+    }
+}
+
+#[test]
+fn test_htlc_timeout_confirmed_before_locktime_suspect() {
+    let alerts = maturity_alerts(&htlc_timeout_tx(true), 886100);
+    assert_eq!(alerts.len(), 1);
+    assert_eq!(alerts[0].severity, Severity::Warning);
+    assert!(alerts[0].description.contains("suspect"));
+}
+
+#[test]
+fn test_htlc_timeout_locktime_at_tip_matured() {
+    let alerts = maturity_alerts(&htlc_timeout_tx(false), 886200);
+    assert_eq!(alerts.len(), 1);
+    assert_eq!(alerts[0].severity, Severity::Informational);
+    assert!(alerts[0].description.contains("already matured (0 blocks ago)"));
+}
+
+#[test]
+fn test_htlc_timeout_locktime_in_past_matured() {
+    let alerts = maturity_alerts(&htlc_timeout_tx(true), 886300);
+    assert_eq!(alerts.len(), 1);
+    assert_eq!(alerts[0].severity, Severity::Informational);
+    assert_eq!(
+        alerts[0].description,
+        "HTLC-timeout locktime at block 886200 already matured (100 blocks ago)."
+    );
+}
+
+#[test]
+fn test_htlc_timeout_maturity_needs_tip() {
+    assert!(maturity_alerts(&htlc_timeout_tx(false), 0).is_empty());
+}
+
 // ═══════════════════════════════════════════════════════════════════════════
 // Goal 5: Alert system structure
 // ═══════════════════════════════════════════════════════════════════════════
@@ -488,3 +1068,28 @@ fn test_mixing_alert_has_reference() {
     let reference = mixing.reference.as_ref().unwrap();
     assert!(reference.authors.contains("Kanjalkar"));
 }
+
+// ─── Sorting ─────────────────────────────────────────────────────────────────
+
+#[test]
+fn test_sort_analyses() {
+    let mut plain = make_tx(0, vec![make_vin(0xFFFFFFFF)], vec![make_vout(50_000, "v0_p2wpkh")]);
+    plain.txid = "plain".to_string();
+    let mut cltv_vin = make_vin(0xFFFFFFFE);
+    cltv_vin.inner_witnessscript_asm = Some(
+        "886000 OP_CHECKLOCKTIMEVERIFY OP_DROP 144 OP_CHECKSEQUENCEVERIFY OP_DROP".to_string(),
+    );
+    let mut scripts = make_tx(886000, vec![cltv_vin], vec![make_vout(50_000, "v0_p2wsh")]);
+    scripts.txid = "scripts".to_string();
+    let mut locktime = make_tx(886000, vec![make_vin(0xFFFFFFFE)], vec![make_vout(50_000, "v0_p2wpkh")]);
+    locktime.txid = "locktime".to_string();
+
+    let sorted = |key| {
+        let mut analyses: Vec<_> = [&plain, &locktime, &scripts].map(extract_timelocks).into();
+        sort_analyses(&mut analyses, key);
+        analyses.into_iter().map(|a| a.txid).collect::<Vec<_>>()
+    };
+    assert_eq!(sorted(SortKey::None), ["plain", "locktime", "scripts"]);
+    assert_eq!(sorted(SortKey::Type), ["locktime", "scripts", "plain"]);
+    assert_eq!(sorted(SortKey::HtlcCount), ["scripts", "plain", "locktime"]);
+}
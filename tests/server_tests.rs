@@ -1,14 +1,16 @@
-use std::sync::Mutex;
+use std::sync::{Arc, Mutex};
 
-use anyhow::Result;
+use cltv_scan::error::{Error, Result};
 use axum::body::Body;
 use axum::http::{Request, StatusCode};
 use tower::ServiceExt;
 
 use cltv_scan::api::source::DataSource;
 use cltv_scan::api::types::*;
+use cltv_scan::lightning::detector::classify_lightning;
 use cltv_scan::security::types::SecurityConfig;
 use cltv_scan::server;
+use cltv_scan::server::metrics::{WatchMetrics, metrics_router};
 
 // ─── Mock DataSource ─────────────────────────────────────────────────────────
 
@@ -30,7 +32,7 @@ impl DataSource for MockClient {
         txs.iter()
             .find(|tx| tx.txid == txid)
             .cloned()
-            .ok_or_else(|| anyhow::anyhow!("tx not found: {txid}"))
+            .ok_or_else(|| Error::NotFound(format!("tx not found: {txid}")))
     }
 
     async fn get_transaction_hex(&self, _txid: &str) -> Result<String> {
@@ -332,3 +334,38 @@ async fn test_cors_headers_present() {
         .get("access-control-allow-origin")
         .is_some());
 }
+
+// ─── Watch metrics ──────────────────────────────────────────────────────────
+
+#[tokio::test]
+async fn test_metrics_endpoint() {
+    let metrics = Arc::new(WatchMetrics::default());
+    let before = metrics.render();
+    assert!(before.contains("cltv_scan_blocks_scanned_total 0"));
+    assert!(!before.contains("cltv_scan_last_scanned_height"));
+
+    let results: Vec<_> = sample_txs()
+        .iter()
+        .map(|tx| (tx.txid.clone(), classify_lightning(tx)))
+        .collect();
+    metrics.record_block(886000, &results);
+    metrics.record_block(886001, &results);
+
+    let response = metrics_router(metrics)
+        .oneshot(Request::builder().uri("/metrics").body(Body::empty()).unwrap())
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(response.headers()["content-type"].to_str().unwrap().starts_with("text/plain"));
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    let body = String::from_utf8(body.to_vec()).unwrap();
+
+    assert!(body.contains("# TYPE cltv_scan_blocks_scanned_total counter"));
+    assert!(body.contains("cltv_scan_blocks_scanned_total 2\n"));
+    assert!(body.contains("cltv_scan_lightning_transactions_total{type=\"commitment\"} 2\n"));
+    assert!(body.contains("cltv_scan_lightning_transactions_total{type=\"mutual_close\"} 0\n"));
+    assert!(body.contains("# TYPE cltv_scan_last_scanned_height gauge"));
+    assert!(body.contains("cltv_scan_last_scanned_height 886001\n"));
+}
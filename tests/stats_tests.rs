@@ -0,0 +1,79 @@
+use cltv_scan::api::types::*;
+use cltv_scan::lightning::detector::classify_lightning;
+use cltv_scan::stats::RangeStats;
+use cltv_scan::testing::{make_tx, make_vin, make_vout};
+use cltv_scan::timelock::extractor::analyze_transaction;
+
+// ─── Test helpers ────────────────────────────────────────────────────────────
+
+fn record(stats: &mut RangeStats, tx: &ApiTransaction) {
+    stats.record(&analyze_transaction(tx), &classify_lightning(tx));
+}
+
+/// Commitment with `htlcs` HTLC outputs after to_local, to_remote and two anchors.
+fn commitment(htlcs: usize) -> ApiTransaction {
+    let mut vouts = vec![
+        make_vout(100_000, "v0_p2wsh"),
+        make_vout(200_000, "v0_p2wpkh"),
+        make_vout(330, "v0_p2wsh"),
+        make_vout(330, "v0_p2wsh"),
+    ];
+    vouts.extend((0..htlcs).map(|_| make_vout(50_000, "v0_p2wsh")));
+    make_tx("bb", 0x20000001, vec![make_vin(0x80000001)], vouts)
+}
+
+fn htlc_success() -> ApiTransaction {
+    let mut vin = make_vin(0);
+    vin.witness = Some(vec!["ab".repeat(32), "3045".to_string()]);
+    vin.inner_witnessscript_asm = Some(
+        "OP_SIZE 32 OP_EQUAL OP_IF OP_HASH160 abc OP_EQUALVERIFY OP_CHECKSIG OP_ELSE 1 OP_CHECKSEQUENCEVERIFY OP_DROP OP_ENDIF".to_string(),
+    );
+    make_tx("bb", 0, vec![vin], vec![make_vout(50_000, "v0_p2wsh")])
+}
+
+// ─── Range statistics ────────────────────────────────────────────────────────
+
+#[test]
+fn test_stats_counts_and_averages() {
+    let mut stats = RangeStats::default();
+    record(&mut stats, &commitment(1));
+    record(&mut stats, &commitment(3));
+    record(&mut stats, &htlc_success());
+    record(&mut stats, &make_tx("bb", 0, vec![make_vin(0xFFFFFFFF)], vec![make_vout(10_000, "v0_p2wpkh")]));
+
+    let report = stats.report(886000, 886001);
+    assert_eq!(report.transactions, 4);
+    assert_eq!(report.lightning.commitments, 2);
+    assert_eq!(report.avg_htlc_outputs_per_commitment, Some(2.0));
+    assert_eq!(report.preimages_revealed, 1);
+    // Both commitments enforce their nLockTime; the HTLC-success input's sequence 0
+    // is a zero-block relative timelock
+    assert_eq!(report.with_active_timelocks, 3);
+    assert_eq!(report.active_timelock_pct, 75.0);
+}
+
+#[test]
+fn test_stats_csv_distribution_and_merge() {
+    let mut vin = make_vin(0x80000001);
+    vin.inner_witnessscript_asm = Some(
+        "OP_IF abc OP_ELSE 144 OP_CHECKSEQUENCEVERIFY OP_DROP def OP_ENDIF OP_CHECKSIG".to_string(),
+    );
+    let tx = make_tx("bb", 0x20000001, vec![vin], vec![make_vout(100_000, "v0_p2wsh")]);
+
+    let mut first = RangeStats::default();
+    record(&mut first, &tx);
+    let mut second = RangeStats::default();
+    record(&mut second, &tx);
+    first.add(&second);
+
+    let report = first.report(886000, 886001);
+    assert_eq!(report.csv_delay_distribution.get(&144), Some(&2));
+}
+
+#[test]
+fn test_stats_empty_range() {
+    let report = RangeStats::default().report(886000, 886000);
+    assert_eq!(report.transactions, 0);
+    assert_eq!(report.active_timelock_pct, 0.0);
+    assert_eq!(report.avg_htlc_outputs_per_commitment, None);
+}
@@ -0,0 +1,78 @@
+use cltv_scan::util::{parse_block_position, parse_timestamp, parse_txid};
+
+// ─── Txid validation ─────────────────────────────────────────────────────────
+
+#[test]
+fn test_parse_txid_accepts_hex() {
+    let txid = "ab".repeat(32);
+    assert_eq!(parse_txid(&txid).unwrap(), txid);
+    assert_eq!(parse_txid(&"AB".repeat(32)).unwrap(), "AB".repeat(32));
+}
+
+#[test]
+fn test_parse_txid_trims_whitespace() {
+    let txid = "ab".repeat(32);
+    assert_eq!(parse_txid(&format!("  {txid}\n")).unwrap(), txid);
+}
+
+#[test]
+fn test_parse_txid_rejects_wrong_length() {
+    let err = parse_txid("abc").unwrap_err();
+    assert_eq!(err.to_string(), "invalid txid: expected 64 hex characters, got 3");
+
+    let err = parse_txid(&"ab".repeat(33)).unwrap_err();
+    assert_eq!(err.to_string(), "invalid txid: expected 64 hex characters, got 66");
+
+    assert!(parse_txid("").is_err());
+}
+
+#[test]
+fn test_parse_txid_rejects_non_hex() {
+    let txid = format!("{}zz", "ab".repeat(31));
+    let err = parse_txid(&txid).unwrap_err();
+    assert_eq!(err.to_string(), "invalid txid: 'z' is not a hex character");
+}
+
+#[test]
+fn test_parse_txid_counts_characters_not_bytes() {
+    let err = parse_txid(&"é".repeat(32)).unwrap_err();
+    assert_eq!(err.to_string(), "invalid txid: expected 64 hex characters, got 32");
+}
+
+// ─── Block positions ─────────────────────────────────────────────────────────
+
+#[test]
+fn test_parse_block_position() {
+    assert_eq!(parse_block_position("886000:5").unwrap(), (886000, 5));
+    assert_eq!(parse_block_position(" 0:0 ").unwrap(), (0, 0));
+}
+
+#[test]
+fn test_parse_block_position_rejects_malformed() {
+    let err = parse_block_position("886000").unwrap_err();
+    assert_eq!(err.to_string(), "invalid position \"886000\": expected <height>:<index>, e.g. 886000:5");
+    let err = parse_block_position("886000:x").unwrap_err();
+    assert_eq!(err.to_string(), "invalid transaction index \"x\"");
+    assert!(parse_block_position("-1:5").is_err());
+}
+
+// ─── Timestamps ──────────────────────────────────────────────────────────────
+
+#[test]
+fn test_parse_timestamp_formats() {
+    assert_eq!(parse_timestamp("1740830400").unwrap(), 1_740_830_400);
+    assert_eq!(parse_timestamp("2025-03-01T12:00:00Z").unwrap(), 1_740_830_400);
+    assert_eq!(parse_timestamp("2025-03-01T14:00:00+02:00").unwrap(), 1_740_830_400);
+    assert_eq!(parse_timestamp("2025-03-01T12:00:00").unwrap(), 1_740_830_400);
+    assert_eq!(parse_timestamp("2025-03-01").unwrap(), 1_740_787_200);
+}
+
+#[test]
+fn test_parse_timestamp_rejects_garbage() {
+    let err = parse_timestamp("yesterday").unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "invalid timestamp \"yesterday\": expected Unix seconds or an ISO 8601 date"
+    );
+    assert!(parse_timestamp("1969-12-31").is_err());
+}
@@ -0,0 +1,217 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use cltv_scan::error::{Error, Result};
+use bitcoin::ScriptBuf;
+use cltv_scan::api::source::DataSource;
+use cltv_scan::api::types::*;
+use cltv_scan::lightning::detector::classify_lightning;
+use cltv_scan::lightning::types::*;
+use cltv_scan::lightning::verify::verify_commitment;
+use cltv_scan::testing::{make_tx, make_vin, make_vout};
+
+// ─── Test helpers ────────────────────────────────────────────────────────────
+
+const FUNDING_SCRIPT: &str = concat!(
+    "52",
+    "21023da092f6980e58d2c037173180e9a465476026ee50f96695963e8efe436f54eb",
+    "21030e9f7b623d2ccc7c9bd44d66d5ce21ce504c0acf6385a132cec6d3c39fa711c1",
+    "52ae",
+);
+
+fn funding_script() -> ScriptBuf {
+    ScriptBuf::from_hex(FUNDING_SCRIPT).unwrap()
+}
+
+/// Anchor output paying `<funding_pubkey> OP_CHECKSIG OP_IFDUP OP_NOTIF OP_16 OP_CSV
+/// OP_ENDIF` for one of the funding script's keys.
+fn anchor_vout(funding_pubkey: &str) -> ApiVout {
+    let script = ScriptBuf::from_hex(&format!("21{funding_pubkey}ac736460b268")).unwrap();
+    p2wsh_vout(330, &script.to_p2wsh().to_hex_string())
+}
+
+fn p2wsh_vout(value: u64, scriptpubkey: &str) -> ApiVout {
+    ApiVout {
+        scriptpubkey: scriptpubkey.to_string(),
+        ..make_vout(value, "v0_p2wsh")
+    }
+}
+
+/// Commitment input spending output 0 of the `aa…` funding transaction.
+fn funding_vin(witness_script: &str) -> ApiVin {
+    ApiVin {
+        witness: Some(vec![
+            String::new(),
+            format!("30{}", "44".repeat(70)),
+            format!("30{}", "45".repeat(70)),
+            witness_script.to_string(),
+        ]),
+        ..make_vin(0x80000001)
+    }
+}
+
+/// Funding transaction whose output 0 is `scriptpubkey`.
+fn make_funding_tx(scriptpubkey: &str) -> ApiTransaction {
+    make_tx(&"aa".repeat(32), 0, vec![], vec![p2wsh_vout(1_000_000, scriptpubkey)])
+}
+
+/// Commitment with locktime and sequence markers but no anchors: `Possible` on its own.
+fn make_commitment(witness_script: &str) -> ApiTransaction {
+    make_tx(
+        &"bb".repeat(32),
+        0x20000042,
+        vec![funding_vin(witness_script)],
+        vec![
+            make_vout(600_000, "v0_p2wsh"),
+            make_vout(390_000, "v0_p2wpkh"),
+        ],
+    )
+}
+
+struct MockSource {
+    txs: Vec<ApiTransaction>,
+    tx_fetches: Arc<AtomicUsize>,
+}
+
+impl MockSource {
+    fn new(txs: Vec<ApiTransaction>) -> Self {
+        Self {
+            txs,
+            tx_fetches: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+}
+
+impl DataSource for MockSource {
+    async fn get_transaction(&self, txid: &str) -> Result<ApiTransaction> {
+        self.tx_fetches.fetch_add(1, Ordering::SeqCst);
+        self.txs
+            .iter()
+            .find(|tx| tx.txid == txid)
+            .cloned()
+            .ok_or_else(|| Error::NotFound(format!("tx not found: {txid}")))
+    }
+
+    async fn get_transaction_hex(&self, _txid: &str) -> Result<String> {
+        Ok("00".to_string())
+    }
+
+    async fn get_block_txs(&self, _hash: &str, _start_index: u32) -> Result<Vec<ApiTransaction>> {
+        Ok(vec![])
+    }
+
+    async fn get_block_tip_height(&self) -> Result<u64> {
+        Ok(886000)
+    }
+
+    async fn get_block_hash(&self, _height: u64) -> Result<String> {
+        Ok("ab".repeat(32))
+    }
+
+    async fn get_all_block_txs(&self, _height: u64) -> Result<Vec<ApiTransaction>> {
+        Ok(vec![])
+    }
+
+    async fn get_mempool_recent_txids(&self) -> Result<Vec<String>> {
+        Ok(vec![])
+    }
+}
+
+// ─── Funding output verification ─────────────────────────────────────────────
+
+#[tokio::test]
+async fn test_verified_commitment_upgraded() {
+    let p2wsh = funding_script().to_p2wsh().to_hex_string();
+    let source = MockSource::new(vec![make_funding_tx(&p2wsh)]);
+    let tx = make_commitment(FUNDING_SCRIPT);
+
+    let mut result = classify_lightning(&tx);
+    assert_eq!(result.confidence, Confidence::Possible);
+    verify_commitment(&source, &tx, &mut result).await.unwrap();
+
+    assert_eq!(result.confidence, Confidence::HighlyLikely);
+    assert_eq!(
+        result.params.funding_outpoint,
+        Some(format!("{}:0", "aa".repeat(32)))
+    );
+    assert!(result.reasons.contains(&"input spends a 2-of-2 funding output".to_string()));
+    assert_eq!(source.tx_fetches.load(Ordering::SeqCst), 1);
+}
+
+#[tokio::test]
+async fn test_inline_prevout_skips_fetch() {
+    let p2wsh = funding_script().to_p2wsh().to_hex_string();
+    let source = MockSource::new(vec![]);
+    let mut tx = make_commitment(FUNDING_SCRIPT);
+    tx.vin[0].prevout = Some(ApiPrevout {
+        scriptpubkey: p2wsh,
+        scriptpubkey_asm: String::new(),
+        scriptpubkey_type: "v0_p2wsh".to_string(),
+        scriptpubkey_address: None,
+        value: 1_000_000,
+    });
+
+    let mut result = classify_lightning(&tx);
+    verify_commitment(&source, &tx, &mut result).await.unwrap();
+
+    assert_eq!(result.confidence, Confidence::HighlyLikely);
+    assert_eq!(source.tx_fetches.load(Ordering::SeqCst), 0);
+}
+
+#[tokio::test]
+async fn test_script_hash_mismatch_downgraded() {
+    // The revealed script is a 2-of-2, but not the one the funding output commits to
+    let other = ScriptBuf::from_hex(&FUNDING_SCRIPT.replace("023d", "033d")).unwrap();
+    let source = MockSource::new(vec![make_funding_tx(&other.to_p2wsh().to_hex_string())]);
+    let mut tx = make_commitment(FUNDING_SCRIPT);
+    // Anchors of the two keys FUNDING_SCRIPT pushes
+    tx.vout.push(anchor_vout(&FUNDING_SCRIPT[4..70]));
+    tx.vout.push(anchor_vout(&FUNDING_SCRIPT[72..138]));
+
+    let mut result = classify_lightning(&tx);
+    assert_eq!(result.confidence, Confidence::HighlyLikely);
+    verify_commitment(&source, &tx, &mut result).await.unwrap();
+
+    assert_eq!(result.confidence, Confidence::Possible);
+    assert_eq!(result.params.funding_outpoint, None);
+    assert!(result.reasons.contains(&"input does not spend a 2-of-2 funding output".to_string()));
+}
+
+#[tokio::test]
+async fn test_non_multisig_script_downgraded() {
+    // Single-key script committed to correctly by the spent output
+    let single = format!("21{}ac", "02".repeat(33));
+    let p2wsh = ScriptBuf::from_hex(&single).unwrap().to_p2wsh().to_hex_string();
+    let source = MockSource::new(vec![make_funding_tx(&p2wsh)]);
+    let tx = make_commitment(&single);
+
+    let mut result = classify_lightning(&tx);
+    verify_commitment(&source, &tx, &mut result).await.unwrap();
+    assert_eq!(result.confidence, Confidence::Possible);
+}
+
+#[tokio::test]
+async fn test_non_commitment_untouched() {
+    let source = MockSource::new(vec![]);
+    let mut tx = make_commitment(FUNDING_SCRIPT);
+    tx.locktime = 0;
+    tx.vin[0].sequence = 0xFFFFFFFF;
+
+    let mut result = classify_lightning(&tx);
+    let before = result.confidence;
+    verify_commitment(&source, &tx, &mut result).await.unwrap();
+
+    assert_ne!(result.tx_type, Some(LightningTxType::Commitment));
+    assert_eq!(result.confidence, before);
+    assert!(result.params.funding_outpoint.is_none());
+    assert_eq!(source.tx_fetches.load(Ordering::SeqCst), 0);
+}
+
+#[tokio::test]
+async fn test_missing_funding_tx_is_an_error() {
+    let source = MockSource::new(vec![]);
+    let tx = make_commitment(FUNDING_SCRIPT);
+    let mut result = classify_lightning(&tx);
+    let err = verify_commitment(&source, &tx, &mut result).await.unwrap_err();
+    assert!(format!("{err:#}").contains("fetching funding transaction"), "{err:#}");
+}